@@ -177,20 +177,28 @@ fn string_enum_config(ident: &syn::Ident, e: syn::DataEnum) -> proc_macro2::Toke
   }
 
   quote::quote! {
+    impl ::std::str::FromStr for #ident {
+      type Err = String;
+
+      fn from_str(s: &str) -> ::std::result::Result<Self, String> {
+        Ok(match s {
+          #(#variant_arms,)*
+          s => return Err(format!("unknown variant: '{s}'")),
+        })
+      }
+    }
+
     impl ::be_config::parse::ParseValue for #ident {
       fn parse(
         &mut self,
         value: ::be_config::parse::DeValue,
         de: &mut ::be_config::parse::Parser,
       ) -> ::std::result::Result<(), String> {
-        let ::be_config::parse::DeValue::String(mut s) = value else {
+        let ::be_config::parse::DeValue::String(s) = value else {
           return Err("expected string".to_string());
         };
 
-        *self = match &*s {
-          #(#variant_arms,)*
-          s => return Err(format!("unknown variant: '{s}'")),
-        };
+        *self = s.parse()?;
 
         Ok(())
       }
@@ -0,0 +1,151 @@
+use std::fmt;
+
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum Key {
+  Char(char),
+  Backspace,
+  Delete,
+  Escape,
+  Tab,
+
+  ArrowUp,
+  ArrowDown,
+  ArrowLeft,
+  ArrowRight,
+}
+
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct KeyStroke {
+  pub key:     Key,
+  pub control: bool,
+  pub alt:     bool,
+}
+
+impl PartialEq<char> for Key {
+  fn eq(&self, other: &char) -> bool {
+    match self {
+      Key::Char(c) => c == other,
+      _ => false,
+    }
+  }
+}
+
+impl KeyStroke {
+  pub const fn char(c: char) -> Self { KeyStroke { key: Key::Char(c), control: false, alt: false } }
+
+  /// Parses keys written in vim's notation, like `dw<Esc>` or `<C-v>j`. This
+  /// is the inverse of the `Display` impl, and is used to store keys in
+  /// registers as text.
+  pub fn parse_all(mut s: &str) -> Vec<KeyStroke> {
+    let mut keys = vec![];
+
+    while let Some(c) = s.chars().next() {
+      if c == '<'
+        && let Some(end) = s.find('>')
+        && let Some(key) = KeyStroke::parse_special(&s[1..end])
+      {
+        keys.push(key);
+        s = &s[end + 1..];
+      } else {
+        keys.push(KeyStroke::char(c));
+        s = &s[c.len_utf8()..];
+      }
+    }
+
+    keys
+  }
+
+  /// Parses keys like `parse_all`, but fails on anything in angle brackets
+  /// that isn't a key name. This is used for key sequences in the config, where
+  /// a typo like `<C-space>` shouldn't bind the characters instead.
+  pub fn parse_strict(mut s: &str) -> Result<Vec<KeyStroke>, String> {
+    let mut keys = vec![];
+
+    while let Some(c) = s.chars().next() {
+      if c == '<'
+        && let Some(end) = s.find('>')
+      {
+        let key = KeyStroke::parse_special(&s[1..end])
+          .ok_or_else(|| format!("invalid key: '{}'", &s[..=end]))?;
+        keys.push(key);
+        s = &s[end + 1..];
+      } else {
+        keys.push(KeyStroke::char(c));
+        s = &s[c.len_utf8()..];
+      }
+    }
+
+    Ok(keys)
+  }
+
+  fn parse_special(mut s: &str) -> Option<KeyStroke> {
+    let mut stroke = KeyStroke::char(' ');
+    loop {
+      if let Some(rest) = s.strip_prefix("C-") {
+        stroke.control = true;
+        s = rest;
+      } else if let Some(rest) = s.strip_prefix("M-") {
+        stroke.alt = true;
+        s = rest;
+      } else {
+        break;
+      }
+    }
+
+    stroke.key = match s {
+      "Esc" => Key::Escape,
+      "BS" => Key::Backspace,
+      "Del" => Key::Delete,
+      "Tab" => Key::Tab,
+      "CR" => Key::Char('\n'),
+      "Space" => Key::Char(' '),
+      "lt" => Key::Char('<'),
+      "Up" => Key::ArrowUp,
+      "Down" => Key::ArrowDown,
+      "Left" => Key::ArrowLeft,
+      "Right" => Key::ArrowRight,
+      _ => {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+          (Some(c), None) if stroke.control || stroke.alt => Key::Char(c),
+          _ => return None,
+        }
+      }
+    };
+
+    Some(stroke)
+  }
+}
+
+impl fmt::Display for KeyStroke {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let name = match self.key {
+      Key::Char('\n') => "CR",
+      Key::Char('<') => "lt",
+      Key::Char(' ') if self.control || self.alt => "Space",
+      Key::Char(c) if !self.control && !self.alt => return write!(f, "{c}"),
+      Key::Char(_) => "",
+      Key::Backspace => "BS",
+      Key::Delete => "Del",
+      Key::Escape => "Esc",
+      Key::Tab => "Tab",
+      Key::ArrowUp => "Up",
+      Key::ArrowDown => "Down",
+      Key::ArrowLeft => "Left",
+      Key::ArrowRight => "Right",
+    };
+
+    write!(f, "<")?;
+    if self.control {
+      write!(f, "C-")?;
+    }
+    if self.alt {
+      write!(f, "M-")?;
+    }
+    match self.key {
+      Key::Char(c) if name.is_empty() => write!(f, "{c}")?,
+      _ => write!(f, "{name}")?,
+    }
+    write!(f, ">")
+  }
+}
@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+
+use be_config_macros::Config;
+
+use crate::{
+  KeyStroke,
+  parse::{DeValue, ParseTable, ParseValue, Parser},
+};
+
+/// The `[keys]` section, which binds key sequences to actions. These are
+/// checked before the built-in bindings, so they can add new sequences, replace
+/// built-in ones, or unbind them.
+#[derive(Default, Config, Clone)]
+pub struct KeySettings {
+  /// The key that `<leader>` stands for in key sequences.
+  pub leader: String,
+  pub normal: Keymap,
+  pub insert: Keymap,
+  pub visual: Keymap,
+}
+
+/// Key sequences in vim's notation, like `"<leader>w"` or `"<C-s>"`, and what
+/// they are bound to.
+#[derive(Default, Clone, Debug)]
+pub struct Keymap {
+  pub bindings: HashMap<String, Binding>,
+  /// The parsed keys of each binding, filled in by `KeySettings::resolve`.
+  sequences:    Vec<(Vec<KeyStroke>, Binding)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Binding {
+  /// One of the editor's actions, written as its name, like `"delete-line"`.
+  Action(KeyAction),
+  /// An ex command, written with a leading `:`, like `":w"`.
+  Command(String),
+  /// Removes a built-in binding, written as `"none"`.
+  Unbound,
+}
+
+/// The actions that keys can be bound to by name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Config)]
+pub enum KeyAction {
+  // Moves.
+  MoveLeft,
+  MoveRight,
+  MoveUp,
+  MoveDown,
+  NextWord,
+  EndWord,
+  PrevWord,
+  LineStart,
+  LineStartOfText,
+  LineEnd,
+  MatchingBracket,
+  FileStart,
+  FileEnd,
+  NextResult,
+  PrevResult,
+  NextChange,
+  PrevChange,
+  NextDiagnostic,
+  PrevDiagnostic,
+  GotoDefinition,
+  BackDefinition,
+  JumpBack,
+  JumpForward,
+
+  // Edits.
+  DeleteChar,
+  DeleteLine,
+  CutLine,
+  YankLine,
+  DeleteRestOfLine,
+  PasteAfter,
+  PasteBefore,
+  Backspace,
+  Undo,
+  Redo,
+  Earlier,
+  Later,
+  SwitchCase,
+  ReindentLine,
+  CommentLine,
+  Repeat,
+
+  // Modes.
+  Normal,
+  Insert,
+  Append,
+  OpenBelow,
+  OpenAbove,
+  Visual,
+  VisualLine,
+  VisualBlock,
+  Command,
+  Search,
+
+  // Visual mode.
+  DeleteSelection,
+  ChangeSelection,
+  YankSelection,
+  IndentSelection,
+  DedentSelection,
+  ReindentSelection,
+  CommentSelection,
+
+  // Folds.
+  CloseFold,
+  OpenFold,
+  ToggleFold,
+  OpenAllFolds,
+  CloseAllFolds,
+
+  // Cursors.
+  AddCursorAtNextMatch,
+  AddCursorAbove,
+  AddCursorBelow,
+  SplitSelection,
+  ClearCursors,
+
+  // Windows.
+  FindFile,
+  FocusLeft,
+  FocusRight,
+  FocusUp,
+  FocusDown,
+  SplitVertical,
+  SplitHorizontal,
+
+  Autocomplete,
+  Copy,
+  Paste,
+}
+
+impl KeySettings {
+  /// Parses the keys of every binding, replacing `<leader>` with the leader
+  /// key. This is called once all the config files are loaded, as a later file
+  /// can change the leader. If the leader is invalid, bindings that use it are
+  /// left out.
+  pub fn resolve(&mut self) -> Result<(), String> {
+    let leader = KeyStroke::parse_strict(&self.leader);
+    for keymap in [&mut self.normal, &mut self.insert, &mut self.visual] {
+      keymap.sequences = keymap
+        .bindings
+        .iter()
+        .filter_map(|(keys, binding)| {
+          let leader = match &leader {
+            Ok(leader) => leader.as_slice(),
+            Err(_) if keys.contains("<leader>") => return None,
+            Err(_) => &[],
+          };
+          Some((parse_sequence(keys, leader).ok()?, binding.clone()))
+        })
+        .collect();
+    }
+
+    leader.map(|_| ()).map_err(|e| format!("{e} for leader"))
+  }
+}
+
+impl Keymap {
+  /// The parsed keys of each binding, with `<leader>` replaced.
+  pub fn sequences(&self) -> &[(Vec<KeyStroke>, Binding)] { &self.sequences }
+}
+
+/// Parses a key sequence from the config, replacing `<leader>` with `leader`.
+fn parse_sequence(sequence: &str, leader: &[KeyStroke]) -> Result<Vec<KeyStroke>, String> {
+  let mut keys = vec![];
+  for (i, part) in sequence.split("<leader>").enumerate() {
+    if i > 0 {
+      keys.extend_from_slice(leader);
+    }
+    keys.extend(KeyStroke::parse_strict(part)?);
+  }
+
+  Ok(keys)
+}
+
+impl ParseTable for Keymap {
+  fn required_keys() -> &'static [&'static str] { &[] }
+
+  fn set_key(&mut self, key: &str, value: DeValue, de: &mut Parser) -> bool {
+    let mut binding = Binding::Unbound;
+    match parse_sequence(key, &[]).and_then(|_| binding.parse(value, de)) {
+      Ok(()) => {
+        self.bindings.insert(key.to_string(), binding);
+      }
+      Err(e) => de.error(format!("{e} for keys '{key}'"), 0..0),
+    }
+
+    true
+  }
+}
+
+impl ParseValue for Binding {
+  fn parse(&mut self, value: DeValue, _de: &mut Parser) -> Result<(), String> {
+    let DeValue::String(name) = value else { return Err("expected string".to_string()) };
+
+    *self = if let Some(command) = name.strip_prefix(':') {
+      Binding::Command(command.to_string())
+    } else if name == "none" {
+      Binding::Unbound
+    } else {
+      Binding::Action(name.parse().map_err(|_| format!("unknown action: '{name}'"))?)
+    };
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn bindings() {
+    let mut keys = crate::Config::default_ref().settings.keys.clone();
+    let diagnostics = crate::parse::parse_into(
+      &mut keys,
+      r#"
+      leader = ","
+
+      [normal]
+      "<leader>w" = ":w"
+      "gh" = "line-start"
+      "x" = "none"
+
+      [insert]
+      "jk" = "normal"
+      "#,
+    );
+    assert!(diagnostics.is_empty());
+
+    assert_eq!(keys.leader, ",");
+    assert_eq!(keys.normal.bindings["<leader>w"], Binding::Command("w".to_string()));
+    assert_eq!(keys.normal.bindings["gh"], Binding::Action(KeyAction::LineStart));
+    assert_eq!(keys.normal.bindings["x"], Binding::Unbound);
+    assert_eq!(keys.insert.bindings["jk"], Binding::Action(KeyAction::Normal));
+
+    keys.resolve().unwrap();
+    let find = |sequence: &str| {
+      let sequence = KeyStroke::parse_all(sequence);
+      keys.normal.sequences().iter().find(|(k, _)| *k == sequence).map(|(_, b)| b.clone())
+    };
+    assert_eq!(find(",w"), Some(Binding::Command("w".to_string())));
+    assert_eq!(find("<Space>s"), None);
+  }
+
+  #[test]
+  fn unknown_action() {
+    let mut keys = KeySettings::default();
+    let diagnostics = crate::parse::parse_into(
+      &mut keys,
+      r#"
+      [normal]
+      "gh" = "go-home"
+      "gl" = 3
+      "#,
+    );
+
+    let mut titles = diagnostics.iter().map(|d| d.title.as_str()).collect::<Vec<_>>();
+    titles.sort();
+    assert_eq!(
+      titles,
+      ["expected string for keys 'gl'", "unknown action: 'go-home' for keys 'gh'"]
+    );
+    assert!(keys.normal.bindings.is_empty());
+  }
+
+  #[test]
+  fn invalid_keys() {
+    let mut keys = KeySettings::default();
+    let diagnostics = crate::parse::parse_into(
+      &mut keys,
+      r#"
+      leader = "<Spce>"
+
+      [normal]
+      "<C-Space>" = "line-start"
+      "<leader>w" = ":w"
+      "<Cr>" = "line-end"
+      "#,
+    );
+
+    let titles = diagnostics.iter().map(|d| d.title.as_str()).collect::<Vec<_>>();
+    assert_eq!(titles, ["invalid key: '<Cr>' for keys '<Cr>'"]);
+    assert_eq!(keys.normal.bindings.len(), 2);
+
+    assert_eq!(keys.resolve(), Err("invalid key: '<Spce>' for leader".to_string()));
+    assert_eq!(
+      keys.normal.sequences(),
+      [(KeyStroke::parse_all("<C-Space>"), Binding::Action(KeyAction::LineStart))]
+    );
+  }
+}
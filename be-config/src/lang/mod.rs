@@ -10,7 +10,7 @@ macro_rules! builtin {
   }
 }
 
-builtin!["markdown", "rust", "toml"];
+builtin!["markdown", "python", "rust", "toml", "yaml"];
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct LanguageName {
@@ -20,11 +20,26 @@ pub struct LanguageName {
 #[derive(Clone, serde::Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Language {
-  pub display_name: String,
-  pub extensions:   Vec<String>,
-  pub icon:         Option<String>,
-  pub tree_sitter:  Option<TreeSitterSettings>,
-  pub lsp:          Option<LspSettings>,
+  pub display_name:  String,
+  pub extensions:    Vec<String>,
+  pub icon:          Option<String>,
+  pub tree_sitter:   Option<TreeSitterSettings>,
+  pub lsp:           Option<LspSettings>,
+  /// Brackets and quotes that are closed as they are typed, written as the
+  /// opener followed by the closer, like `"()"`.
+  #[serde(default)]
+  pub auto_pairs:    Vec<AutoPair>,
+  /// Wraps long lines at the edge of the editor, instead of scrolling
+  /// sideways.
+  #[serde(default)]
+  pub soft_wrap:     bool,
+  /// With `soft-wrap`, lines wrap at this many columns if the editor is wider.
+  pub text_width:    Option<usize>,
+  /// The token that starts a line comment, like `"//"`.
+  pub line_comment:  Option<String>,
+  /// The tokens around a block comment, like `["/*", "*/"]`. Languages without
+  /// line comments comment out each line with these.
+  pub block_comment: Option<[String; 2]>,
 }
 
 #[derive(Clone, serde::Deserialize)]
@@ -40,6 +55,25 @@ pub struct LspSettings {
   pub command: String,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub struct AutoPair {
+  pub open:  char,
+  pub close: char,
+}
+
+impl TryFrom<String> for AutoPair {
+  type Error = String;
+
+  fn try_from(s: String) -> Result<Self, Self::Error> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+      (Some(open), Some(close), None) => Ok(AutoPair { open, close }),
+      _ => Err(format!("expected an opener and a closer, like \"()\", got {s:?}")),
+    }
+  }
+}
+
 impl Language {
   pub fn builtin() -> HashMap<LanguageName, Language> {
     BUILTIN_LANGUAGES
@@ -69,4 +103,46 @@ mod tests {
       }
     }
   }
+
+  #[test]
+  fn auto_pairs() {
+    let lang =
+      Language::parse("display-name = \"a\"\nextensions = []\nauto-pairs = [\"()\", '\"\"']")
+        .unwrap();
+    assert_eq!(
+      lang.auto_pairs,
+      [AutoPair { open: '(', close: ')' }, AutoPair { open: '"', close: '"' }]
+    );
+
+    assert!(
+      Language::parse("display-name = \"a\"\nextensions = []\nauto-pairs = [\"(\"]").is_err()
+    );
+  }
+
+  #[test]
+  fn soft_wrap() {
+    let lang = Language::parse("display-name = \"a\"\nextensions = []").unwrap();
+    assert!(!lang.soft_wrap);
+    assert_eq!(lang.text_width, None);
+
+    let lang =
+      Language::parse("display-name = \"a\"\nextensions = []\nsoft-wrap = true\ntext-width = 80")
+        .unwrap();
+    assert!(lang.soft_wrap);
+    assert_eq!(lang.text_width, Some(80));
+  }
+
+  #[test]
+  fn comments() {
+    let lang = Language::parse(
+      "display-name = \"a\"\nextensions = []\nline-comment = \"//\"\nblock-comment = [\"/*\", \"*/\"]",
+    )
+    .unwrap();
+    assert_eq!(lang.line_comment.as_deref(), Some("//"));
+    assert_eq!(lang.block_comment, Some(["/*".to_string(), "*/".to_string()]));
+
+    assert!(
+      Language::parse("display-name = \"a\"\nextensions = []\nblock-comment = [\"/*\"]").is_err()
+    );
+  }
 }
@@ -1,9 +1,13 @@
 use std::{collections::HashMap, io, path::PathBuf, sync::LazyLock};
 
+mod key;
+mod keys;
 mod lang;
 mod parse;
 mod settings;
 
+pub use key::*;
+pub use keys::*;
 pub use lang::*;
 pub use settings::*;
 
@@ -65,4 +69,16 @@ impl Config {
 
     None
   }
+
+  /// Finds a language by the name used for it in other languages, like the
+  /// language of a code block in Markdown. This is the name of the language,
+  /// its display name, or one of its extensions.
+  pub fn language_for_name(&self, name: &str) -> Option<LanguageName> {
+    self.languages.iter().find_map(|(&ft, language)| {
+      (ft.name().eq_ignore_ascii_case(name)
+        || language.display_name.eq_ignore_ascii_case(name)
+        || language.extensions.iter().any(|ext| ext == name))
+      .then_some(ft)
+    })
+  }
 }
@@ -1,4 +1,7 @@
-use crate::parse::ParseResult;
+use crate::{
+  KeySettings,
+  parse::{Diagnostic, DiagnosticLevel, ParseResult},
+};
 
 use be_config_macros::Config;
 
@@ -7,13 +10,27 @@ pub struct Settings {
   pub editor: EditorSettings,
   pub ui:     UiSettings,
   pub layout: LayoutSettings,
+  pub keys:   KeySettings,
 }
 
 #[derive(Default, Config, Clone)]
 pub struct EditorSettings {
-  pub font:          FontSettings,
-  pub scroll_offset: u32,
-  pub indent_width:  u32,
+  pub font:            FontSettings,
+  pub scroll_offset:   u32,
+  pub indent_width:    u32,
+  /// Files larger than this many bytes are loaded in the background, without
+  /// highlighting, LSP, or git diffs.
+  pub large_file_size: u64,
+  /// Whether `j` and `k` move by lines or by screen rows when lines are soft
+  /// wrapped. `gj` and `gk` move by the other.
+  pub wrap_moves:      WrapMoves,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Config)]
+pub enum WrapMoves {
+  #[default]
+  Line,
+  Screen,
 }
 
 #[derive(Default, Config, Clone)]
@@ -64,13 +81,14 @@ impl Settings {
   pub fn load() -> ParseResult<Settings> {
     let mut config = crate::Config::default_ref().settings.clone();
 
-    let diagnostics = if let Ok(data) =
+    let mut diagnostics = if let Ok(data) =
       std::fs::read_to_string(crate::config_root().unwrap().join("config.toml"))
     {
       crate::parse::parse_into(&mut config, &data)
     } else {
       vec![]
     };
+    resolve_keys(&mut config, &mut diagnostics);
 
     ParseResult { value: config, diagnostics }
   }
@@ -79,7 +97,16 @@ impl Settings {
 }
 
 fn parse_default_config() -> ParseResult<Settings> {
-  crate::parse::parse(include_str!("../default.toml"))
+  let mut res = crate::parse::parse(include_str!("../default.toml"));
+  resolve_keys(&mut res.value, &mut res.diagnostics);
+  res
+}
+
+/// Parses the key bindings, once every config file has been read.
+fn resolve_keys(settings: &mut Settings, diagnostics: &mut Vec<Diagnostic>) {
+  if let Err(title) = settings.keys.resolve() {
+    diagnostics.push(Diagnostic { title, line: 0, level: DiagnosticLevel::Error });
+  }
 }
 
 #[cfg(test)]
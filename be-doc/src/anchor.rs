@@ -0,0 +1,133 @@
+use std::ops::Range;
+
+use crate::{Change, Document};
+
+/// Which way an anchor moves when text is inserted exactly at it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Gravity {
+  /// The anchor stays before the inserted text.
+  Left,
+  /// The anchor moves after the inserted text, so it stays on the same
+  /// character.
+  Right,
+}
+
+/// A byte offset registered with [`Document::anchor`]. Every change applied to
+/// the document shifts it, so it keeps pointing at the same text.
+///
+/// Once an anchor is removed, its id may be reused by a new anchor.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Anchor(usize);
+
+#[derive(Default, Clone)]
+pub(crate) struct Anchors {
+  slots: Vec<Option<(usize, Gravity)>>,
+}
+
+impl Anchors {
+  pub(crate) fn apply(&mut self, change: &Change) {
+    for (offset, gravity) in self.slots.iter_mut().flatten() {
+      *offset = shift(*offset, *gravity, change.range.clone(), change.text.len());
+    }
+  }
+}
+
+/// Moves `offset` to where the same text is after `range` is replaced with
+/// `len` bytes. An offset inside the removed text ends up on the side of the
+/// inserted text given by its gravity.
+fn shift(offset: usize, gravity: Gravity, range: Range<usize>, len: usize) -> usize {
+  if offset < range.start || (offset == range.start && gravity == Gravity::Left) {
+    offset
+  } else if offset >= range.end {
+    offset - range.len() + len
+  } else {
+    match gravity {
+      Gravity::Left => range.start,
+      Gravity::Right => range.start + len,
+    }
+  }
+}
+
+impl Document {
+  /// Registers an anchor at `offset`.
+  pub fn anchor(&mut self, offset: usize, gravity: Gravity) -> Anchor {
+    let offset = offset.min(self.len());
+    let slot = Some((offset, gravity));
+
+    match self.anchors.slots.iter().position(Option::is_none) {
+      Some(i) => {
+        self.anchors.slots[i] = slot;
+        Anchor(i)
+      }
+      None => {
+        self.anchors.slots.push(slot);
+        Anchor(self.anchors.slots.len() - 1)
+      }
+    }
+  }
+
+  /// Returns the current offset of `anchor`, or `None` if it was removed.
+  pub fn anchor_offset(&self, anchor: Anchor) -> Option<usize> {
+    self.anchors.slots.get(anchor.0).copied().flatten().map(|(offset, _)| offset)
+  }
+
+  pub fn remove_anchor(&mut self, anchor: Anchor) {
+    if let Some(slot) = self.anchors.slots.get_mut(anchor.0) {
+      *slot = None;
+    }
+
+    while self.anchors.slots.last().is_some_and(Option::is_none) {
+      self.anchors.slots.pop();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn gravity() {
+    let mut doc = Document::from("hello world");
+    let left = doc.anchor(6, Gravity::Left);
+    let right = doc.anchor(6, Gravity::Right);
+    let end = doc.anchor(11, Gravity::Left);
+
+    doc.apply(&Change::insert(6, "big "));
+    assert_eq!(doc.anchor_offset(left), Some(6));
+    assert_eq!(doc.anchor_offset(right), Some(10));
+    assert_eq!(doc.anchor_offset(end), Some(15));
+
+    doc.apply(&Change::remove(0..6));
+    assert_eq!(doc.anchor_offset(left), Some(0));
+    assert_eq!(doc.anchor_offset(right), Some(4));
+    assert_eq!(doc.anchor_offset(end), Some(9));
+  }
+
+  #[test]
+  fn removed_text() {
+    let mut doc = Document::from("abcdef");
+    let left = doc.anchor(3, Gravity::Left);
+    let right = doc.anchor(3, Gravity::Right);
+
+    doc.apply(&Change::replace(2..5, "xy"));
+    assert_eq!(doc, "abxyf");
+    assert_eq!(doc.anchor_offset(left), Some(2));
+    assert_eq!(doc.anchor_offset(right), Some(4));
+  }
+
+  #[test]
+  fn remove() {
+    let mut doc = Document::from("abc");
+    let a = doc.anchor(1, Gravity::Left);
+    let b = doc.anchor(2, Gravity::Left);
+
+    doc.remove_anchor(a);
+    assert_eq!(doc.anchor_offset(a), None);
+    assert_eq!(doc.anchor_offset(b), Some(2));
+
+    let c = doc.anchor(3, Gravity::Left);
+    assert_eq!(c, a);
+    assert_eq!(doc.anchor_offset(c), Some(3));
+  }
+}
@@ -1,14 +1,16 @@
 use std::ops::Range;
 
+use serde::{Deserialize, Serialize};
+
 use crate::Document;
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Edit {
   forward:  Vec<Change>,
   backward: Vec<Change>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Change {
   pub range: Range<usize>,
   pub text:  String,
@@ -26,6 +28,8 @@ impl Edit {
     self.forward.push(change.clone());
   }
 
+  pub fn is_empty(&self) -> bool { self.forward.is_empty() }
+
   pub fn redo(&self) -> impl Iterator<Item = &Change> { self.forward.iter() }
 
   pub fn undo(&self) -> impl Iterator<Item = &Change> { self.backward.iter().rev() }
@@ -49,6 +53,7 @@ impl Change {
 impl Document {
   pub fn apply(&mut self, change: &Change) {
     self.snap.rope.replace(change.range.clone(), &change.text);
+    self.anchors.apply(change);
   }
 }
 
@@ -0,0 +1,248 @@
+use std::{fmt, io};
+
+use encoding_rs::{CoderResult, Decoder, EncoderResult, Encoding, UTF_8, UTF_16BE, UTF_16LE};
+
+use crate::DocumentSnapshot;
+
+/// The encoding of a file on disk. Documents are always UTF-8 in memory, and
+/// are converted to and from this when reading and writing.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FileEncoding {
+  encoding: &'static Encoding,
+  bom:      bool,
+}
+
+impl Default for FileEncoding {
+  fn default() -> Self { FileEncoding::UTF_8 }
+}
+
+impl FileEncoding {
+  pub const UTF_8: FileEncoding = FileEncoding { encoding: UTF_8, bom: false };
+
+  /// Parses an encoding name, like `utf-8`, `latin1`, or `shift_jis`. A
+  /// `-bom` suffix adds a byte order mark, which UTF-16 files always have.
+  pub fn from_label(label: &str) -> Option<FileEncoding> {
+    let (label, bom) = match label.strip_suffix("-bom") {
+      Some(label) => (label, true),
+      None => (label, false),
+    };
+
+    // `for_label` only knows the names from the WHATWG spec, so add the UCS-2
+    // names that vim uses.
+    let encoding = match label.to_ascii_lowercase().as_str() {
+      "ucs-2le" => UTF_16LE,
+      "ucs-2" | "ucs-2be" => UTF_16BE,
+      label => Encoding::for_label(label.as_bytes())?,
+    };
+
+    Some(FileEncoding { encoding, bom: bom || is_utf16(encoding) })
+  }
+
+  /// Detects the encoding of the contents of a file. A byte order mark always
+  /// wins, then UTF-16 and UTF-8, and otherwise this guesses a legacy encoding
+  /// from the byte frequencies. A character cut off at the end of `bytes` is
+  /// ignored, so this also works on the start of a file.
+  pub fn detect(bytes: &[u8]) -> FileEncoding {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+      return FileEncoding { encoding, bom: true };
+    }
+
+    if let Some(encoding) = guess_utf16(bytes) {
+      return FileEncoding { encoding, bom: false };
+    }
+
+    match str::from_utf8(bytes) {
+      Ok(_) => return FileEncoding::UTF_8,
+      Err(e) if e.error_len().is_none() => return FileEncoding::UTF_8,
+      Err(_) => {}
+    }
+
+    let mut detector = chardetng::EncodingDetector::new();
+    detector.feed(bytes, true);
+    FileEncoding { encoding: detector.guess(None, false), bom: false }
+  }
+
+  pub fn name(&self) -> &'static str { self.encoding.name() }
+  pub fn has_bom(&self) -> bool { self.bom }
+  pub fn is_utf16(&self) -> bool { is_utf16(self.encoding) }
+
+  /// Decodes the contents of a file. Invalid bytes are replaced with U+FFFD.
+  pub fn decode(&self, bytes: &[u8]) -> String {
+    let bytes = match Encoding::for_bom(bytes) {
+      Some((encoding, len)) if self.bom && encoding == self.encoding => &bytes[len..],
+      _ => bytes,
+    };
+
+    self.encoding.decode_without_bom_handling(bytes).0.into_owned()
+  }
+
+  /// Returns a decoder for reading a file in chunks.
+  pub fn chunk_decoder(&self) -> ChunkDecoder {
+    ChunkDecoder {
+      decoder: if self.bom {
+        self.encoding.new_decoder_with_bom_removal()
+      } else {
+        self.encoding.new_decoder_without_bom_handling()
+      },
+    }
+  }
+
+  /// Encodes the document. Returns `None` when the document is already in the
+  /// right format, so it can be written as-is. This fails if the document
+  /// contains characters this encoding can't represent.
+  pub fn encode(&self, doc: &DocumentSnapshot) -> io::Result<Option<Vec<u8>>> {
+    if self.encoding == UTF_8 {
+      if !self.bom {
+        return Ok(None);
+      }
+
+      let mut out = b"\xef\xbb\xbf".to_vec();
+      for chunk in doc.rope.chunks() {
+        out.extend_from_slice(chunk.as_bytes());
+      }
+      return Ok(Some(out));
+    }
+
+    // `encoding_rs` never encodes to UTF-16, as the web doesn't need that.
+    if is_utf16(self.encoding) {
+      let le = self.encoding == UTF_16LE;
+      let mut out = Vec::with_capacity(doc.rope.byte_len() * 2 + 2);
+      for unit in
+        self.bom.then_some(0xfeff).into_iter().chain(doc.rope.chunks().flat_map(str::encode_utf16))
+      {
+        out.extend_from_slice(&if le { unit.to_le_bytes() } else { unit.to_be_bytes() });
+      }
+      return Ok(Some(out));
+    }
+
+    let mut encoder = self.encoding.new_encoder();
+    let mut out = Vec::with_capacity(doc.rope.byte_len());
+    let mut offset = 0;
+    for chunk in doc.rope.chunks().chain([""]) {
+      let last = chunk.is_empty();
+      let mut chunk = chunk;
+      loop {
+        let (result, read) =
+          encoder.encode_from_utf8_to_vec_without_replacement(chunk, &mut out, last);
+        chunk = &chunk[read..];
+        offset += read;
+
+        match result {
+          EncoderResult::InputEmpty => break,
+          EncoderResult::OutputFull => out.reserve(chunk.len().max(16)),
+          EncoderResult::Unmappable(c) => {
+            let line = doc.rope.line_of_byte(offset - c.len_utf8()) + 1;
+            return Err(io::Error::new(
+              io::ErrorKind::InvalidData,
+              format!("cannot write {c:?} on line {line} as {}", self.name()),
+            ));
+          }
+        }
+      }
+    }
+
+    Ok(Some(out))
+  }
+}
+
+/// Decodes a file one chunk at a time. Characters split between two chunks are
+/// kept until the next chunk.
+pub struct ChunkDecoder {
+  decoder: Decoder,
+}
+
+impl ChunkDecoder {
+  /// Decodes the next chunk. `last` must be set for the end of the file.
+  pub fn decode(&mut self, mut bytes: &[u8], last: bool) -> String {
+    let mut out = String::with_capacity(bytes.len());
+    loop {
+      let (result, read, _) = self.decoder.decode_to_string(bytes, &mut out, last);
+      bytes = &bytes[read..];
+
+      match result {
+        CoderResult::InputEmpty => return out,
+        CoderResult::OutputFull => out.reserve(bytes.len().max(16)),
+      }
+    }
+  }
+}
+
+impl fmt::Display for FileEncoding {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.name())?;
+    if self.bom && !is_utf16(self.encoding) {
+      write!(f, " BOM")?;
+    }
+    Ok(())
+  }
+}
+
+fn is_utf16(encoding: &'static Encoding) -> bool { encoding == UTF_16LE || encoding == UTF_16BE }
+
+/// Text in UTF-16 without a byte order mark is mostly ASCII with a zero byte
+/// before or after each character, so check which side the zeros are on.
+fn guess_utf16(bytes: &[u8]) -> Option<&'static Encoding> {
+  let sample = &bytes[..bytes.len().min(1024) & !1];
+  if sample.is_empty() {
+    return None;
+  }
+
+  let pairs = sample.len() / 2;
+  let even = sample.iter().step_by(2).filter(|&&b| b == 0).count();
+  let odd = sample.iter().skip(1).step_by(2).filter(|&&b| b == 0).count();
+
+  if odd > pairs / 2 && even == 0 {
+    Some(UTF_16LE)
+  } else if even > pairs / 2 && odd == 0 {
+    Some(UTF_16BE)
+  } else {
+    None
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::Document;
+
+  #[test]
+  fn detect() {
+    assert_eq!(FileEncoding::detect(b"foo"), FileEncoding::UTF_8);
+    assert_eq!(FileEncoding::detect(b"\xef\xbb\xbffoo").to_string(), "UTF-8 BOM");
+    assert_eq!(FileEncoding::detect(b"\xff\xfef\0o\0").to_string(), "UTF-16LE");
+    assert_eq!(FileEncoding::detect(b"f\0o\0o\0").to_string(), "UTF-16LE");
+    assert_eq!(FileEncoding::detect(b"caf\xe9 cr\xe8me br\xfbl\xe9e").to_string(), "windows-1252");
+  }
+
+  #[test]
+  fn round_trip() {
+    for bytes in
+      [&b"\xef\xbb\xbfcaf\xc3\xa9\n"[..], b"\xfe\xff\0c\0a\0f\0\xe9\0\n", b"caf\xe9 cr\xe8me\n"]
+    {
+      let encoding = FileEncoding::detect(bytes);
+      let doc = Document::from(encoding.decode(bytes).as_str());
+      assert!(doc.rope.to_string().starts_with("caf\u{e9}"));
+      assert_eq!(encoding.encode(&doc).unwrap().unwrap(), bytes);
+    }
+  }
+
+  #[test]
+  fn chunks() {
+    let bytes = "caf\u{e9} cr\u{e8}me".as_bytes();
+    assert_eq!(FileEncoding::detect(&bytes[..4]), FileEncoding::UTF_8);
+
+    let mut decoder = FileEncoding::UTF_8.chunk_decoder();
+    assert_eq!(decoder.decode(&bytes[..4], false), "caf");
+    assert_eq!(decoder.decode(&bytes[4..], true), "\u{e9} cr\u{e8}me");
+  }
+
+  #[test]
+  fn unrepresentable() {
+    let encoding = FileEncoding::from_label("latin1").unwrap();
+    let doc = Document::from("caf\u{e9}\n\u{1f496}\n");
+    assert_eq!(
+      encoding.encode(&doc).unwrap_err().to_string(),
+      "cannot write '\u{1f496}' on line 2 as windows-1252"
+    );
+  }
+}
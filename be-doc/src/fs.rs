@@ -5,7 +5,7 @@ use std::{
 
 use crop::RopeBuilder;
 
-use crate::{Document, DocumentSnapshot};
+use crate::{Document, FileEncoding};
 
 impl Document {
   pub fn read_lossy(reader: &mut impl std::io::Read) -> io::Result<Document> {
@@ -49,7 +49,7 @@ impl Document {
       }
     }
 
-    Ok(Document { snap: DocumentSnapshot { rope: builder.build() } })
+    Ok(Document::from_rope(builder.build()))
   }
 
   pub fn write(&self, writer: &mut impl std::io::Write) -> io::Result<()> {
@@ -59,11 +59,25 @@ impl Document {
       writer.write_all(chunk.as_bytes())?;
     }
 
-    Ok(())
+    writer.flush()
   }
 
-  pub fn read(path: &Path) -> io::Result<Document> {
-    Document::read_lossy(&mut std::fs::File::open(path)?)
+  pub fn read(path: &Path) -> io::Result<Document> { Ok(Document::read_encoded(path, None)?.0) }
+
+  /// Reads a file in the given encoding, or detects the encoding if it's
+  /// `None`.
+  pub fn read_encoded(
+    path: &Path,
+    encoding: Option<FileEncoding>,
+  ) -> io::Result<(Document, FileEncoding)> {
+    Ok(Document::decode(&std::fs::read(path)?, encoding))
+  }
+
+  /// Decodes the contents of a file in the given encoding, or detects the
+  /// encoding if it's `None`.
+  pub fn decode(bytes: &[u8], encoding: Option<FileEncoding>) -> (Document, FileEncoding) {
+    let encoding = encoding.unwrap_or_else(|| FileEncoding::detect(bytes));
+    (Document::from(encoding.decode(bytes).as_str()), encoding)
   }
 }
 
@@ -4,15 +4,26 @@ use std::{
 };
 
 use crop::Rope;
+use serde::{Deserialize, Serialize};
 
+mod anchor;
 mod edit;
+mod encoding;
 mod fs;
+mod line_ending;
+mod regex;
 mod search;
+mod selection;
 mod snap;
 
+pub use anchor::{Anchor, Gravity};
 pub use crop;
 pub use edit::{Change, Edit};
+pub use encoding::{ChunkDecoder, FileEncoding};
+pub use line_ending::LineEnding;
+pub use regex::{Captures, Regex};
 pub use search::FindIter;
+pub use selection::Selection;
 pub use snap::DocumentSnapshot;
 
 #[macro_use]
@@ -20,10 +31,11 @@ extern crate be_macros;
 
 #[derive(Default, Clone)]
 pub struct Document {
-  snap: DocumentSnapshot,
+  snap:    DocumentSnapshot,
+  anchors: anchor::Anchors,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Cursor {
   pub line:          Line,
   pub column:        Column,
@@ -31,24 +43,22 @@ pub struct Cursor {
 }
 
 /// A logical line, ie, lines from the start of the file.
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Line(pub usize);
 
 /// A logical column, ie, graphemes from the start of the line.
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Column(pub usize);
 
 /// A visual column, ie, counted in unicode-width from the start of the line.
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct VisualColumn(pub usize);
 
 impl From<&str> for Document {
-  fn from(s: &str) -> Document { Document { snap: DocumentSnapshot { rope: Rope::from(s) } } }
+  fn from(s: &str) -> Document { Document::from_rope(Rope::from(s)) }
 }
 impl From<std::borrow::Cow<'_, str>> for Document {
-  fn from(s: std::borrow::Cow<'_, str>) -> Document {
-    Document { snap: DocumentSnapshot { rope: Rope::from(s) } }
-  }
+  fn from(s: std::borrow::Cow<'_, str>) -> Document { Document::from_rope(Rope::from(s)) }
 }
 
 impl Cursor {
@@ -86,7 +96,11 @@ impl Deref for Document {
 }
 
 impl Document {
-  pub fn new() -> Document { Document { snap: DocumentSnapshot { rope: Rope::new() } } }
+  pub fn new() -> Document { Document::from_rope(Rope::new()) }
+
+  fn from_rope(rope: Rope) -> Document {
+    Document { snap: DocumentSnapshot { rope }, anchors: Default::default() }
+  }
 }
 
 impl Column {
@@ -0,0 +1,110 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::DocumentSnapshot;
+
+/// The line terminator used by a document.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LineEnding {
+  #[default]
+  Lf,
+  CrLf,
+}
+
+impl LineEnding {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      LineEnding::Lf => "\n",
+      LineEnding::CrLf => "\r\n",
+    }
+  }
+
+  /// Parses a vim `fileformat`, which is either `unix` or `dos`.
+  pub fn from_fileformat(name: &str) -> Option<LineEnding> {
+    match name {
+      "unix" => Some(LineEnding::Lf),
+      "dos" => Some(LineEnding::CrLf),
+      _ => None,
+    }
+  }
+
+  pub fn fileformat(&self) -> &'static str {
+    match self {
+      LineEnding::Lf => "unix",
+      LineEnding::CrLf => "dos",
+    }
+  }
+}
+
+impl fmt::Display for LineEnding {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      LineEnding::Lf => write!(f, "LF"),
+      LineEnding::CrLf => write!(f, "CRLF"),
+    }
+  }
+}
+
+impl DocumentSnapshot {
+  /// Finds the line ending used by most lines. Only the start of the document
+  /// is checked, so this is cheap for large files.
+  pub fn detect_line_ending(&self) -> LineEnding {
+    let mut crlf = 0;
+    let mut lf = 0;
+    for line in self.rope.raw_lines().take(1000) {
+      match line.byte_len().checked_sub(2).map(|i| (line.byte(i), line.byte(i + 1))) {
+        Some((b'\r', b'\n')) => crlf += 1,
+        _ if line.byte_len() > 0 && line.byte(line.byte_len() - 1) == b'\n' => lf += 1,
+        _ => {}
+      }
+    }
+
+    if crlf > lf { LineEnding::CrLf } else { LineEnding::Lf }
+  }
+
+  /// The ranges of line terminators that don't match `ending`, and so need to
+  /// be replaced to convert the document to it.
+  pub fn mismatched_line_endings(&self, ending: LineEnding) -> Vec<std::ops::Range<usize>> {
+    let mut ranges = vec![];
+    let mut offset = 0;
+    for line in self.rope.raw_lines() {
+      offset += line.byte_len();
+
+      let len = line.byte_len();
+      let terminator = if len >= 2 && line.byte(len - 2) == b'\r' && line.byte(len - 1) == b'\n' {
+        offset - 2..offset
+      } else if len >= 1 && line.byte(len - 1) == b'\n' {
+        offset - 1..offset
+      } else {
+        continue;
+      };
+
+      if terminator.len() != ending.as_str().len() {
+        ranges.push(terminator);
+      }
+    }
+    ranges
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::Document;
+
+  #[test]
+  fn detect() {
+    assert_eq!(Document::from("a\nb\n").detect_line_ending(), LineEnding::Lf);
+    assert_eq!(Document::from("a\r\nb\r\n").detect_line_ending(), LineEnding::CrLf);
+    assert_eq!(Document::from("a\r\nb\r\nc\n").detect_line_ending(), LineEnding::CrLf);
+    assert_eq!(Document::from("a").detect_line_ending(), LineEnding::Lf);
+  }
+
+  #[test]
+  fn mismatched() {
+    let doc = Document::from("a\r\nb\nc\r\nd");
+    assert_eq!(doc.mismatched_line_endings(LineEnding::Lf), vec![1..3, 6..8]);
+    assert_eq!(doc.mismatched_line_endings(LineEnding::CrLf), vec![4..5]);
+  }
+}
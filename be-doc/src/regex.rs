@@ -0,0 +1,406 @@
+use std::{ops::Range, sync::OnceLock};
+
+use crop::Rope;
+use regex_automata::{
+  Anchored, Input,
+  hybrid::{
+    BuildError,
+    dfa::{Cache, DFA},
+    regex,
+  },
+  meta,
+  util::{captures, start, syntax},
+};
+
+/// A compiled search pattern. Searching runs a lazy DFA over the chunks of a
+/// rope, so the document never needs to be copied into a contiguous string.
+#[derive(Debug)]
+pub struct Regex {
+  inner:            regex::Regex,
+  /// Used if the lazy DFA gives up. This is only built when it's needed.
+  fallback:         OnceLock<Option<meta::Regex>>,
+  pattern:          String,
+  case_insensitive: bool,
+}
+
+/// A match from `Regex::captures_iter`, along with its capture groups.
+pub struct Captures(captures::Captures);
+
+/// The lazy DFA gave up on a search, because its cache was cleared too often
+/// or because it reached a byte it can't handle.
+struct GaveUp;
+
+/// The fallback regex searches a window of this many lines at first. The
+/// window doubles whenever a match might continue past its edge.
+const FALLBACK_WINDOW_LINES: usize = 64;
+
+impl Regex {
+  pub fn new(pattern: &str) -> Result<Regex, Box<BuildError>> { Regex::build(pattern, false) }
+
+  /// Builds a regex from a search pattern typed by the user. The pattern is
+  /// case insensitive unless it contains an uppercase letter. `\c` and `\C`
+  /// anywhere in the pattern force case insensitive and sensitive matching.
+  pub fn smart_case(pattern: &str) -> Result<Regex, Box<BuildError>> {
+    let (pattern, case_insensitive, has_upper) = case_flags(pattern);
+    Regex::build(&pattern, case_insensitive.unwrap_or(!has_upper))
+  }
+
+  /// Builds a regex that is case insensitive if `case_insensitive` is set. As
+  /// with `smart_case`, `\c` and `\C` in the pattern override this.
+  pub fn with_case(pattern: &str, case_insensitive: bool) -> Result<Regex, Box<BuildError>> {
+    let (pattern, flag, _) = case_flags(pattern);
+    Regex::build(&pattern, flag.unwrap_or(case_insensitive))
+  }
+
+  fn build(pattern: &str, case_insensitive: bool) -> Result<Regex, Box<BuildError>> {
+    let inner = regex::Regex::builder()
+      .syntax(syntax::Config::new().case_insensitive(case_insensitive))
+      .build(&ascii_word_boundaries(pattern))?;
+
+    Ok(Regex { inner, fallback: OnceLock::new(), pattern: pattern.to_string(), case_insensitive })
+  }
+
+  /// The pattern, in `regex` syntax. This excludes any `\c` or `\C` flags.
+  pub fn as_str(&self) -> &str { &self.pattern }
+  pub fn is_case_insensitive(&self) -> bool { self.case_insensitive }
+
+  pub(crate) fn create_cache(&self) -> regex::Cache { self.inner.create_cache() }
+
+  /// Finds the matches in `text` that start at or after `start`, along with
+  /// their capture groups. This searches a string instead of a rope, so it's
+  /// meant for short text like a single line.
+  pub fn captures_iter<'a>(
+    &'a self,
+    text: &'a str,
+    start: usize,
+  ) -> impl Iterator<Item = Captures> + 'a {
+    self
+      .fallback()
+      .into_iter()
+      .flat_map(move |regex| regex.captures_iter(Input::new(text).range(start..)).map(Captures))
+  }
+
+  pub fn is_match(&self, text: &str) -> bool { self.fallback().is_some_and(|r| r.is_match(text)) }
+
+  /// Finds the leftmost-first match that starts at or after `start`.
+  pub(crate) fn find_at(
+    &self,
+    cache: &mut regex::Cache,
+    rope: &Rope,
+    start: usize,
+  ) -> Option<Range<usize>> {
+    match self.dfa_find_at(cache, rope, start) {
+      Ok(found) => found,
+      Err(GaveUp) => self.fallback_find_at(rope, start),
+    }
+  }
+
+  /// Finds the last match that lies entirely before `end`.
+  pub(crate) fn rfind_at(
+    &self,
+    cache: &mut regex::Cache,
+    rope: &Rope,
+    end: usize,
+  ) -> Option<Range<usize>> {
+    match self.dfa_rfind_at(cache, rope, end) {
+      Ok(found) => found,
+      Err(GaveUp) => self.fallback_rfind_at(rope, end),
+    }
+  }
+
+  fn dfa_find_at(
+    &self,
+    cache: &mut regex::Cache,
+    rope: &Rope,
+    start: usize,
+  ) -> Result<Option<Range<usize>>, GaveUp> {
+    let (forward, reverse) = cache.as_parts_mut();
+
+    let Some(end) =
+      search_forward(self.inner.forward(), forward, rope, start..rope.byte_len(), false)?
+    else {
+      return Ok(None);
+    };
+    let start = search_reverse(self.inner.reverse(), reverse, rope, start..end, true)?;
+    Ok(start.map(|start| start..end))
+  }
+
+  fn dfa_rfind_at(
+    &self,
+    cache: &mut regex::Cache,
+    rope: &Rope,
+    end: usize,
+  ) -> Result<Option<Range<usize>>, GaveUp> {
+    let (forward, reverse) = cache.as_parts_mut();
+
+    // The unanchored reverse DFA finds the last position a match can start at.
+    // That might be in the middle of a match found by searching forwards, so
+    // search forwards from the start of its line to find the real match.
+    let Some(candidate) = search_reverse(self.inner.reverse(), reverse, rope, 0..end, false)?
+    else {
+      return Ok(None);
+    };
+
+    let mut found = None;
+    let mut at = rope.byte_of_line(rope.line_of_byte(candidate));
+    while at <= candidate {
+      let Some(match_end) = search_forward(self.inner.forward(), forward, rope, at..end, false)?
+      else {
+        break;
+      };
+      let Some(match_start) =
+        search_reverse(self.inner.reverse(), reverse, rope, at..match_end, true)?
+      else {
+        return Ok(None);
+      };
+      if match_start > candidate {
+        break;
+      }
+
+      found = Some(match_start..match_end);
+      at = if match_end > match_start {
+        match_end
+      } else {
+        match rope.byte_slice(match_end..).chars().next() {
+          Some(c) => match_end + c.len_utf8(),
+          None => break,
+        }
+      };
+    }
+
+    Ok(found)
+  }
+
+  /// The meta regex, which never gives up like the lazy DFA can, and which
+  /// finds capture groups. It needs a contiguous string, so documents are only
+  /// searched with it when the lazy DFA fails, and only over a window of lines
+  /// copied out of the rope.
+  fn fallback(&self) -> Option<&meta::Regex> {
+    self
+      .fallback
+      .get_or_init(|| {
+        meta::Regex::builder()
+          .syntax(syntax::Config::new().case_insensitive(self.case_insensitive))
+          .build(&ascii_word_boundaries(&self.pattern))
+          .ok()
+      })
+      .as_ref()
+  }
+
+  pub(crate) fn fallback_find_at(&self, rope: &Rope, start: usize) -> Option<Range<usize>> {
+    let fallback = self.fallback()?;
+    let first = rope.line_of_byte(start);
+    let window_start = rope.byte_of_line(first);
+
+    let mut lines = FALLBACK_WINDOW_LINES;
+    loop {
+      let last = (first + lines).min(rope.line_len());
+      let text = rope.byte_slice(window_start..rope.byte_of_line(last)).to_string();
+      let found = fallback
+        .find(Input::new(&text).range(start - window_start..))
+        .map(|m| m.start() + window_start..m.end() + window_start);
+
+      // A match that reaches the last line of the window might be different
+      // with the text after it.
+      if last == rope.line_len()
+        || found.as_ref().is_some_and(|m| m.end < rope.byte_of_line(last - 1))
+      {
+        return found;
+      }
+      lines *= 2;
+    }
+  }
+
+  pub(crate) fn fallback_rfind_at(&self, rope: &Rope, end: usize) -> Option<Range<usize>> {
+    let fallback = self.fallback()?;
+    // The rest of the line after `end` is kept for look-ahead, like `$`.
+    let last = (rope.line_of_byte(end) + 1).min(rope.line_len());
+    let window_end = rope.byte_of_line(last);
+
+    let mut lines = FALLBACK_WINDOW_LINES;
+    loop {
+      let first = last.saturating_sub(lines);
+      let window_start = rope.byte_of_line(first);
+      let text = rope.byte_slice(window_start..window_end).to_string();
+      let found = fallback
+        .find_iter(Input::new(&text).range(..end - window_start))
+        .last()
+        .map(|m| m.start() + window_start..m.end() + window_start);
+
+      // A match in the first line of the window might overlap one that starts
+      // before it, like the lazy DFA search above.
+      if first == 0 || found.as_ref().is_some_and(|m| m.start >= rope.byte_of_line(first + 1)) {
+        return found;
+      }
+      lines *= 2;
+    }
+  }
+}
+
+/// Strips the `\c` and `\C` flags out of `pattern`. Returns the rest of the
+/// pattern, whether the last flag makes it case insensitive, and whether the
+/// pattern has any uppercase letters.
+fn case_flags(pattern: &str) -> (String, Option<bool>, bool) {
+  let mut out = String::with_capacity(pattern.len());
+  let mut case_insensitive = None;
+  let mut has_upper = false;
+
+  let mut chars = pattern.chars();
+  while let Some(c) = chars.next() {
+    if c != '\\' {
+      has_upper |= c.is_uppercase();
+      out.push(c);
+      continue;
+    }
+
+    match chars.next() {
+      Some('c') => case_insensitive = Some(true),
+      Some('C') => case_insensitive = Some(false),
+      Some(c) => {
+        out.push('\\');
+        out.push(c);
+      }
+      None => out.push('\\'),
+    }
+  }
+
+  (out, case_insensitive, has_upper)
+}
+
+impl Captures {
+  /// The range of the whole match.
+  pub fn range(&self) -> Range<usize> { self.0.get_match().unwrap().range() }
+
+  /// Replaces `$1` or `${1}` in `replacement` with the text of that group in
+  /// `text`, which must be the text that was searched. `$$` is a literal `$`.
+  pub fn expand(&self, text: &str, replacement: &str) -> String {
+    self.0.interpolate_string(text, replacement)
+  }
+}
+
+/// The lazy DFA can't handle unicode word boundaries, so all of `\b`, `\B`,
+/// `\<`, and `\>` are switched to their ASCII versions.
+fn ascii_word_boundaries(pattern: &str) -> String {
+  let mut out = String::with_capacity(pattern.len());
+
+  let mut chars = pattern.chars();
+  while let Some(c) = chars.next() {
+    if c != '\\' {
+      out.push(c);
+      continue;
+    }
+
+    match chars.next() {
+      Some(c @ ('b' | 'B' | '<' | '>')) => {
+        out.push_str("(?-u:\\");
+        out.push(c);
+        out.push(')');
+      }
+      Some(c) => {
+        out.push('\\');
+        out.push(c);
+      }
+      None => out.push('\\'),
+    }
+  }
+
+  out
+}
+
+/// Runs `dfa` forward over `range`, returning the end of the leftmost-first
+/// match.
+fn search_forward(
+  dfa: &DFA,
+  cache: &mut Cache,
+  rope: &Rope,
+  range: Range<usize>,
+  anchored: bool,
+) -> Result<Option<usize>, GaveUp> {
+  let config = start::Config::new()
+    .anchored(if anchored { Anchored::Yes } else { Anchored::No })
+    .look_behind(range.start.checked_sub(1).map(|i| rope.byte(i)));
+  let mut sid = dfa.start_state(cache, &config).map_err(|_| GaveUp)?;
+
+  // Match states are delayed by one byte, so a match state after `at` means a
+  // match ended at `at`.
+  let mut found = None;
+  let mut at = range.start;
+  for chunk in rope.byte_slice(range.clone()).chunks() {
+    for &byte in chunk.as_bytes() {
+      sid = dfa.next_state(cache, sid, byte).map_err(|_| GaveUp)?;
+      if sid.is_match() {
+        found = Some(at);
+      } else if sid.is_quit() {
+        return Err(GaveUp);
+      } else if sid.is_dead() {
+        return Ok(found);
+      }
+      at += 1;
+    }
+  }
+
+  sid = if range.end < rope.byte_len() {
+    dfa.next_state(cache, sid, rope.byte(range.end))
+  } else {
+    dfa.next_eoi_state(cache, sid)
+  }
+  .map_err(|_| GaveUp)?;
+  if sid.is_quit() {
+    return Err(GaveUp);
+  }
+  if sid.is_match() {
+    found = Some(range.end);
+  }
+
+  Ok(found)
+}
+
+/// Runs the reverse `dfa` backward over `range`, returning the start of a
+/// match. Anchored searches find the leftmost start of a match ending at
+/// `range.end`, and unanchored searches stop at the first start found.
+fn search_reverse(
+  dfa: &DFA,
+  cache: &mut Cache,
+  rope: &Rope,
+  range: Range<usize>,
+  anchored: bool,
+) -> Result<Option<usize>, GaveUp> {
+  let config = start::Config::new()
+    .anchored(if anchored { Anchored::Yes } else { Anchored::No })
+    .look_behind((range.end < rope.byte_len()).then(|| rope.byte(range.end)));
+  let mut sid = dfa.start_state(cache, &config).map_err(|_| GaveUp)?;
+
+  let mut found = None;
+  let mut at = range.end;
+  for chunk in rope.byte_slice(range.clone()).chunks().rev() {
+    for &byte in chunk.as_bytes().iter().rev() {
+      sid = dfa.next_state(cache, sid, byte).map_err(|_| GaveUp)?;
+      if sid.is_match() {
+        found = Some(at);
+        if !anchored {
+          return Ok(found);
+        }
+      } else if sid.is_quit() {
+        return Err(GaveUp);
+      } else if sid.is_dead() {
+        return Ok(found);
+      }
+      at -= 1;
+    }
+  }
+
+  sid = if range.start > 0 {
+    dfa.next_state(cache, sid, rope.byte(range.start - 1))
+  } else {
+    dfa.next_eoi_state(cache, sid)
+  }
+  .map_err(|_| GaveUp)?;
+  if sid.is_quit() {
+    return Err(GaveUp);
+  }
+  if sid.is_match() {
+    found = Some(range.start);
+  }
+
+  Ok(found)
+}
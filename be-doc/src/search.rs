@@ -1,16 +1,30 @@
 use crop::{Rope, RopeSlice};
 
-use crate::Document;
+use crate::{Document, Regex};
 use std::{
   cmp,
-  ops::{Index, RangeBounds},
+  ops::{Index, Range, RangeBounds},
 };
 
+/// An iterator over the non-overlapping matches of a pattern in a document,
+/// yielding the byte range of each match.
 pub struct FindIter<'a>(FindIterImpl<'a>);
 
 enum FindIterImpl<'a> {
   Empty,
-  TwoWay { rope: &'a Rope, offset: usize, two_way: TwoWay<'a>, reversed: bool },
+  TwoWay {
+    rope:     &'a Rope,
+    offset:   usize,
+    two_way:  TwoWay<'a>,
+    reversed: bool,
+  },
+  Regex {
+    rope:     &'a Rope,
+    offset:   Option<usize>,
+    regex:    &'a Regex,
+    cache:    Box<regex_automata::hybrid::regex::Cache>,
+    reversed: bool,
+  },
 }
 
 struct RopeAccess<'a> {
@@ -57,22 +71,42 @@ impl Document {
   }
 }
 
-impl<'a> FindIter<'a> {
-  pub fn needle(&self) -> &'a str {
-    match self {
-      FindIter(FindIterImpl::Empty) => "",
-      FindIter(FindIterImpl::TwoWay { two_way, .. }) => two_way.needle.str,
-    }
+impl Document {
+  pub fn find_regex<'a>(&'a self, regex: &'a Regex) -> FindIter<'a> {
+    self.find_regex_from(0, regex)
+  }
+  pub fn rfind_regex<'a>(&'a self, regex: &'a Regex) -> FindIter<'a> {
+    self.rfind_regex_from(self.rope.byte_len(), regex)
+  }
+
+  pub fn find_regex_from<'a>(&'a self, start: usize, regex: &'a Regex) -> FindIter<'a> {
+    FindIter(FindIterImpl::Regex {
+      rope: &self.rope,
+      offset: Some(start),
+      regex,
+      cache: Box::new(regex.create_cache()),
+      reversed: false,
+    })
+  }
+
+  pub fn rfind_regex_from<'a>(&'a self, start: usize, regex: &'a Regex) -> FindIter<'a> {
+    FindIter(FindIterImpl::Regex {
+      rope: &self.rope,
+      offset: Some(start),
+      regex,
+      cache: Box::new(regex.create_cache()),
+      reversed: true,
+    })
   }
 }
 
 impl Iterator for FindIter<'_> {
-  type Item = usize;
+  type Item = Range<usize>;
 
   fn next(&mut self) -> Option<Self::Item> {
-    match *self {
-      FindIter(FindIterImpl::Empty) => None,
-      FindIter(FindIterImpl::TwoWay { rope, ref mut offset, two_way, reversed }) => {
+    match self.0 {
+      FindIterImpl::Empty => None,
+      FindIterImpl::TwoWay { rope, ref mut offset, two_way, reversed } => {
         let haystack = RopeAccess {
           slice: if reversed { rope.byte_slice(..*offset) } else { rope.byte_slice(*offset..) },
           reversed,
@@ -81,23 +115,41 @@ impl Iterator for FindIter<'_> {
         if let Some(advance) = two_way.find_in(haystack) {
           if reversed {
             *offset -= advance + two_way.needle.len();
-            Some(*offset)
+            Some(*offset..*offset + two_way.needle.len())
           } else {
             let ret = *offset + advance;
             *offset += advance + two_way.needle.len();
-            Some(ret)
+            Some(ret..*offset)
           }
         } else {
           None
         }
       }
+      FindIterImpl::Regex { rope, ref mut offset, regex, ref mut cache, reversed } => {
+        let range = if reversed {
+          regex.rfind_at(cache, rope, (*offset)?)?
+        } else {
+          regex.find_at(cache, rope, (*offset)?)?
+        };
+
+        // Step over empty matches, so that the same one isn't found again.
+        *offset = if !range.is_empty() {
+          Some(if reversed { range.start } else { range.end })
+        } else if reversed {
+          rope.byte_slice(..range.start).chars().next_back().map(|c| range.start - c.len_utf8())
+        } else {
+          rope.byte_slice(range.end..).chars().next().map(|c| range.end + c.len_utf8())
+        };
+
+        Some(range)
+      }
     }
   }
 
   fn size_hint(&self) -> (usize, Option<usize>) {
-    match self {
-      FindIter(FindIterImpl::Empty) => (0, Some(0)),
-      FindIter(FindIterImpl::TwoWay { .. }) => (0, None),
+    match self.0 {
+      FindIterImpl::Empty => (0, Some(0)),
+      FindIterImpl::TwoWay { .. } | FindIterImpl::Regex { .. } => (0, None),
     }
   }
 }
@@ -393,23 +445,110 @@ mod tests {
   fn find_works() {
     let doc = Document::from("foo bar baz ooo quoox");
 
-    assert_eq!(doc.find("oo").collect::<Vec<_>>(), &[1, 12, 18]);
+    assert_eq!(doc.find("oo").collect::<Vec<_>>(), &[1..3, 12..14, 18..20]);
   }
 
   #[test]
   fn find_nothing_for_empty() {
     let doc = Document::from("foo bar baz ooo quoox");
 
-    assert_eq!(doc.find("").collect::<Vec<_>>(), &[]);
+    assert_eq!(doc.find("").next(), None);
   }
 
   #[test]
   fn rfind_works() {
     let doc = Document::from("foo bar baz ooo quoox");
-    assert_eq!(doc.rfind("oo").collect::<Vec<_>>(), &[18, 13, 1]);
+    assert_eq!(doc.rfind("oo").map(|r| r.start).collect::<Vec<_>>(), &[18, 13, 1]);
 
     let doc = Document::from("fob bar baz obo quobx");
-    assert_eq!(doc.rfind("ob").collect::<Vec<_>>(), &[18, 12, 1]);
+    assert_eq!(doc.rfind("ob").map(|r| r.start).collect::<Vec<_>>(), &[18, 12, 1]);
+  }
+
+  #[test]
+  fn find_regex_works() {
+    let doc = Document::from("foo bar baz ooo quoox");
+    let regex = Regex::new(r"\bba\w").unwrap();
+    assert_eq!(doc.find_regex(&regex).collect::<Vec<_>>(), &[4..7, 8..11]);
+
+    let regex = Regex::new(r"\Bo\B").unwrap();
+    assert_eq!(doc.find_regex(&regex).collect::<Vec<_>>(), &[1..2, 13..14, 18..19, 19..20]);
+
+    let regex = Regex::new("o+x|bar").unwrap();
+    assert_eq!(doc.find_regex(&regex).collect::<Vec<_>>(), &[4..7, 18..21]);
+  }
+
+  #[test]
+  fn find_regex_across_lines() {
+    let doc = Document::from("fn foo() {\n  bar();\n}\nfn baz() {}\n");
+    let regex = Regex::new(r"\{\n\s*bar").unwrap();
+    assert_eq!(doc.find_regex(&regex).collect::<Vec<_>>(), &[9..16; 1]);
+
+    let regex = Regex::new(r"(?m)^fn \w+").unwrap();
+    assert_eq!(doc.find_regex(&regex).collect::<Vec<_>>(), &[0..6, 22..28]);
+  }
+
+  #[test]
+  fn find_regex_empty() {
+    let doc = Document::from("ab\n\ncd\n");
+    let regex = Regex::new("(?m)^").unwrap();
+    assert_eq!(doc.find_regex(&regex).map(|r| r.start).collect::<Vec<_>>(), &[0, 3, 4, 7]);
+    assert_eq!(doc.rfind_regex(&regex).map(|r| r.start).collect::<Vec<_>>(), &[7, 4, 3, 0]);
+  }
+
+  #[test]
+  fn rfind_regex_works() {
+    let doc = Document::from("foo bar baz ooo quoox");
+    let regex = Regex::new("o+").unwrap();
+    assert_eq!(doc.rfind_regex(&regex).collect::<Vec<_>>(), &[18..20, 12..15, 1..3]);
+    assert_eq!(doc.rfind_regex_from(14, &regex).collect::<Vec<_>>(), &[12..14, 1..3]);
+  }
+
+  #[test]
+  fn regex_fallback() {
+    let rope = crop::Rope::from("foo bar baz ooo quoox");
+    let regex = Regex::new("o+x|ba.").unwrap();
+    let mut cache = regex.create_cache();
+
+    assert_eq!(regex.fallback_find_at(&rope, 5), Some(8..11));
+    assert_eq!(regex.fallback_find_at(&rope, 5), regex.find_at(&mut cache, &rope, 5));
+    assert_eq!(regex.fallback_rfind_at(&rope, 18), Some(8..11));
+    assert_eq!(regex.fallback_rfind_at(&rope, 18), regex.rfind_at(&mut cache, &rope, 18));
+
+    // The match crosses the end of the first window.
+    let rope = crop::Rope::from(format!("{}a\nb\n{}", "x\n".repeat(63), "x\n".repeat(100)));
+    let regex = Regex::new(r"(?m)a\nb$").unwrap();
+    assert_eq!(regex.fallback_find_at(&rope, 0), Some(126..129));
+    assert_eq!(regex.fallback_rfind_at(&rope, rope.byte_len()), Some(126..129));
+    assert_eq!(regex.fallback_rfind_at(&rope, 128), None);
+  }
+
+  #[test]
+  fn smart_case() {
+    let doc = Document::from("Foo foo FOO");
+    let find = |pattern| {
+      let regex = Regex::smart_case(pattern).unwrap();
+      doc.find_regex(&regex).map(|r| r.start).collect::<Vec<_>>()
+    };
+
+    assert_eq!(find("foo"), &[0, 4, 8]);
+    assert_eq!(find("Foo"), &[0]);
+    assert_eq!(find(r"Foo\c"), &[0, 4, 8]);
+    assert_eq!(find(r"foo\C"), &[4]);
+    assert_eq!(find(r"\<FOO\>"), &[8]);
+
+    let regex = Regex::with_case("foo", true).unwrap();
+    assert_eq!(doc.find_regex(&regex).map(|r| r.start).collect::<Vec<_>>(), &[0, 4, 8]);
+    let regex = Regex::with_case(r"foo\C", true).unwrap();
+    assert_eq!(doc.find_regex(&regex).map(|r| r.start).collect::<Vec<_>>(), &[4]);
+  }
+
+  #[test]
+  fn captures() {
+    let regex = Regex::new(r"(\w+) = (\w+)").unwrap();
+    let text = "a = b, c = d";
+    let found = regex.captures_iter(text, 1).map(|c| (c.range(), c.expand(text, "$2 = ${1}$$")));
+    assert_eq!(found.collect::<Vec<_>>(), &[(7..12, "d = c$".to_string())]);
+    assert!(regex.is_match(text));
   }
 
   #[test]
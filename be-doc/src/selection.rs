@@ -0,0 +1,137 @@
+use std::ops::Range;
+
+use unicode_width::UnicodeWidthStr;
+
+use crate::{Cursor, DocumentSnapshot, Line, VisualColumn};
+
+/// A selected region of a document. The `anchor` stays where the selection was
+/// started, and the `head` follows the cursor.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Selection {
+  pub anchor: Cursor,
+  pub head:   Cursor,
+}
+
+impl Selection {
+  pub fn new(anchor: Cursor, head: Cursor) -> Self { Selection { anchor, head } }
+
+  /// The first cursor of the selection, in document order.
+  pub fn start(&self) -> Cursor { self.anchor.min(self.head) }
+  /// The last cursor of the selection, in document order.
+  pub fn end(&self) -> Cursor { self.anchor.max(self.head) }
+
+  pub fn start_line(&self) -> Line { self.start().line }
+  pub fn end_line(&self) -> Line { self.end().line }
+}
+
+impl DocumentSnapshot {
+  /// Returns the bytes covered by a charwise selection. Both ends are
+  /// inclusive, so the grapheme under the last cursor is included.
+  pub fn selection_chars(&self, selection: Selection) -> Range<usize> {
+    let start = self.cursor_offset(selection.start());
+    let end = selection.end();
+
+    if end.target_column == VisualColumn::MAX {
+      // After `$`, the line terminator is selected as well.
+      return start..self.byte_of_line(end.line + 1);
+    }
+
+    start..self.grapheme_slice(end, 1).end
+  }
+
+  /// Returns the bytes covered by a linewise selection, including the line
+  /// terminator of the last line.
+  pub fn selection_lines(&self, selection: Selection) -> Range<usize> {
+    self.byte_of_line(selection.start_line())..self.byte_of_line(selection.end_line() + 1)
+  }
+
+  /// Returns the bytes covered by a blockwise selection, one range per line.
+  /// Lines that are too short to reach the block produce an empty range at
+  /// the end of the line.
+  pub fn selection_block(&self, selection: Selection) -> Vec<Range<usize>> {
+    let (left, right) = self.block_columns(selection);
+
+    (selection.start_line().0..=selection.end_line().0)
+      .map(|line| {
+        let line = Line(line);
+        let line_start = self.byte_of_line(line);
+
+        let mut offset = line_start;
+        let mut visual = 0;
+        let mut start = None;
+        let mut end = None;
+        for g in self.line(line).graphemes() {
+          if start.is_none() && visual + g.width() > left {
+            start = Some(offset);
+          }
+          if visual > right {
+            end = Some(offset);
+            break;
+          }
+
+          visual += g.width();
+          offset += g.len();
+        }
+
+        let start = start.unwrap_or(offset);
+        start..end.unwrap_or(offset).max(start)
+      })
+      .collect()
+  }
+
+  /// Returns the inclusive visual columns spanned by a blockwise selection.
+  pub fn block_columns(&self, selection: Selection) -> (usize, usize) {
+    let anchor = self.visual_column(selection.anchor).0;
+    let head = self.visual_column(selection.head).0;
+
+    let right = if selection.anchor.target_column == VisualColumn::MAX
+      || selection.head.target_column == VisualColumn::MAX
+    {
+      usize::MAX
+    } else {
+      anchor.max(head)
+    };
+
+    (anchor.min(head), right)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{Column, Document};
+
+  fn cursor(doc: &Document, line: usize, column: usize) -> Cursor {
+    let mut cursor = Cursor { line: Line(line), column: Column(column), ..Cursor::START };
+    cursor.target_column = doc.visual_column(cursor);
+    cursor
+  }
+
+  #[test]
+  fn chars_are_inclusive() {
+    let doc = Document::from("foo bar\nbaz\n");
+    let sel = Selection::new(cursor(&doc, 0, 4), cursor(&doc, 1, 1));
+    assert_eq!(doc.range(doc.selection_chars(sel)), "bar\nba");
+
+    let sel = Selection::new(cursor(&doc, 1, 1), cursor(&doc, 0, 4));
+    assert_eq!(doc.range(doc.selection_chars(sel)), "bar\nba");
+  }
+
+  #[test]
+  fn lines_include_terminator() {
+    let doc = Document::from("foo\nbar\nbaz\n");
+    let sel = Selection::new(cursor(&doc, 1, 2), cursor(&doc, 0, 1));
+    assert_eq!(doc.range(doc.selection_lines(sel)), "foo\nbar\n");
+  }
+
+  #[test]
+  fn block_skips_short_lines() {
+    let doc = Document::from("abcd\na\nabcd\n");
+    let sel = Selection::new(cursor(&doc, 0, 1), cursor(&doc, 2, 2));
+    let ranges = doc.selection_block(sel);
+    assert_eq!(
+      ranges.iter().map(|r| doc.range(r.clone()).to_string()).collect::<Vec<_>>(),
+      ["bc", "", "bc"]
+    );
+  }
+}
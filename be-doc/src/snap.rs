@@ -30,6 +30,20 @@ impl DocumentSnapshot {
 
   pub fn snapshot(&self) -> DocumentSnapshot { DocumentSnapshot { rope: self.rope.clone() } }
 
+  /// A hash of the document's text. This is stable between runs (unlike
+  /// `DefaultHasher`), so it may be stored on disk.
+  pub fn content_hash(&self) -> u64 {
+    // 64-bit FNV-1a.
+    let mut hash = 0xcbf29ce484222325_u64;
+    for chunk in self.rope.chunks() {
+      for &byte in chunk.as_bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+      }
+    }
+    hash
+  }
+
   #[track_caller]
   pub fn line(&self, line: Line) -> RopeSlice<'_> {
     if line.0 >= self.len_lines() {
@@ -0,0 +1 @@
+(list_item) @indent
@@ -0,0 +1,32 @@
+[
+  (class_definition)
+  (function_definition)
+  (if_statement)
+  (for_statement)
+  (while_statement)
+  (try_statement)
+  (with_statement)
+  (match_statement)
+  (case_clause)
+  (argument_list)
+  (parameters)
+  (list)
+  (tuple)
+  (set)
+  (dictionary)
+  (parenthesized_expression)
+  (list_comprehension)
+  (set_comprehension)
+  (dictionary_comprehension)
+  (generator_expression)
+] @indent
+
+[
+  "elif"
+  "else"
+  "except"
+  "finally"
+  "}"
+  "]"
+  ")"
+] @outdent
@@ -0,0 +1,24 @@
+[
+  (mod_item)
+  (function_item)
+  (impl_item)
+  (trait_item)
+  (struct_item)
+  (enum_item)
+  (union_item)
+  (macro_definition)
+  (use_declaration)
+  (const_item)
+  (static_item)
+  (let_declaration)
+  (if_expression)
+  (match_expression)
+  (match_arm)
+  (for_expression)
+  (while_expression)
+  (loop_expression)
+  (closure_expression)
+  (call_expression)
+  (macro_invocation)
+  (block_comment)
+] @fold
@@ -0,0 +1,33 @@
+[
+  (block)
+  (declaration_list)
+  (field_declaration_list)
+  (ordered_field_declaration_list)
+  (enum_variant_list)
+  (field_initializer_list)
+  (match_block)
+  (use_list)
+  (arguments)
+  (parameters)
+  (closure_parameters)
+  (type_arguments)
+  (type_parameters)
+  (tuple_expression)
+  (tuple_pattern)
+  (tuple_type)
+  (array_expression)
+  (token_tree)
+  (where_clause)
+  (match_arm)
+  (let_declaration)
+  (assignment_expression)
+  (compound_assignment_expr)
+  (binary_expression)
+  (field_expression)
+] @indent
+
+[
+  "}"
+  "]"
+  ")"
+] @outdent
@@ -0,0 +1,9 @@
+[
+  (array)
+  (inline_table)
+] @indent
+
+[
+  "]"
+  "}"
+] @outdent
@@ -0,0 +1,11 @@
+(table) @class.outer
+
+(table_array_element) @class.outer
+
+(array
+  (_) @parameter.inner)
+
+(inline_table
+  (_) @parameter.inner)
+
+(comment) @comment.outer
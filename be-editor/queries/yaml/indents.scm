@@ -0,0 +1,11 @@
+[
+  (block_mapping_pair)
+  (block_sequence_item)
+  (flow_mapping)
+  (flow_sequence)
+] @indent
+
+[
+  "]"
+  "}"
+] @outdent
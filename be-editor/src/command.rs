@@ -0,0 +1,476 @@
+//! Ex commands, like `:w` or `:set wrap`. Every command is listed once in
+//! `COMMANDS`, with the shortest abbreviation it can be typed as, whether it
+//! takes a `!`, and the kind of argument it takes. Commands that only affect
+//! the current editor run here, and the rest, like `:q` or `:vs`, are sent to
+//! the workspace as an `EditorEvent::Command`.
+//!
+//! `:s` isn't listed, as it can start with a range and doesn't need a space
+//! before its argument.
+
+use std::path::{Path, PathBuf};
+
+use be_config::Axis;
+
+use crate::{EditorEvent, EditorState, UndoArg, fs::UNSAVED_CHANGES, status::Status};
+
+/// An ex command, parsed from the command line.
+#[derive(Debug, Clone)]
+pub enum Command {
+  /// `:w`. With `!`, overwrites the file even if it changed on disk.
+  Write { force: bool },
+  /// `:wq` and `:x`, which save the file and then close the view.
+  WriteQuit { force: bool },
+  /// `:wa`, which saves every file with unsaved changes.
+  WriteAll,
+  /// `:q`, which closes the view. The file stays open in the workspace.
+  Quit,
+  /// `:qa`, which exits. Without `!`, this refuses to exit with unsaved
+  /// changes.
+  QuitAll { force: bool },
+  /// `:e`. Without a path, reloads the current file, which only throws away
+  /// unsaved changes with `!`. `++enc=` sets the encoding.
+  Edit { path: Option<PathBuf>, encoding: Option<String>, force: bool },
+  /// `:sp`, `:hs` and `:vs`.
+  Split { axis: Axis },
+  /// `:bd`, which closes the view and the file. Without `!`, this refuses to
+  /// close a file with unsaved changes.
+  BufferDelete { force: bool },
+  /// `:reg` and `:di`.
+  Registers,
+  /// `:ju`.
+  Jumps,
+  /// `:noh`.
+  NoHighlight,
+  /// `:set name` or `:set name=value`.
+  Set { name: String, value: Option<String> },
+  /// `:earlier`.
+  Earlier(UndoArg),
+  /// `:later`.
+  Later(UndoArg),
+}
+
+/// The kind of argument a command takes, which decides how it's completed.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Args {
+  None,
+  Path,
+  Setting,
+  Other,
+}
+
+struct CommandInfo {
+  name:  &'static str,
+  /// The length of the shortest abbreviation of `name`.
+  short: usize,
+  bang:  bool,
+  args:  Args,
+  parse: fn(bool, &str) -> Result<Command, String>,
+}
+
+const COMMANDS: &[CommandInfo] = &[
+  CommandInfo {
+    name:  "write",
+    short: 1,
+    bang:  true,
+    args:  Args::None,
+    parse: |force, _| Ok(Command::Write { force }),
+  },
+  CommandInfo {
+    name:  "wq",
+    short: 2,
+    bang:  true,
+    args:  Args::None,
+    parse: |force, _| Ok(Command::WriteQuit { force }),
+  },
+  CommandInfo {
+    name:  "wall",
+    short: 2,
+    bang:  false,
+    args:  Args::None,
+    parse: |_, _| Ok(Command::WriteAll),
+  },
+  CommandInfo {
+    name:  "xit",
+    short: 1,
+    bang:  true,
+    args:  Args::None,
+    parse: |force, _| Ok(Command::WriteQuit { force }),
+  },
+  CommandInfo {
+    name:  "quit",
+    short: 1,
+    bang:  true,
+    args:  Args::None,
+    parse: |_, _| Ok(Command::Quit),
+  },
+  CommandInfo {
+    name:  "qall",
+    short: 2,
+    bang:  true,
+    args:  Args::None,
+    parse: |force, _| Ok(Command::QuitAll { force }),
+  },
+  CommandInfo {
+    name:  "edit",
+    short: 1,
+    bang:  true,
+    args:  Args::Path,
+    parse: |force, arg| {
+      let (encoding, path) = match arg.strip_prefix("++enc=") {
+        Some(rest) => {
+          let (encoding, path) = rest.split_once(' ').unwrap_or((rest, ""));
+          (Some(encoding.to_string()), path.trim())
+        }
+        None => (None, arg),
+      };
+      let path = (!path.is_empty()).then(|| PathBuf::from(path));
+      Ok(Command::Edit { path, encoding, force })
+    },
+  },
+  CommandInfo {
+    name:  "split",
+    short: 2,
+    bang:  false,
+    args:  Args::None,
+    parse: |_, _| Ok(Command::Split { axis: Axis::Horizontal }),
+  },
+  CommandInfo {
+    name:  "hsplit",
+    short: 2,
+    bang:  false,
+    args:  Args::None,
+    parse: |_, _| Ok(Command::Split { axis: Axis::Horizontal }),
+  },
+  CommandInfo {
+    name:  "vsplit",
+    short: 2,
+    bang:  false,
+    args:  Args::None,
+    parse: |_, _| Ok(Command::Split { axis: Axis::Vertical }),
+  },
+  CommandInfo {
+    name:  "bdelete",
+    short: 2,
+    bang:  true,
+    args:  Args::None,
+    parse: |force, _| Ok(Command::BufferDelete { force }),
+  },
+  CommandInfo {
+    name:  "registers",
+    short: 3,
+    bang:  false,
+    args:  Args::None,
+    parse: |_, _| Ok(Command::Registers),
+  },
+  CommandInfo {
+    name:  "display",
+    short: 2,
+    bang:  false,
+    args:  Args::None,
+    parse: |_, _| Ok(Command::Registers),
+  },
+  CommandInfo {
+    name:  "jumps",
+    short: 2,
+    bang:  false,
+    args:  Args::None,
+    parse: |_, _| Ok(Command::Jumps),
+  },
+  CommandInfo {
+    name:  "nohlsearch",
+    short: 3,
+    bang:  false,
+    args:  Args::None,
+    parse: |_, _| Ok(Command::NoHighlight),
+  },
+  CommandInfo {
+    name:  "set",
+    short: 2,
+    bang:  false,
+    args:  Args::Setting,
+    parse: |_, arg| {
+      if arg.is_empty() {
+        return Err("argument required".into());
+      }
+      let (name, value) = match arg.split_once('=') {
+        Some((name, value)) => (name, Some(value.to_string())),
+        None => (arg, None),
+      };
+      Ok(Command::Set { name: name.to_string(), value })
+    },
+  },
+  CommandInfo {
+    name:  "earlier",
+    short: 2,
+    bang:  false,
+    args:  Args::Other,
+    parse: |_, arg| Ok(Command::Earlier(crate::undo::parse_undo_arg(arg)?)),
+  },
+  CommandInfo {
+    name:  "later",
+    short: 3,
+    bang:  false,
+    args:  Args::Other,
+    parse: |_, arg| Ok(Command::Later(crate::undo::parse_undo_arg(arg)?)),
+  },
+];
+
+/// Finds the command that `name` is an abbreviation of.
+fn find_command(name: &str) -> Option<&'static CommandInfo> {
+  COMMANDS.iter().find(|c| name.len() >= c.short && c.name.starts_with(name))
+}
+
+/// Splits a command line into the command name, whether it has a `!`, and the
+/// argument.
+fn split_command(text: &str) -> (&str, bool, &str) {
+  let text = text.trim_start();
+  let len = text.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(text.len());
+  let (name, rest) = text.split_at(len);
+  match rest.strip_prefix('!') {
+    Some(arg) => (name, true, arg.trim()),
+    None => (name, false, rest.trim()),
+  }
+}
+
+impl Command {
+  pub fn parse(text: &str) -> Result<Command, String> {
+    let (name, force, arg) = split_command(text);
+    let Some(command) = find_command(name) else {
+      return Err(format!("not an editor command: {}", text.trim()));
+    };
+
+    if force && !command.bang {
+      return Err(format!("no ! allowed: {}", command.name));
+    }
+    if command.args == Args::None && !arg.is_empty() {
+      return Err(format!("trailing characters: {arg}"));
+    }
+
+    (command.parse)(force, arg)
+  }
+}
+
+/// Every way to complete the command line `text`, as the whole new command
+/// line. Paths are completed relative to `root`.
+pub fn complete_command(text: &str, root: &Path) -> Vec<String> {
+  let (name, _, arg) = split_command(text);
+  let Some(arg_start) = text.find(' ') else {
+    let mut names =
+      COMMANDS.iter().map(|c| c.name).filter(|n| n.starts_with(name)).collect::<Vec<_>>();
+    names.sort();
+    return names.into_iter().map(String::from).collect();
+  };
+
+  let before = &text[..=arg_start];
+  let completions = match find_command(name).map(|c| c.args) {
+    Some(Args::Path) => {
+      let (encoding, path) = match arg.split_once(' ') {
+        Some((encoding, path)) if encoding.starts_with("++enc=") => (format!("{encoding} "), path),
+        _ => (String::new(), arg),
+      };
+      complete_path(path, root).into_iter().map(|p| format!("{encoding}{p}")).collect()
+    }
+    Some(Args::Setting) => crate::set::OPTIONS
+      .iter()
+      .filter(|option| option.starts_with(arg))
+      .map(|option| option.to_string())
+      .collect(),
+    _ => vec![],
+  };
+
+  completions.into_iter().map(|c| format!("{before}{c}")).collect()
+}
+
+/// The files and directories that `path` could be the start of. Hidden files
+/// are only listed once a `.` is typed.
+fn complete_path(path: &str, root: &Path) -> Vec<String> {
+  let (dir, prefix) = match path.rfind('/') {
+    Some(i) => path.split_at(i + 1),
+    None => ("", path),
+  };
+
+  let Ok(entries) = std::fs::read_dir(root.join(dir)) else { return vec![] };
+  let mut paths = entries
+    .flatten()
+    .filter_map(|entry| {
+      let name = entry.file_name().into_string().ok()?;
+      if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+        return None;
+      }
+
+      let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+      Some(format!("{dir}{name}{}", if is_dir { "/" } else { "" }))
+    })
+    .collect::<Vec<_>>();
+  paths.sort();
+  paths
+}
+
+impl EditorState {
+  /// Runs a command typed on the command line or bound to a key. Errors are
+  /// shown in the status bar.
+  pub fn execute_command(&mut self, text: &str) {
+    self.preview_command("");
+    if text.trim().is_empty() || self.run_substitute(text) {
+      return;
+    }
+
+    match Command::parse(text) {
+      Ok(command) => self.run_ex_command(command),
+      Err(e) => self.status = Some(Status::for_error_message(e)),
+    }
+  }
+
+  fn run_ex_command(&mut self, command: Command) {
+    match command {
+      Command::Write { force } => self.begin_save(force),
+      Command::WriteQuit { force } => self.begin_save_and_quit(force),
+      Command::Edit { path: None, encoding, force } => {
+        match (encoding, self.file.as_ref().map(|f| f.path().to_path_buf())) {
+          (Some(encoding), _) => self.reopen_with_encoding(&encoding, force),
+          (None, Some(_)) if self.unsaved() && !force => {
+            self.status = Some(Status::for_error_message(UNSAVED_CHANGES));
+          }
+          (None, Some(path)) => {
+            if let Err(e) = self.open(&path) {
+              self.status = Some(Status::for_error(e));
+            }
+          }
+          (None, None) => self.status = Some(Status::for_error_message("no file open")),
+        }
+      }
+      Command::BufferDelete { force: false } if self.unsaved() => {
+        self.status = Some(Status::for_error_message(UNSAVED_CHANGES));
+      }
+      Command::Registers => self.show_registers(),
+      Command::Jumps => self.show_jumps(),
+      Command::NoHighlight => self.clear_search(),
+      Command::Set { name, value } => self.set_option(&name, value.as_deref()),
+      Command::Earlier(arg) => self.undo_command(true, arg),
+      Command::Later(arg) => self.undo_command(false, arg),
+
+      command => self.send_command(command),
+    }
+  }
+
+  /// Completes the command line. Paths are relative to the workspace root.
+  pub(crate) fn complete_command_line(&mut self) {
+    let Some(command) = &mut self.command else { return };
+
+    if command.complete(&self.root) {
+      let text = command.text.clone();
+      self.preview_command(&text);
+    }
+  }
+
+  pub(crate) fn send_command(&self, command: Command) {
+    if let Some(send) = &self.send {
+      send(EditorEvent::Command(command));
+    }
+  }
+
+  /// Shows an error from a command that was run outside of the editor.
+  pub fn show_error(&mut self, message: impl Into<String>) {
+    self.status = Some(Status::for_error_message(message));
+  }
+  /// Shows the result of a command that was run outside of the editor.
+  pub fn show_message(&mut self, message: impl Into<String>) {
+    self.status = Some(Status::for_success(message));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{cell::Cell, rc::Rc};
+
+  use super::*;
+  use crate::tests::*;
+
+  #[test]
+  fn parse() {
+    assert!(matches!(Command::parse("w"), Ok(Command::Write { force: false })));
+    assert!(matches!(Command::parse("wri!"), Ok(Command::Write { force: true })));
+    assert!(matches!(Command::parse("wa"), Ok(Command::WriteAll)));
+    assert!(matches!(Command::parse("qa!"), Ok(Command::QuitAll { force: true })));
+    assert!(matches!(Command::parse("sp"), Ok(Command::Split { axis: Axis::Horizontal })));
+    assert!(matches!(
+      Command::parse("e ++enc=latin1 foo.rs"),
+      Ok(Command::Edit { path: Some(p), encoding: Some(e), force: false })
+        if p == Path::new("foo.rs") && e == "latin1"
+    ));
+    assert!(matches!(
+      Command::parse("se ff=unix"),
+      Ok(Command::Set { name, value: Some(value) }) if name == "ff" && value == "unix"
+    ));
+
+    assert_eq!(Command::parse("s").unwrap_err(), "not an editor command: s");
+    assert_eq!(Command::parse("wa!").unwrap_err(), "no ! allowed: wall");
+    assert_eq!(Command::parse("q foo").unwrap_err(), "trailing characters: foo");
+    assert_eq!(Command::parse("set").unwrap_err(), "argument required");
+    assert_eq!(Command::parse("earlier 3x").unwrap_err(), "invalid argument: 3x");
+  }
+
+  #[test]
+  fn errors() {
+    let mut editor = editor("foo\n");
+    editor.keys(":foo<CR>");
+    assert_eq!(editor.status.as_ref().unwrap().message, "not an editor command: foo");
+
+    editor.keys("x:bd<CR>");
+    assert_eq!(
+      editor.status.as_ref().unwrap().message,
+      "no write since last change (add ! to override)"
+    );
+  }
+
+  #[test]
+  fn write_quit() {
+    let dir = TempDir::new("write-quit");
+    std::fs::write(dir.join("file"), "foo\n").unwrap();
+
+    let mut editor = editor("");
+    editor.open(&dir.join("file")).unwrap();
+    let quits = Rc::new(Cell::new(0));
+    editor.send = Some(Box::new({
+      let quits = quits.clone();
+      move |event| {
+        if let EditorEvent::Command(Command::Quit) = event {
+          quits.set(quits.get() + 1);
+        }
+      }
+    }));
+
+    editor.keys("x:wq<CR>");
+    assert_eq!(std::fs::read_to_string(dir.join("file")).unwrap(), "oo\n");
+    assert_eq!(quits.get(), 1);
+
+    // The view stays open if the save fails.
+    std::fs::remove_dir_all(&*dir).unwrap();
+    editor.keys("x:wq<CR>");
+    assert!(!editor.status.as_ref().unwrap().success);
+    assert_eq!(quits.get(), 1);
+  }
+
+  #[test]
+  fn complete() {
+    let root = TempDir::new("complete");
+    std::fs::create_dir_all(root.join("src")).unwrap();
+    std::fs::write(root.join("src/main.rs"), "").unwrap();
+    std::fs::write(root.join("src/mod.rs"), "").unwrap();
+    std::fs::write(root.join(".hidden"), "").unwrap();
+
+    assert_eq!(complete_command("vs", &root), ["vsplit"]);
+    assert_eq!(complete_command("w", &root), ["wall", "wq", "write"]);
+    assert_eq!(complete_command("e ", &root), ["e src/"]);
+    assert_eq!(complete_command("e src/m", &root), ["e src/main.rs", "e src/mod.rs"]);
+    assert_eq!(complete_command("e ++enc=utf-8 src/ma", &root), ["e ++enc=utf-8 src/main.rs"]);
+    assert_eq!(complete_command("set ", &root), ["set fileformat", "set nowrap", "set wrap"]);
+    assert_eq!(complete_command("set w", &root), ["set wrap"]);
+
+    // Tab completes paths in the workspace root, which `:e` opens them from.
+    let mut editor = editor("\n");
+    editor.root = root.to_path_buf();
+    editor.keys(":e src/ma<Tab>");
+    assert_eq!(editor.command().unwrap().text, "e src/main.rs");
+  }
+}
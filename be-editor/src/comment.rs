@@ -0,0 +1,169 @@
+//! Comment toggling, as with `gcc` or `gc{motion}`. Lines are commented with
+//! the language's `line-comment` token, or wrapped in its `block-comment`
+//! tokens if it has no line comments. The tokens of each line come from the
+//! language injected there, like a code block in Markdown, or from the language
+//! of the file.
+
+use be_doc::{Change, Line};
+use be_input::Move;
+
+use crate::EditorState;
+
+/// The tokens that a line is commented out with.
+struct CommentTokens {
+  start: String,
+  end:   Option<String>,
+}
+
+impl EditorState {
+  /// The comment tokens for the language at `offset`.
+  fn comment_tokens(&self, offset: usize) -> Option<CommentTokens> {
+    let config = self.config.borrow();
+    let injected = self
+      .highligher
+      .as_ref()
+      .and_then(|h| h.injected_language(&self.doc, offset))
+      .and_then(|name| config.language_for_name(&name));
+    let language = config.languages.get(&injected.or(self.filetype)?)?;
+
+    match (&language.line_comment, &language.block_comment) {
+      (Some(token), _) => Some(CommentTokens { start: token.clone(), end: None }),
+      (None, Some([start, end])) => {
+        Some(CommentTokens { start: start.clone(), end: Some(end.clone()) })
+      }
+      (None, None) => None,
+    }
+  }
+
+  /// Comments out the lines from `start` to `end`, or uncomments them if they
+  /// are all commented already. Blank lines are left alone. The comment tokens
+  /// all go in the column of the least indented line, so that the indentation
+  /// stays lined up.
+  pub(crate) fn toggle_comments(&mut self, start: Line, end: Line) {
+    let end = end.clamp(self.max_line());
+
+    // Each non-blank line, with the byte length of its indent and its tokens.
+    let mut lines = vec![];
+    for line in start.0..=end.0 {
+      let line = Line(line);
+      let text = self.doc.line(line);
+      let Some(indent) = text.chars().position(|c| !c.is_whitespace()) else { continue };
+      let indent = text.chars().take(indent).map(char::len_utf8).sum::<usize>();
+
+      let Some(tokens) = self.comment_tokens(self.doc.byte_of_line(line) + indent) else {
+        continue;
+      };
+      lines.push((line, indent, tokens));
+    }
+
+    let commented = !lines.is_empty()
+      && lines.iter().all(|(line, indent, tokens)| {
+        let text = self.doc.line(*line).to_string();
+        let text = text[*indent..].trim_end();
+        text.starts_with(&tokens.start)
+          && tokens.end.as_ref().is_none_or(|end| text.ends_with(end.as_str()))
+      });
+    let column = lines.iter().map(|(_, indent, _)| *indent).min().unwrap_or(0);
+
+    // Lines are changed from the bottom up, so the earlier offsets stay valid.
+    for (line, indent, tokens) in lines.into_iter().rev() {
+      let line_start = self.doc.byte_of_line(line);
+      let text = self.doc.line(line).to_string();
+
+      if commented {
+        let mut start_end = indent + tokens.start.len();
+        if text[start_end..].starts_with(' ') {
+          start_end += 1;
+        }
+        let end = tokens.end.as_ref().map(|end| {
+          let text_end = text.trim_end().len();
+          let mut end_start = text_end - end.len();
+          if end_start > start_end && text[..end_start].ends_with(' ') {
+            end_start -= 1;
+          }
+          end_start..text_end
+        });
+
+        // The tokens overlap on a line like `<!-->`, so it's left alone.
+        if end.as_ref().is_some_and(|end| end.start < start_end) {
+          continue;
+        }
+        if let Some(end) = end {
+          self.change(Change::remove(line_start + end.start..line_start + end.end));
+        }
+        self.change(Change::remove(line_start + indent..line_start + start_end));
+      } else {
+        if let Some(end) = &tokens.end {
+          let text_end = line_start + text.trim_end().len();
+          self.change(Change::insert(text_end, &format!(" {end}")));
+        }
+        self.change(Change::insert(line_start + column, &format!("{} ", tokens.start)));
+      }
+    }
+
+    self.move_to_line(start);
+    self.perform_move(Move::LineStartOfText, None);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::tests::*;
+
+  fn with_language(src: &str, filename: &str) -> TestEditor {
+    let mut editor = editor(src);
+    let filetype = editor.config.borrow().language_for_filename(filename);
+    editor.filetype = filetype;
+    editor
+  }
+
+  #[test]
+  fn line_comments() {
+    let mut editor = with_language("fn foo() {\n  bar();\n\n    baz();\n}\n", "a.rs");
+    editor.keys("jgcj");
+    editor.check(expect![@"fn foo() {\n  ⟦/⟧/ bar();\n\n    baz();\n}\n"]);
+
+    editor.keys("gcj");
+    editor.check(expect![@"fn foo() {\n  ⟦b⟧ar();\n\n    baz();\n}\n"]);
+
+    // The tokens line up with the least indented line.
+    editor.keys("gc2j");
+    editor.check(expect![@"fn foo() {\n  ⟦/⟧/ bar();\n\n  //   baz();\n}\n"]);
+
+    // Every line is commented, so they are all uncommented.
+    editor.keys("gc2j");
+    editor.check(expect![@"fn foo() {\n  ⟦b⟧ar();\n\n    baz();\n}\n"]);
+
+    editor.keys("gcc");
+    editor.check(expect![@"fn foo() {\n  ⟦/⟧/ bar();\n\n    baz();\n}\n"]);
+    editor.keys("u");
+    editor.check(expect![@"fn foo() {\n  ⟦b⟧ar();\n\n    baz();\n}\n"]);
+  }
+
+  #[test]
+  fn block_comments() {
+    let mut editor = with_language("# Title\n\nSome text\n", "a.md");
+    editor.keys("Ggcc");
+    editor.check(expect![@"# Title\n\n⟦<⟧!-- Some text -->\n"]);
+    editor.keys("gcc");
+    editor.check(expect![@"# Title\n\n⟦S⟧ome text\n"]);
+  }
+
+  #[test]
+  fn overlapping_block_comments() {
+    let mut editor = with_language("<!-->\n<!-- a -->\n<!-- -->\n", "a.md");
+    editor.keys("gc2j");
+    editor.check(expect![@"⟦<⟧!-->\na\n\n"]);
+  }
+
+  #[test]
+  fn visual() {
+    let mut editor = with_language("a = 1\nb = 2\n", "a.toml");
+    editor.keys("Vjgc");
+    editor.check(expect![@"⟦#⟧ a = 1\n# b = 2\n"]);
+
+    // The whole toggle is a single undo step.
+    editor.keys("u");
+    editor.check(expect![@"⟦a⟧ = 1\nb = 2\n"]);
+  }
+}
@@ -0,0 +1,308 @@
+//! Multiple cursors. The main cursor is `EditorState::cursor`, and every other
+//! cursor is stored as an anchor, so that it stays on the same text as the
+//! document is edited. Moves and edits are performed once at each cursor, with
+//! that cursor swapped in as the main one, and all the changes from one
+//! keystroke are a single undo step. Cursors that end up in the same spot are
+//! merged.
+//!
+//! Visual mode and the command line only use the main cursor.
+
+use be_doc::{Anchor, Column, Cursor, Edit, Gravity, VisualColumn};
+use be_input::{Action, CursorOp, Mode, Move, TextObject, VerticalDirection};
+
+use crate::{EditorState, status::Status};
+
+/// A cursor besides the main one.
+pub(crate) struct ExtraCursor {
+  anchor:        Anchor,
+  target_column: VisualColumn,
+}
+
+impl EditorState {
+  /// Every cursor besides the main one, in the order they were added.
+  pub fn cursors(&self) -> Vec<Cursor> {
+    self.cursors.iter().map(|c| self.extra_cursor(c)).collect()
+  }
+
+  fn extra_cursor(&self, cursor: &ExtraCursor) -> Cursor {
+    let offset = self.doc.anchor_offset(cursor.anchor).unwrap_or(0);
+    let mut cursor = if offset < self.doc.len() {
+      Cursor { target_column: cursor.target_column, ..self.doc.offset_to_cursor(offset) }
+    } else {
+      // The end of the document is past the last character, after an insert.
+      let line = self.max_line();
+      let column = Column(self.doc.line(line).graphemes().count());
+      Cursor { line, column, target_column: cursor.target_column }
+    };
+
+    let max_column = Column(self.doc.line(cursor.line).graphemes().count());
+    cursor.column = match self.mode {
+      Mode::Insert | Mode::Replace => cursor.column.clamp(max_column),
+      _ => cursor.column.clamp(Column(max_column.0.saturating_sub(1))),
+    };
+    cursor
+  }
+
+  fn new_extra_cursor(&mut self, cursor: Cursor) -> ExtraCursor {
+    let anchor = self.doc.anchor(self.doc.cursor_offset(cursor), Gravity::Right);
+    ExtraCursor { anchor, target_column: cursor.target_column }
+  }
+
+  /// Adds a cursor at `cursor`, unless there is one there already.
+  fn add_cursor(&mut self, cursor: Cursor) {
+    if cursor == self.cursor
+      || self.cursors().iter().any(|c| c.line == cursor.line && c.column == cursor.column)
+    {
+      return;
+    }
+
+    let cursor = self.new_extra_cursor(cursor);
+    self.cursors.push(cursor);
+    self.damage_all = true;
+  }
+
+  /// Moves the main cursor to `cursor`, and leaves another cursor where it was.
+  fn add_cursor_and_move(&mut self, cursor: Cursor) {
+    let main = self.cursor;
+    self.cursor = cursor;
+    self.add_cursor(main);
+  }
+
+  pub(crate) fn clear_cursors(&mut self) {
+    if self.cursors.is_empty() {
+      return;
+    }
+
+    for cursor in std::mem::take(&mut self.cursors) {
+      self.doc.remove_anchor(cursor.anchor);
+    }
+    self.damage_all = true;
+  }
+
+  pub(crate) fn perform_cursor_op(&mut self, op: CursorOp) {
+    match op {
+      CursorOp::NextMatch => self.add_cursor_at_next_match(),
+      CursorOp::Add(dir) => {
+        let line = match dir {
+          VerticalDirection::Up if self.cursor.line.0 > 0 => self.cursor.line - 1,
+          VerticalDirection::Down if self.cursor.line < self.max_line() => self.cursor.line + 1,
+          _ => return,
+        };
+
+        let main = self.cursor;
+        self.move_to_line(line);
+        let cursor = self.cursor;
+        self.cursor = main;
+        self.add_cursor_and_move(cursor);
+      }
+      CursorOp::SplitSelection => self.split_selection(),
+      CursorOp::Clear => self.clear_cursors(),
+    }
+  }
+
+  /// Adds a cursor at the next match of the word under the main cursor, which
+  /// becomes the main cursor. The search wraps around the end of the file, and
+  /// skips matches that already have a cursor.
+  fn add_cursor_at_next_match(&mut self) {
+    let Some(word) = self.text_object(TextObject::Word, true) else { return };
+    let text = self.doc.range(word.range.clone()).to_string();
+    if text.trim().is_empty() {
+      return;
+    }
+
+    // New cursors go in the same place within the match.
+    let within = self.doc.cursor_offset(self.cursor) - word.range.start;
+    let taken = self
+      .cursors()
+      .into_iter()
+      .chain([self.cursor])
+      .map(|c| self.doc.cursor_offset(c))
+      .collect::<Vec<_>>();
+
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    let target = self
+      .doc
+      .find_from(word.range.end, &text)
+      .chain(self.doc.find(&text).take_while(|r| r.start < word.range.start))
+      .find(|r| {
+        !taken.contains(&(r.start + within))
+          && !is_word(self.doc.range(..r.start).chars().next_back())
+          && !is_word(self.doc.range(r.end..).chars().next())
+      });
+
+    match target {
+      Some(range) => {
+        let cursor = self.doc.offset_to_cursor(range.start + within);
+        self.add_cursor_and_move(cursor);
+      }
+      None => self.status = Some(Status::for_error_message(format!("no more matches: {text}"))),
+    }
+  }
+
+  /// Leaves visual mode with a cursor on each line of the selection, in the
+  /// column of the main cursor.
+  fn split_selection(&mut self) {
+    let Some(selection) = self.selection() else { return };
+    self.set_mode(Mode::Normal);
+
+    let main = self.cursor;
+    for line in selection.start_line().0..=selection.end_line().0 {
+      self.move_to_line(be_doc::Line(line));
+      let cursor = self.cursor;
+      self.cursor = main;
+      self.add_cursor(cursor);
+    }
+  }
+
+  /// Returns `true` if `action` should be performed at every cursor, rather
+  /// than just the main one.
+  pub(crate) fn applies_to_each_cursor(&self, action: &Action) -> bool {
+    if self.cursors.is_empty()
+      || self.command.is_some()
+      || !matches!(self.mode, Mode::Normal | Mode::Insert | Mode::Replace)
+    {
+      return false;
+    }
+
+    match action {
+      Action::SetMode { mode, .. } => matches!(mode, Mode::Normal | Mode::Insert | Mode::Replace),
+      Action::Append { .. } | Action::Paste => true,
+      Action::Move { m, .. } => {
+        !matches!(m, Move::Jump(_) | Move::GotoDefinition | Move::BackDefinition)
+      }
+      Action::Edit { e, .. } => !matches!(
+        e,
+        be_input::Edit::Undo
+          | be_input::Edit::Redo
+          | be_input::Edit::Earlier
+          | be_input::Edit::Later
+          | be_input::Edit::Visual(_)
+      ),
+      _ => false,
+    }
+  }
+
+  /// Performs `action` at every cursor. Each cursor starts out in the mode the
+  /// editor was in before the action, so that mode changes like `a` or `<Esc>`
+  /// apply to all of them.
+  pub(crate) fn perform_at_each_cursor(&mut self, action: Action) {
+    if self.current_edit.is_none() {
+      self.current_edit = Some(Edit::empty());
+      // Undo goes back to the main cursor, rather than the first cursor that
+      // changed anything.
+      self.undo.start(self.cursor);
+    }
+    self.each_cursor = true;
+
+    // The main cursor is stored with the others while the action runs, so that
+    // it follows the changes made at the other cursors.
+    let main = self.new_extra_cursor(self.cursor);
+    self.cursors.push(main);
+
+    let mode = self.mode;
+    for i in 0..self.cursors.len() {
+      self.mode = mode;
+      self.cursor = self.extra_cursor(&self.cursors[i]);
+      self.perform_action_at_cursor(action.clone());
+
+      let cursor = self.new_extra_cursor(self.cursor);
+      let old = std::mem::replace(&mut self.cursors[i], cursor);
+      self.doc.remove_anchor(old.anchor);
+    }
+
+    let main = self.cursors.pop().unwrap();
+    self.cursor = self.extra_cursor(&main);
+    self.doc.remove_anchor(main.anchor);
+    self.merge_cursors();
+
+    self.each_cursor = false;
+    self.damage_all = true;
+    // An insert is a single undo step, so the edit stays open until insert
+    // mode is left.
+    if self.mode != Mode::Insert {
+      self.remove_current_edit();
+    }
+  }
+
+  /// Removes cursors that ended up in the same spot as another cursor.
+  fn merge_cursors(&mut self) {
+    let mut seen = vec![(self.cursor.line, self.cursor.column)];
+    let mut i = 0;
+    while i < self.cursors.len() {
+      let cursor = self.extra_cursor(&self.cursors[i]);
+      if seen.contains(&(cursor.line, cursor.column)) {
+        let removed = self.cursors.remove(i);
+        self.doc.remove_anchor(removed.anchor);
+      } else {
+        seen.push((cursor.line, cursor.column));
+        i += 1;
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::tests::*;
+
+  #[test]
+  fn add_vertical() {
+    let mut editor = editor("one\ntwo\nthree\n");
+    editor.keys("l<C-j><C-j>");
+    assert_eq!(editor.cursors().len(), 2);
+
+    editor.keys("x");
+    editor.check(expect![@"oe\nto\nt⟦r⟧ee\n"]);
+
+    editor.keys("<Esc>");
+    assert!(editor.cursors().is_empty());
+  }
+
+  #[test]
+  fn insert() {
+    let mut editor = editor("foo\nfoo\n");
+    editor.keys("<C-j>afoo<Esc>");
+    editor.check(expect![@"ffoooo\nffo⟦o⟧oo\n"]);
+
+    // The whole insert is a single undo step, as is each normal mode edit.
+    editor.keys("u");
+    editor.check(expect![@"f⟦o⟧o\nfoo\n"]);
+
+    let mut editor = crate::tests::editor("foo\nfoo\n");
+    editor.keys("<C-j>x");
+    editor.check(expect![@"oo\n⟦o⟧o\n"]);
+    editor.keys("u");
+    editor.check(expect![@"⟦f⟧oo\nfoo\n"]);
+  }
+
+  #[test]
+  fn next_match() {
+    let mut editor = editor("foo bar foo baz foo\n");
+    editor.keys("<C-n><C-n>");
+    editor.check(expect![@"foo bar foo baz ⟦f⟧oo\n"]);
+    assert_eq!(editor.cursors().len(), 2);
+
+    // Each change moves the cursors after it.
+    editor.keys("celonger<Esc>");
+    editor.check(expect![@"longer bar longer baz longe⟦r⟧\n"]);
+    editor.keys("x");
+    editor.check(expect![@"longe bar longe baz long⟦e⟧\n"]);
+
+    // The cursors merge once they end up in the same spot.
+    editor.keys("0");
+    assert!(editor.cursors().is_empty());
+
+    editor.keys("w<C-n>");
+    assert_eq!(editor.status.as_ref().unwrap().message, "no more matches: bar");
+  }
+
+  #[test]
+  fn split_selection() {
+    let mut editor = editor("one\ntwo\nthree\nfour\n");
+    editor.keys("lVjj<M-s>");
+    assert_eq!(editor.cursors().len(), 2);
+
+    editor.keys("i-<Esc>");
+    editor.check(expect![@"o-ne\nt-wo\nt⟦-⟧hree\nfour\n"]);
+  }
+}
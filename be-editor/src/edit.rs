@@ -1,13 +1,12 @@
 use std::{num::NonZero, ops::Range};
 
-use be_doc::Change;
+use be_doc::{Change, Line};
 use be_input::{Direction, Mode, Move, VerticalDirection};
-use unicode_segmentation::UnicodeSegmentation;
 
-use crate::{CommandMode, EditorState};
+use crate::{CommandMode, EditorState, Register, RegisterKind};
 
 impl EditorState {
-  pub(crate) fn perform_edit(&mut self, e: be_input::Edit) {
+  pub(crate) fn perform_edit(&mut self, e: be_input::Edit, count: Option<NonZero<u32>>) {
     use be_input::Edit;
 
     if let Some(command) = &mut self.command {
@@ -18,20 +17,44 @@ impl EditorState {
       }
 
       command.perform_edit(e);
-      if command.mode == CommandMode::Search {
-        self.search_text = Some(command.text.clone());
-        self.damage_all = true;
+      match command.mode {
+        CommandMode::Search => {
+          let text = command.text.clone();
+          let _ = self.set_search(&text);
+        }
+        CommandMode::Command => {
+          let text = command.text.clone();
+          self.preview_command(&text);
+        }
       }
 
       return;
     }
 
+    // A counted command is a single undo step. Changes that enter insert mode
+    // keep the edit open until insert mode is left.
+    let group = (count.is_some()
+      || matches!(
+        e,
+        Edit::Reindent(_) | Edit::ReindentLine | Edit::Comment(_) | Edit::CommentLine
+      ))
+      && self.current_edit.is_none()
+      && !matches!(e, Edit::Undo | Edit::Redo | Edit::Earlier | Edit::Later);
+    if group {
+      self.current_edit = Some(be_doc::Edit::empty());
+    }
+
+    let n = count.map_or(1, |c| c.get());
+
     match e {
+      Edit::Insert(c) if n == 1 && self.mode == Mode::Insert && self.auto_pair(c) => {}
       Edit::Insert(c) => {
         let mut bytes = [0; 4];
-        let s = c.encode_utf8(&mut bytes);
-        self.change(Change::insert(self.doc.cursor_offset(self.cursor), s));
-        self.move_graphemes(1);
+        let s = if c == '\n' { self.line_ending.as_str() } else { c.encode_utf8(&mut bytes) };
+        for _ in 0..n {
+          self.change(Change::insert(self.doc.cursor_offset(self.cursor), s));
+          self.move_graphemes(1);
+        }
 
         match c {
           '\n' => {
@@ -43,98 +66,161 @@ impl EditorState {
         }
       }
       Edit::Replace(c) => {
-        let mut bytes = [0; 4];
-        let s = c.encode_utf8(&mut bytes);
-        self.change(Change::replace(self.doc.grapheme_slice(self.cursor, 1), s));
+        let range = self.doc.grapheme_slice(self.cursor, n as usize);
+        let line_end = self.doc.byte_of_line_end(self.cursor.line);
+
+        // Like vim, a count that goes past the end of the line does nothing.
+        if range.end <= line_end && self.doc.range(range.clone()).graphemes().count() == n as usize
+        {
+          let mut bytes = [0; 4];
+          let s = c.encode_utf8(&mut bytes);
+          self.change(Change::replace(range, &s.repeat(n as usize)));
+          self.move_col_rel(n as i32 - 1);
+        }
       }
-      // TODO: Parse counts before these moves
-      Edit::Delete(m) => self.perform_delete_move(m, None),
+      Edit::Delete(m) => self.perform_delete_move(m, count),
       Edit::Cut(m) => {
         self.set_mode(Mode::Insert);
-        self.perform_delete_move(m, None);
+        self.perform_delete_move(m, count);
       }
       Edit::DeleteLine => {
-        let end = if self.cursor.line == self.max_line() {
-          if self.cursor.line == 0 {
-            self.doc.byte_of_line_end(self.cursor.line)
-          } else {
-            self.doc.len()
-          }
-        } else {
-          self.doc.byte_of_line(self.cursor.line + 1)
-        };
-
-        self.delete_copy(self.doc.byte_of_line(self.cursor.line)..end);
-        self.clamp_cursor();
+        self.delete_lines(self.cursor.line, self.last_of_lines(self.cursor.line, n))
       }
       Edit::CutLine => {
         self.set_mode(Mode::Insert);
-        self.delete_copy(
-          self.doc.byte_of_line(self.cursor.line)..self.doc.byte_of_line_end(self.cursor.line),
-        );
-        self.clamp_column();
-
-        self.auto_indent(VerticalDirection::Up);
+        self.cut_lines(self.cursor.line, self.last_of_lines(self.cursor.line, n));
+      }
+      Edit::Reindent(m) => self.perform_reindent_move(m, count),
+      Edit::ReindentLine => {
+        self.reindent_lines(self.cursor.line, self.last_of_lines(self.cursor.line, n))
+      }
+      Edit::Comment(m) => {
+        if let Some((start, end)) = self.lines_of_move(m, count) {
+          self.toggle_comments(start, end);
+        }
+      }
+      Edit::CommentLine => {
+        self.toggle_comments(self.cursor.line, self.last_of_lines(self.cursor.line, n))
       }
       Edit::DeleteRestOfLine => {
+        let end = (self.cursor.line + (n - 1) as i32).clamp(self.max_line());
         self.delete_copy(
           self.doc.cursor_offset(self.cursor)
-            ..self.doc.offset_by_graphemes(self.doc.byte_of_line(self.cursor.line + 1), -1),
+            ..self.doc.offset_by_graphemes(self.doc.byte_of_line(end + 1), -1),
         );
         self.clamp_column();
       }
-      Edit::Paste { after } => self.paste(after),
-      Edit::Backspace => {
-        if self.doc.cursor_offset(self.cursor) > 0 {
-          self.move_graphemes(-1);
-          self.change(Change::remove(self.doc.grapheme_slice(self.cursor, 1)));
-        }
+      Edit::Yank(m) => self.perform_yank_move(m, count),
+      Edit::YankLine => {
+        let end = self.last_of_lines(self.cursor.line, n).clamp(self.max_line());
+        self.yank_register(self.line_register(self.cursor.line, end));
       }
-      Edit::Undo => {
-        if self.history_position < self.history.len() {
-          self.history_position += 1;
-          for change in self.history[self.history.len() - self.history_position].clone().undo() {
-            self.keep_cursor_for_change(change);
-            self.change_no_history(change.clone());
+      Edit::Paste { after } => self.paste(after, n as usize),
+      Edit::Backspace if n == 1 && self.mode == Mode::Insert && self.auto_pair_backspace() => {}
+      Edit::Backspace => {
+        for _ in 0..n {
+          if self.doc.cursor_offset(self.cursor) > 0 {
+            self.move_graphemes(-1);
+            self.change(Change::remove(self.doc.grapheme_slice(self.cursor, 1)));
           }
-          self.clamp_cursor();
         }
       }
-      Edit::Redo => {
-        if self.history_position > 0 {
-          for change in self.history[self.history.len() - self.history_position].clone().redo() {
-            self.keep_cursor_for_change(change);
-            self.change_no_history(change.clone());
+      Edit::Undo => self.undo(n),
+      Edit::Redo => self.redo(n),
+      Edit::Earlier => self.undo_steps(-(n as isize)),
+      Edit::Later => self.undo_steps(n as isize),
+      Edit::SwitchCase => {
+        for _ in 0..n {
+          let range = self.doc.grapheme_slice(self.cursor, 1);
+          if let Some(c @ ('a'..='z' | 'A'..='Z')) = self.doc.range(range.clone()).chars().next() {
+            let c = ((c as u8) ^ 0x20) as char;
+            let mut buf = [0; 4];
+            let s = c.encode_utf8(&mut buf);
+            self.change(Change::replace(range, s));
           }
-          self.history_position -= 1;
+
+          self.move_col_rel(1);
           self.clamp_cursor();
         }
       }
-      Edit::SwitchCase => {
-        let range = self.doc.grapheme_slice(self.cursor, 1);
-        if let Some(c @ ('a'..='z' | 'A'..='Z')) = self.doc.range(range.clone()).chars().next() {
-          let c = ((c as u8) ^ 0x20) as char;
-          let mut buf = [0; 4];
-          let s = c.encode_utf8(&mut buf);
-          self.change(Change::replace(range, s));
-        }
+      Edit::Visual(op) => self.perform_visual(op),
+    }
 
-        self.move_col_rel(1);
-        self.clamp_cursor();
-      }
+    if group && self.mode != Mode::Insert {
+      self.remove_current_edit();
     }
   }
 
+  /// Deletes whole lines, as with `dd` and `dj`.
+  fn delete_lines(&mut self, start: Line, end: Line) {
+    let end = end.clamp(self.max_line());
+    let end_offset = if end == self.max_line() {
+      if start == 0 { self.doc.byte_of_line_end(end) } else { self.doc.len() }
+    } else {
+      self.doc.byte_of_line(end + 1)
+    };
+
+    self.delete_register(self.line_register(start, end));
+    self.change(Change::remove(self.doc.byte_of_line(start)..end_offset));
+    self.cursor.line = start;
+    self.clamp_cursor();
+  }
+
+  /// Replaces whole lines with a single empty line, as with `cc` and `cj`.
+  fn cut_lines(&mut self, start: Line, end: Line) {
+    let end = end.clamp(self.max_line());
+
+    self.delete_register(self.line_register(start, end));
+    self.change(Change::remove(self.doc.byte_of_line(start)..self.doc.byte_of_line_end(end)));
+    self.cursor.line = start;
+    self.clamp_column();
+
+    self.auto_indent(VerticalDirection::Up);
+  }
+
+  /// Returns the line that a vertical move ends on. Operators treat these moves
+  /// as linewise, so `dj` deletes two whole lines.
+  fn linewise_target(&self, m: Move, count: Option<NonZero<u32>>) -> Option<Line> {
+    let n = count.map_or(1, |c| c.get()) as i32;
+    let line = match m {
+      Move::Single(Direction::Up) => self.cursor.line - n,
+      Move::Single(Direction::Down) => self.cursor.line + n,
+      Move::FileStart => count.map_or(Line(0), |c| Line(c.get() as usize - 1)),
+      Move::FileEnd => count.map_or(self.max_line(), |c| Line(c.get() as usize - 1)),
+      Move::Mark { name, linewise: true } => self.mark(name)?.line,
+      _ => return None,
+    };
+
+    Some(line.clamp(self.max_line()))
+  }
+
   // Perform the move after 'd' or 'c'.
   fn perform_delete_move(&mut self, m: Move, count: Option<NonZero<u32>>) {
+    if let Move::TextObject { inner, object } = m {
+      self.delete_text_object(object, inner);
+      return;
+    }
+
     if matches!(m, Move::Single(Direction::Right)) {
-      let range = self.doc.grapheme_slice(self.cursor, 1);
-      if !self.doc.range(range.clone()).chars().any(|c| c == '\n') {
+      // `x` never deletes past the end of the line.
+      let range = self.doc.grapheme_slice(self.cursor, count.map_or(1, |c| c.get()) as usize);
+      let range = range.start..range.end.min(self.doc.byte_of_line_end(self.cursor.line));
+      if !range.is_empty() {
         self.delete_copy(range);
       }
       return;
     }
 
+    if let Some(target) = self.linewise_target(m, count) {
+      let (start, end) = (self.cursor.line.min(target), self.cursor.line.max(target));
+      if self.mode == Mode::Insert {
+        self.cut_lines(start, end)
+      } else {
+        self.delete_lines(start, end)
+      }
+      return;
+    }
+
     let inclusive = match m {
       Move::EndWord => true,
       _ => false,
@@ -154,40 +240,73 @@ impl EditorState {
     self.delete_copy(start..end);
   }
 
-  /// Copy the given range, then delete it, then fix the cursor. This is used
-  /// for all the 'd*' and 'c*' commands.
-  fn delete_copy(&mut self, range: Range<usize>) {
-    self.copy(range.clone());
-    self.change(Change::remove(range));
-    self.clamp_cursor();
-  }
+  // Perform the move after 'y'. The cursor ends up at the start of the yanked
+  // text.
+  fn perform_yank_move(&mut self, m: Move, count: Option<NonZero<u32>>) {
+    if let Move::TextObject { inner, object } = m {
+      self.yank_text_object(object, inner);
+      return;
+    }
 
-  fn copy(&mut self, range: Range<usize>) {
-    let text = self.doc.range(range);
-    self.copied = text.to_string();
+    if let Some(target) = self.linewise_target(m, count) {
+      let (start, end) = (self.cursor.line.min(target), self.cursor.line.max(target));
+      self.yank_register(self.line_register(start, end));
+      self.move_to_line(start);
+      return;
+    }
+
+    let inclusive = matches!(m, Move::EndWord);
+
+    let cursor = self.cursor;
+    let before = self.doc.cursor_offset(self.cursor);
+    self.perform_move(m, count);
+    if inclusive {
+      self.move_graphemes(1);
+    }
+    let after = self.doc.cursor_offset(self.cursor);
+
+    self.yank_register(Register::new(
+      self.doc.range(before.min(after)..before.max(after)).to_string(),
+      RegisterKind::Charwise,
+    ));
+    if after > before {
+      self.cursor = cursor;
+    }
   }
 
-  fn paste(&mut self, after: bool) {
-    if self.copied.chars().any(|c| c == '\n') {
-      // Multiline copies are inserted at the end of the line.
-      let idx = if after {
-        self.doc.byte_of_line((self.cursor.line + 1).clamp(self.max_line()))
-      } else {
-        self.doc.byte_of_line(self.cursor.line)
-      };
+  // Perform the move after '='. This is always linewise, so `=w` reindents the
+  // lines between the cursor and the end of the move.
+  fn perform_reindent_move(&mut self, m: Move, count: Option<NonZero<u32>>) {
+    if let Some((start, end)) = self.lines_of_move(m, count) {
+      self.reindent_lines(start, end);
+    }
+  }
 
-      self.change(Change::insert(idx, &self.copied));
-      if after {
-        self.move_line_rel(self.copied.chars().filter(|c| *c == '\n').count() as i32);
-      }
+  /// Returns the first and last line covered by a move, for the operators that
+  /// work on whole lines, like `=` and `gc`.
+  fn lines_of_move(&mut self, m: Move, count: Option<NonZero<u32>>) -> Option<(Line, Line)> {
+    Some(if let Move::TextObject { inner, object } = m {
+      let object = self.text_object(object, inner)?;
+      let end = self.doc.offset_by_graphemes(object.range.end, -1).max(object.range.start);
+      (self.doc.line_of_byte(object.range.start), self.doc.line_of_byte(end))
+    } else if let Some(target) = self.linewise_target(m, count) {
+      (self.cursor.line.min(target), self.cursor.line.max(target))
     } else {
-      if after {
-        self.move_graphemes(1);
-      }
+      let before = self.cursor.line;
+      self.perform_move(m, count);
+      (before.min(self.cursor.line), before.max(self.cursor.line))
+    })
+  }
 
-      self.change(Change::insert(self.doc.cursor_offset(self.cursor), &self.copied));
-      self.move_graphemes(self.copied.graphemes(true).count().saturating_sub(1) as isize);
-    }
+  /// Copy the given range, then delete it, then fix the cursor. This is used
+  /// for all the 'd*' and 'c*' commands.
+  fn delete_copy(&mut self, range: Range<usize>) {
+    self.delete_register(Register::new(
+      self.doc.range(range.clone()).to_string(),
+      RegisterKind::Charwise,
+    ));
+    self.change(Change::remove(range));
+    self.clamp_cursor();
   }
 }
 
@@ -204,7 +323,7 @@ mod tests {
     editor.perform_move(Move::LineEnd, None);
 
     editor.check_repeated(
-      |e| e.perform_edit(Edit::Delete(Move::Single(Direction::Right))),
+      |e| e.perform_edit(Edit::Delete(Move::Single(Direction::Right)), None),
       &[
         expect![@r#"
           fo⟦o⟧
@@ -232,7 +351,7 @@ mod tests {
     editor.perform_move(Move::Single(Direction::Down), None);
 
     editor.check_repeated(
-      |e| e.perform_edit(Edit::Delete(Move::Single(Direction::Right))),
+      |e| e.perform_edit(Edit::Delete(Move::Single(Direction::Right)), None),
       &[
         expect![@r#"
 
@@ -264,7 +383,7 @@ mod tests {
     editor.perform_move(Move::LineEnd, None);
     editor.perform_action(be_input::Action::SetMode { mode: be_input::Mode::Insert, delta: 1 });
     editor.check_repeated(
-      |e| e.perform_edit(Edit::Backspace),
+      |e| e.perform_edit(Edit::Backspace, None),
       &[
         expect![@r#"
           foo‖
@@ -295,7 +414,7 @@ mod tests {
     let mut editor = editor("foo\nbar\n");
     editor.perform_move(Move::FileEnd, None);
     editor.check_repeated(
-      |e| e.perform_edit(Edit::DeleteLine),
+      |e| e.perform_edit(Edit::DeleteLine, None),
       &[
         expect![@r#"
           foo
@@ -315,7 +434,7 @@ mod tests {
   fn delete_back() {
     let mut editor = editor("foo bar\n");
     editor.perform_move(Move::LineEnd, None);
-    editor.perform_edit(Edit::Delete(Move::PrevWord));
+    editor.perform_edit(Edit::Delete(Move::PrevWord), None);
     // TODO: Make this inclusive
     editor.check(expect![@r#"
       foo ⟦r⟧
@@ -326,11 +445,85 @@ mod tests {
   fn delete_up_line() {
     let mut editor = editor("foo\nbar\nbaz\n");
     editor.perform_move(Move::FileEnd, None);
-    editor.perform_edit(Edit::Delete(Move::Single(Direction::Up)));
-    // TODO: Make this delete both lines correctly
+    editor.perform_edit(Edit::Delete(Move::Single(Direction::Up)), None);
+    editor.check(expect![@r#"
+      ⟦f⟧oo
+    "#]);
+  }
+
+  #[test]
+  fn counted_delete() {
+    let mut editor = editor("a b c d e f g\n");
+
+    editor.keys("d3w");
+    editor.check(expect![@"⟦d⟧ e f g\n"]);
+
+    editor.keys("2d2w");
+    editor.check(expect![@"⟦g⟧\n"]);
+
+    editor.keys("u");
+    editor.check(expect![@"⟦d⟧ e f g\n"]);
+  }
+
+  #[test]
+  fn counted_delete_chars() {
+    let mut editor = editor("abcdef\nghi\n");
+
+    editor.keys("5x");
+    editor.check(expect![@r#"
+      ⟦f⟧
+      ghi
+    "#]);
+
+    editor.keys("j5x");
+    editor.check(expect![@r#"
+      f
+      ⟦ ⟧
+    "#]);
+  }
+
+  #[test]
+  fn counted_lines() {
+    let mut editor = editor("a\nb\nc\nd\ne\n");
+
+    editor.keys("3dd");
     editor.check(expect![@r#"
-      foo
-      ⟦b⟧az
+      ⟦d⟧
+      e
     "#]);
+
+    editor.keys("ugg");
+    editor.check(expect![@r#"
+      ⟦a⟧
+      b
+      c
+      d
+      e
+    "#]);
+
+    editor.keys("d2j");
+    editor.check(expect![@r#"
+      ⟦d⟧
+      e
+    "#]);
+
+    editor.keys("2yyP");
+    editor.check(expect![@r#"
+      ⟦d⟧
+      e
+      d
+      e
+    "#]);
+  }
+
+  #[test]
+  fn counted_paste() {
+    let mut editor = editor("ab\n");
+
+    editor.keys("yl2p");
+    editor.check(expect![@"aa⟦a⟧b\n"]);
+
+    editor.keys("u");
+    editor.check(expect![@"⟦a⟧b\n"]);
   }
 }
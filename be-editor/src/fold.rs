@@ -0,0 +1,401 @@
+//! Folding. The folds that can be closed come from the tree-sitter `folds.scm`
+//! query, or from indentation without a grammar. Only closed folds are stored,
+//! each as a pair of anchors, so that they stay on the same text as the
+//! document is edited. A closed fold hides every line after its first one, and
+//! the view draws its first line as a summary of the fold. The lines of the
+//! closed folds are worked out again whenever the document or the folds change,
+//! and folds whose lines were deleted go away then.
+
+use std::ops::RangeInclusive;
+
+use be_doc::{Anchor, Gravity, Line};
+use be_input::{ChangeDirection, FoldOp};
+
+use crate::{EditorState, IndentLevel, status::Status};
+
+/// A closed fold, from the start of its first line to the end of its last one.
+#[derive(Clone, Copy)]
+struct Fold {
+  start: Anchor,
+  end:   Anchor,
+}
+
+#[derive(Default)]
+pub(crate) struct Folds {
+  folds:  Vec<Fold>,
+  /// The lines of each fold in `folds`, sorted by their first line.
+  closed: Vec<RangeInclusive<Line>>,
+  /// The closed folds that aren't inside another closed fold.
+  outer:  Vec<RangeInclusive<Line>>,
+}
+
+impl EditorState {
+  /// The lines of every fold that can be closed, sorted by their first line.
+  fn fold_ranges(&self) -> Vec<RangeInclusive<Line>> {
+    let mut ranges = match self.highligher.as_ref().and_then(|h| h.fold_ranges(&self.doc)) {
+      Some(ranges) => ranges,
+      None => self.indent_fold_ranges(),
+    };
+
+    ranges.sort_by_key(|r| (*r.start(), std::cmp::Reverse(*r.end())));
+    ranges.dedup();
+    ranges
+  }
+
+  /// Each line followed by more indented lines starts a fold, which ends at the
+  /// last of those lines.
+  fn indent_fold_ranges(&self) -> Vec<RangeInclusive<Line>> {
+    let config = self.config.borrow();
+
+    let mut ranges = vec![];
+    // The lines that may start a fold, with their indent, innermost last.
+    let mut open: Vec<(Line, usize)> = vec![];
+    let mut last = Line(0);
+    for i in 0..self.doc.len_lines() {
+      let text = self.doc.line(Line(i));
+      if text.chars().all(char::is_whitespace) {
+        continue;
+      }
+
+      let level = IndentLevel::guess(&config.settings.editor, text).0;
+      while let Some(&(start, start_level)) = open.last()
+        && start_level >= level
+      {
+        open.pop();
+        if last > start {
+          ranges.push(start..=last);
+        }
+      }
+
+      open.push((Line(i), level));
+      last = Line(i);
+    }
+
+    for (start, _) in open {
+      if last > start {
+        ranges.push(start..=last);
+      }
+    }
+
+    ranges
+  }
+
+  /// The lines of every closed fold, sorted by their first line.
+  pub fn closed_folds(&self) -> &[RangeInclusive<Line>] { &self.folds.closed }
+
+  /// The closed folds that aren't inside another closed fold. These are the
+  /// folds that are actually visible.
+  pub(crate) fn outer_closed_folds(&self) -> &[RangeInclusive<Line>] { &self.folds.outer }
+
+  /// Works out the lines of the closed folds again, after the document or the
+  /// folds change. A fold whose lines were deleted, so that it's down to a
+  /// single line, is removed.
+  pub(crate) fn update_folds(&mut self) {
+    let mut closed = vec![];
+    let mut i = 0;
+    while let Some(&fold) = self.folds.folds.get(i) {
+      match self.fold_lines(fold) {
+        Some(lines) => {
+          closed.push(lines);
+          i += 1;
+        }
+        None => {
+          self.folds.folds.remove(i);
+          self.doc.remove_anchor(fold.start);
+          self.doc.remove_anchor(fold.end);
+        }
+      }
+    }
+    closed.sort_by_key(|f| *f.start());
+
+    let mut outer: Vec<RangeInclusive<Line>> = vec![];
+    for fold in &closed {
+      match outer.last_mut() {
+        Some(last) if fold.start() <= last.end() => {
+          if fold.end() > last.end() {
+            *last = *last.start()..=*fold.end();
+          }
+        }
+        _ => outer.push(fold.clone()),
+      }
+    }
+
+    // The lines after the first fold that was opened or closed moved to other
+    // screen rows.
+    let old = &self.folds.outer;
+    let i = old.iter().zip(&outer).position(|(a, b)| a != b).unwrap_or(old.len().min(outer.len()));
+    if let Some(line) = [old.get(i), outer.get(i)].into_iter().flatten().map(|f| f.start().0).min()
+    {
+      self.wrap_cache.get_mut().forget_starts_after(line);
+    }
+
+    self.folds.closed = closed;
+    self.folds.outer = outer;
+  }
+
+  fn fold_lines(&self, fold: Fold) -> Option<RangeInclusive<Line>> {
+    let start = self.doc.line_of_byte(self.doc.anchor_offset(fold.start)?);
+    let end = self.doc.line_of_byte(self.doc.anchor_offset(fold.end)?);
+    (end > start).then_some(start..=end)
+  }
+
+  /// Returns the outer closed fold that starts at or before `line`.
+  fn outer_fold_before(&self, line: Line) -> Option<&RangeInclusive<Line>> {
+    let i = self.folds.outer.partition_point(|f| *f.start() <= line);
+    self.folds.outer[..i].last()
+  }
+
+  /// Returns the last line of the closed fold shown on `line`, if there is one.
+  pub fn closed_fold_at(&self, line: Line) -> Option<Line> {
+    self.outer_fold_before(line).filter(|f| *f.start() == line).map(|f| *f.end())
+  }
+
+  /// Returns the line that `line` is shown on, which is the first line of the
+  /// closed fold hiding it, if there is one.
+  pub fn shown_line(&self, line: Line) -> Line {
+    self.outer_fold_before(line).filter(|f| f.contains(&line)).map_or(line, |f| *f.start())
+  }
+
+  /// Returns the last of `count` lines starting at `line`, where a closed fold
+  /// counts as a single line, so that `dd` on a closed fold deletes all of it.
+  pub(crate) fn last_of_lines(&self, line: Line, count: u32) -> Line {
+    let last = self.line_of_fold_row(self.fold_row_of_line(line) + count as usize - 1);
+    self.closed_fold_at(last).unwrap_or(last)
+  }
+
+  /// Returns the row that `line` is shown on, ignoring soft wrap, where each
+  /// closed fold takes up a single row. Lines hidden in a fold are on the row
+  /// of the fold.
+  pub(crate) fn fold_row_of_line(&self, line: Line) -> usize {
+    let mut row = line.0;
+    for fold in self.outer_closed_folds() {
+      if *fold.start() >= line {
+        break;
+      }
+      row -= line.min(*fold.end()).0 - fold.start().0;
+    }
+    row
+  }
+
+  /// Returns the line shown on `row`. This is the inverse of
+  /// `fold_row_of_line`.
+  pub(crate) fn line_of_fold_row(&self, row: usize) -> Line {
+    let mut line = row;
+    for fold in self.outer_closed_folds() {
+      if fold.start().0 >= line {
+        break;
+      }
+      line += fold.end().0 - fold.start().0;
+    }
+    Line(line)
+  }
+
+  /// Moves up or down `dist` lines, counting each closed fold as one line.
+  pub(crate) fn move_rows(&mut self, dist: i32) {
+    let row = (self.fold_row_of_line(self.cursor.line) as i64 + i64::from(dist)).max(0);
+    self.move_to_line(self.line_of_fold_row(row as usize));
+  }
+
+  pub(crate) fn perform_fold(&mut self, op: FoldOp) {
+    let line = self.cursor.line;
+    let found = match op {
+      FoldOp::Close => self.close_fold(line),
+      FoldOp::Open => self.open_fold(line),
+      FoldOp::Toggle => self.open_fold(line) || self.close_fold(line),
+      FoldOp::OpenAll => {
+        self.clear_folds();
+        true
+      }
+      FoldOp::CloseAll => {
+        self.clear_folds();
+        for range in self.fold_ranges() {
+          self.add_fold(range);
+        }
+        true
+      }
+    };
+
+    if !found {
+      self.status = Some(Status::for_error_message("no fold found"));
+    }
+
+    // The cursor stays on the first line of the fold it was in.
+    self.move_to_line(self.shown_line(self.cursor.line));
+    self.damage_all = true;
+  }
+
+  /// Closes the innermost open fold around `line`. Inside a closed fold, this
+  /// is a fold around it.
+  fn close_fold(&mut self, line: Line) -> bool {
+    let closed = self.closed_folds().to_vec();
+    let around = closed.iter().filter(|f| f.contains(&line)).collect::<Vec<_>>();
+
+    let fold = self
+      .fold_ranges()
+      .into_iter()
+      .filter(|range| {
+        range.contains(&line)
+          && around.iter().all(|f| range.start() <= f.start() && f.end() <= range.end())
+          && !closed.contains(range)
+      })
+      .min_by_key(|range| range.end().0 - range.start().0);
+
+    match fold {
+      Some(range) => {
+        self.add_fold(range);
+        true
+      }
+      None => false,
+    }
+  }
+
+  /// Opens the outermost closed fold around `line`.
+  fn open_fold(&mut self, line: Line) -> bool {
+    let outer = self
+      .folds
+      .folds
+      .iter()
+      .enumerate()
+      .filter_map(|(i, &fold)| Some((i, self.fold_lines(fold)?)))
+      .filter(|(_, f)| f.contains(&line))
+      .max_by_key(|(_, f)| f.end().0 - f.start().0);
+
+    let Some((i, _)) = outer else { return false };
+
+    let fold = self.folds.folds.remove(i);
+    self.doc.remove_anchor(fold.start);
+    self.doc.remove_anchor(fold.end);
+    self.update_folds();
+    true
+  }
+
+  fn add_fold(&mut self, range: RangeInclusive<Line>) {
+    // Lines inserted right above or right below the fold stay outside of it.
+    let start = self.doc.anchor(self.doc.byte_of_line(*range.start()), Gravity::Right);
+    let end = self.doc.anchor(self.doc.byte_of_line_end(*range.end()), Gravity::Left);
+    self.folds.folds.push(Fold { start, end });
+    self.update_folds();
+  }
+
+  pub(crate) fn clear_folds(&mut self) {
+    for fold in std::mem::take(&mut self.folds.folds) {
+      self.doc.remove_anchor(fold.start);
+      self.doc.remove_anchor(fold.end);
+    }
+    self.update_folds();
+  }
+
+  /// Opens the folds that hide the cursor, after it moves into one.
+  pub(crate) fn open_folds_at_cursor(&mut self) {
+    let line = self.cursor.line;
+    while self.shown_line(line) != line && self.open_fold(line) {
+      self.damage_all = true;
+    }
+  }
+
+  /// Moves to the start of the next fold, or the end of the previous one, as
+  /// with `zj` and `zk`.
+  pub(crate) fn move_to_fold(&mut self, dir: ChangeDirection, count: u32) {
+    let ranges = self.fold_ranges();
+    for _ in 0..count {
+      let line = self.cursor.line;
+      let target = match dir {
+        ChangeDirection::Next => ranges.iter().map(|r| *r.start()).filter(|&s| s > line).min(),
+        ChangeDirection::Prev => ranges.iter().map(|r| *r.end()).filter(|&e| e < line).max(),
+      };
+
+      match target {
+        Some(target) => self.move_to_line(target),
+        None => break,
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use be_doc::Line;
+
+  use crate::tests::*;
+
+  const SRC: &str = "fn foo() {\n  if a {\n    b\n  }\n}\nbar\n";
+
+  #[test]
+  fn close_and_open() {
+    let mut editor = editor(SRC);
+    editor.keys("jjzc");
+    assert_eq!(editor.closed_folds(), [Line(1)..=Line(2)]);
+    assert_eq!(editor.cursor.line, Line(1));
+
+    // Closing again closes the fold around it.
+    editor.keys("zc");
+    assert_eq!(editor.closed_folds(), [Line(0)..=Line(3), Line(1)..=Line(2)]);
+    assert_eq!(editor.cursor.line, Line(0));
+
+    editor.keys("zo");
+    assert_eq!(editor.closed_folds(), [Line(1)..=Line(2)]);
+    editor.keys("za");
+    assert_eq!(editor.closed_folds(), [Line(0)..=Line(3), Line(1)..=Line(2)]);
+    editor.keys("zR");
+    assert!(editor.closed_folds().is_empty());
+    editor.keys("zM");
+    assert_eq!(editor.closed_folds(), [Line(0)..=Line(3), Line(1)..=Line(2)]);
+  }
+
+  #[test]
+  fn rows() {
+    let mut editor = editor(SRC);
+    editor.keys("jzc");
+    assert_eq!(editor.row_of_line(Line(1)), 1);
+    assert_eq!(editor.row_of_line(Line(2)), 1);
+    assert_eq!(editor.row_of_line(Line(3)), 2);
+    assert_eq!(editor.line_of_row(2), Line(3));
+    assert_eq!(editor.closed_fold_at(Line(1)), Some(Line(2)));
+
+    // `j` and `k` skip over the fold.
+    editor.keys("j");
+    assert_eq!(editor.cursor.line, Line(3));
+    editor.keys("kk");
+    assert_eq!(editor.cursor.line, Line(0));
+
+    // Moving into the fold opens it.
+    editor.keys("/b<CR>n");
+    assert_eq!(editor.cursor.line, Line(2));
+    assert!(editor.closed_folds().is_empty());
+  }
+
+  #[test]
+  fn edits() {
+    let mut editor = editor(SRC);
+    editor.keys("jzcggOzero<Esc>");
+    assert_eq!(editor.closed_folds(), [Line(2)..=Line(3)]);
+
+    editor.keys("jjdd");
+    assert!(editor.closed_folds().is_empty());
+    // The fold is removed along with its lines.
+    assert!(editor.folds.folds.is_empty());
+  }
+
+  #[test]
+  fn delete_closed() {
+    let mut editor = editor(SRC);
+    editor.keys("jzcdd");
+    editor.check(expect![@"fn foo() {\n⟦ ⟧ }\n}\nbar\n"]);
+    assert!(editor.closed_folds().is_empty());
+
+    editor.keys("uzcyyP");
+    assert_eq!(
+      editor.doc.to_string(),
+      "fn foo() {\n  if a {\n    b\n  if a {\n    b\n  }\n}\nbar\n"
+    );
+  }
+
+  #[test]
+  fn moves() {
+    let mut editor = editor(SRC);
+    editor.keys("zj");
+    assert_eq!(editor.cursor.line, Line(1));
+    editor.keys("Gzk");
+    assert_eq!(editor.cursor.line, Line(3));
+  }
+}
@@ -1,31 +1,56 @@
 use std::{
-  io,
-  os::unix::fs::MetadataExt,
+  ffi::{CString, c_char},
+  fmt::Write as _,
+  fs::{File, OpenOptions},
+  io::{self, Write},
+  os::unix::{
+    ffi::OsStrExt,
+    fs::{MetadataExt, OpenOptionsExt},
+    io::AsRawFd,
+  },
   path::{Path, PathBuf},
+  sync::Arc,
+  time::SystemTime,
 };
 
-use be_doc::Document;
+use be_doc::{Document, FileEncoding};
 
-use crate::EditorState;
+use crate::{EditorState, hex, large, status::Status};
+
+/// The error for commands that would throw away unsaved changes without `!`.
+pub(crate) const UNSAVED_CHANGES: &str = "no write since last change (add ! to override)";
+
+/// How many names to try for the temporary file a save is written to.
+const MAX_TEMP_ATTEMPTS: usize = 100;
 
 pub struct OpenedFile {
-  path:  PathBuf,
-  mtime: i64,
+  path:              PathBuf,
+  mtime:             SystemTime,
+  encoding:          FileEncoding,
+  /// Binary files are edited as hex, and written back byte for byte.
+  pub(crate) binary: bool,
+  /// Large files are read in the background, and skip highlighting, LSP, and
+  /// git diffs.
+  pub(crate) large:  bool,
+  pub(crate) loader: Option<large::Loader>,
 
-  /// This is a little dumb, but: history is built in reverse on the editor. So,
-  /// while editting, the history says at zero, and undoing increases it. We
-  /// want the opposite behavior: we want a stable index into history.
-  ///
-  /// So, to index into real editor history, use `history.len() -
-  /// saved_history_position`.
-  pub(crate) saved_history_position: usize,
+  /// The undo node that was last written to disk.
+  pub(crate) saved_undo_node: usize,
 
   /// Set if the file was modified underneath the editor session.
   modified: bool,
 }
 
 impl EditorState {
-  pub fn open(&mut self, path: &Path) -> io::Result<()> {
+  pub fn open(&mut self, path: &Path) -> io::Result<()> { self.open_with_encoding(path, None) }
+
+  /// Opens a file in the given encoding, or detects the encoding if it's
+  /// `None`.
+  pub fn open_with_encoding(
+    &mut self,
+    path: &Path,
+    encoding: Option<FileEncoding>,
+  ) -> io::Result<()> {
     let canon = path.canonicalize()?;
 
     if let Some(current) = &self.file
@@ -34,27 +59,73 @@ impl EditorState {
       // TODO: Confirm save
     }
 
-    let (file, doc) = OpenedFile::open(&canon)?;
+    let large_file_size = self.config.borrow().settings.editor.large_file_size;
+    let (file, doc) = OpenedFile::open(&canon, encoding, large_file_size, self.waker.clone())?;
     self.file = Some(file);
     self.doc = doc;
+    self.wrap_cache = Default::default();
+    // The anchors of the folds, cursors, and diagnostics went away with the old
+    // document.
+    self.folds = Default::default();
+    self.cursors.clear();
+    self.lsp.diagnostics.clear();
+    self.lsp.goto_definition = None;
+    self.line_ending = self.doc.detect_line_ending();
     self.damage_all = true;
 
+    self.load_undo();
+    self.load_marks();
+    self.file.as_mut().unwrap().saved_undo_node = self.undo.current();
+
     self.on_open_file();
 
     Ok(())
   }
 
-  pub fn save(&mut self) -> io::Result<()> {
-    if let Some(file) = &mut self.file {
-      file.save(&self.doc)?;
-      file.saved_history_position = self.history.len() - self.history_position;
+  /// Reads the current file again in the encoding named `label`, as with
+  /// `:e ++enc=latin1`. Unless `force` is set, this refuses to throw away
+  /// unsaved changes.
+  pub fn reopen_with_encoding(&mut self, label: &str, force: bool) {
+    if self.unsaved() && !force {
+      self.status = Some(Status::for_error_message(UNSAVED_CHANGES));
+      return;
+    }
+    let Some(encoding) = FileEncoding::from_label(label) else {
+      self.status = Some(Status::for_error_message(format!("unknown encoding: {label}")));
+      return;
+    };
+    let Some(path) = self.file.as_ref().map(|f| f.path.clone()) else {
+      self.status = Some(Status::for_error_message("no file open"));
+      return;
+    };
+
+    if let Err(e) = self.open_with_encoding(&path, Some(encoding)) {
+      self.status = Some(Status::for_error(e));
+    }
+  }
+
+  /// Writes the document to disk. Unless `force` is set, this fails if the
+  /// file was changed on disk since it was opened. Returns `true` if the file
+  /// had to be overwritten in place, rather than replaced atomically.
+  pub fn save(&mut self, force: bool) -> io::Result<bool> {
+    let in_place = if let Some(file) = &mut self.file {
+      let in_place = file.save(&self.doc, force)?;
+      file.saved_undo_node = self.undo.current();
+      in_place
     } else {
       return Err(io::Error::new(io::ErrorKind::NotFound, "no file open"));
+    };
+
+    if let Err(e) = self.save_undo() {
+      warn!("failed to save undo history: {e}");
+    }
+    if let Err(e) = self.save_marks() {
+      warn!("failed to save marks: {e}");
     }
 
     self.lsp_notify_did_save();
 
-    Ok(())
+    Ok(in_place)
   }
 
   pub fn on_file_changed(&mut self) {
@@ -64,41 +135,449 @@ impl EditorState {
         file.modified = true;
       } else {
         let p = file.path().to_path_buf();
-        self.open(&p).unwrap();
+        let encoding = file.encoding;
+        self.open_with_encoding(&p, Some(encoding)).unwrap();
       }
     }
   }
 
   pub fn modified(&self) -> bool { self.file.as_ref().is_some_and(|f| f.modified) }
+  pub fn encoding(&self) -> Option<FileEncoding> { self.file.as_ref().map(|f| f.encoding) }
 }
 
 impl OpenedFile {
   pub fn path(&self) -> &Path { &self.path }
 
-  pub fn open(path: &Path) -> io::Result<(OpenedFile, Document)> {
+  /// Opens the file at `path`. Files larger than `large_file_size` bytes are
+  /// only partially read, and the rest is loaded in the background, calling
+  /// `waker` as it arrives.
+  pub fn open(
+    path: &Path,
+    encoding: Option<FileEncoding>,
+    large_file_size: u64,
+    waker: Option<Arc<dyn Fn() + Send + Sync>>,
+  ) -> io::Result<(OpenedFile, Document)> {
     let path = path.canonicalize()?;
     let stat = path.metadata()?;
 
-    let doc = Document::read(&path)?;
-    let file = OpenedFile { path, mtime: stat.mtime(), saved_history_position: 0, modified: false };
+    if stat.len() > large_file_size {
+      let (doc, encoding, loader) = large::Loader::start(File::open(&path)?, encoding, waker)?;
+      let file = OpenedFile {
+        path,
+        mtime: stat.modified()?,
+        encoding: encoding.unwrap_or(FileEncoding::UTF_8),
+        binary: encoding.is_none(),
+        large: true,
+        loader: Some(loader),
+        saved_undo_node: 0,
+        modified: false,
+      };
+
+      return Ok((file, doc));
+    }
+
+    let bytes = std::fs::read(&path)?;
+    let binary = encoding.is_none() && hex::is_binary(&bytes);
+    let (doc, encoding) = if binary {
+      (Document::from(hex::encode(&bytes).as_str()), FileEncoding::UTF_8)
+    } else {
+      Document::decode(&bytes, encoding)
+    };
+
+    let file = OpenedFile {
+      path,
+      mtime: stat.modified()?,
+      encoding,
+      binary,
+      large: false,
+      loader: None,
+      saved_undo_node: 0,
+      modified: false,
+    };
 
     Ok((file, doc))
   }
 
-  pub fn save(&mut self, doc: &Document) -> io::Result<()> {
-    let stat = self.path.metadata()?;
-    if stat.mtime() > self.mtime {
-      // TODO: Confirm popup
-      // return Err(io::Error::new(io::ErrorKind::Other, "file has been modified"));
-      warn!("file has been modified");
+  /// Saves the document atomically: it is written to a temporary file next to
+  /// the target, synced, and then renamed over the target. The target keeps
+  /// its permissions, owner, and extended attributes. Returns `true` if the
+  /// directory isn't writable, and so the target was overwritten in place.
+  pub fn save(&mut self, doc: &Document, force: bool) -> io::Result<bool> {
+    // Follow symlinks, so that the link is kept and its target is replaced.
+    let target = match self.path.canonicalize() {
+      Ok(p) => p,
+      Err(e) if e.kind() == io::ErrorKind::NotFound => self.path.clone(),
+      Err(e) => return Err(e),
+    };
+
+    let stat = match target.metadata() {
+      Ok(stat) => Some(stat),
+      Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+      Err(e) => return Err(e),
+    };
+
+    if self.loader.is_some() {
+      return Err(io::Error::other(format!("{}: file is still loading", self.path.display())));
     }
 
-    let mut file = std::fs::OpenOptions::new().write(true).truncate(true).open(&self.path)?;
-    doc.write(&mut file)?;
-    drop(file);
+    if !force
+      && let Some(stat) = &stat
+      && (self.modified || stat.modified()? != self.mtime)
+    {
+      return Err(io::Error::other(format!(
+        "{}: file changed on disk since it was opened (add ! to overwrite)",
+        self.path.display()
+      )));
+    }
 
-    let stat = self.path.metadata()?;
-    self.mtime = stat.mtime();
-    Ok(())
+    // Encode before touching the file, so that a character the encoding can't
+    // represent fails the save without writing anything.
+    let encoded = if self.binary { Some(hex::decode(doc)?) } else { self.encoding.encode(doc)? };
+
+    let mode = stat.as_ref().map_or(0o666, |s| s.mode() & 0o7777);
+    let in_place = match create_temp(&target, mode) {
+      Ok((temp, file)) => {
+        if let Err(e) = write_temp(file, &target, stat.as_ref(), doc, encoded.as_deref())
+          .and_then(|()| std::fs::rename(&temp, &target))
+        {
+          let _ = std::fs::remove_file(&temp);
+          return Err(e);
+        }
+
+        // Sync the directory, so that the rename itself is durable.
+        if let Some(dir) = target.parent()
+          && let Err(e) = File::open(dir).and_then(|d| d.sync_all())
+        {
+          warn!("failed to sync {}: {e}", dir.display());
+        }
+        false
+      }
+
+      // If we can't create files next to the target, fall back to writing it in
+      // place.
+      Err(e) if e.kind() == io::ErrorKind::PermissionDenied && stat.is_some() => {
+        warn!("cannot create a file next to {}, writing in place: {e}", target.display());
+        let mut file = OpenOptions::new().write(true).truncate(true).open(&target)?;
+        write_doc(&mut file, doc, encoded.as_deref())?;
+        file.sync_all()?;
+        true
+      }
+      Err(e) => return Err(e),
+    };
+
+    self.mtime = target.metadata()?.modified()?;
+    self.modified = false;
+    Ok(in_place)
+  }
+}
+
+/// The file in the `dir` cache directory that stores data for `path`, such as
+/// its undo history. Like vim's `undodir`, it is named after the full path,
+/// but `/` and `%` are escaped as `%2F` and `%25` (and invalid UTF-8 is
+/// escaped as well), so that no two paths share a file.
+pub(crate) fn cache_path(dir: &str, path: &Path) -> io::Result<PathBuf> {
+  Ok(be_config::cache_root()?.join(dir).join(cache_name(path)))
+}
+
+fn cache_name(path: &Path) -> String {
+  let mut name = String::new();
+  for chunk in path.as_os_str().as_bytes().utf8_chunks() {
+    for c in chunk.valid().chars() {
+      match c {
+        '/' => name.push_str("%2F"),
+        '%' => name.push_str("%25"),
+        c => name.push(c),
+      }
+    }
+    for byte in chunk.invalid() {
+      write!(name, "%{byte:02X}").unwrap();
+    }
+  }
+  name
+}
+
+/// Creates the temporary file that `target` is saved through. A crash can leave
+/// a temporary file behind, and the next process to get the same pid would
+/// fail to create it again, so this tries a few names.
+fn create_temp(target: &Path, mode: u32) -> io::Result<(PathBuf, File)> {
+  let name = target
+    .file_name()
+    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid file name"))?
+    .to_string_lossy();
+
+  let mut attempt = 0;
+  loop {
+    let temp = match attempt {
+      0 => target.with_file_name(format!(".{name}.{}.tmp", std::process::id())),
+      n => target.with_file_name(format!(".{name}.{}.{n}.tmp", std::process::id())),
+    };
+
+    match OpenOptions::new().write(true).create_new(true).mode(mode).open(&temp) {
+      Ok(file) => return Ok((temp, file)),
+      Err(e) if e.kind() == io::ErrorKind::AlreadyExists && attempt < MAX_TEMP_ATTEMPTS => {
+        attempt += 1;
+      }
+      Err(e) => return Err(e),
+    }
+  }
+}
+
+/// Writes `doc` to the temporary `file`, copies the metadata of `target` (if
+/// it exists) onto it, and syncs it to disk.
+fn write_temp(
+  mut file: File,
+  target: &Path,
+  stat: Option<&std::fs::Metadata>,
+  doc: &Document,
+  encoded: Option<&[u8]>,
+) -> io::Result<()> {
+  write_doc(&mut file, doc, encoded)?;
+
+  if let Some(stat) = stat {
+    // Changing the owner only works for root (or when the owner is us already),
+    // and otherwise the file just ends up owned by us.
+    if let Err(e) = std::os::unix::fs::fchown(&file, Some(stat.uid()), Some(stat.gid())) {
+      warn!("failed to keep owner of {}: {e}", target.display());
+    }
+    // `chown` can clear the setuid bits, so set permissions after.
+    file.set_permissions(stat.permissions())?;
+    copy_xattrs(target, &file);
+  }
+
+  file.sync_all()
+}
+
+/// Writes the `encoded` bytes of `doc`, or the document itself if it didn't
+/// need encoding.
+fn write_doc(file: &mut File, doc: &Document, encoded: Option<&[u8]>) -> io::Result<()> {
+  match encoded {
+    Some(bytes) => file.write_all(bytes),
+    None => doc.write(file),
+  }
+}
+
+/// Copies the extended attributes of `from` onto `to`. This is best-effort:
+/// filesystems without xattrs, or attributes we may not set, are skipped.
+fn copy_xattrs(from: &Path, to: &File) {
+  let Ok(path) = CString::new(from.as_os_str().as_bytes()) else { return };
+
+  let Some(names) = read_xattr(|buf, len| unsafe { sys::listxattr(path.as_ptr(), buf, len) })
+  else {
+    return;
+  };
+
+  for name in names.split(|&b| b == 0).filter(|n| !n.is_empty()) {
+    let Ok(name) = CString::new(name) else { continue };
+    let Some(value) =
+      read_xattr(|buf, len| unsafe { sys::getxattr(path.as_ptr(), name.as_ptr(), buf, len) })
+    else {
+      continue;
+    };
+
+    let res = unsafe { sys::fsetxattr(to.as_raw_fd(), name.as_ptr(), &value) };
+    if res != 0 {
+      warn!("failed to copy xattr {name:?}: {}", io::Error::last_os_error());
+    }
+  }
+}
+
+/// Calls `f` once to get the size of a value, and then again to read it.
+fn read_xattr(f: impl Fn(*mut c_char, usize) -> isize) -> Option<Vec<u8>> {
+  let len = f(std::ptr::null_mut(), 0);
+  if len < 0 {
+    return None;
+  }
+
+  let mut buf = vec![0; len as usize];
+  let len = f(buf.as_mut_ptr().cast(), buf.len());
+  if len < 0 {
+    return None;
+  }
+  buf.truncate(len as usize);
+  Some(buf)
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+  use std::ffi::c_char;
+
+  pub unsafe fn listxattr(path: *const c_char, buf: *mut c_char, len: usize) -> isize {
+    unsafe { libc::listxattr(path, buf, len) }
+  }
+
+  pub unsafe fn getxattr(
+    path: *const c_char,
+    name: *const c_char,
+    buf: *mut c_char,
+    len: usize,
+  ) -> isize {
+    unsafe { libc::getxattr(path, name, buf.cast(), len) }
+  }
+
+  pub unsafe fn fsetxattr(fd: i32, name: *const c_char, value: &[u8]) -> i32 {
+    unsafe { libc::fsetxattr(fd, name, value.as_ptr().cast(), value.len(), 0) }
+  }
+}
+
+#[cfg(target_os = "macos")]
+mod sys {
+  use std::ffi::c_char;
+
+  pub unsafe fn listxattr(path: *const c_char, buf: *mut c_char, len: usize) -> isize {
+    unsafe { libc::listxattr(path, buf, len, 0) }
+  }
+
+  pub unsafe fn getxattr(
+    path: *const c_char,
+    name: *const c_char,
+    buf: *mut c_char,
+    len: usize,
+  ) -> isize {
+    unsafe { libc::getxattr(path, name, buf.cast(), len, 0, 0) }
+  }
+
+  pub unsafe fn fsetxattr(fd: i32, name: *const c_char, value: &[u8]) -> i32 {
+    unsafe { libc::fsetxattr(fd, name, value.as_ptr().cast(), value.len(), 0, 0) }
+  }
+}
+
+/// Other platforms don't have extended attributes to copy.
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+mod sys {
+  use std::ffi::c_char;
+
+  pub unsafe fn listxattr(_: *const c_char, _: *mut c_char, _: usize) -> isize { -1 }
+
+  pub unsafe fn getxattr(_: *const c_char, _: *const c_char, _: *mut c_char, _: usize) -> isize {
+    -1
+  }
+
+  pub unsafe fn fsetxattr(_: i32, _: *const c_char, _: &[u8]) -> i32 { -1 }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    ffi::OsStr,
+    os::unix::fs::PermissionsExt,
+    time::{Duration, UNIX_EPOCH},
+  };
+
+  use super::*;
+  use crate::tests::TempDir;
+
+  #[test]
+  fn save_through_symlink() {
+    let dir = TempDir::new("save-symlink");
+    std::fs::write(dir.join("real"), "foo\n").unwrap();
+    std::fs::set_permissions(dir.join("real"), std::fs::Permissions::from_mode(0o640)).unwrap();
+    std::os::unix::fs::symlink(dir.join("real"), dir.join("link")).unwrap();
+
+    let (mut file, _) = OpenedFile::open(&dir.join("link"), None, u64::MAX, None).unwrap();
+    file.save(&Document::from("bar\n"), false).unwrap();
+
+    assert!(dir.join("link").symlink_metadata().unwrap().is_symlink());
+    assert_eq!(std::fs::read_to_string(dir.join("link")).unwrap(), "bar\n");
+    assert_eq!(dir.join("real").metadata().unwrap().mode() & 0o777, 0o640);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+  }
+
+  #[test]
+  fn save_conflict() {
+    let dir = TempDir::new("save-conflict");
+    std::fs::write(dir.join("file"), "foo\n").unwrap();
+
+    let (mut file, _) = OpenedFile::open(&dir.join("file"), None, u64::MAX, None).unwrap();
+    std::fs::write(dir.join("file"), "baz\n").unwrap();
+    File::options()
+      .write(true)
+      .open(dir.join("file"))
+      .unwrap()
+      .set_modified(UNIX_EPOCH + Duration::from_secs(1000))
+      .unwrap();
+
+    let err = file.save(&Document::from("bar\n"), false).unwrap_err();
+    assert!(err.to_string().contains("file changed on disk"));
+    assert_eq!(std::fs::read_to_string(dir.join("file")).unwrap(), "baz\n");
+
+    file.save(&Document::from("bar\n"), true).unwrap();
+    assert_eq!(std::fs::read_to_string(dir.join("file")).unwrap(), "bar\n");
+    file.save(&Document::from("qux\n"), false).unwrap();
+  }
+
+  #[test]
+  fn save_with_stale_temp_file() {
+    let dir = TempDir::new("save-stale");
+    std::fs::write(dir.join("file"), "foo\n").unwrap();
+    let stale = dir.join(format!(".file.{}.tmp", std::process::id()));
+    std::fs::write(&stale, "stale\n").unwrap();
+
+    let (mut file, _) = OpenedFile::open(&dir.join("file"), None, u64::MAX, None).unwrap();
+    file.save(&Document::from("bar\n"), false).unwrap();
+    assert_eq!(std::fs::read_to_string(dir.join("file")).unwrap(), "bar\n");
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+  }
+
+  #[test]
+  fn save_encoding() {
+    let dir = TempDir::new("save-encoding");
+    std::fs::write(dir.join("file"), b"caf\xe9\n").unwrap();
+
+    let (mut file, doc) = OpenedFile::open(&dir.join("file"), None, u64::MAX, None).unwrap();
+    assert_eq!(doc.to_string(), "caf\u{e9}\n");
+    assert_eq!(file.encoding.name(), "windows-1252");
+
+    file.save(&Document::from("caf\u{e9}s\n"), false).unwrap();
+    assert_eq!(std::fs::read(dir.join("file")).unwrap(), b"caf\xe9s\n");
+
+    // Characters that can't be encoded fail the save, and the file is left alone.
+    let err = file.save(&Document::from("caf\u{2603}\n"), false).unwrap_err();
+    assert_eq!(err.to_string(), "cannot write '\u{2603}' on line 1 as windows-1252");
+    assert_eq!(std::fs::read(dir.join("file")).unwrap(), b"caf\xe9s\n");
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+  }
+
+  #[test]
+  fn cache_names() {
+    assert_eq!(cache_name(Path::new("/a/b%c")), "%2Fa%2Fb%25c");
+    assert_ne!(cache_name(Path::new("/a/b")), cache_name(Path::new("/a%b")));
+    assert_eq!(cache_name(Path::new(OsStr::from_bytes(b"/caf\xe9"))), "%2Fcaf%E9");
+  }
+
+  #[test]
+  fn open_utf16_with_bom() {
+    let dir = TempDir::new("open-utf16");
+    std::fs::write(dir.join("file"), b"\xff\xfeh\0i\0\n\0").unwrap();
+
+    let (file, doc) = OpenedFile::open(&dir.join("file"), None, u64::MAX, None).unwrap();
+    assert!(!file.binary);
+    assert_eq!(file.encoding.name(), "UTF-16LE");
+    assert_eq!(doc.to_string(), "hi\n");
+  }
+
+  #[test]
+  fn open_utf16_without_bom() {
+    let dir = TempDir::new("open-utf16-no-bom");
+    std::fs::write(dir.join("file"), b"h\0i\0\n\0").unwrap();
+
+    let (file, doc) = OpenedFile::open(&dir.join("file"), None, u64::MAX, None).unwrap();
+    assert!(!file.binary);
+    assert_eq!(file.encoding.name(), "UTF-16LE");
+    assert_eq!(doc.to_string(), "hi\n");
+  }
+
+  #[test]
+  fn reopen_with_unsaved_changes() {
+    let dir = TempDir::new("reopen-unsaved");
+    std::fs::write(dir.join("file"), b"caf\xc3\xa9\n").unwrap();
+
+    let mut editor = crate::tests::editor("");
+    editor.open(&dir.join("file")).unwrap();
+    editor.keys("x:e ++enc=latin1<CR>");
+    assert_eq!(editor.status.as_ref().unwrap().message, UNSAVED_CHANGES);
+    assert_eq!(editor.doc.to_string(), "af\u{e9}\n");
+
+    editor.keys(":e! ++enc=latin1<CR>");
+    assert_eq!(editor.doc.to_string(), "caf\u{c3}\u{a9}\n");
   }
 }
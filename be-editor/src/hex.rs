@@ -0,0 +1,307 @@
+//! Editing for binary files. The document of a binary file holds two hex
+//! digits for each byte, with no separators, so every byte edit is a normal
+//! [`Change`] and goes through the usual undo history. The cursor column is
+//! the index of the nibble under the cursor.
+
+use std::{fmt::Write, io};
+
+use be_doc::{Change, Column, Document, Edit, FileEncoding};
+use be_input::{Action, Direction, Key, KeyStroke, Mode, Move};
+
+use crate::EditorState;
+
+/// The number of bytes shown on each row of the hex view.
+pub const HEX_ROW_LEN: usize = 16;
+
+/// Binary files contain NUL bytes, or are mostly invalid UTF-8. Only the start
+/// of the file is checked. The encoding is detected first, as UTF-16 is full
+/// of NUL bytes. Files with a byte order mark are always text, and a guess of
+/// UTF-16 without one is only trusted if it decodes to text.
+pub(crate) fn is_binary(bytes: &[u8]) -> bool {
+  let sample = &bytes[..bytes.len().min(8192)];
+  let encoding = FileEncoding::detect(sample);
+  if encoding.has_bom() {
+    return false;
+  }
+  if encoding.is_utf16() {
+    return encoding.decode(sample).chars().any(|c| c.is_control() && !c.is_whitespace());
+  }
+
+  if sample.contains(&0) {
+    return true;
+  }
+
+  let mut invalid = 0;
+  let mut rest = sample;
+  while let Err(e) = str::from_utf8(rest) {
+    // The sample can cut a character in half at the end.
+    let Some(len) = e.error_len() else { break };
+    invalid += len;
+    rest = &rest[e.valid_up_to() + len..];
+  }
+
+  invalid * 3 > sample.len()
+}
+
+pub(crate) fn encode(bytes: &[u8]) -> String {
+  let mut hex = String::with_capacity(bytes.len() * 2);
+  for byte in bytes {
+    write!(hex, "{byte:02x}").unwrap();
+  }
+  hex
+}
+
+/// Converts the hex digits of a binary file back to bytes. Whitespace is
+/// skipped, and anything else that isn't a whole byte is an error.
+pub(crate) fn decode(doc: &Document) -> io::Result<Vec<u8>> {
+  let mut bytes = Vec::with_capacity(doc.len() / 2);
+  let mut high = None;
+  for chunk in doc.range(..).chunks() {
+    for c in chunk.chars() {
+      if c.is_whitespace() {
+        continue;
+      }
+      let Some(digit) = c.to_digit(16) else {
+        return Err(io::Error::other(format!("invalid hex digit: '{c}'")));
+      };
+      match high.take() {
+        Some(high) => bytes.push((high << 4 | digit) as u8),
+        None => high = Some(digit),
+      }
+    }
+  }
+
+  if high.is_some() {
+    return Err(io::Error::other("the last byte is missing a hex digit"));
+  }
+  Ok(bytes)
+}
+
+impl EditorState {
+  pub fn is_binary(&self) -> bool { self.file.as_ref().is_some_and(|f| f.binary) }
+
+  /// The number of bytes in a binary file.
+  pub fn hex_len(&self) -> usize { self.doc.len() / 2 }
+
+  /// The byte at `index` in a binary file, or `None` if the document doesn't
+  /// hold two hex digits there.
+  pub fn hex_byte(&self, index: usize) -> Option<u8> {
+    let end = (index * 2 + 2).min(self.doc.len());
+    let hex = self.doc.range(index * 2..end).to_string();
+    if hex.len() != 2 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+      return None;
+    }
+    u8::from_str_radix(&hex, 16).ok()
+  }
+
+  /// The index of the byte under the cursor, and whether the cursor is on its
+  /// low nibble.
+  pub fn hex_cursor(&self) -> (usize, bool) {
+    (self.cursor.column.0 / 2, self.cursor.column.0 % 2 == 1)
+  }
+
+  /// Moves the cursor to the high nibble of the byte at `index`.
+  pub fn hex_move_to(&mut self, index: usize) { self.move_to_col(Column(index * 2)); }
+
+  /// Handles keys typed in insert and replace mode in a binary file. Hex digits
+  /// insert or overwrite nibbles, and all other text is ignored. Returns
+  /// `false` for keys that should be handled as usual.
+  pub fn hex_key(&mut self, key: KeyStroke) -> bool {
+    if !self.is_binary() || !matches!(self.mode, Mode::Insert | Mode::Replace) || key.control {
+      return false;
+    }
+
+    let nibble = self.cursor.column.0;
+    match key.key {
+      Key::Char(c) if c.is_ascii_hexdigit() => self.hex_type(c.to_digit(16).unwrap()),
+      Key::Backspace if self.mode == Mode::Insert && nibble > 0 => {
+        // Deletes the byte being typed, or the one before the cursor.
+        let start = (nibble - 1) / 2 * 2;
+        self.change(Change::remove(start..start + 2));
+        self.move_to_col(Column(start));
+      }
+      Key::Backspace => self.move_to_col(Column(nibble.saturating_sub(1))),
+      Key::Escape => {
+        self.set_mode(Mode::Normal);
+        self.hex_move_to(nibble / 2);
+      }
+      Key::ArrowLeft => self.move_to_col(Column(nibble.saturating_sub(1))),
+      Key::ArrowRight => self.move_to_col(Column(nibble + 1)),
+      Key::ArrowUp => self.move_to_col(Column(nibble.saturating_sub(HEX_ROW_LEN * 2))),
+      Key::ArrowDown => self.move_to_col(Column(nibble + HEX_ROW_LEN * 2)),
+      _ => {}
+    }
+
+    true
+  }
+
+  /// Types a hex digit. In insert mode, a digit on the high nibble inserts a
+  /// new byte, and otherwise the nibble under the cursor is overwritten.
+  fn hex_type(&mut self, digit: u32) {
+    let nibble = self.cursor.column.0;
+    let c = char::from_digit(digit, 16).unwrap();
+
+    if nibble >= self.doc.len() || (self.mode == Mode::Insert && nibble.is_multiple_of(2)) {
+      self.change(Change::insert(nibble, &format!("{c}0")));
+    } else {
+      self.change(Change::replace(nibble..nibble + 1, c.encode_utf8(&mut [0; 4])));
+    }
+
+    self.move_to_col(Column(nibble + 1));
+  }
+
+  /// Performs an action in a binary file. Only moves, byte edits, undo, and
+  /// mode changes apply, and everything else is ignored.
+  pub(crate) fn perform_hex_action(&mut self, action: Action) {
+    let nibble = self.cursor.column.0;
+    let last = self.hex_len().saturating_sub(1);
+    let row = HEX_ROW_LEN * 2;
+
+    match action {
+      Action::SetMode { mode: mode @ (Mode::Normal | Mode::Insert | Mode::Replace), delta } => {
+        if mode == Mode::Replace && self.current_edit.is_none() {
+          self.current_edit = Some(Edit::empty());
+        }
+        self.set_mode(mode);
+
+        // Bytes are inserted before the current one, or after it with `a`.
+        if mode == Mode::Insert {
+          self.hex_move_to(nibble / 2 + if delta > 0 { 1 } else { 0 });
+        }
+      }
+      Action::SetMode { mode: Mode::Command, .. } => self.set_mode(Mode::Command),
+
+      Action::Move { count, m } => {
+        let n = count.map_or(1, |c| c.get() as usize);
+        match m {
+          Move::Single(Direction::Left) => self.move_to_col(Column(nibble.saturating_sub(n))),
+          Move::Single(Direction::Right) => self.move_to_col(Column(nibble + n)),
+          Move::Single(Direction::Up) => self.move_to_col(Column(nibble.saturating_sub(row * n))),
+          Move::Single(Direction::Down) if nibble + row * n < self.doc.len() => {
+            self.move_to_col(Column(nibble + row * n));
+          }
+          Move::LineStart | Move::LineStartOfText => self.move_to_col(Column(nibble / row * row)),
+          Move::LineEnd => self.move_to_col(Column(nibble / row * row + row - 1)),
+          Move::FileStart => self.hex_move_to(0),
+          Move::FileEnd => self.hex_move_to(last),
+          _ => {}
+        }
+      }
+
+      Action::Edit { count, e } => {
+        let n = count.map_or(1, |c| c.get());
+        match e {
+          be_input::Edit::Replace(c) if c.is_ascii_hexdigit() && nibble < self.doc.len() => {
+            self.hex_type(c.to_digit(16).unwrap());
+            self.move_to_col(Column(nibble));
+          }
+          be_input::Edit::Delete(Move::Single(Direction::Right)) if self.doc.len() > 0 => {
+            let start = nibble / 2 * 2;
+            let end = (start + n as usize * 2).min(self.doc.len());
+            self.change(Change::remove(start..end));
+            self.hex_move_to((start / 2).min(self.hex_len().saturating_sub(1)));
+          }
+          be_input::Edit::Undo => self.undo(n),
+          be_input::Edit::Redo => self.redo(n),
+          be_input::Edit::Earlier => self.undo_steps(-(n as isize)),
+          be_input::Edit::Later => self.undo_steps(n as isize),
+          _ => {}
+        }
+      }
+
+      _ => {}
+    }
+
+    self.undo.finish(self.cursor);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::tests::*;
+
+  fn binary(name: &str, bytes: &[u8]) -> TestEditor {
+    let dir = TempDir::new(&format!("hex-{name}"));
+    let path = dir.join("file");
+    std::fs::write(&path, bytes).unwrap();
+
+    let mut editor = editor("");
+    editor.open(&path).unwrap();
+    assert!(editor.is_binary());
+    editor
+  }
+
+  #[test]
+  fn detect() {
+    assert!(!is_binary(b"hello\n"));
+    assert!(!is_binary("caf\u{e9}\n".as_bytes()));
+    assert!(!is_binary(b"caf\xe9 cr\xe8me\n"));
+    assert!(!is_binary(b"h\0i\0\n\0"));
+    assert!(is_binary(b"\x7fELF\x02\x01\x01\0"));
+    assert!(is_binary(b"\xff\xd8\xff\xe0\xc3\x99\x88"));
+  }
+
+  #[test]
+  fn round_trip() {
+    let bytes = [0x00, 0x7f, 0x80, 0xff, b'\n', b'a'];
+    assert_eq!(decode(&Document::from(encode(&bytes).as_str())).unwrap(), bytes);
+  }
+
+  #[test]
+  fn invalid() {
+    let mut editor = binary("invalid", &[0x00, 0x11]);
+    editor.doc.apply(&Change::replace(2..3, "z"));
+    assert_eq!(editor.hex_byte(0), Some(0x00));
+    assert_eq!(editor.hex_byte(1), None);
+    assert_eq!(decode(&editor.doc).unwrap_err().to_string(), "invalid hex digit: 'z'");
+    assert!(editor.save(false).is_err());
+  }
+
+  #[test]
+  fn overwrite() {
+    let mut editor = binary("overwrite", &[0x00, 0x11, 0x22]);
+    editor.keys("lRab<Esc>");
+    editor.check(expect![@"0a⟦b⟧122"]);
+    editor.keys("rf");
+    editor.check(expect![@"0a⟦f⟧122"]);
+
+    editor.keys("u");
+    assert_eq!(decode(&editor.doc).unwrap(), [0x0a, 0xb1, 0x22]);
+    editor.keys("u");
+    assert_eq!(decode(&editor.doc).unwrap(), [0x00, 0x11, 0x22]);
+  }
+
+  #[test]
+  fn insert() {
+    let mut editor = binary("insert", &[0x00, 0x11]);
+    editor.keys("laabc");
+    editor.check(expect![@"00abc‖011"]);
+    editor.keys("<BS><Esc>");
+    editor.check(expect![@"00ab⟦1⟧1"]);
+
+    editor.keys("x");
+    editor.check(expect![@"00⟦a⟧b"]);
+    editor.keys("u");
+    assert_eq!(decode(&editor.doc).unwrap(), [0x00, 0xab, 0x11]);
+    editor.keys("u");
+    assert_eq!(decode(&editor.doc).unwrap(), [0x00, 0x11]);
+  }
+
+  #[test]
+  fn moves() {
+    let mut editor = binary("moves", &[0; 40]);
+    editor.keys("jl$");
+    assert_eq!(editor.hex_cursor(), (31, true));
+    editor.keys("j");
+    assert_eq!(editor.hex_cursor(), (31, true));
+    editor.keys("G");
+    assert_eq!(editor.hex_cursor(), (39, false));
+    editor.keys("0gg");
+    assert_eq!(editor.hex_cursor(), (0, false));
+
+    // Text edits don't apply.
+    editor.keys("ddp");
+    assert_eq!(decode(&editor.doc).unwrap(), [0; 40]);
+  }
+}
@@ -4,7 +4,7 @@ use std::{
   ops::Range,
 };
 
-use be_doc::FindIter;
+use be_doc::{Document, FindIter};
 
 use crate::{Diagnostic, EditorState, lsp::DiagnosticLevel, treesitter::CapturesIter};
 
@@ -24,6 +24,7 @@ pub struct HighlightStack<'a> {
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum HighlightKey<'a> {
   SearchResult,
+  Selection,
   Diagnostic(DiagnosticLevel),
   TreeSitter(&'a str),
   SemanticToken(&'a str),
@@ -33,6 +34,7 @@ enum HighlightIter<'a> {
   TreeSitter(CapturesIter<'a>),
   Diagnostics(DiagnosticIter<'a>),
   Search(SearchResults<'a>),
+  Ranges(std::vec::IntoIter<Highlight<'a>>),
 
   #[cfg(test)]
   Slice(std::slice::Iter<'a, Highlight<'a>>),
@@ -46,11 +48,13 @@ struct StartNode<'a> {
 
 struct DiagnosticIter<'a> {
   iter:  std::slice::Iter<'a, Diagnostic>,
+  doc:   &'a Document,
   range: Range<usize>,
 }
 
 struct SearchResults<'a> {
   iter: FindIter<'a>,
+  end:  usize,
 }
 
 impl Ord for StartNode<'_> {
@@ -97,11 +101,30 @@ impl EditorState {
 
     iterators.push(HighlightIter::Diagnostics(DiagnosticIter {
       iter:  self.lsp.diagnostics.iter(),
+      doc:   &self.doc,
       range: range.clone(),
     }));
 
-    if let Some(text) = &self.search_text {
-      iterators.push(HighlightIter::Search(SearchResults { iter: self.doc.find(text) }));
+    if let Some(regex) = &self.search {
+      let start = self.doc.byte_of_line(self.doc.offset_to_cursor(range.start).line);
+      iterators.push(HighlightIter::Search(SearchResults {
+        iter: self.doc.find_regex_from(start, regex),
+        end:  range.end,
+      }));
+    }
+
+    let selection = self
+      .selection_ranges()
+      .into_iter()
+      .map(|r| Highlight { start: r.start, end: r.end, key: HighlightKey::Selection })
+      .collect::<Vec<_>>();
+    if !selection.is_empty() {
+      iterators.push(HighlightIter::Ranges(selection.into_iter()));
+    }
+
+    let substitute = self.substitute_highlights(range.clone());
+    if !substitute.is_empty() {
+      iterators.push(HighlightIter::Ranges(substitute.into_iter()));
     }
 
     MergeIterator::new(iterators, range.start)
@@ -116,6 +139,7 @@ impl<'a> Iterator for HighlightIter<'a> {
       HighlightIter::TreeSitter(iter) => iter.next(),
       HighlightIter::Diagnostics(iter) => iter.next(),
       HighlightIter::Search(iter) => iter.next(),
+      HighlightIter::Ranges(iter) => iter.next(),
 
       #[cfg(test)]
       HighlightIter::Slice(iter) => iter.next().copied(),
@@ -128,9 +152,9 @@ impl<'a> Iterator for DiagnosticIter<'a> {
 
   fn next(&mut self) -> Option<Self::Item> {
     loop {
-      let it = self.iter.next()?;
-      if self.range.contains(&it.range.start) || self.range.contains(&it.range.end) {
-        return Some(it.highlight());
+      let it = self.iter.next()?.highlight(self.doc);
+      if self.range.contains(&it.start) || self.range.contains(&it.end) {
+        return Some(it);
       }
     }
   }
@@ -140,12 +164,8 @@ impl<'a> Iterator for SearchResults<'a> {
   type Item = Highlight<'a>;
 
   fn next(&mut self) -> Option<Self::Item> {
-    let start = self.iter.next()?;
-    Some(Highlight {
-      start,
-      end: start + self.iter.needle().len(),
-      key: HighlightKey::SearchResult,
-    })
+    let range = self.iter.next().filter(|r| r.start < self.end)?;
+    Some(Highlight { start: range.start, end: range.end, key: HighlightKey::SearchResult })
   }
 }
 
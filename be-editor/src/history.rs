@@ -0,0 +1,186 @@
+//! The command line history, which `<Up>` and `<Down>` walk through, and Tab
+//! completion. The history is shared between every editor in a workspace, and
+//! saved in the cache directory so it lasts between sessions.
+
+use std::{
+  io,
+  path::{Path, PathBuf},
+};
+
+use crate::command::complete_command;
+
+/// The oldest commands are dropped past this many.
+const MAX_HISTORY: usize = 100;
+
+#[derive(Default)]
+pub struct CommandHistory {
+  commands: Vec<String>,
+  /// Where the history is saved. This is `None` for a history that isn't
+  /// saved, like in tests.
+  path:     Option<PathBuf>,
+}
+
+/// The state of `<Up>`, `<Down>` and Tab, for one command line.
+#[derive(Default)]
+pub struct CommandInput {
+  /// The text that was typed before browsing or completing, which history is
+  /// filtered by, and which `<Down>` returns to.
+  typed:       Option<String>,
+  history:     Option<usize>,
+  completions: Vec<String>,
+  completion:  usize,
+}
+
+impl CommandHistory {
+  /// Loads the history from the cache directory. An unreadable file is
+  /// treated as an empty history.
+  pub fn load() -> Self {
+    let path = match history_path() {
+      Ok(path) => path,
+      Err(e) => {
+        warn!("could not find command history: {e}");
+        return CommandHistory::default();
+      }
+    };
+
+    let commands = match std::fs::read_to_string(&path) {
+      Ok(text) => text.lines().map(String::from).collect(),
+      Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+      Err(e) => {
+        warn!("could not read command history {}: {e}", path.display());
+        vec![]
+      }
+    };
+
+    CommandHistory { commands, path: Some(path) }
+  }
+
+  /// Adds a command to the end of the history, moving it there if it was
+  /// already run before.
+  pub fn push(&mut self, command: &str) {
+    if command.trim().is_empty() || command.contains('\n') {
+      return;
+    }
+
+    self.commands.retain(|c| c != command);
+    self.commands.push(command.to_string());
+    if self.commands.len() > MAX_HISTORY {
+      self.commands.drain(..self.commands.len() - MAX_HISTORY);
+    }
+
+    if let Some(path) = &self.path
+      && let Err(e) = self.save(path)
+    {
+      warn!("could not save command history {}: {e}", path.display());
+    }
+  }
+
+  fn save(&self, path: &Path) -> io::Result<()> {
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    std::fs::write(path, self.commands.iter().map(|c| format!("{c}\n")).collect::<String>())
+  }
+}
+
+impl CommandInput {
+  /// Completes `text`, relative to `root`. Completing again without typing
+  /// cycles through the other completions, back to the typed text. A single
+  /// completion is completed again, so that Tab walks into directories.
+  pub fn complete(&mut self, text: &str, root: &Path) -> Option<String> {
+    // The typed text is the last completion, so there are 2 for one match.
+    if self.completions.len() <= 2 || !self.completions.iter().any(|c| c == text) {
+      let typed = text.to_string();
+      self.completions = complete_command(text, root);
+      self.completion = 0;
+      if self.completions.is_empty() {
+        return None;
+      }
+      self.completions.push(typed);
+    } else {
+      self.completion = (self.completion + 1) % self.completions.len();
+    }
+
+    self.history = None;
+    self.typed = None;
+    Some(self.completions[self.completion].clone())
+  }
+
+  /// Goes to the previous command in the history that starts with the typed
+  /// text.
+  pub fn older(&mut self, history: &CommandHistory, text: &str) -> Option<String> {
+    let typed = self.typed.get_or_insert_with(|| text.to_string());
+    let start = self.history.unwrap_or(history.commands.len());
+
+    let index = (0..start).rev().find(|&i| history.commands[i].starts_with(typed.as_str()))?;
+    self.history = Some(index);
+    self.completions.clear();
+    Some(history.commands[index].clone())
+  }
+
+  /// Goes to the next command in the history that starts with the typed text,
+  /// or back to the typed text past the newest command.
+  pub fn newer(&mut self, history: &CommandHistory) -> Option<String> {
+    let start = self.history? + 1;
+    let typed = self.typed.as_deref().unwrap_or_default();
+    self.completions.clear();
+
+    match (start..history.commands.len()).find(|&i| history.commands[i].starts_with(typed)) {
+      Some(index) => {
+        self.history = Some(index);
+        Some(history.commands[index].clone())
+      }
+      None => {
+        self.history = None;
+        self.typed.take()
+      }
+    }
+  }
+
+  /// Forgets the history position and completions, after the text is edited.
+  pub fn reset(&mut self) { *self = CommandInput::default(); }
+}
+
+fn history_path() -> io::Result<PathBuf> { Ok(be_config::cache_root()?.join("history")) }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn browse() {
+    let mut history = CommandHistory::default();
+    history.push("set wrap");
+    history.push("w");
+    history.push("set ff=unix");
+    history.push("w");
+
+    let mut input = CommandInput::default();
+    assert_eq!(input.older(&history, "").as_deref(), Some("w"));
+    assert_eq!(input.older(&history, "w").as_deref(), Some("set ff=unix"));
+    assert_eq!(input.newer(&history).as_deref(), Some("w"));
+    assert_eq!(input.newer(&history).as_deref(), Some(""));
+    assert_eq!(input.newer(&history), None);
+
+    let mut input = CommandInput::default();
+    assert_eq!(input.older(&history, "se").as_deref(), Some("set ff=unix"));
+    assert_eq!(input.older(&history, "set ff=unix").as_deref(), Some("set wrap"));
+    assert_eq!(input.older(&history, "set wrap"), None);
+    assert_eq!(input.newer(&history).as_deref(), Some("set ff=unix"));
+    assert_eq!(input.newer(&history).as_deref(), Some("se"));
+  }
+
+  #[test]
+  fn complete() {
+    let root = std::env::temp_dir();
+    let mut input = CommandInput::default();
+    assert_eq!(input.complete("wq", &root).as_deref(), Some("wq"));
+    assert_eq!(input.complete("wq", &root).as_deref(), Some("wq"));
+
+    let mut input = CommandInput::default();
+    assert_eq!(input.complete("w", &root).as_deref(), Some("wall"));
+    assert_eq!(input.complete("wall", &root).as_deref(), Some("wq"));
+    assert_eq!(input.complete("wq", &root).as_deref(), Some("write"));
+    assert_eq!(input.complete("write", &root).as_deref(), Some("w"));
+    assert_eq!(input.complete("wa", &root).as_deref(), Some("wall"));
+    assert_eq!(input.complete("xyz", &root), None);
+  }
+}
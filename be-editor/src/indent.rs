@@ -0,0 +1,229 @@
+//! Automatic indentation. With a tree-sitter grammar, the `indents.scm` query
+//! decides how far each line is indented. Without one, the indent is guessed
+//! from the brackets at the end of the previous line.
+
+use be_doc::{Change, Column, Line, crop::RopeSlice};
+use be_input::{Move, VerticalDirection};
+
+use crate::EditorState;
+
+#[derive(Copy, Clone)]
+pub struct IndentLevel(pub usize);
+
+impl IndentLevel {
+  pub const ZERO: IndentLevel = IndentLevel(0);
+
+  pub fn guess(config: &be_config::EditorSettings, line: RopeSlice<'_>) -> IndentLevel {
+    let indent_width = config.indent_width as usize;
+
+    let mut width = 0;
+    for c in line.chars() {
+      match c {
+        // A tab goes to the next indent stop.
+        '\t' => width += indent_width - width % indent_width,
+        ' ' => width += 1,
+        _ => break,
+      }
+    }
+
+    IndentLevel(width / indent_width)
+  }
+}
+
+impl EditorState {
+  pub fn auto_indent(&mut self, direction: VerticalDirection) {
+    if self.cursor.column != 0 || !self.doc.line(self.cursor.line).is_empty() {
+      return;
+    }
+
+    let line = self.cursor.line;
+    let indent_str = self.indent_str(self.guess_indent(line, direction));
+    self.change(Change::insert(self.doc.byte_of_line(line), &indent_str));
+    self.move_col_rel(indent_str.len() as i32);
+  }
+
+  /// Reindents the current line after a closing bracket is typed at the start
+  /// of it.
+  pub fn fix_indent(&mut self) {
+    let line = self.doc.line(self.cursor.line);
+    let mut iter = line.bytes().rev();
+    if !matches!(iter.next(), Some(b'}' | b']' | b')')) {
+      return;
+    }
+    if !iter.all(|c| c.is_ascii_whitespace()) {
+      return;
+    }
+
+    let len = self.set_indent(self.cursor.line, self.expected_indent(self.cursor.line));
+    self.move_to_col(Column(len + 1));
+  }
+
+  /// Returns the indent of `line`, or the indent a new line should get if it's
+  /// blank. `direction` is the side a new line was opened from, as with `o`
+  /// and `O`.
+  pub fn guess_indent(&self, line: Line, direction: VerticalDirection) -> IndentLevel {
+    {
+      let line = self.doc.line(line);
+      if !line.chars().all(|c| c.is_whitespace()) {
+        return IndentLevel::guess(&self.config.borrow().settings.editor, line);
+      }
+    }
+
+    if let Some(level) = self.tree_sitter_indent(line) {
+      return level;
+    }
+
+    match direction {
+      VerticalDirection::Up => self.indent_after_prev_line(line),
+      VerticalDirection::Down => {
+        if let Some(next) = self.next_non_empty_line(line) {
+          let mut level = IndentLevel::guess(&self.config.borrow().settings.editor, next);
+          for c in next.chars() {
+            match c {
+              '}' | ')' | ']' => level.0 += 1,
+              ' ' | '\t' => {}
+              _ => break,
+            }
+          }
+          level
+        } else {
+          IndentLevel::ZERO
+        }
+      }
+    }
+  }
+
+  /// Returns the indent that `line` should have, regardless of its current
+  /// indent.
+  fn expected_indent(&self, line: Line) -> IndentLevel {
+    if let Some(level) = self.tree_sitter_indent(line) {
+      return level;
+    }
+
+    let mut level = self.indent_after_prev_line(line);
+    let text = self.doc.line(line);
+    if matches!(text.chars().find(|c| !c.is_whitespace()), Some('}' | ')' | ']')) {
+      level.0 = level.0.saturating_sub(1);
+    }
+    level
+  }
+
+  fn tree_sitter_indent(&self, line: Line) -> Option<IndentLevel> {
+    self.highligher.as_ref()?.indent_level(&self.doc, line).map(IndentLevel)
+  }
+
+  /// The indent of the previous non-blank line, plus a level for each bracket
+  /// it ends with.
+  fn indent_after_prev_line(&self, line: Line) -> IndentLevel {
+    let Some(prev) = self.prev_non_empty_line(line) else { return IndentLevel::ZERO };
+
+    let mut level = IndentLevel::guess(&self.config.borrow().settings.editor, prev);
+    for c in prev.chars().rev() {
+      match c {
+        '{' | '(' | '[' => level.0 += 1,
+        ' ' | '\t' => {}
+        _ => break,
+      }
+    }
+    level
+  }
+
+  fn prev_non_empty_line(&self, mut line: Line) -> Option<RopeSlice<'_>> {
+    while line.0 > 0 {
+      line.0 -= 1;
+      let line = self.doc.line(line);
+      if !line.chars().all(|c| c.is_whitespace()) {
+        return Some(line);
+      }
+    }
+    None
+  }
+
+  fn next_non_empty_line(&self, mut line: Line) -> Option<RopeSlice<'_>> {
+    while line.0 < self.doc.len_lines() {
+      line.0 += 1;
+      let line = self.doc.line(line);
+      if !line.chars().all(|c| c.is_whitespace()) {
+        return Some(line);
+      }
+    }
+    None
+  }
+
+  /// The whitespace for an indent of `level`. Files that are indented with
+  /// tabs get tabs, and everything else gets spaces.
+  fn indent_str(&self, level: IndentLevel) -> String {
+    if self.indents_with_tabs() {
+      "\t".repeat(level.0)
+    } else {
+      " ".repeat(level.0 * self.config.borrow().settings.editor.indent_width as usize)
+    }
+  }
+
+  /// Checks the first indented line near the top of the file.
+  fn indents_with_tabs(&self) -> bool {
+    (0..self.doc.len_lines().min(1000))
+      .find_map(|line| match self.doc.line(Line(line)).chars().next() {
+        Some(c @ (' ' | '\t')) => Some(c == '\t'),
+        _ => None,
+      })
+      .unwrap_or(false)
+  }
+
+  /// Replaces the leading whitespace of `line`, and returns the length of the
+  /// new indent.
+  fn set_indent(&mut self, line: Line, level: IndentLevel) -> usize {
+    let start = self.doc.byte_of_line(line);
+    let old = self.doc.line(line).chars().take_while(|c| matches!(c, ' ' | '\t')).count();
+
+    let indent_str = self.indent_str(level);
+    if self.doc.range(start..start + old) != indent_str.as_str() {
+      self.change(Change::replace(start..start + old, &indent_str));
+    }
+    indent_str.len()
+  }
+
+  /// Reindents whole lines, as with `==` or `=j`. Blank lines, and lines that
+  /// start inside a string or comment, are left alone.
+  pub(crate) fn reindent_lines(&mut self, start: Line, end: Line) {
+    for line in start.0..=end.clamp(self.max_line()).0 {
+      let line = Line(line);
+      let text = self.doc.line(line);
+      let Some(leading) = text.chars().position(|c| !c.is_whitespace()) else { continue };
+
+      let offset = self.doc.byte_of_line(line) + leading;
+      if self.highligher.as_ref().is_some_and(|h| h.in_string_or_comment(&self.doc, offset)) {
+        continue;
+      }
+
+      self.set_indent(line, self.expected_indent(line));
+    }
+
+    self.move_to_line(start);
+    self.perform_move(Move::LineStartOfText, None);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::tests::*;
+
+  #[test]
+  fn reindent() {
+    let mut editor = editor("fn foo() {\nbar(\n      baz,\n  )\n    }\n");
+    editor.keys("=G");
+    editor.check(expect![@"⟦f⟧n foo() {\n  bar(\n    baz,\n  )\n}\n"]);
+
+    editor.keys("j0i  <Esc>==");
+    editor.check(expect![@"fn foo() {\n  ⟦b⟧ar(\n    baz,\n  )\n}\n"]);
+  }
+
+  #[test]
+  fn tabs() {
+    let mut editor = editor("fn foo() {\n\tbar {\n}\n");
+    editor.keys("jo");
+    editor.check(expect![@"fn foo() {\n\tbar {\n\t\t‖\n}\n"]);
+    editor.keys("}");
+    editor.check(expect![@"fn foo() {\n\tbar {\n\t}‖\n}\n"]);
+  }
+}
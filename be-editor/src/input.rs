@@ -0,0 +1,322 @@
+use std::{collections::VecDeque, num::NonZero};
+
+use be_input::{Action, ActionError, KeyStroke, Mode};
+
+use crate::{EditorState, Register, RegisterKind, status::Status};
+
+/// Macros can call other macros, so this stops a macro that calls itself. It
+/// limits how deeply macros are nested, so a macro can still call another one
+/// any number of times.
+const MAX_MACRO_DEPTH: usize = 100;
+
+#[derive(Default)]
+pub(crate) struct InputState {
+  keys:       Vec<KeyStroke>,
+  recording:  Option<(char, Vec<KeyStroke>)>,
+  last_macro: Option<char>,
+  /// The keys of the macro that `@` just played, which haven't been replayed
+  /// yet.
+  macro_keys: Vec<KeyStroke>,
+}
+
+/// Handles a typed key with `handle_key`, and then replays the keys of any
+/// macro it played. Replayed keys go through the same path as typed keys, so
+/// that they're handled exactly as they were recorded.
+///
+/// `be-gui` handles keys for the whole window, so that a macro can switch views
+/// like the keys it was recorded from. `editor` returns the editor that keys
+/// currently go to, which is where macros played with `@` are queued.
+pub fn replay_macros<T>(
+  target: &mut T,
+  key: KeyStroke,
+  mut handle_key: impl FnMut(&mut T, KeyStroke, bool),
+  mut editor: impl FnMut(&mut T) -> Option<&mut EditorState>,
+) {
+  handle_key(target, key, false);
+
+  // The keys left in each macro being played, with the innermost last. A macro
+  // that was played by the last key of another one still counts as nested, so
+  // that a macro ending with a call to itself is stopped.
+  let mut macros: Vec<VecDeque<KeyStroke>> = vec![];
+  loop {
+    if let Some(editor) = editor(target) {
+      let played = editor.take_macro_keys();
+      if !played.is_empty() {
+        if macros.len() >= MAX_MACRO_DEPTH {
+          editor.status = Some(Status::for_error_message("macros nested too deeply"));
+          break;
+        }
+        macros.push(played.into());
+      }
+    }
+
+    while macros.last().is_some_and(|keys| keys.is_empty()) {
+      macros.pop();
+    }
+    let Some(key) = macros.last_mut().and_then(|keys| keys.pop_front()) else { break };
+    handle_key(target, key, true);
+  }
+}
+
+impl EditorState {
+  /// Handles a key typed by the user. `be-gui` keeps its own key buffer, as
+  /// some actions are handled outside of the editor, but this follows the same
+  /// path through `Action::from_input`.
+  pub fn on_key(&mut self, key: KeyStroke) {
+    replay_macros(
+      self,
+      key,
+      |editor, key, replaying| editor.handle_key(key, replaying),
+      |editor| Some(editor),
+    );
+  }
+
+  fn handle_key(&mut self, key: KeyStroke, replaying: bool) {
+    self.input.keys.push(key);
+
+    let keys = self.input.keys.clone();
+    if (!replaying && self.record_key(self.mode, &keys))
+      || self.confirm_substitute_key(key)
+      || self.hex_key(key)
+    {
+      self.input.keys.clear();
+      return;
+    }
+
+    self.handle_keys();
+  }
+
+  fn handle_keys(&mut self) {
+    let res = Action::from_input(&self.config.borrow().settings.keys, self.mode, &self.input.keys);
+    match res {
+      Ok((action, len)) => {
+        let rest = self.input.keys.split_off(len);
+        self.input.keys.clear();
+        self.perform_action(action);
+
+        // Keys held back for a binding that wasn't finished are handled again.
+        for key in rest {
+          self.handle_key(key, true);
+        }
+      }
+      Err(ActionError::Unrecognized) => self.input.keys.clear(),
+      Err(ActionError::Incomplete) => {}
+    }
+  }
+
+  /// The register a macro is being recorded into, if any.
+  pub fn recording_macro(&self) -> Option<char> { self.input.recording.as_ref().map(|(r, _)| *r) }
+
+  /// Records the keys typed while a macro is being recorded. `keys` are the
+  /// keys that haven't been handled yet, ending with the key that was just
+  /// typed.
+  ///
+  /// Returns `true` if the key stopped the recording, in which case it should
+  /// not be handled any further.
+  pub fn record_key(&mut self, mode: Mode, keys: &[KeyStroke]) -> bool {
+    let Some((_, recorded)) = &mut self.input.recording else { return false };
+
+    if mode == Mode::Normal && keys == [KeyStroke::char('q')] {
+      self.stop_recording();
+      return true;
+    }
+
+    recorded.extend(keys.last());
+    false
+  }
+
+  pub(crate) fn start_recording(&mut self, name: char) {
+    self.input.recording = Some((name, vec![]));
+    self.status = Some(Status::for_success(format!("recording @{name}")));
+  }
+
+  fn stop_recording(&mut self) {
+    let Some((name, keys)) = self.input.recording.take() else { return };
+
+    let text = keys.iter().map(|k| k.to_string()).collect::<String>();
+    self.status = match self.registers.set(name, Register::new(text, RegisterKind::Charwise)) {
+      Ok(()) => None,
+      Err(e) => Some(Status::for_error_message(e)),
+    };
+  }
+
+  /// Plays a macro, by queueing its keys to be replayed once the current key
+  /// is handled.
+  pub(crate) fn play_macro(&mut self, name: Option<char>, count: Option<NonZero<u32>>) {
+    let Some(name) = name.or(self.input.last_macro) else { return };
+
+    self.input.last_macro = Some(name);
+    let Some(register) = self.registers.get(name) else { return };
+    let keys = KeyStroke::parse_all(&register.text);
+
+    for _ in 0..count.map_or(1, |c| c.get()) {
+      self.input.macro_keys.extend(&keys);
+    }
+  }
+
+  /// Takes the keys of the macro that `@` just played, for `replay_macros`.
+  pub fn take_macro_keys(&mut self) -> Vec<KeyStroke> { std::mem::take(&mut self.input.macro_keys) }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::tests::editor;
+
+  #[test]
+  fn record_and_play() {
+    let mut editor = editor("foo\nbar\nbaz\n");
+
+    editor.keys("qa$ax<Esc>jq");
+    assert_eq!(editor.registers.get('a').unwrap().text, "$ax<Esc>j");
+    editor.check(expect![@r#"
+      foox
+      ba⟦r⟧
+      baz
+    "#]);
+
+    editor.keys("@a");
+    editor.check(expect![@r#"
+      foox
+      barx
+      ba⟦z⟧
+    "#]);
+
+    editor.keys("@@");
+    editor.check(expect![@r#"
+      foox
+      barx
+      baz⟦x⟧
+    "#]);
+  }
+
+  #[test]
+  fn play_with_count() {
+    let mut editor = editor("a\nb\nc\nd\n");
+
+    editor.keys("qq0i-<Esc>jq2@q");
+    editor.check(expect![@r#"
+      -a
+      -b
+      -c
+      ⟦d⟧
+    "#]);
+  }
+
+  #[test]
+  fn edit_macro_as_text() {
+    let mut editor = editor("one\n");
+
+    editor.keys("\"ayy");
+    editor
+      .registers
+      .set('a', crate::Register::new("$a!<Esc>", crate::RegisterKind::Charwise))
+      .unwrap();
+    editor.keys("@a");
+    editor.check(expect![@"one⟦!⟧\n"]);
+  }
+
+  #[test]
+  fn macro_in_command_mode() {
+    let mut editor = editor("foo bar\nbar\n");
+
+    editor.keys("qs/bar<CR>nxq");
+    editor.check(expect![@r#"
+      foo ⟦a⟧r
+      bar
+    "#]);
+
+    editor.keys("@s");
+    editor.check(expect![@r#"
+      foo ar
+      ⟦a⟧r
+    "#]);
+  }
+
+  #[test]
+  fn macro_confirming_substitute() {
+    let mut editor = editor("aaa\naaa\n");
+
+    editor.keys("qa:s/a/b/gc<CR>ynyjq");
+    editor.check(expect![@r#"
+      bab
+      aa⟦a⟧
+    "#]);
+
+    editor.keys("@a");
+    editor.check(expect![@r#"
+      bab
+      ba⟦b⟧
+    "#]);
+  }
+
+  #[test]
+  fn recursive_macro() {
+    let mut editor = editor("abc\ndef\n");
+
+    editor.registers.set('a', crate::Register::new("x@a", crate::RegisterKind::Charwise)).unwrap();
+    editor.keys("@a");
+    editor.check(expect![@"⟦ ⟧\ndef\n"]);
+    assert_eq!(editor.status.as_ref().unwrap().message, "macros nested too deeply");
+  }
+
+  #[test]
+  fn many_macro_calls() {
+    let mut editor = editor(&format!("{}\n", "a".repeat(150)));
+
+    // Only nesting is limited, so a macro can call another one many times.
+    editor.registers.set('a', crate::Register::new("@b", crate::RegisterKind::Charwise)).unwrap();
+    editor.registers.set('b', crate::Register::new("x", crate::RegisterKind::Charwise)).unwrap();
+    editor.keys("120@a");
+    assert_eq!(editor.doc.to_string(), format!("{}\n", "a".repeat(30)));
+    assert!(editor.status.is_none());
+  }
+
+  #[test]
+  fn key_bindings() {
+    use be_config::{Binding, KeyAction};
+
+    let mut editor = editor("one\ntwo\nthree\nfour\n");
+    {
+      let keys = &mut editor.config.borrow_mut().settings.keys;
+      keys.leader = ",".into();
+      keys.normal.bindings.insert("<leader>d".into(), Binding::Action(KeyAction::DeleteLine));
+      keys.normal.bindings.insert("gh".into(), Binding::Action(KeyAction::LineEnd));
+      keys.normal.bindings.insert("x".into(), Binding::Unbound);
+      keys.normal.bindings.insert("<C-s>".into(), Binding::Command("s/o/0/".into()));
+      keys.insert.bindings.insert("jk".into(), Binding::Action(KeyAction::Normal));
+      keys.resolve().unwrap();
+    }
+
+    editor.keys("2,d");
+    editor.check(expect![@"⟦t⟧hree\nfour\n"]);
+
+    // Built-in bindings that start the same way still work.
+    editor.keys("ggx");
+    editor.check(expect![@"⟦t⟧hree\nfour\n"]);
+    editor.keys("gh");
+    editor.check(expect![@"thre⟦e⟧\nfour\n"]);
+
+    editor.keys("a!jk");
+    editor.check(expect![@"three⟦!⟧\nfour\n"]);
+
+    editor.keys("j<C-s>");
+    editor.check(expect![@"three!\n⟦f⟧0ur\n"]);
+  }
+
+  #[test]
+  fn unfinished_key_binding() {
+    use be_config::{Binding, KeyAction};
+
+    let mut editor = editor("\n");
+    {
+      let keys = &mut editor.config.borrow_mut().settings.keys;
+      keys.insert.bindings.insert("jk".into(), Binding::Action(KeyAction::Normal));
+      keys.resolve().unwrap();
+    }
+
+    editor.keys("ijoin<Esc>");
+    editor.check(expect![@"joi⟦n⟧\n"]);
+    editor.keys("ajjk");
+    editor.check(expect![@"join⟦j⟧\n"]);
+  }
+}
@@ -0,0 +1,184 @@
+//! The jumplist records where the cursor was before each big motion, like `gg`,
+//! `n`, or opening a file, so that `<C-o>` and `<C-i>` can walk back and forth
+//! through them. It is shared between every editor in a workspace, so it
+//! crosses files and splits.
+
+use std::path::PathBuf;
+
+use be_doc::Cursor;
+use be_input::ChangeDirection;
+
+use crate::{EditorEvent, EditorState, status::Status};
+
+/// The oldest jumps are dropped past this many.
+const MAX_JUMPS: usize = 100;
+
+/// A new jump replaces older jumps in the same file within this many lines, so
+/// that hopping around one spot doesn't fill the list.
+const NEARBY_LINES: usize = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Jump {
+  pub path:   PathBuf,
+  pub cursor: Cursor,
+}
+
+#[derive(Default)]
+pub struct JumpList {
+  jumps: Vec<Jump>,
+  /// The jump that `<C-o>` and `<C-i>` last went to. This is `jumps.len()`
+  /// when they haven't been used since the last jump.
+  index: usize,
+}
+
+impl Jump {
+  fn is_near(&self, other: &Jump) -> bool {
+    self.path == other.path && self.cursor.line.0.abs_diff(other.cursor.line.0) <= NEARBY_LINES
+  }
+}
+
+impl JumpList {
+  /// Records a jump from `jump`, and moves to the end of the list.
+  pub fn push(&mut self, jump: Jump) {
+    self.jumps.retain(|j| !j.is_near(&jump));
+    self.jumps.push(jump);
+    if self.jumps.len() > MAX_JUMPS {
+      self.jumps.drain(..self.jumps.len() - MAX_JUMPS);
+    }
+    self.index = self.jumps.len();
+  }
+
+  /// Goes back `count` jumps, as with `<C-o>`. `from` is the cursor now, which
+  /// is recorded first when at the end of the list, so that `<C-i>` can
+  /// return to it.
+  pub fn back(&mut self, from: Jump, count: usize) -> Option<&Jump> {
+    if self.index == self.jumps.len() {
+      self.push(from);
+      self.index = self.jumps.len() - 1;
+    }
+
+    self.index = self.index.checked_sub(count)?;
+    self.jumps.get(self.index)
+  }
+
+  /// Goes forward `count` jumps, as with `<C-i>`.
+  pub fn forward(&mut self, count: usize) -> Option<&Jump> {
+    let index = self.index + count;
+    if index >= self.jumps.len() {
+      return None;
+    }
+
+    self.index = index;
+    self.jumps.get(index)
+  }
+
+  /// Lists the jumps, oldest first, with how many jumps away from the current
+  /// one each is.
+  pub fn list(&self) -> impl Iterator<Item = (isize, &Jump)> {
+    self.jumps.iter().enumerate().map(|(i, jump)| (i as isize - self.index as isize, jump))
+  }
+}
+
+impl EditorState {
+  /// Records the cursor before a jump, in the `'` mark and in the jumplist.
+  pub fn record_jump(&mut self) {
+    self.set_mark('\'', self.doc.cursor_offset(self.cursor));
+
+    if let Some(file) = &self.file {
+      self.jumps.push(Jump { path: file.path().to_path_buf(), cursor: self.cursor });
+    }
+  }
+
+  /// Handles `<C-o>` and `<C-i>`. Jumps to other files open them in this view.
+  pub(crate) fn perform_jump(&mut self, dir: ChangeDirection, count: Option<u32>) {
+    let Some(path) = self.file.as_ref().map(|f| f.path().to_path_buf()) else { return };
+    let count = count.unwrap_or(1) as usize;
+
+    let target = match dir {
+      ChangeDirection::Prev => {
+        self.jumps.back(Jump { path: path.clone(), cursor: self.cursor }, count)
+      }
+      ChangeDirection::Next => self.jumps.forward(count),
+    };
+    let Some(target) = target.cloned() else { return };
+
+    if target.path == path {
+      self.move_to(target.cursor);
+    } else if let Some(send) = &self.send {
+      send(EditorEvent::OpenFile(target.path, Some(target.cursor)));
+    }
+  }
+
+  /// Shows the jumplist in the status bar, as with `:jumps`.
+  pub fn show_jumps(&mut self) {
+    let listing = self
+      .jumps
+      .list()
+      .map(|(distance, jump)| {
+        let name = jump.path.file_name().unwrap_or_default().to_string_lossy();
+        let current = if distance == 0 { ">" } else { "" };
+        format!("{current}{} {name}:{}", distance.abs(), jump.cursor.line.0 + 1)
+      })
+      .collect::<Vec<_>>()
+      .join("  ");
+
+    self.status = Some(Status::for_success(listing));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use be_doc::{Column, Line};
+
+  fn jump(path: &str, line: usize) -> Jump {
+    Jump {
+      path:   path.into(),
+      cursor: Cursor { line: Line(line), column: Column(0), ..Cursor::START },
+    }
+  }
+
+  #[test]
+  fn back_and_forth() {
+    let mut jumps = JumpList::default();
+    jumps.push(jump("a", 0));
+    jumps.push(jump("a", 10));
+    jumps.push(jump("b", 0));
+
+    assert_eq!(jumps.back(jump("b", 20), 1), Some(&jump("b", 0)));
+    assert_eq!(jumps.back(jump("b", 0), 2), Some(&jump("a", 0)));
+    assert_eq!(jumps.back(jump("a", 0), 1), None);
+    assert_eq!(jumps.forward(1), Some(&jump("a", 10)));
+    assert_eq!(jumps.forward(2), Some(&jump("b", 20)));
+    assert_eq!(jumps.forward(1), None);
+  }
+
+  #[test]
+  fn dedupe() {
+    let mut jumps = JumpList::default();
+    jumps.push(jump("a", 0));
+    jumps.push(jump("a", 10));
+    jumps.push(jump("a", 1));
+    jumps.push(jump("b", 10));
+
+    let lines = jumps.list().map(|(_, j)| j.clone()).collect::<Vec<_>>();
+    assert_eq!(lines, [jump("a", 10), jump("a", 1), jump("b", 10)]);
+  }
+
+  #[test]
+  fn editor() {
+    let dir = crate::tests::TempDir::new("jumps");
+    let path = dir.join("file");
+    std::fs::write(&path, "one\n".repeat(20)).unwrap();
+
+    let mut editor = crate::tests::editor("");
+    editor.open(&path).unwrap();
+
+    editor.keys("5jG");
+    assert_eq!(editor.cursor.line, Line(19));
+    editor.keys("<C-o>");
+    assert_eq!(editor.cursor.line, Line(5));
+    editor.keys("<C-i>");
+    assert_eq!(editor.cursor.line, Line(19));
+  }
+}
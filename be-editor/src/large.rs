@@ -0,0 +1,217 @@
+//! Files larger than the `large-file-size` setting are read in chunks. The
+//! first chunk is read when the file is opened, and the rest is read on a
+//! background thread and appended as it arrives, so the start of the file can
+//! be viewed and edited right away. Large files skip highlighting, LSP, and
+//! git diffs, as those all work on the whole file. Large binary files are read
+//! the same way, and each chunk is converted to hex as it's read.
+
+use std::{
+  fs::File,
+  io::{self, Read},
+  sync::{Arc, mpsc},
+};
+
+use be_doc::{Change, ChunkDecoder, Document, FileEncoding};
+
+use crate::{EditorState, hex, status::Status};
+
+const CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+pub(crate) struct Loader {
+  chunks: mpsc::Receiver<io::Result<String>>,
+}
+
+/// How the chunks of a file are turned into the text of the document.
+enum Decoder {
+  Text(ChunkDecoder),
+  Hex,
+}
+
+impl Loader {
+  /// Reads the first chunk of `file`, and starts reading the rest of it in the
+  /// background. `waker` is called whenever another chunk can be appended. The
+  /// encoding is `None` for binary files, which are read as hex.
+  pub(crate) fn start(
+    mut file: File,
+    encoding: Option<FileEncoding>,
+    waker: Option<Arc<dyn Fn() + Send + Sync>>,
+  ) -> io::Result<(Document, Option<FileEncoding>, Loader)> {
+    let first = read_chunk(&mut file)?;
+    let encoding = match encoding {
+      Some(encoding) => Some(encoding),
+      None if hex::is_binary(&first) => None,
+      None => Some(FileEncoding::detect(&first)),
+    };
+
+    let mut decoder = match encoding {
+      Some(encoding) => Decoder::Text(encoding.chunk_decoder()),
+      None => Decoder::Hex,
+    };
+    let last = first.len() < CHUNK_SIZE;
+    let doc = Document::from(decoder.decode(&first, last).as_str());
+
+    // If the file shrunk since it was checked, this drops the sender, and so
+    // the loader finishes right away.
+    let (tx, rx) = mpsc::channel();
+    if !last {
+      let waker = waker.unwrap_or_else(|| Arc::new(|| {}));
+      std::thread::spawn(move || read_rest(file, decoder, tx, waker));
+    }
+
+    Ok((doc, encoding, Loader { chunks: rx }))
+  }
+}
+
+impl Decoder {
+  fn decode(&mut self, chunk: &[u8], last: bool) -> String {
+    match self {
+      Decoder::Text(decoder) => decoder.decode(chunk, last),
+      Decoder::Hex => hex::encode(chunk),
+    }
+  }
+}
+
+fn read_chunk(file: &mut File) -> io::Result<Vec<u8>> {
+  let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+  file.take(CHUNK_SIZE as u64).read_to_end(&mut chunk)?;
+  Ok(chunk)
+}
+
+fn read_rest(
+  mut file: File,
+  mut decoder: Decoder,
+  tx: mpsc::Sender<io::Result<String>>,
+  waker: Arc<dyn Fn() + Send + Sync>,
+) {
+  loop {
+    let (chunk, last) = match read_chunk(&mut file) {
+      Ok(chunk) => {
+        let last = chunk.len() < CHUNK_SIZE;
+        (Ok(decoder.decode(&chunk, last)), last)
+      }
+      Err(e) => (Err(e), true),
+    };
+
+    // The editor was closed, so stop reading.
+    if tx.send(chunk).is_err() {
+      return;
+    }
+    if last {
+      break;
+    }
+    waker();
+  }
+
+  // The sender is dropped before the last wake up, so that the editor sees
+  // that loading has finished.
+  drop(tx);
+  waker();
+}
+
+impl EditorState {
+  pub fn is_large(&self) -> bool { self.file.as_ref().is_some_and(|f| f.large) }
+
+  /// Returns `true` while the rest of a large file is being read.
+  pub fn is_loading(&self) -> bool { self.file.as_ref().is_some_and(|f| f.loader.is_some()) }
+
+  /// Appends the chunks of a large file that have been read since the last
+  /// update. The appended text isn't part of the undo history.
+  pub(crate) fn update_loading(&mut self) {
+    let Some(file) = &mut self.file else { return };
+    let Some(loader) = &file.loader else { return };
+
+    let mut text = String::new();
+    let mut error = None;
+    let done = loop {
+      match loader.chunks.try_recv() {
+        Ok(Ok(chunk)) => text.push_str(&chunk),
+        Ok(Err(e)) => {
+          error = Some(e);
+          break true;
+        }
+        Err(mpsc::TryRecvError::Empty) => break false,
+        Err(mpsc::TryRecvError::Disconnected) => break true,
+      }
+    };
+
+    if done {
+      file.loader = None;
+    }
+    if let Some(e) = error {
+      self.status =
+        Some(Status::for_error_message(format!("{}: failed to read: {e}", file.path().display())));
+    }
+
+    if !text.is_empty() {
+      self.doc.apply(&Change::insert(self.doc.len(), &text));
+      self.damage_all = true;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::{Arc, mpsc};
+
+  use crate::tests::*;
+
+  #[test]
+  fn load() {
+    let dir = TempDir::new("large");
+    let path = dir.join("file.rs");
+    let text = "hello\n".repeat(super::CHUNK_SIZE / 3);
+    std::fs::write(&path, &text).unwrap();
+
+    let (mut editor, woken) = large_editor();
+    editor.open(&path).unwrap();
+
+    assert!(editor.is_large());
+    assert!(editor.file_type().is_none());
+    assert!(editor.doc.len() <= super::CHUNK_SIZE);
+    assert!(editor.save(false).is_err());
+
+    wait_for_load(&mut editor, &woken);
+    assert_eq!(editor.doc.to_string(), text);
+
+    editor.keys("ggx");
+    assert_eq!(editor.doc.len(), text.len() - 1);
+  }
+
+  #[test]
+  fn load_binary() {
+    let dir = TempDir::new("large-binary");
+    let path = dir.join("file.bin");
+    let bytes = (0..super::CHUNK_SIZE + 100).map(|i| i as u8).collect::<Vec<_>>();
+    std::fs::write(&path, &bytes).unwrap();
+
+    let (mut editor, woken) = large_editor();
+    editor.open(&path).unwrap();
+
+    assert!(editor.is_large());
+    assert!(editor.is_binary());
+    assert_eq!(editor.doc.len(), super::CHUNK_SIZE * 2);
+
+    wait_for_load(&mut editor, &woken);
+    assert_eq!(editor.hex_len(), bytes.len());
+    assert_eq!(editor.hex_byte(super::CHUNK_SIZE + 99), Some(99));
+  }
+
+  /// An editor that opens files over 1KB as large files, and a channel that
+  /// receives each time the editor is woken up.
+  fn large_editor() -> (TestEditor, mpsc::Receiver<()>) {
+    let mut editor = editor("");
+    editor.config.borrow_mut().settings.editor.large_file_size = 1024;
+    let (tx, rx) = mpsc::channel();
+    editor.waker = Some(Arc::new(move || {
+      let _ = tx.send(());
+    }));
+    (editor, rx)
+  }
+
+  fn wait_for_load(editor: &mut TestEditor, woken: &mpsc::Receiver<()>) {
+    while editor.is_loading() {
+      woken.recv().unwrap();
+      editor.update_loading();
+    }
+  }
+}
@@ -1,7 +1,14 @@
-use std::{cell::RefCell, collections::HashSet, ops::Range, path::PathBuf, rc::Rc};
+use std::{
+  cell::RefCell,
+  collections::{HashMap, HashSet},
+  ops::Range,
+  path::{Path, PathBuf},
+  rc::Rc,
+  sync::Arc,
+};
 
 use be_config::{Config, LanguageName};
-use be_doc::{Change, Column, Cursor, Document, Edit, Line, crop::RopeSlice};
+use be_doc::{Anchor, Change, Column, Cursor, Document, Edit, Line, LineEnding, Regex};
 use be_git::{LineDiffSimilarity, Repo};
 use be_input::{Action, Clipboard, Direction, Mode, Move, VerticalDirection};
 use be_shared::SharedHandle;
@@ -9,20 +16,48 @@ use unicode_segmentation::UnicodeSegmentation;
 
 use crate::{fs::OpenedFile, status::Status};
 
+mod command;
+mod comment;
+mod cursors;
 mod edit;
 mod filetype;
+mod fold;
 mod fs;
+mod hex;
 mod highlight;
+mod history;
+mod indent;
+mod input;
+mod jumps;
+mod large;
 mod lsp;
+mod marks;
 mod moves;
+mod pairs;
+mod register;
+mod repeat;
+mod set;
 mod status;
+mod substitute;
+mod textobject;
 mod treesitter;
+mod undo;
+mod visual;
+mod wrap;
 
 #[cfg(test)]
 mod tests;
 
+pub use command::Command;
+pub use hex::HEX_ROW_LEN;
 pub use highlight::HighlightKey;
+pub use history::{CommandHistory, CommandInput};
+pub use indent::IndentLevel;
+pub use input::replay_macros;
+pub use jumps::{Jump, JumpList};
 pub use lsp::{Diagnostic, DiagnosticLevel};
+pub use register::{Register, RegisterKind, Registers};
+pub use undo::UndoArg;
 
 #[macro_use]
 extern crate log;
@@ -40,10 +75,20 @@ pub struct EditorState {
   cursor: Cursor,
   mode:   Mode,
 
-  file:        Option<OpenedFile>,
-  status:      Option<Status>,
-  command:     Option<CommandState>,
-  search_text: Option<String>,
+  /// Every cursor besides `cursor`, the main one.
+  cursors:     Vec<cursors::ExtraCursor>,
+  /// Set while an action is performed at each cursor, so that the changes at
+  /// every cursor end up in a single undo step.
+  each_cursor: bool,
+
+  file:            Option<OpenedFile>,
+  status:          Option<Status>,
+  command:         Option<CommandState>,
+  search:          Option<Regex>,
+  /// Set by `:wq`, so that the view closes once the save finishes.
+  quit_after_save: bool,
+
+  line_ending: LineEnding,
 
   filetype:   Option<LanguageName>,
   highligher: Option<treesitter::Highlighter>,
@@ -51,15 +96,38 @@ pub struct EditorState {
   damage_all: bool,
 
   current_edit:     Option<Edit>,
-  history_position: usize,
-  history:          Vec<Edit>,
-  copied:           String,
+  undo:             undo::UndoTree,
+  pending_register: Option<char>,
+  repeat:           repeat::RepeatState,
+  input:            input::InputState,
+
+  visual_anchor: Cursor,
+  block_insert:  Option<visual::BlockInsert>,
+  last_visual:   Option<be_doc::Selection>,
+  marks:         HashMap<char, Anchor>,
+  folds:         fold::Folds,
+  /// How many columns fit in the view, which soft wrapped lines wrap at.
+  view_columns:  Option<usize>,
+  /// Set with `:set wrap` and `:set nowrap`, which overrides the language.
+  soft_wrap:     Option<bool>,
+  wrap_cache:    RefCell<wrap::WrapCache>,
+
+  substitute_preview: Option<substitute::Substitute>,
+  confirm:            Option<substitute::Confirm>,
 
   pub config:    Rc<RefCell<Config>>,
   pub repo:      SharedHandle<Option<Repo>>,
   pub lsp:       lsp::LspState,
   pub send:      Option<Box<dyn Fn(EditorEvent)>>,
   pub clipboard: SharedHandle<Clipboard>,
+  pub registers: SharedHandle<Registers>,
+  pub jumps:     SharedHandle<JumpList>,
+  pub history:   SharedHandle<CommandHistory>,
+  /// The workspace root, which paths in the command line are relative to.
+  pub root:      PathBuf,
+  /// Wakes up the UI from a background thread, such as when more of a large
+  /// file has been read.
+  pub waker:     Option<Arc<dyn Fn() + Send + Sync>>,
 
   pub changes: Option<LineDiffSimilarity>,
 }
@@ -67,6 +135,7 @@ pub struct EditorState {
 #[derive(Debug)]
 pub enum EditorEvent {
   RunCommand(String),
+  Command(Command),
   OpenFile(PathBuf, Option<Cursor>),
   // NB: This is moderately dumb. Ideally, we'd pick the 'goto definition' even up in
   // `EditorView::layout` and do it directly in there. But also, this nicely only affects the
@@ -79,6 +148,7 @@ pub struct CommandState {
   pub text:   String,
   pub mode:   CommandMode,
   pub cursor: usize, // in bytes
+  pub input:  CommandInput,
 }
 
 #[derive(Default, Copy, Clone, PartialEq, Eq)]
@@ -88,17 +158,11 @@ pub enum CommandMode {
   Search,
 }
 
-#[derive(Copy, Clone)]
-pub struct IndentLevel(pub usize);
-
-impl IndentLevel {
-  pub const ZERO: IndentLevel = IndentLevel(0);
-}
-
 impl From<&str> for EditorState {
   fn from(s: &str) -> EditorState {
     let mut state = EditorState::default();
     state.doc = Document::from(s);
+    state.line_ending = state.doc.detect_line_ending();
     state
   }
 }
@@ -113,6 +177,7 @@ impl EditorState {
   pub fn status(&self) -> Option<&Status> { self.status.as_ref() }
   pub fn file(&self) -> Option<&std::path::Path> { self.file.as_ref().map(|f| f.path()) }
   pub fn file_type(&self) -> Option<LanguageName> { self.filetype }
+  pub fn line_ending(&self) -> LineEnding { self.line_ending }
   pub fn is_damage_all(&self) -> bool { self.damage_all }
   pub fn damages(&self) -> impl Iterator<Item = &Line> { self.damages.iter() }
   pub fn progress(&self) -> Vec<String> { self.lsp.progress() }
@@ -131,6 +196,7 @@ impl EditorState {
     self.lsp_update_goto_definition();
     self.lsp_update_completions();
     self.update_save_task();
+    self.update_loading();
 
     if let Some(repo) = &*self.repo {
       if let Some(file) = &self.file.as_ref() {
@@ -145,7 +211,16 @@ impl EditorState {
     self.move_to_line(Line(0));
     self.move_to_col(Column(0));
 
-    let Some(_) = self.file.as_ref() else { return };
+    let Some(file) = self.file.as_ref() else { return };
+
+    // Binary files aren't text, so they get no highlighting, LSP, or git diff.
+    // Large files skip these too, as they would need the whole file.
+    if file.binary || file.large {
+      self.filetype = None;
+      self.highligher = None;
+      self.changes = None;
+      return;
+    }
 
     self.detect_filetype();
     self.on_open_file_highlight();
@@ -237,7 +312,7 @@ impl EditorState {
     let line = self.doc.line(self.cursor.line);
 
     let mut max_col = line.graphemes().count();
-    if self.mode == Mode::Normal {
+    if matches!(self.mode, Mode::Normal | Mode::Visual(_)) {
       max_col = max_col.saturating_sub(1);
     }
 
@@ -271,23 +346,40 @@ impl EditorState {
   }
 
   pub fn set_mode(&mut self, m: Mode) {
+    let from_visual = matches!(self.mode, Mode::Visual(_));
+    if matches!(m, Mode::Visual(_)) && !from_visual {
+      self.visual_anchor = self.cursor;
+    }
+    if from_visual && !matches!(m, Mode::Visual(_)) {
+      self.last_visual = self.selection();
+    }
     self.mode = m;
     self.move_to_col(self.cursor.column.clamp(self.max_column()));
 
     if m == Mode::Command {
-      self.command = Some(CommandState::default());
+      // Commands typed from visual mode apply to the selected lines.
+      let text = if from_visual { "'<,'>".to_string() } else { String::new() };
+      self.command = Some(CommandState {
+        cursor: text.len(),
+        text,
+        mode: CommandMode::Command,
+        ..Default::default()
+      });
     } else {
       self.command = None;
+      self.preview_command("");
     }
 
     match m {
       Mode::Normal => {
+        self.finish_block_insert();
         self.trim_line(self.cursor.line);
         self.remove_current_edit();
         self.lsp.completions.hide();
       }
 
-      Mode::Insert => {
+      // Visual changes start the edit before entering insert mode.
+      Mode::Insert if self.current_edit.is_none() => {
         self.current_edit = Some(Edit::empty());
       }
 
@@ -295,32 +387,77 @@ impl EditorState {
     }
   }
 
+  /// Called when a command line is opened outside of the editor. Leaves visual
+  /// mode, and returns the text to start the command with.
+  pub fn enter_command(&mut self) -> String {
+    if matches!(self.mode, Mode::Visual(_)) {
+      self.set_mode(Mode::Normal);
+      "'<,'>".to_string()
+    } else {
+      String::new()
+    }
+  }
+
   pub fn unsaved(&self) -> bool {
-    let saved_history_position =
-      self.history.len() - self.file.as_ref().map_or(0, |f| f.saved_history_position);
+    let saved_node = self.file.as_ref().map_or(0, |f| f.saved_undo_node);
 
-    self.history_position != saved_history_position || self.current_edit.is_some()
+    self.undo.current() != saved_node || self.current_edit.is_some()
   }
 
   fn remove_current_edit(&mut self) {
+    if self.each_cursor {
+      return;
+    }
+
     if let Some(edit) = self.current_edit.take() {
-      self.add_to_history(edit);
+      if edit.is_empty() {
+        self.undo.cancel();
+      } else {
+        self.undo.push(edit, self.cursor);
+      }
     }
   }
 
-  /// Should only be called after calling `current_edit.take()` or when applying
-  /// a change.
-  fn add_to_history(&mut self, edit: Edit) {
-    if self.history_position > 0 {
-      self.history.drain(self.history.len() - self.history_position..);
+  pub fn perform_action(&mut self, action: Action) {
+    if self.is_binary() {
+      self.perform_hex_action(action);
+      return;
+    }
+
+    let selection = self.selection();
+    let selects_register = matches!(action, Action::SelectRegister { .. });
+    let is_fold = matches!(action, Action::Fold { .. });
+    self.record_change(&action);
+
+    if self.applies_to_each_cursor(&action) {
+      self.perform_at_each_cursor(action);
+    } else {
+      self.perform_action_at_cursor(action);
+    }
+
+    if !selects_register {
+      self.pending_register = None;
+    }
+    if !is_fold {
+      self.open_folds_at_cursor();
+    }
+    self.finish_change();
+    self.undo.finish(self.cursor);
+
+    if self.selection() != selection {
+      self.damage_selection(selection);
+      self.damage_selection(self.selection());
     }
-    self.history_position = 0;
-    self.history.push(edit);
   }
 
-  pub fn perform_action(&mut self, action: Action) {
+  /// Performs `action` at the main cursor.
+  fn perform_action_at_cursor(&mut self, action: Action) {
     match action {
       Action::SetMode { mode, delta } => {
+        if self.mode == Mode::Insert && mode != Mode::Insert {
+          self.set_mark('^', self.doc.cursor_offset(self.cursor));
+        }
+
         if delta < 0 {
           self.move_col_rel(delta);
           self.set_mode(mode);
@@ -342,13 +479,13 @@ impl EditorState {
 
         if after {
           let target = self.doc.byte_of_line(self.cursor().line + 1);
-          self.change(Change::insert(target, "\n"));
+          self.change(Change::insert(target, self.line_ending.as_str()));
           self.move_to_line(self.cursor.line + 1);
           self.move_to_col(Column(0));
           self.auto_indent(VerticalDirection::Up);
         } else {
           let target = self.doc.byte_of_line(self.cursor().line);
-          self.change(Change::insert(target, "\n"));
+          self.change(Change::insert(target, self.line_ending.as_str()));
           self.move_to_col(Column(0));
           self.auto_indent(VerticalDirection::Down);
         }
@@ -359,14 +496,22 @@ impl EditorState {
       {
         self.accept_completion()
       }
-      Action::Edit { count: _, e } => self.perform_edit(e),
+      Action::Edit { count, e } => self.perform_edit(e, count),
       Action::Autocomplete => self.perform_autocomplete(),
       Action::MoveCompletion { next } => self.move_completion(next),
       Action::Navigate { nav } => error!("unhandled navigate passed to editor: {nav:?}"),
       Action::Control { .. } => {} // only really used for the terminal
-      Action::Tab => {}            // TODO
-
-      Action::Copy => {} // TODO: Get at selection bytes.
+      Action::Tab => self.complete_command_line(),
+      Action::SelectRegister { name } => self.pending_register = Some(name),
+      Action::Repeat { count } => self.repeat_change(count),
+      Action::RecordMacro { name } => self.start_recording(name),
+      Action::PlayMacro { name, count } => self.play_macro(name, count),
+      Action::SetMark { name } => self.perform_set_mark(name),
+      Action::Fold { op } => self.perform_fold(op),
+      Action::Cursor { op } => self.perform_cursor_op(op),
+      Action::Command { command } => self.execute_command(&command),
+
+      Action::Copy => self.copy_selection(),
       Action::Paste => {
         // TODO: Do we de-duplicate with 'p'?
         let text = self.clipboard.paste();
@@ -381,10 +526,15 @@ impl EditorState {
   fn perform_autocomplete(&mut self) { self.lsp_request_completions(); }
 
   fn change(&mut self, change: Change) {
+    self.set_mark('.', change.range.start);
+
     if let Some(edit) = &mut self.current_edit {
+      if edit.is_empty() {
+        self.undo.start(self.cursor);
+      }
       edit.push(&change, &self.doc);
     } else {
-      self.add_to_history(Edit::new(&change, &self.doc));
+      self.undo.push(Edit::new(&change, &self.doc), self.cursor);
     }
 
     self.change_no_history(change);
@@ -413,20 +563,31 @@ impl EditorState {
       self.damage_line(Line(line));
     }
 
-    if change.text.contains('\n') || self.doc.range(change.range.clone()).chars().any(|c| c == '\n')
+    // Binary files have no trailing newline, so an insert can start at the end
+    // of the document. `range` doesn't allow that.
+    if change.text.contains('\n')
+      || (!change.range.is_empty()
+        && self.doc.range(change.range.clone()).chars().any(|c| c == '\n'))
     {
       self.damage_all = true;
     }
 
+    let new_lines = change.text.matches('\n').count() + 1;
+    self.wrap_cache.get_mut().replace(start_pos.row..=end_pos.row, new_lines);
+
     let before = self.doc.snapshot();
 
     self.doc.apply(&change);
+    self.update_folds();
 
     self.lsp_notify_change(&change, before);
     self.on_change_highlight(&change, start_pos, end_pos);
 
     if let Some(repo) = &mut *self.repo {
-      if let Some(file) = &self.file.as_ref() {
+      if let Some(file) = &self.file.as_ref()
+        && !file.binary
+        && !file.large
+      {
         repo.update_file(file.path(), self.doc.snapshot());
       }
     }
@@ -437,14 +598,14 @@ impl EditorState {
 
     match command.mode {
       CommandMode::Search => {
-        self.search_text = Some(command.text);
-        self.damage_all = true;
-        self.status = None;
+        self.status = match self.set_search(&command.text) {
+          Ok(()) => None,
+          Err(e) => Some(Status::for_error_message(e)),
+        };
       }
       CommandMode::Command => {
-        if let Some(send) = &self.send {
-          send(EditorEvent::RunCommand(command.text.clone()));
-        }
+        self.history.push(&command.text);
+        self.execute_command(&command.text);
 
         /*
         match res {
@@ -457,19 +618,34 @@ impl EditorState {
   }
 
   fn update_save_task(&mut self) {
-    if self.lsp.save_task.is_some() {
+    if let Some(task) = &self.lsp.save_task {
+      let force = task.force;
       self.lsp_finish_on_save();
 
       if self.lsp.save_task.is_none() {
-        let res = self
-          .save()
-          .map(|()| format!("{}: written", self.file.as_ref().unwrap().path().display()));
+        self.finish_save(force);
+      }
+    }
+  }
 
-        match res {
-          Ok(m) => self.status = Some(Status::for_success(m)),
-          Err(e) => self.status = Some(Status::for_error(e)),
+  /// Writes the file once the language server is done formatting it. This
+  /// closes the view if the save came from `:wq`.
+  fn finish_save(&mut self, force: bool) {
+    let quit = std::mem::take(&mut self.quit_after_save);
+
+    match self.save(force) {
+      Ok(in_place) => {
+        let path = self.file.as_ref().unwrap().path().display();
+        self.status = Some(Status::for_success(if in_place {
+          format!("{path}: written in place, as the directory isn't writable")
+        } else {
+          format!("{path}: written")
+        }));
+        if quit {
+          self.send_command(Command::Quit);
         }
       }
+      Err(e) => self.status = Some(Status::for_error(e)),
     }
   }
 
@@ -487,151 +663,74 @@ impl EditorState {
     }
   }
 
-  pub fn auto_indent(&mut self, direction: VerticalDirection) {
-    if self.cursor.column != 0 || !self.doc.line(self.cursor.line).is_empty() {
-      return;
-    }
-
-    let line = self.cursor.line;
-    let indent = self.guess_indent(line, direction);
-    let columns = indent.0 * self.config.borrow().settings.editor.indent_width as usize;
-    let indent_str = " ".repeat(columns);
-    self.change(Change::insert(self.doc.byte_of_line(line), &indent_str));
-    self.move_col_rel(columns as i32);
-  }
+  /// Saves the file, after formatting it with the language server. `force`
+  /// overwrites the file even if it changed on disk since it was opened.
+  pub fn begin_save(&mut self, force: bool) {
+    self.lsp_on_save(force);
 
-  pub fn fix_indent(&mut self) {
-    let line = self.doc.line(self.cursor.line);
-    let mut iter = line.bytes().rev();
-    if !matches!(iter.next(), Some(b'}' | b']' | b')')) {
-      return;
-    }
-    let whitespace = iter.len();
-    if !iter.all(|c| c.is_ascii_whitespace()) {
-      return;
-    }
-
-    let mut indent = self.guess_indent(self.cursor.line, VerticalDirection::Up);
-    indent.0 = indent.0.saturating_sub(1);
-
-    let columns = indent.0 * self.config.borrow().settings.editor.indent_width as usize;
-    let indent_str = " ".repeat(columns);
-    self.change(Change::replace(
-      self.doc.byte_of_line(self.cursor.line)..self.doc.byte_of_line(self.cursor.line) + whitespace,
-      &indent_str,
-    ));
-    self.move_to_col(be_doc::Column(columns + 1));
-  }
-
-  pub fn guess_indent(&self, line: Line, direction: VerticalDirection) -> IndentLevel {
-    {
-      let line = self.doc.line(line);
-      if !line.chars().all(|c| c.is_whitespace()) {
-        return IndentLevel::guess(&self.config.borrow().settings.editor, line);
-      }
-    }
-
-    match direction {
-      VerticalDirection::Up => {
-        if let Some(prev) = self.prev_non_empty_line(line) {
-          let mut level = IndentLevel::guess(&self.config.borrow().settings.editor, prev);
-          for c in prev.chars().rev() {
-            match c {
-              '{' | '(' | '[' => level.0 += 1,
-              ' ' => {}
-              _ => break,
-            }
-          }
-          level
-        } else {
-          IndentLevel::ZERO
-        }
-      }
-      VerticalDirection::Down => {
-        if let Some(next) = self.next_non_empty_line(line) {
-          let mut level = IndentLevel::guess(&self.config.borrow().settings.editor, next);
-          for c in next.chars() {
-            match c {
-              '}' | ')' | ']' => level.0 += 1,
-              ' ' => {}
-              _ => break,
-            }
-          }
-          level
-        } else {
-          IndentLevel::ZERO
-        }
-      }
-    }
-  }
-
-  fn prev_non_empty_line(&self, mut line: Line) -> Option<RopeSlice<'_>> {
-    while line.0 > 0 {
-      line.0 -= 1;
-      let line = self.doc.line(line);
-      if !line.chars().all(|c| c.is_whitespace()) {
-        return Some(line);
-      }
+    if self.lsp.save_task.is_some() {
+      self.status = Some(Status::for_success("saving..."));
+    } else {
+      self.finish_save(force);
     }
-    None
   }
 
-  fn next_non_empty_line(&self, mut line: Line) -> Option<RopeSlice<'_>> {
-    while line.0 < self.doc.len_lines() {
-      line.0 += 1;
-      let line = self.doc.line(line);
-      if !line.chars().all(|c| c.is_whitespace()) {
-        return Some(line);
-      }
-    }
-    None
+  /// Saves the file like `begin_save`, and then closes the view.
+  pub(crate) fn begin_save_and_quit(&mut self, force: bool) {
+    self.quit_after_save = true;
+    self.begin_save(force);
   }
 
-  pub fn begin_save(&mut self) {
-    self.lsp_on_save();
+  /// If a save is waiting on the language server to format the file.
+  pub fn is_saving(&self) -> bool { self.lsp.save_task.is_some() }
 
-    if self.lsp.save_task.is_some() {
-      self.status = Some(Status::for_success("saving..."));
-    } else {
-      match self.save() {
-        Ok(()) => {
-          self.status = Some(Status::for_success(format!(
-            "{}: written",
-            self.file.as_ref().unwrap().path().display()
-          )))
-        }
-        Err(e) => self.status = Some(Status::for_error(e)),
-      }
+  /// Compiles `text` as the current search pattern. An invalid or empty
+  /// pattern clears the search.
+  fn set_search(&mut self, text: &str) -> Result<(), String> {
+    self.damage_all = true;
+    self.search = None;
+    if !text.is_empty() {
+      self.search = Some(Regex::smart_case(text).map_err(|e| format!("invalid pattern: {e}"))?);
     }
+    Ok(())
   }
 
   pub fn clear_search(&mut self) {
-    self.search_text = None;
+    self.search = None;
     self.damage_all = true;
     self.status = None;
   }
 }
 
-impl IndentLevel {
-  pub fn guess(config: &be_config::EditorSettings, line: RopeSlice<'_>) -> IndentLevel {
-    let mut width = 0;
-    for c in line.chars() {
-      match c {
-        '\t' => {} // TODO
-        ' ' => width += 1,
-        _ => break,
+impl CommandState {
+  /// Handles an action typed into the command line. Running the command with
+  /// `<CR>` is left to the caller.
+  pub fn perform_action(&mut self, action: Action, history: &CommandHistory, root: &Path) {
+    match action {
+      Action::Move { m, .. } => self.perform_move(m, history),
+      Action::Edit { e, .. } => self.perform_edit(e),
+      Action::Tab => {
+        self.complete(root);
       }
-    }
 
-    IndentLevel(width / config.indent_width as usize)
+      _ => {}
+    }
   }
-}
 
-impl CommandState {
-  fn perform_move(&mut self, m: Move) {
+  fn perform_move(&mut self, m: Move, history: &CommandHistory) {
     match m {
       Move::Single(Direction::Left) => self.move_cursor(-1),
       Move::Single(Direction::Right) => self.move_cursor(1),
+      Move::Single(Direction::Up) if self.mode == CommandMode::Command => {
+        if let Some(text) = self.input.older(history, &self.text) {
+          self.set_text(text);
+        }
+      }
+      Move::Single(Direction::Down) if self.mode == CommandMode::Command => {
+        if let Some(text) = self.input.newer(history) {
+          self.set_text(text);
+        }
+      }
 
       _ => {}
     }
@@ -639,6 +738,7 @@ impl CommandState {
   fn perform_edit(&mut self, e: be_input::Edit) {
     use be_input::Edit;
 
+    self.input.reset();
     match e {
       Edit::Insert(c) => {
         self.text.insert(self.cursor, c);
@@ -658,6 +758,26 @@ impl CommandState {
     }
   }
 
+  /// Completes a command, with paths relative to `root`. Returns `false` if
+  /// there was nothing to complete.
+  fn complete(&mut self, root: &Path) -> bool {
+    if self.mode != CommandMode::Command {
+      return false;
+    }
+    match self.input.complete(&self.text, root) {
+      Some(text) => {
+        self.set_text(text);
+        true
+      }
+      None => false,
+    }
+  }
+
+  pub fn set_text(&mut self, text: String) {
+    self.cursor = text.len();
+    self.text = text;
+  }
+
   fn move_cursor(&mut self, dist: i32) {
     if dist >= 0 {
       for c in self.text[self.cursor..].graphemes(true).take(dist as usize) {
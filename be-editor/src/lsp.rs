@@ -1,6 +1,6 @@
 use std::{cell::RefCell, ops::Range, rc::Rc};
 
-use be_doc::{Change, DocumentSnapshot, Edit};
+use be_doc::{Anchor, Change, Cursor, Document, DocumentSnapshot, Edit, Gravity};
 use be_lsp::{LanguageClientState, LanguageServerKey, TextEdit, command, types};
 use be_task::Task;
 
@@ -13,7 +13,7 @@ pub struct LspState {
 
   document_version:       u32,
   pub completions:        CompletionsState,
-  pub goto_definition:    Option<Task<Option<Vec<(types::Uri, Range<usize>)>>>>,
+  pub goto_definition:    Option<GotoTask>,
   pub(crate) diagnostics: Vec<Diagnostic>,
 
   // FIXME: ew.
@@ -34,12 +34,25 @@ pub struct CompletionsState {
 }
 
 pub struct SaveTask {
-  task:    Task<Vec<TextEdit>>,
-  started: std::time::Instant,
+  task:             Task<Vec<TextEdit>>,
+  started:          std::time::Instant,
+  /// Overwrite the file even if it changed on disk, as with `:w!`.
+  pub(crate) force: bool,
+}
+
+pub struct GotoTask {
+  task:    Task<Option<Vec<(types::Uri, Range<usize>)>>>,
+  /// Where the definition was requested from. If the document changes before
+  /// the server replies, the offsets in the reply are stale, so the request is
+  /// sent again from here.
+  from:    Anchor,
+  version: u32,
 }
 
 pub struct Diagnostic {
-  pub range:   Range<usize>,
+  /// Anchored in the document, so that the range follows edits made before
+  /// the server publishes diagnostics for them.
+  pub range:   Range<Anchor>,
   pub message: String,
   pub level:   DiagnosticLevel,
 }
@@ -76,51 +89,64 @@ impl EditorState {
   }
 
   pub(crate) fn lsp_update_diagnostics(&mut self) {
-    if self.file.is_none() {
-      return;
-    }
-
-    // TODO: Use drain()
-    for range in self.lsp.diagnostics.iter().map(|d| d.range.clone()).collect::<Vec<_>>() {
-      self.damage_range(range);
-    }
-    self.lsp.diagnostics.clear();
-
-    let file = self.file.as_ref().unwrap();
+    let Some(file) = &self.file else { return };
 
+    // The ranges from the server are only valid for the version of the
+    // document they were published for. Until the server catches up with an
+    // edit, the anchored diagnostics from before it are kept.
+    let mut published = None::<Vec<_>>;
     self.lsp.client.servers(|state| {
-      if let Some(file) = state.files.get(file.path()) {
-        self.lsp.diagnostics.extend(file.diagnostics.iter().map(|d| Diagnostic {
-          range:   d.range.clone(),
-          message: d.message.clone(),
-          level:   match d.severity {
+      if let Some(file) = state.files.get(file.path())
+        && file.diagnostics_version == file.version
+      {
+        published.get_or_insert_default().extend(file.diagnostics.iter().map(|d| {
+          let level = match d.severity {
             Some(types::DiagnosticSeverity::Error) => DiagnosticLevel::Error,
             Some(types::DiagnosticSeverity::Warning) => DiagnosticLevel::Warning,
             _ => DiagnosticLevel::Error,
-          },
+          };
+          (d.range.clone(), d.message.clone(), level)
         }));
       }
     });
+    let Some(published) = published else { return };
+
+    for diagnostic in std::mem::take(&mut self.lsp.diagnostics) {
+      self.damage_range(diagnostic.offsets(&self.doc));
+      self.doc.remove_anchor(diagnostic.range.start);
+      self.doc.remove_anchor(diagnostic.range.end);
+    }
 
-    // TODO: Get rid of this collect()
-    for range in self.lsp.diagnostics.iter().map(|d| d.range.clone()).collect::<Vec<_>>() {
-      self.damage_range(range);
+    for (range, message, level) in published {
+      self.damage_range(range.clone());
+      let start = self.doc.anchor(range.start, Gravity::Right);
+      let end = self.doc.anchor(range.end, Gravity::Left);
+      self.lsp.diagnostics.push(Diagnostic { range: start..end, message, level });
     }
 
-    self.lsp.diagnostics.sort_by_key(|d| d.range.start);
+    let doc = &self.doc;
+    self.lsp.diagnostics.sort_by_key(|d| d.offsets(doc).start);
   }
 
   pub(crate) fn lsp_update_goto_definition(&mut self) {
-    if let Some(task) = &self.lsp.goto_definition {
-      if let Some(Some(defs)) = task.completed() {
-        if defs.len() == 1 {
-          self.lsp_goto_location(&defs[0].0, defs[0].1.start);
-        } else {
-          warn!("unhandled multiple definitions: {defs:?}");
-        }
+    let Some(goto) = &self.lsp.goto_definition else { return };
+    let Some(defs) = goto.task.completed() else { return };
+
+    let goto = self.lsp.goto_definition.take().unwrap();
+    let from = self.doc.anchor_offset(goto.from);
+    self.doc.remove_anchor(goto.from);
 
-        self.lsp.goto_definition = None;
+    if goto.version != self.lsp.document_version {
+      if let Some(from) = from {
+        self.lsp_send_goto_definition(self.doc.offset_to_cursor(from));
       }
+      return;
+    }
+
+    match defs {
+      Some(defs) if defs.len() == 1 => self.lsp_goto_location(&defs[0].0, defs[0].1.start),
+      Some(defs) => warn!("unhandled multiple definitions: {defs:?}"),
+      None => {}
     }
   }
 
@@ -128,6 +154,7 @@ impl EditorState {
     let Some(def_path) = uri.to_file_path() else { return };
 
     let cursor = self.doc.offset_to_cursor(pos);
+    self.record_jump();
     if let Some(file) = &self.file
       && let Some(send) = &self.send
     {
@@ -160,14 +187,15 @@ impl EditorState {
     });
   }
 
-  pub(crate) fn lsp_on_save(&mut self) {
+  pub(crate) fn lsp_on_save(&mut self, force: bool) {
     let Some(file) = &self.file else { return };
 
     let task = self
       .lsp
       .client
       .send_first_capable(&command::DocumentFormat { path: file.path().to_path_buf() });
-    self.lsp.save_task = task.map(|t| SaveTask { task: t, started: std::time::Instant::now() });
+    self.lsp.save_task =
+      task.map(|t| SaveTask { task: t, started: std::time::Instant::now(), force });
   }
 
   pub(crate) fn lsp_finish_on_save(&mut self) {
@@ -269,14 +297,22 @@ impl EditorState {
   pub fn active_completion(&self) -> Option<usize> { self.lsp.completions.active }
 
   pub(crate) fn lsp_request_goto_definition(&mut self) {
+    self.lsp_send_goto_definition(self.cursor);
+  }
+
+  fn lsp_send_goto_definition(&mut self, cursor: Cursor) {
     let Some(file) = &self.file else { return };
 
-    let task = self.lsp.client.send_first_capable(&command::GotoDefinition {
-      path:   file.path().to_path_buf(),
-      cursor: self.cursor,
-    });
+    let task = self
+      .lsp
+      .client
+      .send_first_capable(&command::GotoDefinition { path: file.path().to_path_buf(), cursor });
 
-    self.lsp.goto_definition = task;
+    self.lsp.goto_definition = task.map(|task| GotoTask {
+      task,
+      from: self.doc.anchor(self.doc.cursor_offset(cursor), Gravity::Left),
+      version: self.lsp.document_version,
+    });
   }
 
   pub(crate) fn lsp_update_completions(&mut self) {
@@ -365,11 +401,16 @@ impl CompletionsState {
 }
 
 impl Diagnostic {
-  pub fn highlight(&self) -> Highlight<'_> {
-    Highlight {
-      start: self.range.start,
-      end:   self.range.end,
-      key:   HighlightKey::Diagnostic(self.level),
-    }
+  /// The current range of the diagnostic in `doc`.
+  pub fn offsets(&self, doc: &Document) -> Range<usize> {
+    let end = doc.anchor_offset(self.range.end).unwrap_or(0);
+    let start = doc.anchor_offset(self.range.start).unwrap_or(0);
+    // The start moves past text typed into an empty range.
+    start.min(end)..end
+  }
+
+  pub fn highlight(&self, doc: &Document) -> Highlight<'_> {
+    let range = self.offsets(doc);
+    Highlight { start: range.start, end: range.end, key: HighlightKey::Diagnostic(self.level) }
   }
 }
@@ -0,0 +1,162 @@
+//! Vim marks. Each mark is a [`be_doc::Anchor`] in the document, so it keeps
+//! pointing at the same text as the document is edited. Marks `a` to `z` are
+//! set with `m`, and a few are set automatically:
+//! - `'` is where the cursor was before the last jump.
+//! - `.` is the start of the last change.
+//! - `^` is where insert mode was last left.
+
+use std::{collections::BTreeMap, io};
+
+use be_doc::{Anchor, Cursor, Gravity};
+use serde::{Deserialize, Serialize};
+
+use crate::{EditorState, fs, status::Status};
+
+/// The marks of a file, as stored in the cache directory.
+#[derive(Serialize, Deserialize)]
+struct MarksFile {
+  /// The hash of the file when the marks were saved. If the file is changed
+  /// outside the editor, the marks no longer apply.
+  hash:  u64,
+  marks: BTreeMap<char, usize>,
+}
+
+impl EditorState {
+  /// Sets the mark `name` at `offset`, replacing the previous one.
+  pub(crate) fn set_mark(&mut self, name: char, offset: usize) {
+    // Named marks stay on the character they were set on, and automatic marks
+    // stay at the start of text inserted there.
+    let gravity = if name.is_ascii_lowercase() { Gravity::Right } else { Gravity::Left };
+    let anchor = self.doc.anchor(offset, gravity);
+    if let Some(old) = self.marks.insert(name, anchor) {
+      self.doc.remove_anchor(old);
+    }
+  }
+
+  /// Returns the position of the mark `name`, if it's set.
+  pub fn mark(&self, name: char) -> Option<Cursor> {
+    let offset = self.doc.anchor_offset(*self.marks.get(&name)?)?;
+    Some(self.doc.offset_to_cursor(offset))
+  }
+
+  /// Anchors `cursor` in the document, so that views can keep a position that
+  /// follows edits. The anchor goes away when another file is opened.
+  pub fn anchor_cursor(&mut self, cursor: Cursor) -> Anchor {
+    self.doc.anchor(self.doc.cursor_offset(cursor), Gravity::Left)
+  }
+
+  pub fn anchored_cursor(&self, anchor: Anchor) -> Option<Cursor> {
+    Some(self.doc.offset_to_cursor(self.doc.anchor_offset(anchor)?))
+  }
+
+  pub fn remove_anchor(&mut self, anchor: Anchor) { self.doc.remove_anchor(anchor); }
+
+  /// Handles `ma`. In a file without unsaved changes, the marks are saved
+  /// right away, and otherwise they are saved with the file.
+  pub(crate) fn perform_set_mark(&mut self, name: char) {
+    self.set_mark(name, self.doc.cursor_offset(self.cursor));
+
+    if !self.unsaved()
+      && let Err(e) = self.save_marks()
+    {
+      warn!("failed to save marks: {e}");
+    }
+  }
+
+  /// Jumps to a mark, as with `'a` or `` `a ``. A linewise jump goes to the
+  /// first non-blank character of the line.
+  pub(crate) fn jump_to_mark(&mut self, name: char, linewise: bool) {
+    let Some(cursor) = self.mark(name) else {
+      self.status = Some(Status::for_error_message(format!("mark not set: {name}")));
+      return;
+    };
+
+    self.record_jump();
+    self.move_to_line(cursor.line);
+    if linewise {
+      self.perform_move(be_input::Move::LineStartOfText, None);
+    } else {
+      self.move_to_col(cursor.column);
+    }
+  }
+
+  /// Reads the marks of the current file from the cache directory.
+  pub(crate) fn load_marks(&mut self) {
+    self.marks.clear();
+
+    let Some(file) = self.file.as_ref().filter(|f| !f.large) else { return };
+    let Ok(cache) = fs::cache_path("marks", file.path()) else { return };
+    let Ok(text) = std::fs::read_to_string(&cache) else { return };
+
+    match serde_json::from_str::<MarksFile>(&text) {
+      Ok(marks) if marks.hash == self.doc.content_hash() => {
+        for (name, offset) in marks.marks {
+          self.set_mark(name, offset);
+        }
+      }
+      Ok(_) => {}
+      Err(e) => warn!("invalid marks file {}: {e}", cache.display()),
+    }
+  }
+
+  /// Writes the marks of the current file into the cache directory.
+  pub(crate) fn save_marks(&self) -> io::Result<()> {
+    let Some(file) = self.file.as_ref().filter(|f| !f.large) else { return Ok(()) };
+
+    let path = fs::cache_path("marks", file.path())?;
+    std::fs::create_dir_all(path.parent().unwrap())?;
+
+    let marks = self
+      .marks
+      .iter()
+      .filter_map(|(&name, &anchor)| Some((name, self.doc.anchor_offset(anchor)?)))
+      .collect();
+    let marks = MarksFile { hash: self.doc.content_hash(), marks };
+    std::fs::write(path, serde_json::to_vec(&marks)?)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::tests::*;
+
+  #[test]
+  fn named() {
+    let mut editor = editor("one\n  two\nthree\n");
+    editor.keys("jlllmagg");
+    editor.keys("'a");
+    editor.check(expect![@"one\n  ⟦t⟧wo\nthree\n"]);
+
+    // The mark follows the text it was set on.
+    editor.keys("ggOzero<Esc>gg");
+    editor.keys("`a");
+    editor.check(expect![@"zero\none\n  t⟦w⟧o\nthree\n"]);
+
+    editor.keys("ggd'a");
+    editor.check(expect![@"thr⟦e⟧e\n"]);
+  }
+
+  #[test]
+  fn automatic() {
+    let mut editor = editor("one\ntwo\nthree\n");
+    editor.keys("j$aend<Esc>G");
+    editor.keys("`.");
+    editor.check(expect![@"one\ntwoen⟦d⟧\nthree\n"]);
+    editor.keys("G`^");
+    editor.check(expect![@"one\ntwoen⟦d⟧\nthree\n"]);
+
+    // `` jumps back and forth.
+    editor.keys("gg``");
+    editor.check(expect![@"one\ntwoen⟦d⟧\nthree\n"]);
+    editor.keys("``");
+    editor.check(expect![@"on⟦e⟧\ntwoend\nthree\n"]);
+  }
+
+  #[test]
+  fn unset() {
+    let mut editor = editor("one\n");
+    editor.keys("l'b");
+    editor.check(expect![@"o⟦n⟧e\n"]);
+    assert_eq!(editor.status.as_ref().unwrap().message, "mark not set: b");
+  }
+}
@@ -1,27 +1,53 @@
 use std::num::NonZero;
 
 use be_doc::{Column, Line};
-use be_input::{ChangeDirection, Direction, Move};
+use be_input::{ChangeDirection, Direction, Move, VerticalDirection};
 
 use crate::EditorState;
 
 impl EditorState {
   pub(crate) fn perform_move(&mut self, m: be_input::Move, count: Option<NonZero<u32>>) {
     if let Some(command) = &mut self.command {
-      command.perform_move(m);
+      command.perform_move(m, &self.history);
       return;
     }
 
     let count = count.map(|c| c.get());
 
+    if matches!(
+      m,
+      Move::FileStart
+        | Move::FileEnd
+        | Move::MatchingBracket
+        | Move::Result(_)
+        | Move::Diagnostic(_)
+    ) {
+      self.record_jump();
+    }
+
     match m {
       Move::Single(Direction::Left) => self.move_col_rel(-(count.unwrap_or(1) as i32)),
       Move::Single(Direction::Right) => self.move_col_rel(count.unwrap_or(1) as i32),
-      Move::Single(Direction::Up) => self.move_line_rel(-(count.unwrap_or(1) as i32)),
-      Move::Single(Direction::Down) => self.move_line_rel(count.unwrap_or(1) as i32),
+      Move::Single(Direction::Up) => self.move_vertical(-(count.unwrap_or(1) as i32), false),
+      Move::Single(Direction::Down) => self.move_vertical(count.unwrap_or(1) as i32, false),
+      Move::ScreenLine(VerticalDirection::Up) => {
+        self.move_vertical(-(count.unwrap_or(1) as i32), true)
+      }
+      Move::ScreenLine(VerticalDirection::Down) => {
+        self.move_vertical(count.unwrap_or(1) as i32, true)
+      }
 
       Move::LineEnd => self.move_to_col(Column::MAX),
       Move::LineStart => self.move_to_col(Column(0)),
+      Move::LineStartOfText => {
+        let indent = self
+          .doc
+          .line(self.cursor.line)
+          .graphemes()
+          .take_while(|g| g.chars().all(|c| c.is_whitespace()))
+          .count();
+        self.move_to_col(Column(indent));
+      }
 
       Move::FileStart => self.move_to_line(count.map_or(Line(0), |v| be_doc::Line(v as usize - 1))),
       Move::FileEnd => {
@@ -202,14 +228,20 @@ impl EditorState {
       }
 
       Move::Result(dir) => {
-        if let Some(search) = self.search_text.as_ref() {
+        if let Some(search) = self.search.as_ref() {
           for _ in 0..count.unwrap_or(1) {
-            let offset = self.doc.cursor_offset(self.cursor) + 1;
+            let offset = self.doc.cursor_offset(self.cursor);
             if let Some(res) = match dir {
-              ChangeDirection::Next => self.doc.find_from(offset, search).next(),
-              ChangeDirection::Prev => self.doc.rfind_from(offset, search).next(),
+              ChangeDirection::Next => {
+                let next = self.doc.range(offset..).chars().next().map_or(0, char::len_utf8);
+                self.doc.find_regex_from(offset + next, search).next()
+              }
+              ChangeDirection::Prev => {
+                let end = self.doc.byte_of_line_end(self.cursor.line);
+                self.doc.rfind_regex_from(end, search).find(|r| r.start < offset)
+              }
             } {
-              let cursor = self.doc.offset_to_cursor(res);
+              let cursor = self.doc.offset_to_cursor(res.start);
               self.cursor = cursor;
             } else {
               break;
@@ -237,11 +269,12 @@ impl EditorState {
         for _ in 0..count.unwrap_or(1) {
           let offset = self.doc.cursor_offset(self.cursor);
 
-          if let Some(d) = match dir {
-            ChangeDirection::Next => self.lsp.diagnostics.iter().find(|d| d.range.start > offset),
-            ChangeDirection::Prev => self.lsp.diagnostics.iter().rfind(|d| d.range.start < offset),
+          let mut starts = self.lsp.diagnostics.iter().map(|d| d.offsets(&self.doc).start);
+          if let Some(start) = match dir {
+            ChangeDirection::Next => starts.find(|&start| start > offset),
+            ChangeDirection::Prev => starts.rfind(|&start| start < offset),
           } {
-            let cursor = self.doc.offset_to_cursor(d.range.start);
+            let cursor = self.doc.offset_to_cursor(start);
             self.move_to_line(cursor.line);
             self.move_to_col(cursor.column);
           } else {
@@ -257,6 +290,18 @@ impl EditorState {
       }
 
       Move::BackDefinition => unreachable!("handled in editor view"),
+      Move::Jump(dir) => self.perform_jump(dir, count),
+      Move::Fold(dir) => self.move_to_fold(dir, count.unwrap_or(1)),
+
+      Move::SwapAnchor if self.selection().is_some() => {
+        std::mem::swap(&mut self.visual_anchor, &mut self.cursor);
+      }
+
+      Move::TextObject { inner, object } if self.selection().is_some() => {
+        self.select_text_object(object, inner);
+      }
+
+      Move::Mark { name, linewise } => self.jump_to_mark(name, linewise),
 
       _ => {}
     }
@@ -321,13 +366,13 @@ fn opposite_brace(c: char) -> char {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum WordKind {
+pub(crate) enum WordKind {
   Word,
   Punctuation,
   Blank,
 }
 
-fn word_kind(c: char) -> WordKind {
+pub(crate) fn word_kind(c: char) -> WordKind {
   match c {
     'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => WordKind::Word,
     ' ' | '\r' | '\n' | '\t' => WordKind::Blank,
@@ -338,7 +383,7 @@ fn word_kind(c: char) -> WordKind {
 #[cfg(test)]
 mod tests {
   use crate::tests::*;
-  use be_input::Move;
+  use be_input::{ChangeDirection, Move};
   use std::num::NonZero;
 
   #[test]
@@ -496,4 +541,44 @@ mod tests {
       4
     "#]);
   }
+
+  #[test]
+  fn search_results() {
+    let mut editor = editor("Foo bar\nfoo baz\nfoobar\n");
+    editor.keys(r"/\<foo\><CR>");
+    editor.check_repeated(
+      |e| e.perform_move(Move::Result(ChangeDirection::Next), None),
+      &[
+        expect![@r#"
+          ⟦F⟧oo bar
+          foo baz
+          foobar
+        "#],
+        expect![@r#"
+          Foo bar
+          ⟦f⟧oo baz
+          foobar
+        "#],
+        expect![@r#"
+          Foo bar
+          ⟦f⟧oo baz
+          foobar
+        "#],
+      ],
+    );
+
+    editor.keys("/Foo<CR>N");
+    editor.check(expect![@r#"
+      ⟦F⟧oo bar
+      foo baz
+      foobar
+    "#]);
+
+    editor.keys(r"/z\nf<CR>n");
+    editor.check(expect![@r#"
+      Foo bar
+      foo ba⟦z⟧
+      foobar
+    "#]);
+  }
 }
@@ -0,0 +1,146 @@
+//! Auto-pairs. Typing an opener from the language's `auto-pairs` also inserts
+//! its closer, and an empty pair is edited as a unit: typing the closer steps
+//! over it, backspace deletes both halves, and enter splits a bracket pair onto
+//! an indented block. Nothing is paired inside strings or comments.
+
+use be_config::AutoPair;
+use be_doc::Change;
+use be_input::VerticalDirection;
+
+use crate::EditorState;
+
+impl EditorState {
+  fn auto_pairs(&self) -> Vec<AutoPair> {
+    let Some(ft) = self.filetype else { return vec![] };
+    self.config.borrow().languages.get(&ft).map(|l| l.auto_pairs.clone()).unwrap_or_default()
+  }
+
+  /// The characters on either side of the cursor.
+  fn chars_around_cursor(&self) -> (Option<char>, Option<char>) {
+    let offset = self.doc.cursor_offset(self.cursor);
+    (self.doc.range(..offset).chars().next_back(), self.doc.range(offset..).chars().next())
+  }
+
+  /// Handles typing `c` in insert mode. Returns `false` if `c` should be
+  /// inserted as usual.
+  pub(crate) fn auto_pair(&mut self, c: char) -> bool {
+    let pairs = self.auto_pairs();
+    let (prev, next) = self.chars_around_cursor();
+
+    if c == '\n' {
+      return match (prev, next) {
+        (Some(open), Some(close)) if open != close => {
+          pairs.iter().any(|p| p.open == open && p.close == close) && self.split_pair()
+        }
+        _ => false,
+      };
+    }
+
+    if next == Some(c) && pairs.iter().any(|p| p.close == c) {
+      self.move_graphemes(1);
+      return true;
+    }
+
+    let Some(pair) = pairs.iter().find(|p| p.open == c) else { return false };
+    // Typing an opener in front of a word shouldn't close it right away.
+    if !next.is_none_or(|n| n.is_whitespace() || pairs.iter().any(|p| p.close == n)) {
+      return false;
+    }
+    // Quotes after a word are most likely an apostrophe, like in `don't`.
+    if pair.open == pair.close && prev.is_some_and(char::is_alphanumeric) {
+      return false;
+    }
+
+    let offset = self.doc.cursor_offset(self.cursor);
+    if self.highligher.as_ref().is_some_and(|h| h.in_string_or_comment(&self.doc, offset)) {
+      return false;
+    }
+
+    self.change(Change::insert(offset, &format!("{}{}", pair.open, pair.close)));
+    self.move_graphemes(1);
+    true
+  }
+
+  /// Handles backspace in insert mode, which deletes both halves of an empty
+  /// pair. Returns `false` if nothing was deleted.
+  pub(crate) fn auto_pair_backspace(&mut self) -> bool {
+    let (Some(open), Some(close)) = self.chars_around_cursor() else { return false };
+    if !self.auto_pairs().iter().any(|p| p.open == open && p.close == close) {
+      return false;
+    }
+
+    self.move_graphemes(-1);
+    let offset = self.doc.cursor_offset(self.cursor);
+    self.change(Change::remove(offset..offset + open.len_utf8() + close.len_utf8()));
+    true
+  }
+
+  /// Splits an empty bracket pair onto three lines, with the cursor on the
+  /// indented middle line and the closer lined up with the opener's line.
+  fn split_pair(&mut self) -> bool {
+    let indent = self
+      .doc
+      .line(self.cursor.line)
+      .chars()
+      .take_while(|&c| c == ' ' || c == '\t')
+      .collect::<String>();
+    let le = self.line_ending.as_str();
+
+    let offset = self.doc.cursor_offset(self.cursor);
+    self.change(Change::insert(offset, &format!("{le}{le}{indent}")));
+    self.move_to_line(self.cursor.line + 1);
+    self.move_to_col(be_doc::Column(0));
+    self.trim_line(self.cursor.line - 1);
+    self.auto_indent(VerticalDirection::Up);
+    true
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::tests::*;
+
+  fn rust(src: &str) -> TestEditor {
+    let mut editor = editor(src);
+    let filetype = editor.config.borrow().language_for_filename("a.rs");
+    editor.filetype = filetype;
+    editor
+  }
+
+  #[test]
+  fn open_and_close() {
+    let mut editor = rust("\n");
+    editor.keys("ifoo(");
+    editor.check(expect![@"foo(‖)\n"]);
+    editor.keys("\"bar\")");
+    editor.check(expect![@"foo(\"bar\")‖\n"]);
+
+    // Nothing is paired in front of a word.
+    let mut editor = rust("bar\n");
+    editor.keys("i(");
+    editor.check(expect![@"(‖bar\n"]);
+  }
+
+  #[test]
+  fn backspace() {
+    let mut editor = rust("\n");
+    editor.keys("ifoo([<BS><BS>");
+    editor.check(expect![@"foo‖\n"]);
+    editor.keys("<Esc>u");
+    editor.check(expect![@"⟦ ⟧\n"]);
+  }
+
+  #[test]
+  fn enter() {
+    let mut editor = rust("\n");
+    editor.keys("ifn foo() {<CR>bar");
+    editor.check(expect![@"fn foo() {\n  bar‖\n}\n"]);
+  }
+
+  #[test]
+  fn no_language() {
+    let mut editor = editor("\n");
+    editor.keys("i(");
+    editor.check(expect![@"(‖\n"]);
+  }
+}
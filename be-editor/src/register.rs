@@ -0,0 +1,409 @@
+use std::collections::BTreeMap;
+
+use be_doc::{Change, Cursor, Edit, Line, VisualColumn};
+use be_input::Move;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{EditorState, status::Status};
+
+/// The contents of a single register.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Register {
+  pub text: String,
+  pub kind: RegisterKind,
+}
+
+/// How the text in a register was copied, which changes how it is pasted.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum RegisterKind {
+  #[default]
+  Charwise,
+  /// Whole lines. The text always ends with a newline.
+  Linewise,
+  /// A block of text, with one line of the block per line of text.
+  Blockwise,
+}
+
+/// All the registers, shared between every editor in a workspace. The
+/// clipboard register (`"+`) is not stored here, as it is backed by the
+/// system clipboard.
+#[derive(Default)]
+pub struct Registers {
+  unnamed:  Register,
+  named:    BTreeMap<char, Register>,
+  numbered: [Register; 10],
+}
+
+impl Register {
+  pub fn new(text: impl Into<String>, kind: RegisterKind) -> Self {
+    let mut text = text.into();
+    if kind == RegisterKind::Linewise && !text.ends_with('\n') {
+      text.push('\n');
+    }
+
+    Register { text, kind }
+  }
+
+  fn append(&mut self, other: Register) {
+    if other.kind == RegisterKind::Linewise && !self.text.is_empty() && !self.text.ends_with('\n') {
+      self.text.push('\n');
+    }
+
+    self.text.push_str(&other.text);
+    if other.kind == RegisterKind::Linewise {
+      self.kind = RegisterKind::Linewise;
+    }
+  }
+}
+
+impl Registers {
+  /// Returns the register with the given name. `"` is the unnamed register.
+  pub fn get(&self, name: char) -> Option<&Register> {
+    match name {
+      '"' => Some(&self.unnamed),
+      '0'..='9' => Some(&self.numbered[name as usize - '0' as usize]),
+      'a'..='z' | 'A'..='Z' => self.named.get(&name.to_ascii_lowercase()),
+      _ => None,
+    }
+  }
+
+  /// Lists all non-empty registers, in the order `:registers` shows them.
+  pub fn list(&self) -> impl Iterator<Item = (char, &Register)> {
+    std::iter::once(('"', &self.unnamed))
+      .chain(self.numbered.iter().enumerate().map(|(i, r)| ((b'0' + i as u8) as char, r)))
+      .chain(self.named.iter().map(|(c, r)| (*c, r)))
+      .filter(|(_, r)| !r.text.is_empty())
+  }
+
+  /// Stores yanked text. Without a register name, this fills `"0`. Returns an
+  /// error if the named register can't be written, in which case the text is
+  /// still kept in the unnamed register.
+  pub fn yank(&mut self, name: Option<char>, register: Register) -> Result<(), String> {
+    let res = match name {
+      None => {
+        self.numbered[0] = register.clone();
+        Ok(())
+      }
+      Some(name) => self.store_named(name, register.clone()),
+    };
+
+    self.unnamed = register;
+    res
+  }
+
+  /// Stores deleted text. Without a register name, deletes that span lines are
+  /// pushed onto the numbered registers `"1` to `"9`. Errors like `yank`.
+  pub fn delete(&mut self, name: Option<char>, register: Register) -> Result<(), String> {
+    let res = match name {
+      None => {
+        if register.kind == RegisterKind::Linewise || register.text.contains('\n') {
+          self.numbered[1..].rotate_right(1);
+          self.numbered[1] = register.clone();
+        }
+        Ok(())
+      }
+      Some(name) => self.store_named(name, register.clone()),
+    };
+
+    self.unnamed = register;
+    res
+  }
+
+  /// Replaces the contents of a register, as when recording a macro. Uppercase
+  /// names append to the register.
+  pub fn set(&mut self, name: char, register: Register) -> Result<(), String> {
+    match name {
+      '"' => self.unnamed = register,
+      '0'..='9' => self.numbered[name as usize - '0' as usize] = register,
+      _ => self.store_named(name, register)?,
+    }
+
+    Ok(())
+  }
+
+  /// Stores text in a letter register. The unnamed and clipboard registers are
+  /// filled by the caller, and the numbered registers are only filled by yanks
+  /// and deletes without a register name.
+  fn store_named(&mut self, name: char, register: Register) -> Result<(), String> {
+    match name {
+      '"' | '+' => {}
+      'a'..='z' => {
+        self.named.insert(name, register);
+      }
+      'A'..='Z' => self.named.entry(name.to_ascii_lowercase()).or_default().append(register),
+      _ => return Err(format!("register {name} can't be written to")),
+    }
+
+    Ok(())
+  }
+}
+
+impl EditorState {
+  /// Stores yanked text in the pending register.
+  pub(crate) fn yank_register(&mut self, register: Register) {
+    match self.pending_register.take() {
+      Some('_') => {}
+      name => {
+        if name == Some('+') {
+          self.clipboard.copy(&register.text);
+        }
+        if let Err(e) = self.registers.yank(name, register) {
+          self.status = Some(Status::for_error_message(e));
+        }
+      }
+    }
+  }
+
+  /// Stores deleted text in the pending register.
+  pub(crate) fn delete_register(&mut self, register: Register) {
+    match self.pending_register.take() {
+      Some('_') => {}
+      name => {
+        if name == Some('+') {
+          self.clipboard.copy(&register.text);
+        }
+        if let Err(e) = self.registers.delete(name, register) {
+          self.status = Some(Status::for_error_message(e));
+        }
+      }
+    }
+  }
+
+  /// Reads the pending register, or the unnamed register if none was given.
+  pub(crate) fn read_register(&mut self) -> Register {
+    match self.pending_register.take() {
+      Some('+') => {
+        let text = self.clipboard.paste();
+        let kind =
+          if text.ends_with('\n') { RegisterKind::Linewise } else { RegisterKind::Charwise };
+        Register { text, kind }
+      }
+      name => self.registers.get(name.unwrap_or('"')).cloned().unwrap_or_default(),
+    }
+  }
+
+  /// Shows the contents of all registers in the status bar.
+  pub fn show_registers(&mut self) {
+    let listing = self
+      .registers
+      .list()
+      .map(|(name, register)| {
+        let text = register.text.replace('\n', "^J");
+        format!("\"{name} {}", text.chars().take(40).collect::<String>())
+      })
+      .collect::<Vec<_>>()
+      .join("  ");
+
+    self.status = Some(Status::for_success(listing));
+  }
+
+  /// Pastes the pending register `count` times, as with `3p`.
+  pub(crate) fn paste(&mut self, after: bool, count: usize) {
+    let mut register = self.read_register();
+    if register.text.is_empty() {
+      return;
+    }
+
+    if count > 1 {
+      register.text = match register.kind {
+        RegisterKind::Blockwise => {
+          register.text.split('\n').map(|line| line.repeat(count)).collect::<Vec<_>>().join("\n")
+        }
+        _ => register.text.repeat(count),
+      };
+    }
+
+    match register.kind {
+      RegisterKind::Charwise => {
+        if after {
+          self.move_graphemes(1);
+        }
+
+        self.change(Change::insert(self.doc.cursor_offset(self.cursor), &register.text));
+        self.move_graphemes(register.text.graphemes(true).count().saturating_sub(1) as isize);
+      }
+      RegisterKind::Linewise => {
+        let line = if after { self.cursor.line + 1 } else { self.cursor.line };
+        let idx = self.doc.byte_of_line(line);
+
+        if line.0 == self.doc.len_lines()
+          && idx != 0
+          && self.doc.byte_of_line_end(self.max_line()) == self.doc.len()
+        {
+          // The last line has no terminator, so add one before the pasted lines.
+          let text = format!(
+            "{}{}",
+            self.line_ending.as_str(),
+            register.text.strip_suffix('\n').unwrap_or(&register.text)
+          );
+          self.change(Change::insert(idx, &text));
+        } else {
+          self.change(Change::insert(idx, &register.text));
+        }
+
+        self.move_to_line(line);
+        self.perform_move(Move::LineStartOfText, None);
+      }
+      RegisterKind::Blockwise => {
+        let column = self.doc.visual_column(self.cursor).0 + if after { 1 } else { 0 };
+
+        let group = self.current_edit.is_none();
+        if group {
+          self.current_edit = Some(Edit::empty());
+        }
+
+        for (i, text) in register.text.split('\n').enumerate() {
+          let line = self.cursor.line + i as i32;
+          while line.0 >= self.doc.len_lines() {
+            self.change(Change::insert(self.doc.len(), self.line_ending.as_str()));
+          }
+
+          let width = self.doc.visual_column(Cursor {
+            line,
+            column: be_doc::Column(self.doc.line(line).graphemes().count()),
+            target_column: VisualColumn(0),
+          });
+          let offset = if width.0 < column {
+            let end = self.doc.byte_of_line_end(line);
+            self.change(Change::insert(end, &" ".repeat(column - width.0)));
+            end + column - width.0
+          } else {
+            let column = self.doc.column_from_visual(line, VisualColumn(column));
+            self.doc.cursor_offset(Cursor { line, column, target_column: VisualColumn(0) })
+          };
+
+          self.change(Change::insert(offset, text));
+        }
+
+        if group {
+          self.remove_current_edit();
+        }
+        if after {
+          self.move_col_rel(1);
+        }
+      }
+    }
+  }
+
+  pub(crate) fn line_register(&self, start: Line, end: Line) -> Register {
+    let text = self.doc.range(self.doc.byte_of_line(start)..self.doc.byte_of_line(end + 1));
+    Register::new(text.to_string(), RegisterKind::Linewise)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::tests::editor;
+
+  #[test]
+  fn yank_and_paste_lines() {
+    let mut editor = editor("foo\nbar\n");
+
+    editor.keys("yyjp");
+    editor.check(expect![@r#"
+      foo
+      bar
+      ⟦f⟧oo
+    "#]);
+
+    editor.keys("P");
+    editor.check(expect![@r#"
+      foo
+      bar
+      ⟦f⟧oo
+      foo
+    "#]);
+  }
+
+  #[test]
+  fn paste_line_at_end_of_file() {
+    let mut editor = editor("foo\nbar");
+
+    editor.keys("yyjp");
+    editor.check(expect![@r#"
+      foo
+      bar
+      ⟦f⟧oo"#
+    ]);
+  }
+
+  #[test]
+  fn yank_motion() {
+    let mut editor = editor("foo bar\n");
+
+    editor.keys("wyb");
+    editor.check(expect![@"⟦f⟧oo bar\n"]);
+
+    editor.keys("$p");
+    editor.check(expect![@"foo barfoo⟦ ⟧\n"]);
+  }
+
+  #[test]
+  fn named_registers() {
+    let mut editor = editor("foo\nbar\nbaz\n");
+
+    editor.keys("\"ayyj\"Ayyjyy");
+    assert_eq!(editor.registers.get('a').unwrap().text, "foo\nbar\n");
+    assert_eq!(editor.registers.get('"').unwrap().text, "baz\n");
+
+    editor.keys("\"aP");
+    editor.check(expect![@r#"
+      foo
+      bar
+      ⟦f⟧oo
+      bar
+      baz
+    "#]);
+  }
+
+  #[test]
+  fn numbered_registers() {
+    let mut editor = editor("foo\nbar\nbaz\n");
+
+    editor.keys("yyddx");
+    assert_eq!(editor.registers.get('0').unwrap().text, "foo\n");
+    assert_eq!(editor.registers.get('1').unwrap().text, "foo\n");
+    assert_eq!(editor.registers.get('"').unwrap().text, "b");
+
+    editor.keys("dd");
+    assert_eq!(editor.registers.get('1').unwrap().text, "ar\n");
+    assert_eq!(editor.registers.get('2').unwrap().text, "foo\n");
+
+    // They can be read by name, but not written.
+    editor.keys("\"3yy");
+    assert_eq!(editor.status.as_ref().unwrap().message, "register 3 can't be written to");
+    assert_eq!(editor.registers.get('3').unwrap().text, "");
+    assert_eq!(editor.registers.get('"').unwrap().text, "baz\n");
+  }
+
+  #[test]
+  fn black_hole_register() {
+    let mut editor = editor("foo\nbar\n");
+
+    editor.keys("yyj\"_ddP");
+    editor.check(expect![@r#"
+      ⟦f⟧oo
+      foo
+    "#]);
+  }
+
+  #[test]
+  fn block_paste() {
+    let mut editor = editor("abc\nabc\nx\n");
+
+    editor.keys("<C-v>jy$p");
+    editor.check(expect![@r#"
+      abc⟦a⟧
+      abca
+      x
+    "#]);
+
+    editor.keys("jjp");
+    editor.check(expect![@r#"
+      abca
+      abca
+      x⟦a⟧
+       a
+    "#]);
+  }
+}
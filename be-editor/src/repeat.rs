@@ -0,0 +1,160 @@
+use std::num::NonZero;
+
+use be_input::{Action, Edit, Mode};
+
+use crate::EditorState;
+
+/// The actions that made up a change, so that it can be repeated with `.`.
+#[derive(Default)]
+pub(crate) struct RepeatState {
+  /// The change currently being typed. This is finished once the editor
+  /// returns to normal mode.
+  recording: Option<Vec<Action>>,
+  last:      Vec<Action>,
+  repeating: bool,
+}
+
+impl EditorState {
+  /// Called before `action` is performed, to record it as part of a change.
+  pub(crate) fn record_change(&mut self, action: &Action) {
+    if self.repeat.repeating {
+      return;
+    }
+
+    if let Some(recording) = &mut self.repeat.recording {
+      recording.push(action.clone());
+    } else if self.mode == Mode::Normal && starts_change(action) {
+      let mut recording = vec![];
+      if let Some(name) = self.pending_register {
+        recording.push(Action::SelectRegister { name });
+      }
+      recording.push(action.clone());
+      self.repeat.recording = Some(recording);
+    }
+  }
+
+  /// Called after an action is performed. The change is finished once back in
+  /// normal mode.
+  pub(crate) fn finish_change(&mut self) {
+    if self.mode == Mode::Normal
+      && !self.repeat.repeating
+      && let Some(recording) = self.repeat.recording.take()
+    {
+      self.repeat.last = recording;
+    }
+  }
+
+  /// Repeats the last change. A count replaces the count of the original
+  /// command, so `3.` after `dw` deletes three words.
+  pub(crate) fn repeat_change(&mut self, count: Option<NonZero<u32>>) {
+    let mut actions = self.repeat.last.clone();
+    let mut times = 1;
+
+    if let Some(count) = count {
+      match actions.iter_mut().find(|a| !matches!(a, Action::SelectRegister { .. })) {
+        Some(Action::Edit { count: c, .. }) => *c = Some(count),
+        _ => times = count.get(),
+      }
+    }
+
+    self.repeat.repeating = true;
+    for _ in 0..times {
+      for action in actions.iter().cloned() {
+        self.perform_action(action);
+      }
+    }
+    self.repeat.repeating = false;
+  }
+}
+
+fn starts_change(action: &Action) -> bool {
+  match action {
+    Action::Append { .. } | Action::SetMode { mode: Mode::Insert, .. } => true,
+    Action::Edit { e, .. } => !matches!(
+      e,
+      Edit::Undo
+        | Edit::Redo
+        | Edit::Earlier
+        | Edit::Later
+        | Edit::Yank(_)
+        | Edit::YankLine
+        | Edit::Visual(_)
+    ),
+    _ => false,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::tests::editor;
+
+  #[test]
+  fn repeat_delete() {
+    let mut editor = editor("foo bar baz qux\n");
+
+    editor.keys("dw");
+    editor.check(expect![@"⟦b⟧ar baz qux\n"]);
+
+    editor.keys(".");
+    editor.check(expect![@"⟦b⟧az qux\n"]);
+  }
+
+  #[test]
+  fn repeat_insert() {
+    let mut editor = editor("foo\nbar\n");
+
+    editor.keys("$ahi<Esc>j$.");
+    editor.check(expect![@r#"
+      foohi
+      barh⟦i⟧
+    "#]);
+
+    editor.keys("u");
+    editor.check(expect![@r#"
+      foohi
+      ba⟦r⟧
+    "#]);
+  }
+
+  #[test]
+  fn repeat_with_count() {
+    let mut editor = editor("abcdef\n");
+
+    editor.keys("x3.");
+    editor.check(expect![@"⟦e⟧f\n"]);
+  }
+
+  #[test]
+  fn repeat_replaces_count() {
+    let mut editor = editor("a b c d e f\n");
+
+    editor.keys("2dw3.");
+    editor.check(expect![@"⟦f⟧\n"]);
+
+    editor.keys("u");
+    editor.check(expect![@"⟦c⟧ d e f\n"]);
+  }
+
+  #[test]
+  fn repeat_change() {
+    let mut editor = editor("foo bar\nfoo bar\n");
+
+    editor.keys("cwbaz <Esc>j0.");
+    editor.check(expect![@r#"
+      baz bar
+      baz⟦ ⟧bar
+    "#]);
+  }
+
+  #[test]
+  fn repeat_paste_from_register() {
+    let mut editor = editor("foo\n");
+
+    editor.keys("\"ayyx\"ap.");
+    editor.check(expect![@r#"
+      oo
+      foo
+      ⟦f⟧oo
+    "#]);
+  }
+}
@@ -0,0 +1,89 @@
+use be_doc::{Change, Edit, LineEnding};
+
+use crate::{EditorState, status::Status};
+
+/// The options that `:set` knows about, for completion.
+pub const OPTIONS: &[&str] = &["fileformat", "nowrap", "wrap"];
+
+impl EditorState {
+  /// Runs `:set name` or `:set name=value`.
+  pub fn set_option(&mut self, name: &str, value: Option<&str>) {
+    match (name, value) {
+      ("wrap", None) | ("nowrap", None) => {
+        self.soft_wrap = Some(name == "wrap");
+        self.damage_all = true;
+      }
+      ("fileformat" | "ff", None) => {
+        self.status =
+          Some(Status::for_success(format!("fileformat={}", self.line_ending.fileformat())));
+      }
+      ("fileformat" | "ff", Some(value)) => match LineEnding::from_fileformat(value) {
+        Some(ending) => self.set_line_ending(ending),
+        None => {
+          self.status = Some(Status::for_error_message(format!("invalid fileformat: {value}")))
+        }
+      },
+      _ => self.status = Some(Status::for_error_message(format!("unknown option: {name}"))),
+    }
+  }
+
+  /// Converts every line terminator in the document to `ending`, as a single
+  /// undo step.
+  pub fn set_line_ending(&mut self, ending: LineEnding) {
+    let before = std::mem::replace(&mut self.line_ending, ending);
+
+    let ranges = self.doc.mismatched_line_endings(ending);
+    if ranges.is_empty() {
+      return;
+    }
+
+    let group = self.current_edit.is_none();
+    if group {
+      self.current_edit = Some(Edit::empty());
+    }
+    self.undo.convert_line_ending(before, ending);
+
+    // Replace from the end, so the earlier ranges stay valid.
+    for range in ranges.into_iter().rev() {
+      self.change(Change::replace(range, ending.as_str()));
+    }
+
+    if group {
+      self.remove_current_edit();
+    }
+    self.damage_all = true;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use be_doc::LineEnding;
+
+  use crate::tests::*;
+
+  #[test]
+  fn open_line() {
+    let mut editor = editor("foo\r\nbar\r\n");
+    editor.keys("obaz<Esc>");
+    editor.check(expect![@"foo\r\nba⟦z⟧\r\nbar\r\n"]);
+  }
+
+  #[test]
+  fn fileformat() {
+    let mut editor = editor("foo\r\nbar\nbaz\r\n");
+    editor.keys("j:set ff=unix<CR>");
+    editor.check(expect![@"foo\n⟦b⟧ar\nbaz\n"]);
+
+    // The conversion is a single undo step, which restores the line ending.
+    editor.keys("u");
+    assert_eq!(editor.doc.to_string(), "foo\r\nbar\nbaz\r\n");
+    assert_eq!(editor.line_ending(), LineEnding::CrLf);
+    editor.keys("<C-r>");
+    assert_eq!(editor.line_ending(), LineEnding::Lf);
+    editor.keys("u");
+
+    editor.keys(":set fileformat=dos<CR>");
+    assert_eq!(editor.doc.to_string(), "foo\r\nbar\r\nbaz\r\n");
+    assert_eq!(editor.line_ending(), LineEnding::CrLf);
+  }
+}
@@ -10,4 +10,7 @@ impl Status {
     Status { message: message.into(), success: true }
   }
   pub fn for_error(e: impl Error) -> Self { Status { message: e.to_string(), success: false } }
+  pub fn for_error_message(message: impl Into<String>) -> Self {
+    Status { message: message.into(), success: false }
+  }
 }
@@ -0,0 +1,681 @@
+use std::{ops::Range, rc::Rc};
+
+use be_doc::{Change, Edit, Line, Regex};
+use be_input::{Key, KeyStroke};
+
+use crate::{
+  EditorState,
+  highlight::{Highlight, HighlightKey},
+  status::Status,
+};
+
+/// A parsed `:[range]s/pattern/replacement/[flags]` command.
+pub(crate) struct Substitute {
+  start:       Line,
+  end:         Line,
+  regex:       Rc<Regex>,
+  /// The pattern and the case from the flags, which `regex` was built from.
+  /// The preview keeps the same regex while these don't change.
+  pattern:     String,
+  ignore_case: Option<bool>,
+  /// The replacement, converted to the syntax of `Captures::expand`.
+  replacement: String,
+  /// The replacement as it was typed, for the confirm prompt.
+  typed:       String,
+  global:      bool,
+  confirm:     bool,
+}
+
+/// An inclusive range of lines, from the start of an ex command.
+#[derive(Copy, Clone)]
+pub(crate) struct LineRange {
+  pub start: Line,
+  pub end:   Line,
+}
+
+/// A substitution waiting for the user to confirm each match, from the `c`
+/// flag.
+pub(crate) struct Confirm {
+  sub:     Substitute,
+  line:    Line,
+  /// The byte in `line` to search from.
+  column:  usize,
+  current: Range<usize>,
+  count:   usize,
+  lines:   usize,
+  /// The last line a replacement was made on, to count the changed lines.
+  last:    Option<Line>,
+}
+
+impl EditorState {
+  /// Runs the command if it is a substitution. Returns `false` for any other
+  /// command.
+  pub fn run_substitute(&mut self, command: &str) -> bool {
+    match self.parse_substitute(command) {
+      None => false,
+      Some(Err(e)) => {
+        self.status = Some(Status::for_error_message(e));
+        true
+      }
+      Some(Ok(sub)) if sub.confirm => {
+        let line = sub.start;
+        self.confirm =
+          Some(Confirm { sub, line, column: 0, current: 0..0, count: 0, lines: 0, last: None });
+        self.next_confirm();
+        true
+      }
+      Some(Ok(sub)) => {
+        self.substitute(sub);
+        true
+      }
+    }
+  }
+
+  /// Updates the live preview of a substitution while the command is typed.
+  /// Any other command clears the preview.
+  pub fn preview_command(&mut self, command: &str) {
+    let preview = self.parse_substitute(command).and_then(Result::ok);
+    if preview.is_some() || self.substitute_preview.is_some() {
+      self.damage_all = true;
+    }
+    self.substitute_preview = preview;
+  }
+
+  /// Returns the matches that the substitution being typed or confirmed will
+  /// replace, within `range`.
+  pub(crate) fn substitute_highlights(&self, range: Range<usize>) -> Vec<Highlight<'static>> {
+    if let Some(confirm) = &self.confirm {
+      return vec![Highlight {
+        start: confirm.current.start,
+        end:   confirm.current.end,
+        key:   HighlightKey::SearchResult,
+      }];
+    }
+
+    let Some(sub) = &self.substitute_preview else { return vec![] };
+
+    let first = self.doc.line_of_byte(range.start).max(sub.start);
+    let last = self.doc.line_of_byte(range.end).min(sub.end);
+
+    let mut highlights = vec![];
+    for line in first.0..=last.0 {
+      let line = Line(line);
+      if line.0 >= self.doc.len_lines() {
+        break;
+      }
+
+      let start = self.doc.byte_of_line(line);
+      let text = self.doc.line(line).to_string();
+      for m in sub.regex.captures_iter(&text, 0).take(sub.limit()) {
+        highlights.push(Highlight {
+          start: start + m.range().start,
+          end:   start + m.range().end,
+          key:   HighlightKey::SearchResult,
+        });
+      }
+    }
+
+    highlights
+  }
+
+  /// Handles a key while confirming a substitution. Returns `false` if no
+  /// substitution is being confirmed.
+  pub fn confirm_substitute_key(&mut self, key: KeyStroke) -> bool {
+    if self.confirm.is_none() {
+      return false;
+    }
+
+    match key.key {
+      Key::Char('y') => {
+        self.replace_confirmed();
+        self.next_confirm();
+      }
+      Key::Char('l') => {
+        self.replace_confirmed();
+        self.finish_confirm();
+      }
+      Key::Char('n') => {
+        self.skip_confirmed();
+        self.next_confirm();
+      }
+      Key::Char('a') => {
+        while self.confirm.is_some() {
+          self.replace_confirmed();
+          self.next_confirm();
+        }
+      }
+      Key::Char('q') | Key::Escape => self.finish_confirm(),
+      _ => {}
+    }
+
+    true
+  }
+
+  fn substitute(&mut self, sub: Substitute) {
+    let mut count = 0;
+    let mut lines = 0;
+    let mut last_line = None;
+
+    let group = self.current_edit.is_none();
+    for line in (sub.start.0..=sub.end.0).rev() {
+      let line = Line(line);
+      let text = self.doc.line(line).to_string();
+
+      let (replaced, matches) = sub.replace(&text);
+      if matches == 0 {
+        continue;
+      }
+
+      if self.current_edit.is_none() {
+        self.current_edit = Some(Edit::empty());
+      }
+
+      self.change(Change::replace(
+        self.doc.byte_of_line(line)..self.doc.byte_of_line_end(line),
+        &replaced,
+      ));
+
+      count += matches;
+      lines += 1;
+      last_line.get_or_insert(line);
+    }
+
+    if group {
+      self.remove_current_edit();
+    }
+
+    match last_line {
+      Some(line) => {
+        self.move_to_line(line);
+        self.perform_move(be_input::Move::LineStartOfText, None);
+        self.status = Some(Status::for_success(substitutions(count, lines)));
+      }
+      None => {
+        self.status =
+          Some(Status::for_error_message(format!("pattern not found: {}", sub.pattern)));
+      }
+    }
+  }
+
+  /// Moves to the next match to confirm, or finishes if there are none left.
+  fn next_confirm(&mut self) {
+    let Some(confirm) = &mut self.confirm else { return };
+
+    while confirm.line <= confirm.sub.end && confirm.line.0 < self.doc.len_lines() {
+      let text = self.doc.line(confirm.line).to_string();
+      if confirm.column <= text.len()
+        && let Some(m) = confirm.sub.regex.captures_iter(&text, confirm.column).next()
+      {
+        let start = self.doc.byte_of_line(confirm.line);
+        confirm.current = start + m.range().start..start + m.range().end;
+
+        self.cursor = self.doc.offset_to_cursor(confirm.current.start);
+        self.damage_all = true;
+        self.status =
+          Some(Status::for_success(format!("replace with {} (y/n/a/q/l)?", confirm.sub.typed)));
+        return;
+      }
+
+      confirm.line = confirm.line + 1;
+      confirm.column = 0;
+    }
+
+    self.finish_confirm();
+  }
+
+  fn replace_confirmed(&mut self) {
+    let Some(confirm) = &mut self.confirm else { return };
+
+    let line_start = self.doc.byte_of_line(confirm.line);
+    let text = self.doc.line(confirm.line).to_string();
+    let Some(captures) =
+      confirm.sub.regex.captures_iter(&text, confirm.current.start - line_start).next()
+    else {
+      return;
+    };
+    let replaced = captures.expand(&text, &confirm.sub.replacement);
+
+    let range = confirm.current.clone();
+    let end = range.start + replaced.len();
+    let global = confirm.sub.global;
+    let empty = range.is_empty();
+    confirm.count += 1;
+    if confirm.last != Some(confirm.line) {
+      confirm.lines += 1;
+      confirm.last = Some(confirm.line);
+    }
+    confirm.sub.end = confirm.sub.end + replaced.matches('\n').count() as i32;
+
+    // All the confirmed replacements are a single undo step, which is finished
+    // once there are no matches left.
+    if self.current_edit.is_none() {
+      self.current_edit = Some(Edit::empty());
+    }
+    self.change(Change::replace(range, &replaced));
+
+    // Continue after the replacement, so that it isn't matched again.
+    let Some(confirm) = &mut self.confirm else { return };
+    confirm.line = self.doc.line_of_byte(end);
+    confirm.column = end - self.doc.byte_of_line(confirm.line) + usize::from(empty);
+    if !global {
+      confirm.line = confirm.line + 1;
+      confirm.column = 0;
+    }
+  }
+
+  fn skip_confirmed(&mut self) {
+    let Some(confirm) = &mut self.confirm else { return };
+
+    let line_start = self.doc.byte_of_line(confirm.line);
+    if confirm.sub.global {
+      confirm.column = confirm.current.end - line_start + usize::from(confirm.current.is_empty());
+    } else {
+      confirm.line = confirm.line + 1;
+      confirm.column = 0;
+    }
+  }
+
+  fn finish_confirm(&mut self) {
+    let Some(confirm) = self.confirm.take() else { return };
+
+    self.remove_current_edit();
+    self.damage_all = true;
+    self.clamp_cursor();
+    self.status = Some(Status::for_success(substitutions(confirm.count, confirm.lines)));
+  }
+
+  /// Parses a substitute command, returning `None` if the command isn't one.
+  fn parse_substitute(&self, command: &str) -> Option<Result<Substitute, String>> {
+    // The range is only resolved once the command is known to be a
+    // substitution, so that errors in it don't hide other commands.
+    let (range, rest) = command.split_at(range_len(command));
+    let rest = rest.strip_prefix("substitute").or_else(|| rest.strip_prefix('s'))?;
+    let delimiter = rest.chars().next()?;
+    if delimiter.is_alphanumeric() || delimiter.is_whitespace() || delimiter == '\\' {
+      return None;
+    }
+
+    let mut parts = split_delimited(&rest[delimiter.len_utf8()..], delimiter).into_iter();
+    let pattern = parts.next().unwrap_or_default();
+    let replacement = parts.next().unwrap_or_default();
+    let flags = parts.next().unwrap_or_default();
+
+    Some(
+      self
+        .parse_range(range)
+        .and_then(|(range, _)| self.build_substitute(range, &pattern, &replacement, &flags)),
+    )
+  }
+
+  fn build_substitute(
+    &self,
+    range: Option<LineRange>,
+    pattern: &str,
+    replacement: &str,
+    flags: &str,
+  ) -> Result<Substitute, String> {
+    let mut global = false;
+    let mut confirm = false;
+    let mut ignore_case = None;
+    for flag in flags.trim().chars() {
+      match flag {
+        'g' => global = true,
+        'c' => confirm = true,
+        'i' => ignore_case = Some(true),
+        'I' => ignore_case = Some(false),
+        _ => return Err(format!("invalid flag: {flag}")),
+      }
+    }
+
+    // An empty pattern uses the last search.
+    let (pattern, ignore_case) = if pattern.is_empty() {
+      let search = self.search.as_ref().ok_or("no previous search pattern")?;
+      (search.as_str(), ignore_case.or(Some(search.is_case_insensitive())))
+    } else {
+      (pattern, ignore_case)
+    };
+
+    // The preview is parsed again on every key typed, so the regex is only
+    // built again once the pattern changes.
+    let regex = match &self.substitute_preview {
+      Some(preview) if preview.pattern == pattern && preview.ignore_case == ignore_case => {
+        preview.regex.clone()
+      }
+      _ => Rc::new(
+        match ignore_case {
+          Some(ignore_case) => Regex::with_case(pattern, ignore_case),
+          None => Regex::smart_case(pattern),
+        }
+        .map_err(|e| format!("invalid pattern: {e}"))?,
+      ),
+    };
+
+    let LineRange { start, end } =
+      range.unwrap_or(LineRange { start: self.cursor.line, end: self.cursor.line });
+    Ok(Substitute {
+      start,
+      end: end.clamp(self.max_line()),
+      regex,
+      pattern: pattern.to_string(),
+      ignore_case,
+      replacement: convert_replacement(replacement),
+      typed: replacement.to_string(),
+      global,
+      confirm,
+    })
+  }
+
+  /// Parses the line range at the start of an ex command, like `%` or `.,$`.
+  /// Returns the range, if any, and the rest of the command.
+  pub(crate) fn parse_range<'a>(
+    &self,
+    command: &'a str,
+  ) -> Result<(Option<LineRange>, &'a str), String> {
+    if let Some(rest) = command.strip_prefix('%') {
+      return Ok((Some(LineRange { start: Line(0), end: self.max_line() }), rest));
+    }
+
+    let (start, rest) = self.parse_address(command)?;
+    let Some(start) = start else { return Ok((None, rest)) };
+
+    let Some(rest) = rest.strip_prefix(',') else {
+      return Ok((Some(LineRange { start, end: start }), rest));
+    };
+    let (end, rest) = self.parse_address(rest)?;
+    let end = end.ok_or("missing end of range")?;
+
+    Ok((Some(LineRange { start: start.min(end), end: start.max(end) }), rest))
+  }
+
+  fn parse_address<'a>(&self, text: &'a str) -> Result<(Option<Line>, &'a str), String> {
+    let mut chars = text.char_indices();
+    let (mut line, mut rest) = match chars.next() {
+      Some((_, '.')) => (self.cursor.line, &text[1..]),
+      Some((_, '$')) => (self.max_line(), &text[1..]),
+      Some((_, '\'')) => {
+        let selection = self.last_visual.ok_or("no previous visual selection")?;
+        match chars.next() {
+          Some((_, '<')) => (selection.start_line(), &text[2..]),
+          Some((_, '>')) => (selection.end_line(), &text[2..]),
+          _ => return Err("unknown mark".into()),
+        }
+      }
+      Some((_, c @ ('/' | '?'))) => {
+        let end = text[1..].find(c).map_or(text.len(), |i| i + 1);
+        let regex =
+          Regex::smart_case(&text[1..end]).map_err(|e| format!("invalid pattern: {e}"))?;
+        let line = self.find_line(&regex, c == '/').ok_or("pattern not found")?;
+        (line, text.get(end + 1..).unwrap_or(""))
+      }
+      Some((_, c)) if c.is_ascii_digit() => {
+        let len = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+        let n = text[..len].parse::<usize>().map_err(|e| e.to_string())?;
+        (Line(n.saturating_sub(1)), &text[len..])
+      }
+      Some((_, '+' | '-')) => (self.cursor.line, text),
+      _ => return Ok((None, text)),
+    };
+
+    // Offsets, like `.+3`.
+    while let Some(sign @ ('+' | '-')) = rest.chars().next() {
+      let len = rest[1..].find(|c: char| !c.is_ascii_digit()).map_or(rest.len(), |i| i + 1);
+      let n = if len == 1 { 1 } else { rest[1..len].parse::<i32>().map_err(|e| e.to_string())? };
+      line = if sign == '+' { line + n } else { line - n };
+      rest = &rest[len..];
+    }
+
+    Ok((Some(line.clamp(self.max_line())), rest))
+  }
+
+  /// Finds the next (or previous) line matching `regex`, wrapping around the
+  /// end of the document.
+  fn find_line(&self, regex: &Regex, forward: bool) -> Option<Line> {
+    let found = if forward {
+      let next_line = self.doc.byte_of_line(self.cursor.line + 1);
+      self
+        .doc
+        .find_regex_from(next_line, regex)
+        .next()
+        .or_else(|| self.doc.find_regex(regex).next())
+    } else {
+      let line = self.doc.byte_of_line(self.cursor.line);
+      self.doc.rfind_regex_from(line, regex).next().or_else(|| self.doc.rfind_regex(regex).next())
+    };
+    found.map(|range| self.doc.line_of_byte(range.start))
+  }
+}
+
+impl Substitute {
+  /// How many matches to replace on each line.
+  fn limit(&self) -> usize { if self.global { usize::MAX } else { 1 } }
+
+  /// Replaces the matches in a line. Returns the new line and the number of
+  /// replacements.
+  fn replace(&self, text: &str) -> (String, usize) {
+    let mut out = String::new();
+    let mut count = 0;
+    let mut end = 0;
+    for captures in self.regex.captures_iter(text, 0).take(self.limit()) {
+      out.push_str(&text[end..captures.range().start]);
+      out.push_str(&captures.expand(text, &self.replacement));
+      end = captures.range().end;
+      count += 1;
+    }
+    out.push_str(&text[end..]);
+    (out, count)
+  }
+}
+
+/// The length of the line range at the start of `command`. This only looks at
+/// the syntax, so it finds where the command starts even if the range can't be
+/// resolved, like `'<` without a visual selection.
+fn range_len(command: &str) -> usize {
+  let mut chars = command.char_indices();
+  while let Some((i, c)) = chars.next() {
+    match c {
+      '%' | '.' | '$' | ',' | '+' | '-' | '0'..='9' => {}
+      '\'' => {
+        chars.next();
+      }
+      '/' | '?' => {
+        chars.find(|&(_, d)| d == c);
+      }
+      _ => return i,
+    }
+  }
+  command.len()
+}
+
+/// Splits the arguments of a substitution on `delimiter`. A backslash escapes
+/// the delimiter; other escapes are kept for the pattern and replacement.
+fn split_delimited(text: &str, delimiter: char) -> Vec<String> {
+  let mut parts = vec![String::new()];
+  let mut chars = text.chars();
+  while let Some(c) = chars.next() {
+    match c {
+      '\\' => match chars.next() {
+        Some(c) if c == delimiter => parts.last_mut().unwrap().push(c),
+        Some(c) => {
+          parts.last_mut().unwrap().push('\\');
+          parts.last_mut().unwrap().push(c);
+        }
+        None => parts.last_mut().unwrap().push('\\'),
+      },
+      c if c == delimiter && parts.len() < 3 => parts.push(String::new()),
+      c => parts.last_mut().unwrap().push(c),
+    }
+  }
+  parts
+}
+
+/// Converts a vim replacement (`\1`, `&`) into the syntax used by
+/// `Captures::expand` (`${1}`, `${0}`).
+fn convert_replacement(replacement: &str) -> String {
+  let mut out = String::new();
+  let mut chars = replacement.chars();
+  while let Some(c) = chars.next() {
+    match c {
+      '\\' => match chars.next() {
+        Some(d @ '0'..='9') => out.push_str(&format!("${{{d}}}")),
+        Some('n' | 'r') => out.push('\n'),
+        Some('t') => out.push('\t'),
+        Some('$') => out.push_str("$$"),
+        Some(c) => out.push(c),
+        None => out.push('\\'),
+      },
+      '&' => out.push_str("${0}"),
+      '$' => out.push_str("$$"),
+      c => out.push(c),
+    }
+  }
+  out
+}
+
+fn substitutions(count: usize, lines: usize) -> String {
+  match (count, lines) {
+    (1, _) => "1 substitution on 1 line".into(),
+    (count, 1) => format!("{count} substitutions on 1 line"),
+    (count, lines) => format!("{count} substitutions on {lines} lines"),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::rc::Rc;
+
+  use crate::tests::editor;
+
+  #[test]
+  fn substitute_line() {
+    let mut editor = editor("foo foo\nfoo\n");
+
+    editor.keys(":s/foo/bar/<CR>");
+    editor.check(expect![@r#"
+      ⟦b⟧ar foo
+      foo
+    "#]);
+
+    editor.keys("u:%s/foo/bar/g<CR>");
+    editor.check(expect![@r#"
+      bar bar
+      ⟦b⟧ar
+    "#]);
+
+    editor.keys("u");
+    editor.check(expect![@r#"
+      ⟦f⟧oo foo
+      foo
+    "#]);
+  }
+
+  #[test]
+  fn capture_groups() {
+    let mut editor = editor("let a = b;\n");
+
+    editor.keys(r":s/(\w+) = (\w+)/\2 = \1 (&)/<CR>");
+    editor.check(expect![@"⟦l⟧et b = a (a = b);\n"]);
+  }
+
+  #[test]
+  fn ranges() {
+    let mut editor = editor("a\na\na\na\na\n");
+
+    editor.keys("j:.,$-1s/a/b/<CR>");
+    editor.check(expect![@r#"
+      a
+      b
+      b
+      ⟦b⟧
+      a
+    "#]);
+
+    editor.keys("gg:/a/,/a/s/A/c/i<CR>");
+    editor.check(expect![@r#"
+      a
+      b
+      b
+      b
+      ⟦c⟧
+    "#]);
+  }
+
+  #[test]
+  fn visual_range() {
+    let mut editor = editor("a\na\na\n");
+
+    editor.keys("jVj:s/a/b/<CR>");
+    editor.check(expect![@r#"
+      a
+      b
+      ⟦b⟧
+    "#]);
+  }
+
+  #[test]
+  fn confirm() {
+    let mut editor = editor("a a a\na\n");
+
+    editor.keys(":%s/a/b/gc<CR>ynyy");
+    editor.check(expect![@r#"
+      b a b
+      ⟦b⟧
+    "#]);
+
+    editor.keys("u");
+    editor.check(expect![@r#"
+      ⟦a⟧ a a
+      a
+    "#]);
+  }
+
+  #[test]
+  fn case() {
+    let mut editor = editor("foo Foo\n");
+
+    editor.keys(":s/foo/x/g<CR>");
+    editor.check(expect![@"⟦x⟧ x\n"]);
+
+    editor.keys("u:s/Foo/x/g<CR>");
+    editor.check(expect![@"⟦f⟧oo x\n"]);
+
+    editor.keys(r"u:s/foo\C/x/g<CR>");
+    editor.check(expect![@"⟦x⟧ Foo\n"]);
+
+    editor.keys("u:s/foo/x/gI<CR>");
+    editor.check(expect![@"⟦x⟧ Foo\n"]);
+  }
+
+  #[test]
+  fn preview_reuses_regex() {
+    let mut editor = editor("foo\n");
+
+    editor.keys(":s/foo/b");
+    let regex = editor.substitute_preview.as_ref().unwrap().regex.clone();
+    editor.keys("ar/g");
+    assert!(Rc::ptr_eq(&regex, &editor.substitute_preview.as_ref().unwrap().regex));
+    editor.keys("<BS><BS><BS><BS><BS><BS><BS>x/");
+    assert!(!Rc::ptr_eq(&regex, &editor.substitute_preview.as_ref().unwrap().regex));
+  }
+
+  #[test]
+  fn range_errors() {
+    let mut editor = editor("a\n");
+
+    // Without a visual selection, `'<` can't be resolved.
+    editor.keys(":'<,'>s/a/b/<CR>");
+    assert_eq!(editor.status.as_ref().unwrap().message, "no previous visual selection");
+
+    // Other commands get to report their own errors.
+    editor.keys(":'<,'>sort<CR>");
+    assert_eq!(editor.status.as_ref().unwrap().message, "not an editor command: '<,'>sort");
+  }
+
+  #[test]
+  fn pattern_not_found() {
+    let mut editor = editor("foo\n");
+
+    editor.keys(":s/bar/baz/<CR>");
+    editor.check(expect![@"⟦f⟧oo\n"]);
+    assert!(editor.status.as_ref().is_some_and(|s| s.message.contains("pattern not found")));
+  }
+}
@@ -1,9 +1,11 @@
 use crate::EditorState;
-use be_input::Mode;
+use be_input::{KeyStroke, Mode};
 use expect_test::Expect;
 use std::{
   fmt,
   ops::{Deref, DerefMut},
+  path::{Path, PathBuf},
+  sync::atomic::{AtomicUsize, Ordering},
 };
 use unicode_segmentation::UnicodeSegmentation;
 
@@ -28,6 +30,29 @@ impl TestEditor {
           s.insert(cursor_offset, '⟦');
         }
       }
+      Mode::Visual(_) => {
+        // Selections are shown with `«»`, and the cursor is shown inside them.
+        let mut markers = vec![];
+        for range in self.0.selection_ranges() {
+          if range.is_empty() {
+            markers.push((range.start, 2, "«»"));
+          } else {
+            markers.push((range.start, 2, "«"));
+            markers.push((range.end, 1, "»"));
+          }
+        }
+        if g == "\n" {
+          markers.push((cursor_offset, 3, "⟦ ⟧"));
+        } else {
+          markers.push((cursor_offset, 3, "⟦"));
+          markers.push((cursor_offset + g.len(), 0, "⟧"));
+        }
+        markers.sort();
+
+        for (offset, _, marker) in markers.into_iter().rev() {
+          s.insert_str(offset, marker);
+        }
+      }
       _ => {}
     }
 
@@ -36,6 +61,13 @@ impl TestEditor {
 
   pub fn check(&self, expect: Expect) { expect.assert_eq(&self.state()); }
 
+  /// Types the given keys, written in vim's notation (like `dw<Esc>`).
+  pub fn keys(&mut self, input: &str) {
+    for key in KeyStroke::parse_all(input) {
+      self.0.on_key(key);
+    }
+  }
+
   pub fn check_repeated(&mut self, f: impl Fn(&mut EditorState), expect: &[Expect]) {
     for (i, expect) in expect.iter().enumerate() {
       if i != 0 {
@@ -64,6 +96,37 @@ impl PartialEq<&str> for TestEditor {
   fn eq(&self, other: &&str) -> bool { self.state() == *other }
 }
 
+/// An empty directory for a test's files, which is removed when dropped. The
+/// process id and a counter keep tests from sharing a directory.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+  pub fn new(name: &str) -> TempDir {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+    let id = NEXT.fetch_add(1, Ordering::Relaxed);
+    let dir = std::env::temp_dir().join(format!("be-{name}-{}-{id}", std::process::id()));
+    // Left over from an earlier run that crashed with the same process id.
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    TempDir(dir)
+  }
+}
+
+impl Deref for TempDir {
+  type Target = Path;
+
+  fn deref(&self) -> &Path { &self.0 }
+}
+
+impl AsRef<Path> for TempDir {
+  fn as_ref(&self) -> &Path { &self.0 }
+}
+
+impl Drop for TempDir {
+  fn drop(&mut self) { let _ = std::fs::remove_dir_all(&self.0); }
+}
+
 #[test]
 fn move_col_works() {
   let mut editor = editor("ab");
@@ -144,3 +207,150 @@ fn move_graphemes_handles_graphemes() {
     &[expect![@"⟦f⟧éo"], expect![@"f⟦é⟧o"], expect![@"fé⟦o⟧"]],
   );
 }
+
+#[test]
+fn visual_delete() {
+  let mut editor = editor("foo bar\nbaz qux\n");
+
+  editor.keys("4lvj");
+  editor.check(expect![@r#"
+    foo «bar
+    baz ⟦q⟧»ux
+  "#]);
+
+  editor.keys("d");
+  editor.check(expect![@r#"
+    foo ⟦u⟧x
+  "#]);
+
+  editor.keys("u");
+  editor.check(expect![@r#"
+    foo ⟦b⟧ar
+    baz qux
+  "#]);
+}
+
+#[test]
+fn visual_swap_anchor() {
+  let mut editor = editor("foo bar baz\n");
+
+  editor.keys("wvel");
+  editor.check(expect![@"foo «bar⟦ ⟧»baz\n"]);
+
+  editor.keys("oh");
+  editor.check(expect![@"foo«⟦ ⟧bar »baz\n"]);
+}
+
+#[test]
+fn visual_line_delete() {
+  let mut editor = editor("foo\n  bar\nbaz\nqux\n");
+
+  editor.keys("Vj");
+  editor.check(expect![@r#"
+    «foo
+    ⟦ ⟧ bar
+    »baz
+    qux
+  "#]);
+
+  editor.keys("d");
+  editor.check(expect![@r#"
+    ⟦b⟧az
+    qux
+  "#]);
+}
+
+#[test]
+fn visual_line_change() {
+  let mut editor = editor("foo {\n  bar\n  baz\n}\n");
+
+  editor.keys("jVjcqux<Esc>");
+  editor.check(expect![@r#"
+    foo {
+      qu⟦x⟧
+    }
+  "#]);
+
+  editor.keys("u");
+  editor.check(expect![@r#"
+    foo {
+    ⟦ ⟧ bar
+      baz
+    }
+  "#]);
+}
+
+#[test]
+fn visual_yank_and_paste() {
+  let mut editor = editor("foo bar\n");
+
+  editor.keys("veyP");
+  editor.check(expect![@"fo⟦o⟧foo bar\n"]);
+}
+
+#[test]
+fn visual_indent() {
+  let mut editor = editor("foo\n\nbar\nbaz\n");
+
+  editor.keys("Vjj>");
+  editor.check(expect![@r#"
+      ⟦f⟧oo
+
+      bar
+    baz
+  "#]);
+
+  editor.keys("vjj<");
+  editor.check(expect![@r#"
+    ⟦f⟧oo
+
+    bar
+    baz
+  "#]);
+}
+
+#[test]
+fn visual_switch_case() {
+  let mut editor = editor("foo Bar\n");
+
+  editor.keys("v$~");
+  editor.check(expect![@"⟦F⟧OO bAR\n"]);
+}
+
+#[test]
+fn visual_block_delete() {
+  let mut editor = editor("abcd\na\nabcd\n");
+
+  editor.keys("l<C-v>jjl");
+  editor.check(expect![@r#"
+    a«bc»d
+    a«»
+    a«b⟦c⟧»d
+  "#]);
+
+  editor.keys("d");
+  editor.check(expect![@r#"
+    a⟦d⟧
+    a
+    ad
+  "#]);
+}
+
+#[test]
+fn visual_block_change() {
+  let mut editor = editor("abcd\na\nabcd\n");
+
+  editor.keys("l<C-v>jjlcxy<Esc>");
+  editor.check(expect![@r#"
+    ax⟦y⟧d
+    a
+    axyd
+  "#]);
+
+  editor.keys("u");
+  editor.check(expect![@r#"
+    a⟦b⟧cd
+    a
+    abcd
+  "#]);
+}
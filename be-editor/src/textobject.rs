@@ -0,0 +1,559 @@
+use std::ops::Range;
+
+use be_doc::{Line, Selection};
+use be_input::{Mode, TextObject, VisualMode};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+  EditorState, Register, RegisterKind,
+  moves::{WordKind, word_kind},
+};
+
+/// The bytes covered by a text object.
+pub(crate) struct TextObjectRange {
+  pub range:    Range<usize>,
+  /// Set for objects that cover whole lines, like paragraphs.
+  pub linewise: bool,
+}
+
+impl EditorState {
+  /// Finds the text object around the cursor. `inner` selects the `i` variant
+  /// of the object, which leaves out surrounding whitespace or delimiters.
+  pub(crate) fn text_object(&self, object: TextObject, inner: bool) -> Option<TextObjectRange> {
+    let range = match object {
+      TextObject::Word => self.word_object(inner, false)?,
+      TextObject::BigWord => self.word_object(inner, true)?,
+      TextObject::Sentence => self.sentence_object(inner)?,
+      TextObject::Paragraph => {
+        return Some(TextObjectRange { range: self.paragraph_object(inner)?, linewise: true });
+      }
+      TextObject::Quote(quote) => self.quote_object(quote, inner)?,
+      TextObject::Bracket(open) => self.bracket_object(open, inner)?,
+      TextObject::Function => self.syntax_object("function", inner)?,
+      TextObject::Class => self.syntax_object("class", inner)?,
+      TextObject::Parameter => self.parameter_object(inner)?,
+      TextObject::Comment => self.syntax_object("comment", inner)?,
+    };
+
+    if range.is_empty() && !inner {
+      return None;
+    }
+
+    Some(TextObjectRange { range, linewise: false })
+  }
+
+  /// Deletes a text object, as with `diw`. In insert mode (for `c`), a linewise
+  /// object leaves an empty line behind.
+  pub(crate) fn delete_text_object(&mut self, object: TextObject, inner: bool) {
+    let Some(TextObjectRange { mut range, linewise }) = self.text_object(object, inner) else {
+      return;
+    };
+
+    let kind = if linewise { RegisterKind::Linewise } else { RegisterKind::Charwise };
+    self.delete_register(Register::new(self.doc.range(range.clone()).to_string(), kind));
+    if range.is_empty() {
+      return;
+    }
+
+    if linewise
+      && self.mode == Mode::Insert
+      && self.doc.range(range.clone()).chars().next_back() == Some('\n')
+    {
+      range.end -= 1;
+    }
+
+    self.cursor = self.doc.offset_to_cursor(range.start);
+    self.change(be_doc::Change::remove(range));
+    self.clamp_cursor();
+    if linewise && self.mode == Mode::Insert {
+      self.auto_indent(be_input::VerticalDirection::Up);
+    }
+  }
+
+  /// Yanks a text object, as with `yi(`. The cursor moves to the start of it.
+  pub(crate) fn yank_text_object(&mut self, object: TextObject, inner: bool) {
+    let Some(TextObjectRange { range, linewise }) = self.text_object(object, inner) else {
+      return;
+    };
+
+    let kind = if linewise { RegisterKind::Linewise } else { RegisterKind::Charwise };
+    self.yank_register(Register::new(self.doc.range(range.clone()).to_string(), kind));
+    if range.start < self.doc.len() {
+      self.cursor = self.doc.offset_to_cursor(range.start);
+      self.clamp_cursor();
+    }
+  }
+
+  /// Selects a text object in visual mode.
+  pub(crate) fn select_text_object(&mut self, object: TextObject, inner: bool) {
+    let Some(TextObjectRange { range, linewise }) = self.text_object(object, inner) else {
+      return;
+    };
+    if range.is_empty() {
+      return;
+    }
+
+    let old = self.selection();
+    if linewise {
+      self.mode = Mode::Visual(VisualMode::Line);
+    }
+
+    let last = self.doc.offset_by_graphemes(range.end, -1);
+    self.visual_anchor = self.doc.offset_to_cursor(range.start);
+    self.cursor = self.doc.offset_to_cursor(last);
+    if linewise {
+      // The last grapheme is the line terminator.
+      self.cursor = self.doc.offset_to_cursor(self.doc.byte_of_line(self.cursor.line));
+    }
+
+    self.damage_selection(old);
+    self.damage_selection(Some(Selection::new(self.visual_anchor, self.cursor)));
+  }
+
+  fn word_object(&self, inner: bool, big: bool) -> Option<Range<usize>> {
+    let line_start = self.doc.byte_of_line(self.cursor.line);
+    let text = self.doc.line(self.cursor.line).to_string();
+    let graphemes = text.grapheme_indices(true).collect::<Vec<_>>();
+    if graphemes.is_empty() {
+      return None;
+    }
+
+    let kind = |i: usize| {
+      let kind = word_kind(graphemes[i].1.chars().next().unwrap_or(' '));
+      if big && kind == WordKind::Punctuation { WordKind::Word } else { kind }
+    };
+    let run_end = |mut i: usize| {
+      let start = kind(i);
+      while i < graphemes.len() && kind(i) == start {
+        i += 1;
+      }
+      i
+    };
+
+    let column = self.cursor.column.0.min(graphemes.len() - 1);
+    let mut start = column;
+    while start > 0 && kind(start - 1) == kind(column) {
+      start -= 1;
+    }
+    let mut end = run_end(column);
+
+    if !inner {
+      if kind(column) == WordKind::Blank {
+        // On whitespace, `aw` selects the whitespace and the word after it.
+        if end < graphemes.len() {
+          end = run_end(end);
+        }
+      } else if end < graphemes.len() && kind(end) == WordKind::Blank {
+        end = run_end(end);
+      } else {
+        while start > 0 && kind(start - 1) == WordKind::Blank {
+          start -= 1;
+        }
+      }
+    }
+
+    let offset = |i: usize| line_start + graphemes.get(i).map_or(text.len(), |g| g.0);
+    Some(offset(start)..offset(end))
+  }
+
+  fn quote_object(&self, quote: char, inner: bool) -> Option<Range<usize>> {
+    let line_start = self.doc.byte_of_line(self.cursor.line);
+    let text = self.doc.line(self.cursor.line).to_string();
+    let cursor = self.doc.cursor_column_offset(self.cursor);
+
+    let mut quotes = vec![];
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+      if c == quote && !escaped {
+        quotes.push(i);
+      }
+      escaped = c == '\\' && !escaped;
+    }
+
+    // Quotes are paired up from the start of the line. If the cursor isn't
+    // inside a pair, the next pair on the line is used.
+    let (open, close) = quotes
+      .chunks_exact(2)
+      .map(|pair| (pair[0], pair[1]))
+      .find(|&(open, close)| (open..=close).contains(&cursor))
+      .or_else(|| {
+        quotes.chunks_exact(2).map(|pair| (pair[0], pair[1])).find(|&(open, _)| open > cursor)
+      })?;
+
+    if inner {
+      return Some(line_start + open + 1..line_start + close);
+    }
+
+    let trailing = text[close + 1..].chars().take_while(|c| c.is_whitespace()).count();
+    if trailing > 0 {
+      Some(line_start + open..line_start + close + 1 + trailing)
+    } else {
+      let leading = text[..open].chars().rev().take_while(|c| c.is_whitespace()).count();
+      Some(line_start + open - leading..line_start + close + 1)
+    }
+  }
+
+  fn bracket_object(&self, open: char, inner: bool) -> Option<Range<usize>> {
+    let close = match open {
+      '(' => ')',
+      '[' => ']',
+      '{' => '}',
+      '<' => '>',
+      _ => return None,
+    };
+
+    let cursor = self.doc.cursor_offset(self.cursor);
+    let under = self.doc.range(cursor..).chars().next();
+
+    let start = if under == Some(open) {
+      cursor
+    } else {
+      let mut depth = 0;
+      let mut index = cursor;
+      let mut found = None;
+      for c in self.doc.range(..cursor).chars().rev() {
+        index -= c.len_utf8();
+        if c == close {
+          depth += 1;
+        } else if c == open {
+          if depth == 0 {
+            found = Some(index);
+            break;
+          }
+          depth -= 1;
+        }
+      }
+      found?
+    };
+
+    let mut depth = 0;
+    let mut index = start + open.len_utf8();
+    let mut end = None;
+    for c in self.doc.range(index..).chars() {
+      if c == open {
+        depth += 1;
+      } else if c == close {
+        if depth == 0 {
+          end = Some(index);
+          break;
+        }
+        depth -= 1;
+      }
+      index += c.len_utf8();
+    }
+    let end = end?;
+
+    if inner {
+      Some(self.trim_block(start + open.len_utf8()..end))
+    } else {
+      Some(start..end + close.len_utf8())
+    }
+  }
+
+  /// Trims the inside of a block, like `{ ... }`. If the block spans multiple
+  /// lines, the line terminator after the opening bracket and the indentation
+  /// before the closing bracket are left out, so that `di{` keeps both
+  /// brackets on their own lines.
+  fn trim_block(&self, range: Range<usize>) -> Range<usize> {
+    let text = self.doc.range(range.clone()).to_string();
+
+    let start = if text.starts_with('\n') { range.start + 1 } else { range.start };
+    let end = match text.rfind('\n') {
+      Some(i) if text[i + 1..].chars().all(|c| c == ' ' || c == '\t') => range.start + i + 1,
+      _ => range.end,
+    };
+
+    start..end.max(start)
+  }
+
+  fn paragraph_object(&self, inner: bool) -> Option<Range<usize>> {
+    let blank = |line: Line| self.doc.line(line).chars().all(|c| c.is_whitespace());
+    let max = self.max_line();
+
+    let on_blank = blank(self.cursor.line);
+    let mut start = self.cursor.line;
+    while start > Line(0) && blank(start - 1) == on_blank {
+      start = start - 1;
+    }
+    let mut end = self.cursor.line;
+    while end < max && blank(end + 1) == on_blank {
+      end = end + 1;
+    }
+
+    if !inner {
+      // Include the following run of lines (blank lines after a paragraph, or the
+      // paragraph after blank lines). Without one, use the blank lines before.
+      if end < max {
+        end = end + 1;
+        while end < max && blank(end + 1) != on_blank {
+          end = end + 1;
+        }
+      } else if !on_blank {
+        while start > Line(0) && blank(start - 1) {
+          start = start - 1;
+        }
+      }
+    }
+
+    Some(self.doc.byte_of_line(start)..self.doc.byte_of_line(end + 1))
+  }
+
+  fn sentence_object(&self, inner: bool) -> Option<Range<usize>> {
+    // Sentences never cross paragraph boundaries.
+    let paragraph = self.paragraph_object(true)?;
+    let paragraph_start = paragraph.start;
+    let text = self.doc.range(paragraph).to_string();
+    let text = text.trim_end();
+    let cursor = self.doc.cursor_offset(self.cursor) - paragraph_start;
+
+    // Each sentence is stored as (start, end of text, end including whitespace).
+    let mut sentences = vec![];
+    let mut start = text.len() - text.trim_start().len();
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+      if i < start || !matches!(c, '.' | '!' | '?') {
+        continue;
+      }
+
+      let mut end = i + 1;
+      while let Some(&(j, c)) = chars.peek()
+        && matches!(c, '.' | '!' | '?' | ')' | ']' | '"' | '\'')
+      {
+        end = j + c.len_utf8();
+        chars.next();
+      }
+
+      let next = text[end..].find(|c: char| !c.is_whitespace()).map_or(text.len(), |n| end + n);
+      if next > end || next == text.len() {
+        sentences.push((start, end, next));
+        start = next;
+      }
+    }
+    if start < text.len() {
+      sentences.push((start, text.len(), text.len()));
+    }
+
+    let &(start, end, next) =
+      sentences.iter().find(|&&(_, _, next)| cursor < next).or(sentences.last())?;
+
+    if inner {
+      Some(paragraph_start + start..paragraph_start + end)
+    } else if next > end {
+      Some(paragraph_start + start..paragraph_start + next)
+    } else {
+      let leading = text[..start].chars().rev().take_while(|c| c.is_whitespace()).count();
+      Some(paragraph_start + start - leading..paragraph_start + end)
+    }
+  }
+
+  /// Finds an object from the tree-sitter `textobjects.scm` query. The `inner`
+  /// capture of functions and classes is their body, so the brackets around it
+  /// are trimmed like with `i{`.
+  fn syntax_object(&self, name: &str, inner: bool) -> Option<Range<usize>> {
+    let highlighter = self.highligher.as_ref()?;
+    let (outer, inner_range) =
+      highlighter.text_object(&self.doc, self.doc.cursor_offset(self.cursor), name)?;
+
+    if !inner {
+      return Some(outer);
+    }
+
+    let range = inner_range.unwrap_or(outer);
+    let text = self.doc.range(range.clone());
+    match (text.chars().next(), text.chars().next_back()) {
+      (Some('{'), Some('}')) | (Some('('), Some(')')) | (Some('['), Some(']')) => {
+        Some(self.trim_block(range.start + 1..range.end - 1))
+      }
+      _ => Some(range),
+    }
+  }
+
+  /// Parameters are only captured as `@parameter.inner`. The outer object adds
+  /// the comma separating it from the next parameter, or from the previous one
+  /// if it's the last.
+  fn parameter_object(&self, inner: bool) -> Option<Range<usize>> {
+    let highlighter = self.highligher.as_ref()?;
+    let (range, _) =
+      highlighter.text_object(&self.doc, self.doc.cursor_offset(self.cursor), "parameter")?;
+
+    if inner {
+      return Some(range);
+    }
+
+    let after = self.doc.range(range.end..).chars();
+    let mut end = range.end;
+    let mut seen_comma = false;
+    for c in after {
+      if c == ',' && !seen_comma {
+        seen_comma = true;
+      } else if !(seen_comma && c == ' ') {
+        break;
+      }
+      end += c.len_utf8();
+    }
+    if seen_comma {
+      return Some(range.start..end);
+    }
+
+    let mut start = range.start;
+    for c in self.doc.range(..range.start).chars().rev() {
+      start -= c.len_utf8();
+      if c == ',' {
+        return Some(start..range.end);
+      } else if !c.is_whitespace() {
+        break;
+      }
+    }
+
+    Some(range)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::tests::editor;
+
+  #[test]
+  fn words() {
+    let mut editor = editor("foo bar baz\n");
+
+    editor.keys("wdiw");
+    editor.check(expect![@"foo ⟦ ⟧baz\n"]);
+
+    editor.keys("u0wdaw");
+    editor.check(expect![@"foo ⟦b⟧az\n"]);
+
+    editor.keys("$daw");
+    editor.check(expect![@"fo⟦o⟧\n"]);
+  }
+
+  #[test]
+  fn big_words() {
+    let mut editor = editor("foo a.b-c bar\n");
+
+    editor.keys("4ldiW");
+    editor.check(expect![@"foo ⟦ ⟧bar\n"]);
+  }
+
+  #[test]
+  fn quotes() {
+    let mut editor = editor("let x = \"foo \\\" bar\";\n");
+
+    editor.keys("ci\"hi<Esc>");
+    editor.check(expect![@"let x = \"h⟦i⟧\";\n"]);
+
+    editor.keys("da\"");
+    editor.check(expect![@"let x =⟦;⟧\n"]);
+  }
+
+  #[test]
+  fn brackets() {
+    let mut editor = editor("foo(a, (b), c)\n");
+
+    editor.keys("8ldi(");
+    editor.check(expect![@"foo(a, (⟦)⟧, c)\n"]);
+
+    editor.keys("04ldib");
+    editor.check(expect![@"foo(⟦)⟧\n"]);
+
+    editor.keys("da)");
+    editor.check(expect![@"fo⟦o⟧\n"]);
+  }
+
+  #[test]
+  fn nested_brackets() {
+    let mut editor = editor("a(b(c)d)e\n");
+
+    // The innermost pair around the cursor.
+    editor.keys("4ldi(");
+    editor.check(expect![@"a(b(⟦)⟧d)e\n"]);
+
+    // On an opening or closing bracket, the pair it belongs to.
+    editor.keys("u03lda(");
+    editor.check(expect![@"a(b⟦d⟧)e\n"]);
+    editor.keys("u07ldi(");
+    editor.check(expect![@"a(⟦)⟧e\n"]);
+  }
+
+  #[test]
+  fn unbalanced_brackets() {
+    let mut editor = editor("foo(bar\nbaz)) qux\n");
+
+    // A closing bracket without a match is skipped.
+    editor.keys("j$di(");
+    editor.check(expect![@r#"
+      foo(bar
+      baz)) qu⟦x⟧
+    "#]);
+
+    editor.keys("gg5ldi(");
+    editor.check(expect![@"foo(⟦)⟧) qux\n"]);
+  }
+
+  #[test]
+  fn quote_delimiters() {
+    let mut editor = editor("x \"a\" \"b\"\n");
+
+    // Before any quotes, the next pair on the line.
+    editor.keys("di\"");
+    editor.check(expect![@"x \"⟦\"⟧ \"b\"\n"]);
+
+    // On a closing quote, the pair it closes.
+    editor.keys("u$di\"");
+    editor.check(expect![@"x \"a\" \"⟦\"⟧\n"]);
+  }
+
+  #[test]
+  fn multiline_brackets() {
+    let mut editor = editor("fn foo() {\n  bar();\n  baz();\n}\n");
+
+    editor.keys("jdi{");
+    editor.check(expect![@r#"
+      fn foo() {
+      ⟦}⟧
+    "#]);
+
+    editor.keys("ukyiB");
+    assert_eq!(editor.registers.get('"').unwrap().text, "  bar();\n  baz();\n");
+  }
+
+  #[test]
+  fn paragraphs() {
+    let mut editor = editor("foo\nbar\n\nbaz\n\nqux\n");
+
+    editor.keys("jdap");
+    editor.check(expect![@r#"
+      ⟦b⟧az
+
+      qux
+    "#]);
+
+    editor.keys("jjdip");
+    editor.check(expect![@r#"
+      baz
+      ⟦ ⟧
+    "#]);
+  }
+
+  #[test]
+  fn sentences() {
+    let mut editor = editor("Foo bar. Baz qux! Last one\n");
+
+    editor.keys("9ldas");
+    editor.check(expect![@"Foo bar. ⟦L⟧ast one\n"]);
+
+    editor.keys("dis");
+    editor.check(expect![@"Foo bar.⟦ ⟧\n"]);
+  }
+
+  #[test]
+  fn visual_text_objects() {
+    let mut editor = editor("foo(bar baz)\n");
+
+    editor.keys("4lvi(");
+    editor.check(expect![@"foo(«bar ba⟦z⟧»)\n"]);
+
+    editor.keys("<Esc>vaw");
+    editor.check(expect![@"foo(bar« ba⟦z⟧»)\n"]);
+  }
+}
@@ -1,12 +1,13 @@
 use std::{
+  collections::HashSet,
   ffi::CString,
   mem::ManuallyDrop,
-  ops::Range,
+  ops::{Range, RangeInclusive},
   path::{Path, PathBuf},
 };
 
 use be_config::{Config, LanguageName};
-use be_doc::Document;
+use be_doc::{Document, Line};
 use be_macros::ResultExt;
 use tree_sitter::{
   Language, Node, Parser, Query, QueryCaptures, QueryCursor, StreamingIterator, TextProvider, Tree,
@@ -15,9 +16,17 @@ use tree_sitter::{
 use crate::{Change, EditorState, highlight::Highlight};
 
 pub struct Highlighter {
-  parser:           Parser,
-  tree:             Option<Tree>,
-  highlights_query: Query,
+  parser:            Parser,
+  tree:              Option<Tree>,
+  highlights_query:  Query,
+  /// Captures like `@function.outer` and `@function.inner`, for text objects.
+  textobjects_query: Option<Query>,
+  /// Captures `@indent` and `@outdent`, for automatic indentation.
+  indents_query:     Option<Query>,
+  /// Captures `@fold`, for folding.
+  folds_query:       Option<Query>,
+  /// Captures `@injection.content`, for languages embedded in this one.
+  injections_query:  Option<Query>,
 
   // SAFETY: Drop last!
   _language: LoadedLanguage,
@@ -76,13 +85,60 @@ pub fn load_grammar(config: &Config, ft: LanguageName) -> Option<Highlighter> {
   let mut parser = Parser::new();
   parser.set_language(&language.language).fatal()?;
 
-  let highlights_query = Query::new(
-    &language.language,
-    &std::fs::read_to_string(grammar_path.join(&grammar.highlights.first())).fatal()?,
-  )
-  .fatal()?;
+  let highlights_path = grammar_path.join(&grammar.highlights.first());
+  let highlights_query =
+    Query::new(&language.language, &std::fs::read_to_string(&highlights_path).fatal()?).fatal()?;
+
+  // Our own queries can be replaced by a file next to `highlights.scm`, so that
+  // they can be edited alongside the grammar's queries.
+  let queries_path = highlights_path.parent()?;
+  let textobjects_query = load_query(&language.language, ft, queries_path, "textobjects");
+  let indents_query = load_query(&language.language, ft, queries_path, "indents");
+  let folds_query = load_query(&language.language, ft, queries_path, "folds");
+  // Grammars ship with their own injections.
+  let injections_query = load_query(&language.language, ft, queries_path, "injections");
+
+  Some(Highlighter {
+    parser,
+    tree: None,
+    highlights_query,
+    textobjects_query,
+    indents_query,
+    folds_query,
+    injections_query,
+    _language: language,
+  })
+}
 
-  Some(Highlighter { parser, tree: None, highlights_query, _language: language })
+/// Queries that ship with the editor, rather than with the grammar.
+fn builtin_query(ft: LanguageName, name: &str) -> Option<&'static str> {
+  Some(match (ft.name(), name) {
+    ("rust", "textobjects") => include_str!("../queries/rust/textobjects.scm"),
+    ("toml", "textobjects") => include_str!("../queries/toml/textobjects.scm"),
+    ("rust", "indents") => include_str!("../queries/rust/indents.scm"),
+    ("toml", "indents") => include_str!("../queries/toml/indents.scm"),
+    ("python", "indents") => include_str!("../queries/python/indents.scm"),
+    ("yaml", "indents") => include_str!("../queries/yaml/indents.scm"),
+    ("markdown", "indents") => include_str!("../queries/markdown/indents.scm"),
+    ("rust", "folds") => include_str!("../queries/rust/folds.scm"),
+    ("toml", "folds") => include_str!("../queries/toml/folds.scm"),
+    _ => return None,
+  })
+}
+
+/// Loads `{name}.scm` from the grammar's queries, or the builtin query if the
+/// grammar doesn't have one.
+fn load_query(
+  language: &Language,
+  ft: LanguageName,
+  queries_path: &Path,
+  name: &str,
+) -> Option<Query> {
+  let source = match std::fs::read_to_string(queries_path.join(format!("{name}.scm"))) {
+    Ok(source) => source,
+    Err(_) => builtin_query(ft, name)?.to_string(),
+  };
+  Query::new(language, &source).fatal()
 }
 
 impl EditorState {
@@ -167,6 +223,187 @@ impl Highlighter {
 
     Some(CapturesIter { query: &self.highlights_query, captures, _cursor: cursor })
   }
+
+  /// Returns `true` if `offset` is inside a string or a comment, going by the
+  /// highlight captures. The end of a comment counts as inside, as line
+  /// comments end at the end of the line.
+  pub(crate) fn in_string_or_comment(&self, doc: &Document, offset: usize) -> bool {
+    let start = offset.saturating_sub(1);
+    let Some(mut highlights) = self.highlights(doc, start..offset + 1) else { return false };
+
+    highlights.any(|h| {
+      let crate::HighlightKey::TreeSitter(name) = h.key else { return false };
+      if name.starts_with("comment") {
+        h.start < offset && offset <= h.end
+      } else {
+        name.starts_with("string") && h.start < offset && offset < h.end
+      }
+    })
+  }
+
+  /// Computes the indent level of `line` from the `indents.scm` query. Each
+  /// `@indent` node around the start of the line indents it one level, unless
+  /// the node starts on the same line as another one, or on this line. An
+  /// `@outdent` node at the start of the line, like a closing bracket, takes a
+  /// level back off.
+  pub(crate) fn indent_level(&self, doc: &Document, line: Line) -> Option<usize> {
+    let query = self.indents_query.as_ref()?;
+    let tree = self.tree.as_ref()?;
+
+    let text = doc.line(line);
+    let leading = text.chars().take_while(|c| c.is_whitespace()).map(char::len_utf8).sum::<usize>();
+    let offset = doc.byte_of_line(line) + leading;
+
+    let indent_index = query.capture_index_for_name("indent");
+    let outdent_index = query.capture_index_for_name("outdent");
+
+    let mut indents = HashSet::new();
+    let mut outdents = HashSet::new();
+    let mut cursor = QueryCursor::new();
+    cursor.set_byte_range(offset..offset + 1);
+    let mut captures = cursor.captures(query, tree.root_node(), RopeProvider { doc });
+    while let Some((m, index)) = captures.next() {
+      let capture = m.captures[*index];
+      if Some(capture.index) == indent_index {
+        indents.insert(capture.node.id());
+      } else if Some(capture.index) == outdent_index {
+        outdents.insert(capture.node.id());
+      }
+    }
+
+    let mut node = tree.root_node().descendant_for_byte_range(offset, offset)?;
+    let mut level = 0_usize;
+    let mut outdent = outdents.contains(&node.id()) && node.start_byte() == offset;
+    let mut counted_row = None;
+    loop {
+      let row = node.start_position().row;
+      if indents.contains(&node.id())
+        && row < line.0
+        && node.end_byte() > offset
+        && counted_row != Some(row)
+      {
+        level += 1;
+        counted_row = Some(row);
+      }
+
+      match node.parent() {
+        Some(parent) => node = parent,
+        None => break,
+      }
+      if node.start_byte() == offset && outdents.contains(&node.id()) {
+        outdent = true;
+      }
+    }
+
+    Some(if outdent { level.saturating_sub(1) } else { level })
+  }
+
+  /// Returns the lines of each `@fold` capture from the `folds.scm` query that
+  /// covers more than one line.
+  pub(crate) fn fold_ranges(&self, doc: &Document) -> Option<Vec<RangeInclusive<Line>>> {
+    let query = self.folds_query.as_ref()?;
+    let tree = self.tree.as_ref()?;
+    let fold_index = query.capture_index_for_name("fold")?;
+
+    let mut ranges = vec![];
+    let mut cursor = QueryCursor::new();
+    let mut captures = cursor.captures(query, tree.root_node(), RopeProvider { doc });
+    while let Some((m, index)) = captures.next() {
+      let capture = m.captures[*index];
+      if capture.index != fold_index {
+        continue;
+      }
+
+      let start = capture.node.start_position();
+      let mut end = capture.node.end_position();
+      // Nodes that end with a newline end at the start of the next line.
+      if end.column == 0 && end.row > start.row {
+        end.row -= 1;
+      }
+      if end.row > start.row {
+        ranges.push(Line(start.row)..=Line(end.row));
+      }
+    }
+
+    Some(ranges)
+  }
+
+  /// Returns the name of the language injected at `offset` by the
+  /// `injections.scm` query, like the language of a code block in Markdown.
+  /// The language comes from an `@injection.language` capture, or from an
+  /// `injection.language` property.
+  pub(crate) fn injected_language(&self, doc: &Document, offset: usize) -> Option<String> {
+    let query = self.injections_query.as_ref()?;
+    let tree = self.tree.as_ref()?;
+    let content_index = query.capture_index_for_name("injection.content")?;
+    let language_index = query.capture_index_for_name("injection.language");
+
+    let mut cursor = QueryCursor::new();
+    cursor.set_byte_range(offset..offset + 1);
+    let mut matches = cursor.matches(query, tree.root_node(), RopeProvider { doc });
+
+    // The innermost injection wins.
+    let mut best: Option<(Range<usize>, String)> = None;
+    while let Some(m) = matches.next() {
+      let Some(content) = m.captures.iter().find(|c| c.index == content_index) else { continue };
+      let range = content.node.byte_range();
+      if !range.contains(&offset) || best.as_ref().is_some_and(|(b, _)| b.len() <= range.len()) {
+        continue;
+      }
+
+      let language = match m.captures.iter().find(|c| Some(c.index) == language_index) {
+        Some(capture) => doc.range(capture.node.byte_range()).to_string(),
+        None => {
+          let property = query
+            .property_settings(m.pattern_index)
+            .iter()
+            .find(|p| &*p.key == "injection.language");
+          match property.and_then(|p| p.value.as_deref()) {
+            Some(language) => language.to_string(),
+            None => continue,
+          }
+        }
+      };
+      best = Some((range, language));
+    }
+
+    best.map(|(_, language)| language)
+  }
+
+  /// Finds the smallest `@{name}.outer` capture around `offset`, along with the
+  /// `@{name}.inner` capture from the same match. Objects that only have an
+  /// inner capture return it as the outer range.
+  pub(crate) fn text_object(
+    &self,
+    doc: &Document,
+    offset: usize,
+    name: &str,
+  ) -> Option<(Range<usize>, Option<Range<usize>>)> {
+    let query = self.textobjects_query.as_ref()?;
+    let tree = self.tree.as_ref()?;
+
+    let outer_index = query.capture_index_for_name(&format!("{name}.outer"));
+    let inner_index = query.capture_index_for_name(&format!("{name}.inner"));
+
+    let mut cursor = QueryCursor::new();
+    cursor.set_byte_range(offset..offset + 1);
+    let mut matches = cursor.matches(query, tree.root_node(), RopeProvider { doc });
+
+    let mut best: Option<(Range<usize>, Option<Range<usize>>)> = None;
+    while let Some(m) = matches.next() {
+      let find = |index: Option<u32>| {
+        m.captures.iter().find(|c| Some(c.index) == index).map(|c| c.node.byte_range())
+      };
+
+      let inner = find(inner_index);
+      let Some(outer) = find(outer_index).or_else(|| inner.clone()) else { continue };
+      if outer.contains(&offset) && best.as_ref().is_none_or(|(best, _)| outer.len() < best.len()) {
+        best = Some((outer, inner));
+      }
+    }
+
+    best
+  }
 }
 
 struct RopeProvider<'a> {
@@ -362,11 +599,32 @@ mod tests {
 
   use super::*;
 
+  fn highlighter(language: &str) -> Highlighter {
+    let config = Config::default_ref();
+    let name = config.languages.keys().find(|k| k.name() == language).unwrap();
+    load_grammar(config, *name).unwrap()
+  }
+
+  /// Checks that each line of `text`, which is indented by two spaces for each
+  /// level, is at the level that `indents.scm` gives it.
+  fn check_indents(language: &str, text: &str) {
+    let mut highlighter = highlighter(language);
+    let doc = Document::from(text);
+    highlighter.reparse(&doc);
+
+    for (i, line) in text.lines().enumerate() {
+      if line.trim().is_empty() {
+        continue;
+      }
+
+      let expected = (line.len() - line.trim_start().len()) / 2;
+      assert_eq!(highlighter.indent_level(&doc, Line(i)), Some(expected), "line {i}: {line:?}");
+    }
+  }
+
   #[test]
   fn it_works() {
-    let config = Config::default_ref();
-    let name = config.languages.keys().find(|k| k.name() == "rust").unwrap();
-    let mut highlighter = load_grammar(config, *name).unwrap();
+    let mut highlighter = highlighter("rust");
 
     let doc = "fn main() {}".into();
     highlighter.reparse(&doc);
@@ -384,4 +642,77 @@ mod tests {
       ]
     );
   }
+
+  #[test]
+  fn rust_indents() {
+    check_indents(
+      "rust",
+      "fn foo(
+  a: u32,
+) {
+  let b = [
+    a,
+  ];
+  match b {
+    _ => {}
+  }
+}
+",
+    );
+  }
+
+  #[test]
+  fn python_indents() {
+    check_indents(
+      "python",
+      "class Foo:
+  def bar(self, a):
+    if a:
+      return [
+        1,
+      ]
+    elif a is None:
+      pass
+    else:
+      for x in range(3):
+        print(
+          x,
+        )
+    try:
+      pass
+    except ValueError:
+      pass
+    finally:
+      pass
+",
+    );
+  }
+
+  #[test]
+  fn yaml_indents() {
+    check_indents(
+      "yaml",
+      "a:
+  b: 1
+  c:
+    - d
+    - e: 1
+      f: 2
+g: 3
+",
+    );
+  }
+
+  #[test]
+  fn markdown_list_indents() {
+    check_indents(
+      "markdown",
+      "- one
+  two
+- three
+  - four
+    five
+",
+    );
+  }
 }
@@ -0,0 +1,416 @@
+use std::{
+  io,
+  time::{Duration, SystemTime},
+};
+
+use be_doc::{Cursor, Edit, LineEnding};
+use serde::{Deserialize, Serialize};
+
+use crate::{EditorState, fs};
+
+/// The undo history of a document. Undoing and then making a new edit starts a
+/// new branch, and the old branch is kept. Nodes are stored in the order they
+/// were created, so walking the list walks edits in time order.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct UndoTree {
+  /// The first node is the root, which is the state of the document when it
+  /// was opened.
+  nodes:   Vec<UndoNode>,
+  current: usize,
+
+  /// The cursor before the edit currently being built.
+  #[serde(skip)]
+  pending:     Option<Cursor>,
+  /// A node pushed by the current action, whose `cursor_after` isn't known yet.
+  #[serde(skip)]
+  fresh:       Option<usize>,
+  /// The line ending before and after the edit currently being built, if it
+  /// converts the line endings.
+  #[serde(skip)]
+  line_ending: Option<(LineEnding, LineEnding)>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct UndoNode {
+  /// The edit from `parent` to this node. Empty for the root.
+  edit:   Edit,
+  parent: usize,
+  /// The child to move to on redo. This is the most recent branch that was
+  /// created or undone.
+  redo:   Option<usize>,
+
+  time:          SystemTime,
+  cursor_before: Cursor,
+  cursor_after:  Cursor,
+  /// The line ending before and after this edit, for edits like `:set ff=dos`
+  /// that convert the whole file.
+  #[serde(default)]
+  line_ending:   Option<(LineEnding, LineEnding)>,
+}
+
+/// The most undo states saved for a file. Older states are dropped when saving,
+/// so that the cache doesn't grow forever.
+const MAX_SAVED_NODES: usize = 1000;
+
+/// The undo tree of a file, as stored in the cache directory.
+#[derive(Serialize, Deserialize)]
+struct UndoFile<T> {
+  /// The hash of the file when the history was saved. If the file is changed
+  /// outside the editor, the history no longer applies.
+  hash: u64,
+  tree: T,
+}
+
+impl Default for UndoTree {
+  fn default() -> Self {
+    UndoTree {
+      nodes:       vec![UndoNode {
+        edit:          Edit::empty(),
+        parent:        0,
+        redo:          None,
+        time:          SystemTime::now(),
+        cursor_before: Cursor::START,
+        cursor_after:  Cursor::START,
+        line_ending:   None,
+      }],
+      current:     0,
+      pending:     None,
+      fresh:       None,
+      line_ending: None,
+    }
+  }
+}
+
+impl UndoTree {
+  pub fn current(&self) -> usize { self.current }
+
+  /// Called before the first change of an edit, to record the cursor.
+  pub fn start(&mut self, cursor: Cursor) {
+    if self.pending.is_none() {
+      self.pending = Some(cursor);
+    }
+  }
+
+  /// Drops an edit that ended up not changing anything.
+  pub fn cancel(&mut self) {
+    self.pending = None;
+    self.line_ending = None;
+  }
+
+  /// Records that the edit being built changes the line ending from `before`
+  /// to `after`, so that undo can change it back.
+  pub fn convert_line_ending(&mut self, before: LineEnding, after: LineEnding) {
+    let before = self.line_ending.map_or(before, |(before, _)| before);
+    self.line_ending = Some((before, after));
+  }
+
+  pub fn push(&mut self, edit: Edit, cursor: Cursor) {
+    let index = self.nodes.len();
+    self.nodes.push(UndoNode {
+      edit,
+      parent: self.current,
+      redo: None,
+      time: SystemTime::now(),
+      cursor_before: self.pending.take().unwrap_or(cursor),
+      cursor_after: cursor,
+      line_ending: self.line_ending.take(),
+    });
+    self.nodes[self.current].redo = Some(index);
+    self.current = index;
+    self.fresh = Some(index);
+  }
+
+  /// Called after each action, to record where the cursor ended up after the
+  /// edit the action made.
+  pub fn finish(&mut self, cursor: Cursor) {
+    if let Some(node) = self.fresh.take() {
+      self.nodes[node].cursor_after = cursor;
+    }
+  }
+
+  fn undo_target(&self) -> Option<usize> {
+    (self.current != 0).then(|| self.nodes[self.current].parent)
+  }
+  fn redo_target(&self) -> Option<usize> { self.nodes[self.current].redo }
+
+  /// The node `n` states before or after the current one, in time order.
+  fn step_target(&self, n: isize) -> usize {
+    self.current.saturating_add_signed(n).min(self.nodes.len() - 1)
+  }
+
+  /// The last node made at or before `time`.
+  fn time_target(&self, time: SystemTime) -> usize {
+    self.nodes.iter().rposition(|n| n.time <= time).unwrap_or(0)
+  }
+
+  /// The nodes to walk through to get from the current node to `target`. Each
+  /// step is a node, and whether its edit should be redone (or undone).
+  fn path(&self, target: usize) -> Vec<(usize, bool)> {
+    let mut undo = vec![];
+    let mut redo = vec![];
+
+    // Parents always come before their children, so the higher of the two is
+    // never the common ancestor.
+    let (mut a, mut b) = (self.current, target);
+    while a != b {
+      if a > b {
+        undo.push((a, false));
+        a = self.nodes[a].parent;
+      } else {
+        redo.push((b, true));
+        b = self.nodes[b].parent;
+      }
+    }
+
+    undo.extend(redo.into_iter().rev());
+    undo
+  }
+
+  /// A copy of the tree with at most `max` nodes. The oldest ancestor of the
+  /// current node that fits becomes the new root, and only the branches that
+  /// grow from it are kept.
+  fn truncated(&self, max: usize) -> UndoTree {
+    let mut root = self.current;
+    while root != 0 && self.nodes[root].parent + max >= self.nodes.len() {
+      root = self.nodes[root].parent;
+    }
+
+    // Parents always come before their children, so one pass finds every node
+    // that grows from the new root.
+    let mut index = vec![None; self.nodes.len()];
+    let mut nodes = vec![];
+    for (i, node) in self.nodes.iter().enumerate().skip(root) {
+      if i != root && index[node.parent].is_none() {
+        continue;
+      }
+
+      index[i] = Some(nodes.len());
+      let mut node = node.clone();
+      node.parent = index[node.parent].unwrap_or(0);
+      nodes.push(node);
+    }
+    for node in &mut nodes {
+      node.redo = node.redo.and_then(|redo| index[redo]);
+    }
+    nodes[0].edit = Edit::empty();
+    nodes[0].line_ending = None;
+
+    UndoTree {
+      nodes,
+      current: index[self.current].unwrap(),
+      pending: None,
+      fresh: None,
+      line_ending: None,
+    }
+  }
+
+  fn set_current(&mut self, target: usize) {
+    self.current = target;
+
+    // Point redo at the branch we're on, so that undo and then redo comes back
+    // here.
+    let mut node = target;
+    while node != 0 {
+      let parent = self.nodes[node].parent;
+      self.nodes[parent].redo = Some(node);
+      node = parent;
+    }
+  }
+}
+
+impl EditorState {
+  pub(crate) fn undo(&mut self, n: u32) {
+    for _ in 0..n {
+      let Some(target) = self.undo.undo_target() else { break };
+      self.goto_undo_node(target);
+    }
+  }
+
+  pub(crate) fn redo(&mut self, n: u32) {
+    for _ in 0..n {
+      let Some(target) = self.undo.redo_target() else { break };
+      self.goto_undo_node(target);
+    }
+  }
+
+  /// Moves `n` states backward (or forward) in time, as with `g-` and `g+`.
+  pub(crate) fn undo_steps(&mut self, n: isize) { self.goto_undo_node(self.undo.step_target(n)); }
+
+  fn goto_undo_node(&mut self, target: usize) {
+    for (node, redo) in self.undo.path(target) {
+      let node = &self.undo.nodes[node];
+      let edit = node.edit.clone();
+      let cursor = if redo { node.cursor_after } else { node.cursor_before };
+      if let Some((before, after)) = node.line_ending {
+        self.line_ending = if redo { after } else { before };
+      }
+
+      let changes: Vec<_> =
+        if redo { edit.redo().cloned().collect() } else { edit.undo().cloned().collect() };
+      let start = changes.iter().map(|c| c.range.start).min().unwrap_or(0);
+      for change in changes {
+        self.change_no_history(change);
+      }
+
+      // Leave the cursor at the start of the change, unless it was before the
+      // change to begin with.
+      self.cursor = cursor.min(self.doc.offset_to_cursor(start));
+    }
+
+    self.undo.set_current(target);
+    self.clamp_cursor();
+  }
+
+  /// Runs `:earlier` (or `:later`, if `earlier` is false).
+  pub fn undo_command(&mut self, earlier: bool, arg: UndoArg) {
+    match arg {
+      UndoArg::Steps(n) => self.undo_steps(if earlier { -(n as isize) } else { n as isize }),
+      UndoArg::Time(duration) => {
+        let now = self.undo.nodes[self.undo.current].time;
+        let time = if earlier { now.checked_sub(duration) } else { now.checked_add(duration) };
+        let target = time.map_or(0, |t| self.undo.time_target(t));
+        self.goto_undo_node(target);
+      }
+    }
+  }
+
+  /// Loads the undo history for the current file from the cache directory, if
+  /// it was saved for the same file contents.
+  pub(crate) fn load_undo(&mut self) {
+    self.undo = UndoTree::default();
+
+    // Large files are still loading, so their hash isn't known yet.
+    let Some(file) = self.file.as_ref().filter(|f| !f.large) else { return };
+    let Ok(cache) = fs::cache_path("undo", file.path()) else { return };
+    let Ok(text) = std::fs::read_to_string(&cache) else { return };
+
+    match serde_json::from_str::<UndoFile<UndoTree>>(&text) {
+      Ok(undo) if undo.hash == self.doc.content_hash() => {
+        self.undo = undo.tree;
+      }
+      Ok(_) => {}
+      Err(e) => warn!("invalid undo file {}: {e}", cache.display()),
+    }
+  }
+
+  /// Writes the undo history for the current file into the cache directory.
+  pub(crate) fn save_undo(&self) -> io::Result<()> {
+    let Some(file) = self.file.as_ref().filter(|f| !f.large) else { return Ok(()) };
+
+    let path = fs::cache_path("undo", file.path())?;
+    std::fs::create_dir_all(path.parent().unwrap())?;
+
+    let undo =
+      UndoFile { hash: self.doc.content_hash(), tree: self.undo.truncated(MAX_SAVED_NODES) };
+    std::fs::write(path, serde_json::to_vec(&undo)?)
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum UndoArg {
+  Steps(usize),
+  Time(Duration),
+}
+
+/// Parses the argument to `:earlier` or `:later`, which is a count of states,
+/// or a time like `10s`, `5m`, `1h`, or `2d`.
+pub(crate) fn parse_undo_arg(arg: &str) -> Result<UndoArg, String> {
+  if arg.is_empty() {
+    return Ok(UndoArg::Steps(1));
+  }
+
+  let len = arg.find(|c: char| !c.is_ascii_digit()).unwrap_or(arg.len());
+  let n = arg[..len].parse::<u64>().map_err(|_| format!("invalid argument: {arg}"))?;
+  let seconds = match &arg[len..] {
+    "" => return Ok(UndoArg::Steps(n as usize)),
+    "s" => 1,
+    "m" => 60,
+    "h" => 60 * 60,
+    "d" => 24 * 60 * 60,
+    _ => return Err(format!("invalid argument: {arg}")),
+  };
+
+  Ok(UndoArg::Time(Duration::from_secs(n * seconds)))
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::tests::*;
+
+  #[test]
+  fn branches() {
+    let mut editor = editor("x\n");
+
+    editor.keys("ione <Esc>u");
+    editor.check(expect![@"⟦x⟧\n"]);
+
+    editor.keys("itwo <Esc>");
+    editor.check(expect![@"two⟦ ⟧x\n"]);
+
+    // Undo and redo follow the latest branch.
+    editor.keys("u");
+    editor.check(expect![@"⟦x⟧\n"]);
+    editor.keys("<C-r>");
+    editor.check(expect![@"⟦t⟧wo x\n"]);
+
+    // `g-` and `g+` walk through every state, in time order.
+    editor.keys("g-");
+    editor.check(expect![@"⟦o⟧ne x\n"]);
+    editor.keys("g-");
+    editor.check(expect![@"⟦x⟧\n"]);
+    editor.keys("g+");
+    editor.check(expect![@"⟦o⟧ne x\n"]);
+    editor.keys("2g+");
+    editor.check(expect![@"⟦t⟧wo x\n"]);
+
+    // Redo now follows the branch that was last visited.
+    editor.keys("g-uu<C-r>");
+    editor.check(expect![@"⟦o⟧ne x\n"]);
+  }
+
+  #[test]
+  fn earlier_later() {
+    let mut editor = editor("x\n");
+    editor.keys("ia<Esc>ib<Esc>ic<Esc>");
+    editor.check(expect![@"⟦c⟧bax\n"]);
+
+    editor.keys(":earlier 2<CR>");
+    editor.check(expect![@"⟦a⟧x\n"]);
+    editor.keys(":later<CR>");
+    editor.check(expect![@"⟦b⟧ax\n"]);
+    editor.keys(":earlier 1h<CR>");
+    editor.check(expect![@"⟦x⟧\n"]);
+    editor.keys(":later 10m<CR>");
+    editor.check(expect![@"⟦c⟧bax\n"]);
+  }
+
+  #[test]
+  fn serialize() {
+    let mut editor = editor("x\n");
+    editor.keys("ia<Esc>uib<Esc>");
+
+    let json = serde_json::to_string(&editor.undo).unwrap();
+    editor.undo = serde_json::from_str(&json).unwrap();
+
+    editor.keys("g-");
+    editor.check(expect![@"⟦a⟧x\n"]);
+    editor.keys("g+");
+    editor.check(expect![@"⟦b⟧x\n"]);
+  }
+
+  #[test]
+  fn truncate() {
+    let mut editor = editor("x\n");
+    editor.keys("ia<Esc>ib<Esc>uic<Esc>id<Esc>ie<Esc>");
+    assert_eq!(editor.doc.to_string(), "edcax\n");
+
+    // The branch with `b` is older than the new root, so it's dropped.
+    editor.undo = editor.undo.truncated(3);
+    editor.keys("uuuu");
+    assert_eq!(editor.doc.to_string(), "cax\n");
+    editor.keys("g-");
+    assert_eq!(editor.doc.to_string(), "cax\n");
+    editor.keys("<C-r><C-r>");
+    assert_eq!(editor.doc.to_string(), "edcax\n");
+  }
+}
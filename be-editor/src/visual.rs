@@ -0,0 +1,253 @@
+use std::ops::Range;
+
+use be_doc::{Change, Cursor, Edit, Line, Selection, VisualColumn};
+use be_input::{Mode, Move, Operator, VisualMode};
+
+use crate::{EditorState, Register, RegisterKind};
+
+/// A blockwise change in progress. The text typed on the first line of the
+/// block is copied onto the other lines when leaving insert mode.
+pub(crate) struct BlockInsert {
+  start:     usize,
+  line_len:  usize,
+  len_lines: usize,
+  column:    VisualColumn,
+  lines:     Vec<Line>,
+}
+
+impl EditorState {
+  /// Returns the current selection, if in visual mode.
+  pub fn selection(&self) -> Option<Selection> {
+    match self.mode {
+      Mode::Visual(_) => Some(Selection::new(self.visual_anchor, self.cursor)),
+      _ => None,
+    }
+  }
+
+  /// Returns the bytes covered by the current selection. Blockwise selections
+  /// return one range per line.
+  pub fn selection_ranges(&self) -> Vec<Range<usize>> {
+    let Some(selection) = self.selection() else { return vec![] };
+
+    match self.mode {
+      Mode::Visual(VisualMode::Character) => vec![self.doc.selection_chars(selection)],
+      Mode::Visual(VisualMode::Line) => vec![self.doc.selection_lines(selection)],
+      Mode::Visual(VisualMode::Block) => self.doc.selection_block(selection),
+      _ => vec![],
+    }
+  }
+
+  fn selection_register(&self) -> Register {
+    let kind = match self.mode {
+      Mode::Visual(VisualMode::Line) => RegisterKind::Linewise,
+      Mode::Visual(VisualMode::Block) => RegisterKind::Blockwise,
+      _ => RegisterKind::Charwise,
+    };
+
+    Register::new(self.selection_text(), kind)
+  }
+
+  fn selection_text(&self) -> String {
+    let ranges = self.selection_ranges();
+    let mut text = String::new();
+    for (i, range) in ranges.iter().enumerate() {
+      if i != 0 {
+        text.push('\n');
+      }
+      text.push_str(&self.doc.range(range.clone()).to_string());
+    }
+    text
+  }
+
+  pub(crate) fn damage_selection(&mut self, selection: Option<Selection>) {
+    if let Some(selection) = selection {
+      for line in selection.start_line().0..=selection.end_line().0 {
+        self.damage_line(Line(line));
+      }
+    }
+  }
+
+  pub(crate) fn copy_selection(&mut self) {
+    if self.selection().is_none() {
+      return;
+    }
+
+    self.clipboard.copy(&self.selection_text());
+    let start = self.selection().unwrap().start();
+    self.leave_visual(start);
+  }
+
+  pub(crate) fn perform_visual(&mut self, op: Operator) {
+    let Some(selection) = self.selection() else { return };
+    let Mode::Visual(kind) = self.mode else { return };
+
+    let ranges = self.selection_ranges();
+    let start = selection.start();
+    let (left, _) = self.doc.block_columns(selection);
+    let block_start = Cursor {
+      line:          start.line,
+      column:        self.doc.column_from_visual(start.line, VisualColumn(left)),
+      target_column: VisualColumn(left),
+    };
+
+    // Every operator that changes the document is a single undo step.
+    if op != Operator::Yank && self.current_edit.is_none() {
+      self.current_edit = Some(Edit::empty());
+    }
+
+    match op {
+      Operator::Yank => {
+        self.yank_register(self.selection_register());
+        self.leave_visual(if kind == VisualMode::Block { block_start } else { start });
+      }
+      Operator::Delete => {
+        self.delete_register(self.selection_register());
+        for range in ranges.into_iter().rev() {
+          self.change(Change::remove(range));
+        }
+
+        match kind {
+          VisualMode::Character => self.leave_visual(start),
+          VisualMode::Line => {
+            self.leave_visual(Cursor { line: start.line, ..Cursor::START });
+            self.perform_move(Move::LineStartOfText, None);
+          }
+          VisualMode::Block => self.leave_visual(block_start),
+        }
+      }
+      Operator::Change => {
+        self.delete_register(self.selection_register());
+
+        match kind {
+          VisualMode::Character => {
+            self.change(Change::remove(ranges[0].clone()));
+            self.cursor = start;
+            self.set_mode(Mode::Insert);
+          }
+          VisualMode::Line => {
+            self.change(Change::remove(
+              self.doc.byte_of_line(selection.start_line())
+                ..self.doc.byte_of_line_end(selection.end_line()),
+            ));
+            self.cursor = Cursor { line: start.line, ..Cursor::START };
+            self.set_mode(Mode::Insert);
+            self.auto_indent(be_input::VerticalDirection::Up);
+          }
+          VisualMode::Block => {
+            // Lines that don't reach the block are left alone.
+            let lines = ranges
+              .iter()
+              .enumerate()
+              .skip(1)
+              .filter(|(_, range)| !range.is_empty())
+              .map(|(i, _)| start.line + i as i32)
+              .collect();
+
+            for range in ranges.into_iter().rev() {
+              self.change(Change::remove(range));
+            }
+
+            self.cursor = block_start;
+            self.set_mode(Mode::Insert);
+            self.block_insert = Some(BlockInsert {
+              start: self.doc.cursor_offset(self.cursor),
+              line_len: self.doc.line(start.line).byte_len(),
+              len_lines: self.doc.len_lines(),
+              column: VisualColumn(left),
+              lines,
+            });
+          }
+        }
+      }
+      Operator::Indent | Operator::Dedent => {
+        let indent_width = self.config.borrow().settings.editor.indent_width as usize;
+
+        for line in (selection.start_line().0..=selection.end_line().0).rev() {
+          let line = Line(line);
+          let line_start = self.doc.byte_of_line(line);
+          let text = self.doc.line(line);
+
+          if op == Operator::Indent {
+            if !text.chars().all(|c| c.is_whitespace()) {
+              self.change(Change::insert(line_start, &" ".repeat(indent_width)));
+            }
+          } else {
+            let len = if text.chars().next() == Some('\t') {
+              1
+            } else {
+              text.chars().take(indent_width).take_while(|c| *c == ' ').count()
+            };
+            if len > 0 {
+              self.change(Change::remove(line_start..line_start + len));
+            }
+          }
+        }
+
+        self.leave_visual(Cursor { line: start.line, ..Cursor::START });
+        self.perform_move(Move::LineStartOfText, None);
+      }
+      Operator::Reindent => {
+        self.reindent_lines(selection.start_line(), selection.end_line());
+        self.leave_visual(Cursor { line: start.line, ..Cursor::START });
+        self.perform_move(Move::LineStartOfText, None);
+      }
+      Operator::Comment => {
+        self.toggle_comments(selection.start_line(), selection.end_line());
+        self.leave_visual(Cursor { line: start.line, ..Cursor::START });
+        self.perform_move(Move::LineStartOfText, None);
+      }
+      Operator::SwitchCase => {
+        for range in ranges.into_iter().rev() {
+          let text = self.doc.range(range.clone()).to_string();
+          let mut switched = String::with_capacity(text.len());
+          for c in text.chars() {
+            if c.is_lowercase() {
+              switched.extend(c.to_uppercase());
+            } else if c.is_uppercase() {
+              switched.extend(c.to_lowercase());
+            } else {
+              switched.push(c);
+            }
+          }
+
+          if switched != text {
+            self.change(Change::replace(range, &switched));
+          }
+        }
+
+        self.leave_visual(if kind == VisualMode::Block { block_start } else { start });
+      }
+    }
+  }
+
+  /// Copies the text typed during a blockwise change onto the rest of the
+  /// block. Called when leaving insert mode.
+  pub(crate) fn finish_block_insert(&mut self) {
+    let Some(block) = self.block_insert.take() else { return };
+    if self.doc.len_lines() != block.len_lines {
+      return;
+    }
+
+    let first = self.doc.line_of_byte(block.start);
+    let len = self.doc.line(first).byte_len();
+    if len <= block.line_len {
+      return;
+    }
+
+    let text = self.doc.range(block.start..block.start + len - block.line_len).to_string();
+    for line in block.lines.into_iter().rev() {
+      let column = self.doc.column_from_visual(line, block.column);
+      let offset = self.doc.cursor_offset(Cursor { line, column, target_column: block.column });
+      self.change(Change::insert(offset, &text));
+    }
+  }
+
+  /// Returns to normal mode after a visual operator, and finishes the undo
+  /// edit started by it.
+  fn leave_visual(&mut self, cursor: Cursor) {
+    self.mode = Mode::Normal;
+    self.remove_current_edit();
+    self.cursor = cursor;
+    self.clamp_cursor();
+  }
+}
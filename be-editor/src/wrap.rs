@@ -0,0 +1,383 @@
+//! Soft wrap. In languages with `soft-wrap` set, long lines are shown over
+//! several screen rows, which are as wide as the view or the language's
+//! `text-width`, whichever is smaller. Lines break after whitespace or a
+//! hyphen, and a word longer than a whole row breaks anywhere. The view breaks
+//! its layouts at the columns from `line_wraps`, so the two always agree.
+
+use std::{
+  cell::{Ref, RefMut},
+  ops::RangeInclusive,
+};
+
+use be_config::WrapMoves;
+use be_doc::{Column, Cursor, Line, VisualColumn, crop::RopeSlice};
+use unicode_width::UnicodeWidthStr;
+
+use crate::EditorState;
+
+/// How many screen rows each line takes up. Lines are wrapped the first time
+/// they're needed, and forgotten when they change or the wrap width changes.
+#[derive(Default)]
+pub(crate) struct WrapCache {
+  width:  usize,
+  rows:   Vec<Option<usize>>,
+  /// The first screen row of each line, summed from the start of the document
+  /// as far as it's been needed. Lines hidden in a closed fold start on the
+  /// row of the fold.
+  starts: Vec<usize>,
+}
+
+impl WrapCache {
+  /// Forgets the rows of `lines`, which were replaced with `count` new lines.
+  pub(crate) fn replace(&mut self, lines: RangeInclusive<usize>, count: usize) {
+    self.forget_starts_after(*lines.start());
+    if *lines.start() >= self.rows.len() {
+      return;
+    }
+
+    let end = (*lines.end() + 1).min(self.rows.len());
+    self.rows.splice(*lines.start()..end, std::iter::repeat_n(None, count));
+  }
+
+  /// Forgets the first rows of the lines after `line`, as a change at `line`
+  /// moves them.
+  pub(crate) fn forget_starts_after(&mut self, line: usize) { self.starts.truncate(line + 1); }
+}
+
+impl EditorState {
+  /// Sets how many columns fit in the view. Lines wrap at this width, unless
+  /// the language's `text-width` is smaller.
+  pub fn set_view_columns(&mut self, columns: usize) { self.view_columns = Some(columns); }
+
+  /// Returns the width that lines wrap at, or `None` if soft wrap is off.
+  pub fn wrap_width(&self) -> Option<usize> {
+    // Wrapping looks at every line, which is too slow for large files.
+    if self.is_large() {
+      return None;
+    }
+
+    let config = self.config.borrow();
+    let language = self.filetype.and_then(|ft| config.languages.get(&ft));
+    if !self.soft_wrap.unwrap_or_else(|| language.is_some_and(|l| l.soft_wrap)) {
+      return None;
+    }
+
+    match (language.and_then(|l| l.text_width), self.view_columns) {
+      (Some(text_width), Some(view)) => Some(text_width.min(view)),
+      (text_width, view) => text_width.or(view),
+    }
+    .filter(|&width| width > 0)
+  }
+
+  /// Returns the column that each screen row of `line` after the first starts
+  /// at. This is empty if the line doesn't wrap.
+  pub fn line_wraps(&self, line: Line) -> Vec<Column> {
+    match self.wrap_width() {
+      // The summary of a closed fold is never wrapped.
+      Some(width) if self.closed_fold_at(line).is_none() => wrap_line(self.doc.line(line), width),
+      _ => vec![],
+    }
+  }
+
+  /// Returns how many screen rows `line` takes up.
+  pub fn screen_rows(&self, line: Line) -> usize {
+    match self.wrap_width() {
+      Some(width) if self.closed_fold_at(line).is_none() => self.wrapped_rows(line, width),
+      _ => 1,
+    }
+  }
+
+  /// Returns the wrap cache for `width`, which is cleared if the width
+  /// changed.
+  fn wrap_cache(&self, width: usize) -> RefMut<'_, WrapCache> {
+    let mut cache = self.wrap_cache.borrow_mut();
+    if cache.width != width {
+      cache.width = width;
+      cache.rows.clear();
+      cache.starts.clear();
+    }
+    if cache.starts.is_empty() {
+      cache.starts.push(0);
+    }
+    cache
+  }
+
+  /// Returns how many screen rows `line` takes up when wrapped at `width`,
+  /// ignoring folds.
+  fn wrapped_rows(&self, line: Line, width: usize) -> usize {
+    let mut cache = self.wrap_cache(width);
+    if cache.rows.len() <= line.0 {
+      cache.rows.resize(line.0 + 1, None);
+    }
+
+    *cache.rows[line.0].get_or_insert_with(|| wrap_line(self.doc.line(line), width).len() + 1)
+  }
+
+  /// Sums up the first rows of lines until `done` returns `true` for them, or
+  /// they cover the whole document. The last entry is the row after the
+  /// document.
+  fn sum_rows(&self, width: usize, done: impl Fn(&[usize]) -> bool) -> Ref<'_, [usize]> {
+    loop {
+      let (line, start) = {
+        let cache = self.wrap_cache(width);
+        if done(&cache.starts) || cache.starts.len() > self.doc.len_lines() {
+          break;
+        }
+        (Line(cache.starts.len() - 1), *cache.starts.last().unwrap())
+      };
+
+      // A closed fold takes up a single row, which is counted at its last line.
+      let rows = match self.shown_line(line) {
+        shown if shown != line => usize::from(self.closed_fold_at(shown) == Some(line)),
+        _ => match self.closed_fold_at(line) {
+          Some(_) => 0,
+          None => self.wrapped_rows(line, width),
+        },
+      };
+      self.wrap_cache.borrow_mut().starts.push(start + rows);
+    }
+
+    Ref::map(self.wrap_cache.borrow(), |c| c.starts.as_slice())
+  }
+
+  /// Returns the first screen row that `line` is shown on. Each closed fold
+  /// takes up a single row, and lines hidden in a fold are on the row of the
+  /// fold.
+  pub fn row_of_line(&self, line: Line) -> usize {
+    let Some(width) = self.wrap_width() else { return self.fold_row_of_line(line) };
+
+    let starts = self.sum_rows(width, |starts| starts.len() > line.0);
+    starts[line.0.min(starts.len() - 1)]
+  }
+
+  /// Returns the line shown on `row`, and which of its screen rows `row` is.
+  pub fn row_position(&self, row: usize) -> (Line, usize) {
+    let Some(width) = self.wrap_width() else { return (self.line_of_fold_row(row), 0) };
+
+    let starts = self.sum_rows(width, |starts| starts.last().is_some_and(|&s| s > row));
+    let total = *starts.last().unwrap();
+    if row >= total {
+      return (Line(self.doc.len_lines() + row - total), 0);
+    }
+
+    // The first line that starts on or before `row`, which skips the lines
+    // hidden in a fold.
+    let after = starts.partition_point(|&s| s <= row);
+    let line = self.shown_line(Line(after - 1));
+    (line, row - starts[line.0])
+  }
+
+  /// Returns the line shown on `row`. This is the inverse of `row_of_line`.
+  pub fn line_of_row(&self, row: usize) -> Line { self.row_position(row).0 }
+
+  /// Returns the number of screen rows in the whole document.
+  pub fn total_rows(&self) -> usize { self.row_of_line(Line(self.doc.len_lines())) }
+
+  /// Returns the screen row that `cursor` is shown on.
+  pub fn screen_row(&self, cursor: Cursor) -> usize {
+    let wraps = self.line_wraps(cursor.line);
+    self.row_of_line(cursor.line) + wraps.iter().take_while(|&&c| c <= cursor.column).count()
+  }
+
+  /// Moves up or down `dist` lines, as with `j` and `k`, or `dist` screen rows
+  /// with `screen`, as with `gj` and `gk`. The `wrap-moves` setting swaps the
+  /// two.
+  pub(crate) fn move_vertical(&mut self, dist: i32, screen: bool) {
+    let screen = screen != (self.config.borrow().settings.editor.wrap_moves == WrapMoves::Screen);
+    if screen && self.wrap_width().is_some() {
+      self.move_screen_rows(dist);
+    } else {
+      self.move_rows(dist);
+    }
+  }
+
+  /// Moves to the screen row `row`, as when scrolling the view moves the
+  /// cursor along with it.
+  pub fn move_to_row(&mut self, row: usize) {
+    let dist = row as i64 - self.screen_row(self.cursor) as i64;
+    self.move_screen_rows(dist as i32);
+  }
+
+  /// Moves up or down `dist` screen rows, keeping the cursor in the same
+  /// column on the screen.
+  fn move_screen_rows(&mut self, dist: i32) {
+    let start = self.row_start(self.cursor.line, self.screen_row(self.cursor));
+    let offset = self.cursor.target_column.0.saturating_sub(start.0);
+
+    let row = (self.screen_row(self.cursor) as i64 + i64::from(dist))
+      .clamp(0, self.total_rows().saturating_sub(1) as i64) as usize;
+    let (line, row_in_line) = self.row_position(row);
+    let start = self.row_start(line, row);
+    let target = VisualColumn(start.0.saturating_add(offset));
+
+    let mut column = self.doc.column_from_visual(line, target);
+    // Stay on this row, instead of going on to the start of the next one.
+    if let Some(end) = self.line_wraps(line).get(row_in_line) {
+      column = column.min(Column(end.0 - 1));
+    }
+
+    self.cursor.line = line;
+    self.cursor.column = column.clamp(self.max_column());
+    self.cursor.target_column = target;
+  }
+
+  /// Returns the visual column that the screen row `row` starts at, where `row`
+  /// is one of the rows of `line`.
+  fn row_start(&self, line: Line, row: usize) -> VisualColumn {
+    let row_in_line = row - self.row_of_line(line);
+    let column = match row_in_line.checked_sub(1) {
+      Some(i) => self.line_wraps(line)[i],
+      None => Column(0),
+    };
+    self.doc.visual_column(Cursor { line, column, target_column: VisualColumn(0) })
+  }
+}
+
+/// Breaks `text` into rows at most `width` columns wide, and returns the column
+/// that each row after the first starts at.
+fn wrap_line(text: RopeSlice, width: usize) -> Vec<Column> {
+  let mut wraps = vec![];
+
+  // These are all visual columns.
+  let mut row_start = 0;
+  let mut col = 0;
+  // The last place in this row that the line can break, as a column and a
+  // visual column.
+  let mut last_break = None;
+  let mut prev = None::<char>;
+
+  for (i, g) in text.graphemes().enumerate() {
+    let blank = g.chars().all(char::is_whitespace);
+    let first = g.chars().next().unwrap_or(' ');
+    if !blank && prev.is_some_and(|c| c.is_whitespace() || (c == '-' && first.is_alphabetic())) {
+      last_break = Some((i, col));
+    }
+
+    let w = g.width();
+    // Whitespace at the end of a row hangs past the edge, instead of wrapping.
+    if !blank && col + w > row_start + width && col > row_start {
+      let (at, at_col) = match last_break {
+        Some((at, at_col)) if at_col > row_start => (at, at_col),
+        _ => (i, col),
+      };
+      wraps.push(Column(at));
+      row_start = at_col;
+      last_break = None;
+
+      // A word longer than a whole row breaks anywhere.
+      if col + w > row_start + width && col > row_start {
+        wraps.push(Column(i));
+        row_start = col;
+      }
+    }
+
+    col += w;
+    prev = g.chars().next_back();
+  }
+
+  wraps
+}
+
+#[cfg(test)]
+mod tests {
+  use be_config::WrapMoves;
+  use be_doc::{Column, Line};
+
+  use crate::tests::*;
+
+  fn wrapped(src: &str, columns: usize) -> TestEditor {
+    let mut editor = editor(src);
+    editor.keys(":set wrap<CR>");
+    editor.set_view_columns(columns);
+    editor
+  }
+
+  #[test]
+  fn breaks() {
+    let editor = wrapped("one two three four\nabcdefghijkl\nshort\n", 9);
+    assert_eq!(editor.line_wraps(Line(0)), [Column(8), Column(14)]);
+    assert_eq!(editor.line_wraps(Line(1)), [Column(9)]);
+    assert!(editor.line_wraps(Line(2)).is_empty());
+
+    let editor = wrapped("one-two three    four\n", 5);
+    assert_eq!(editor.line_wraps(Line(0)), [Column(4), Column(8), Column(17)]);
+  }
+
+  #[test]
+  fn rows() {
+    let editor = wrapped("one two three four\nfive\nsix seven eight\n", 9);
+    assert_eq!(editor.row_of_line(Line(1)), 3);
+    assert_eq!(editor.row_of_line(Line(2)), 4);
+    assert_eq!(editor.row_position(2), (Line(0), 2));
+    assert_eq!(editor.row_position(5), (Line(2), 1));
+    assert_eq!(editor.total_rows(), 6);
+
+    // Without wrapping, each line is a single row.
+    let mut editor = editor;
+    editor.keys(":set nowrap<CR>");
+    assert_eq!(editor.row_of_line(Line(2)), 2);
+  }
+
+  #[test]
+  fn rows_after_changes() {
+    let mut editor = wrapped("one\ntwo\nthree four five\n", 9);
+    assert_eq!(editor.total_rows(), 4);
+
+    editor.keys("$a two three<Esc>");
+    assert_eq!(editor.row_of_line(Line(1)), 2);
+    assert_eq!(editor.total_rows(), 5);
+
+    editor.keys("jdd");
+    assert_eq!(editor.row_of_line(Line(1)), 2);
+    assert_eq!(editor.total_rows(), 4);
+
+    editor.keys("j0i<BS><Esc>");
+    assert_eq!(editor.doc.to_string(), "one two threethree four five\n");
+    assert_eq!(editor.screen_rows(Line(0)), 4);
+    assert_eq!(editor.total_rows(), 4);
+
+    editor.set_view_columns(40);
+    assert_eq!(editor.total_rows(), 1);
+  }
+
+  #[test]
+  fn rows_with_folds() {
+    let mut editor = wrapped("one two three four\nfive\n  six seven eight\n  nine\nten\n", 9);
+    assert_eq!(editor.total_rows(), 9);
+
+    editor.keys("jzc");
+    assert_eq!(editor.row_of_line(Line(1)), 3);
+    assert_eq!(editor.row_of_line(Line(3)), 3);
+    assert_eq!(editor.row_of_line(Line(4)), 4);
+    assert_eq!(editor.row_position(3), (Line(1), 0));
+    assert_eq!(editor.row_position(4), (Line(4), 0));
+    assert_eq!(editor.total_rows(), 5);
+
+    editor.keys("zo");
+    assert_eq!(editor.row_of_line(Line(4)), 8);
+    assert_eq!(editor.row_position(5), (Line(2), 1));
+  }
+
+  #[test]
+  fn moves() {
+    let mut editor = wrapped("one two three four\nfive\n", 9);
+    editor.keys("lgj");
+    editor.check(expect![@"one two t⟦h⟧ree four\nfive\n"]);
+    editor.keys("gj");
+    editor.check(expect![@"one two three f⟦o⟧ur\nfive\n"]);
+    editor.keys("gjgk");
+    editor.check(expect![@"one two three f⟦o⟧ur\nfive\n"]);
+    editor.keys("$gkgj");
+    editor.check(expect![@"one two three fou⟦r⟧\nfive\n"]);
+
+    // `j` still moves by lines.
+    editor.keys("gg0j");
+    editor.check(expect![@"one two three four\n⟦f⟧ive\n"]);
+
+    editor.config.borrow_mut().settings.editor.wrap_moves = WrapMoves::Screen;
+    editor.keys("gg0j");
+    editor.check(expect![@"one two ⟦t⟧hree four\nfive\n"]);
+    editor.keys("gj");
+    editor.check(expect![@"one two three four\nfiv⟦e⟧\n"]);
+  }
+}
@@ -118,6 +118,27 @@ impl<'a> Layout<'a> {
     }
   }
 
+  /// Builds a layout like `build_layout`, breaking lines before every cluster
+  /// that follows one styled with `TextWrapMode::Wrap`, and nowhere else. The
+  /// rest of the text should be `NoWrap`, with `OverflowWrap::Anywhere`.
+  pub fn build_wrapped_layout(
+    &mut self,
+    mut layout: parley::Layout<peniko::Brush>,
+    backgrounds: Vec<(usize, Option<peniko::Brush>)>,
+  ) -> TextLayout {
+    // With no room, every cluster overflows, so parley takes each break
+    // opportunity as soon as it reaches it.
+    layout.break_all_lines(Some(0.0));
+    layout.align(None, parley::Alignment::Start, parley::AlignmentOptions::default());
+
+    TextLayout {
+      metrics: self.store.text.font_metrics().clone(),
+      layout,
+      backgrounds,
+      scale: self.scale,
+    }
+  }
+
   pub fn is_stale(&self, layout: &TextLayout) -> bool { layout.scale != self.scale }
 
   pub fn layout_text(&mut self, font: Font, text: &str, color: Color) -> TextLayout {
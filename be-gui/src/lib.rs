@@ -1,6 +1,6 @@
 mod render;
 
-use std::{collections::HashMap, hash::Hash};
+use std::{collections::HashMap, hash::Hash, path::PathBuf};
 
 use be_doc::Cursor;
 use be_input::{Action, KeyStroke, Navigation};
@@ -44,6 +44,14 @@ struct State {
   notify: Notify,
 
   current_hover: Option<ViewId>,
+
+  /// Lists the keys that can follow `keys`, while a sequence is pending.
+  which_key:     Option<view::WhichKey>,
+  /// The hints shown in `which_key` for each sequence.
+  continuations: be_input::Continuations,
+  /// The files that `:wa` is saving, which are reported once they've all been
+  /// written.
+  write_all:     Vec<PathBuf>,
 }
 
 struct ViewCollection {
@@ -114,6 +122,9 @@ impl State {
       tab_layout:    WidgetCollection::new(),
       notify:        store.notifier(),
       current_hover: None,
+      which_key:     None,
+      continuations: Default::default(),
+      write_all:     vec![],
     };
 
     let layout = store.workspace.config.borrow().settings.layout.clone();
@@ -194,6 +205,9 @@ impl State {
       puffin::profile_scope!("layout editor");
       editor.layout();
     }
+    if !self.write_all.is_empty() && !layout.store.workspace.editors().any(|e| e.is_saving()) {
+      self.report_write_all(&layout.store.workspace);
+    }
 
     layout.clipped(
       Rect::new(0.0, 0.0, layout.size().width, layout.size().height - 25.0),
@@ -305,7 +319,16 @@ impl State {
     None
   }
 
-  fn animated(&self) -> bool { self.tabs[self.active].content.animated(&self.views.views) }
+  fn animated(&self) -> bool {
+    // Saves from `:wa` finish in `layout`, which has to keep running even if
+    // their editors aren't visible.
+    self.tabs[self.active].content.animated(&self.views.views) || !self.write_all.is_empty()
+  }
+
+  /// When the window should be redrawn next, if nothing else happens first.
+  fn wake_at(&self) -> Option<std::time::Instant> {
+    self.which_key.as_ref().and_then(|w| w.wake_at(std::time::Instant::now()))
+  }
 
   fn draw(&mut self, render: &mut Render) {
     render.split(
@@ -321,6 +344,9 @@ impl State {
         if let Some(popup) = &mut tab.popup {
           render.clipped(popup.bounds(render.size()), |render| popup.draw(render));
         }
+        if let Some(which_key) = &state.which_key {
+          which_key.draw(render);
+        }
       },
       |state, render| state.draw_tabs(render),
     );
@@ -391,25 +417,57 @@ impl State {
   }
 
   fn on_key(&mut self, key: KeyStroke, store: &mut RenderStore) {
+    // Macros are replayed here, rather than in the editor, so that they can
+    // switch views like the keys they were recorded from.
+    be_editor::replay_macros(
+      self,
+      key,
+      |state, key, replaying| state.handle_key(key, replaying, store),
+      |state| state.active_editor().map(|e| &mut *e.editor),
+    );
+  }
+
+  fn handle_key(&mut self, key: KeyStroke, replaying: bool, store: &mut RenderStore) {
     self.keys.push(key);
+    let which_key = self.which_key.take();
+
+    let mode = self.mode();
+    let keys = self.keys.clone();
+    if let Some(editor) = self.active_editor()
+      && ((!replaying && editor.editor.record_key(mode, &keys))
+        || editor.editor.confirm_substitute_key(key)
+        || editor.editor.hex_key(key))
+    {
+      self.keys.clear();
+      return;
+    }
 
     if let ViewContent::Editor(e) = &mut self.active_view_mut().content {
       e.temporary_mode = None;
     }
 
-    match Action::from_input(self.mode(), &self.keys) {
-      Ok(action) => {
+    let res =
+      Action::from_input(&store.workspace.config.borrow().settings.keys, self.mode(), &self.keys);
+    match res {
+      Ok((action, len)) => {
+        let rest = self.keys.split_off(len);
         self.perform_action(action, store);
         self.keys.clear();
+
+        // Keys held back for a binding that wasn't finished are handled again.
+        for key in rest {
+          self.handle_key(key, true, store);
+        }
       }
       Err(be_input::ActionError::Unrecognized) => self.keys.clear(),
+      Err(be_input::ActionError::Incomplete) if replaying => {}
       Err(be_input::ActionError::Incomplete) => {
         let has_temporary = self.keys.len() == 1 && !self.keys[0].control;
 
         if has_temporary {
           let temporary_mode = match self.keys[0].key {
             be_input::Key::Char('r') => Some(be_input::Mode::Replace),
-            be_input::Key::Char('c' | 'd') => Some(be_input::Mode::Normal),
+            be_input::Key::Char('c' | 'd' | 'y') => Some(be_input::Mode::Normal),
             _ => None,
           };
 
@@ -417,6 +475,20 @@ impl State {
             e.temporary_mode = temporary_mode;
           }
         }
+
+        let mode = self.mode();
+        let hints = self
+          .continuations
+          .get(&store.workspace.config.borrow().settings.keys, mode, &self.keys)
+          .to_vec();
+        // Once the popup is showing, it stays up for the rest of the sequence.
+        self.which_key = match which_key {
+          Some(mut which_key) if which_key.visible(std::time::Instant::now()) => {
+            which_key.hints = hints;
+            Some(which_key)
+          }
+          _ => Some(view::WhichKey::new(hints)),
+        };
       }
     }
   }
@@ -459,30 +531,35 @@ impl State {
         self.active_tab_mut().popup =
           Some(view::Popup::Search(view::Search::new(self.notify.clone())));
       }
-      Action::Navigate { nav: Navigation::Split(axis) } => {
-        let new_view = self.split_active_view(store);
-        self.tabs[self.active].content.split(
-          match axis {
-            be_config::Axis::Horizontal => kurbo::Axis::Horizontal,
-            be_config::Axis::Vertical => kurbo::Axis::Vertical,
-          },
-          &mut self.views.views,
-          store,
-          new_view,
-        );
-      }
+      Action::Navigate { nav: Navigation::Split(axis) } => self.split(axis, store),
       Action::SetMode { mode: be_input::Mode::Command, .. } => {
-        self.active_tab_mut().popup =
-          Some(view::Popup::Command(view::CommandView::new(self.notify.clone())));
+        let text = self.active_editor().map(|e| e.editor.enter_command()).unwrap_or_default();
+        let command = view::CommandView::new(self.notify.clone(), &store.workspace).with_text(text);
+        self.active_tab_mut().popup = Some(view::Popup::Command(command));
       }
       Action::SetMode { mode: be_input::Mode::Normal, .. } if self.active_tab().popup.is_some() => {
         self.active_tab_mut().popup = None;
+        if let Some(editor) = self.active_editor() {
+          editor.editor.preview_command("");
+        }
       }
       _ => {
-        if let Some(popup) = &mut self.active_tab_mut().popup {
-          popup.perform_action(action)
+        let command = if let Some(popup) = &mut self.active_tab_mut().popup {
+          popup.perform_action(action);
+          match popup {
+            view::Popup::Command(command) => Some(command.text().to_string()),
+            _ => None,
+          }
         } else {
-          self.active_view_mut().perform_action(action)
+          self.active_view_mut().perform_action(action);
+          None
+        };
+
+        // Show a live preview of substitutions while they're typed.
+        if let Some(command) = command
+          && let Some(editor) = self.active_editor()
+        {
+          editor.editor.preview_command(&command);
         }
       }
     }
@@ -527,6 +604,15 @@ impl State {
     match event {
       Event::Workspace(WorkspaceEvent::Refresh) => {}
       Event::Workspace(WorkspaceEvent::Editor(be_editor::EditorEvent::OpenFile(path, cursor))) => {
+        // Files opened without a position come from the finder or file tree,
+        // while jumps and goto definition always pass one, and record their
+        // own jumps.
+        if cursor.is_none()
+          && let Some(editor) = self.active_editor()
+        {
+          editor.editor.record_jump();
+        }
+
         self.tabs[self.active].popup = None;
         self.open(&path, cursor, &mut store.workspace);
       }
@@ -539,75 +625,124 @@ impl State {
         }
       }
       Event::Workspace(WorkspaceEvent::Editor(be_editor::EditorEvent::RunCommand(cmd))) => {
-        let (cmd, args) = cmd.split_once(' ').unwrap_or((&cmd, ""));
+        self.tabs[self.active].popup = None;
 
-        match cmd {
-          "w" => {
-            if let Some(editor) = self.active_editor() {
-              editor.editor.begin_save();
-            }
-          }
-          "q" => {
-            let tab = &mut self.tabs[self.active];
-            let active_view = tab.content.active();
-            tab.content.close(active_view, &mut self.views.views);
-            // FIXME: Deduplicate layouts closing themselves (ie, terminal exit).
-            self.views.views.remove(&active_view);
-          }
-          "e" => {
-            if args.is_empty() {
-              if let Some(editor) = self.active_editor() {
-                if let Some(path) = editor.editor.file().map(|p| p.to_path_buf()) {
-                  let _ = editor.editor.open(&path);
-                }
-              }
-            } else {
-              let path = std::path::Path::new(args);
-              let path = if path.is_absolute() {
-                path.to_path_buf()
-              } else {
-                store.workspace.root.as_path().join(path)
-              };
-              self.open(&path, None, &mut store.workspace);
-            }
-          }
-          "noh" => {
-            if let Some(editor) = self.active_editor() {
-              editor.editor.clear_search();
-            }
-          }
-          "vs" => {
-            let new_view = self.split_active_view(store);
-            self.tabs[self.active].content.split(
-              Axis::Vertical,
-              &mut self.views.views,
-              store,
-              new_view,
-            );
-          }
-          "hs" => {
-            let new_view = self.split_active_view(store);
-            self.tabs[self.active].content.split(
-              Axis::Horizontal,
-              &mut self.views.views,
-              store,
-              new_view,
-            );
+        if let Some(editor) = self.active_editor() {
+          editor.editor.execute_command(&cmd);
+        } else {
+          match be_editor::Command::parse(&cmd) {
+            Ok(command) => return self.run_command(command, store),
+            Err(e) => warn!("{e}"),
           }
+        }
+      }
+      Event::Workspace(WorkspaceEvent::Editor(be_editor::EditorEvent::Command(command))) => {
+        return self.run_command(command, store);
+      }
+      Event::Exit => return true,
+    }
+
+    false
+  }
+
+  /// Runs an ex command that the active editor sent up, because it affects the
+  /// layout or other editors. Returns `true` if the app should close.
+  fn run_command(&mut self, command: be_editor::Command, store: &mut RenderStore) -> bool {
+    use be_editor::Command;
 
-          _ => {
-            println!("unknown command: {}", cmd);
+    match command {
+      Command::Quit => self.close_active_view(),
+      Command::BufferDelete { .. } => {
+        if let Some(editor) = self.active_editor() {
+          let editor = editor.editor.clone();
+          store.workspace.close_editor(&editor);
+        }
+        self.close_active_view();
+      }
+      Command::QuitAll { force } => {
+        let unsaved = store.workspace.editors().filter(|e| e.unsaved()).count();
+        if force || unsaved == 0 {
+          return true;
+        }
+
+        let message = match unsaved {
+          1 => "1 file has unsaved changes (add ! to override)".to_string(),
+          n => format!("{n} files have unsaved changes (add ! to override)"),
+        };
+        match self.active_editor() {
+          Some(editor) => editor.editor.show_error(message),
+          None => warn!("{message}"),
+        }
+      }
+      Command::WriteAll => {
+        for editor in store.workspace.editors_mut() {
+          if editor.unsaved()
+            && let Some(path) = editor.file().map(|p| p.to_path_buf())
+          {
+            editor.begin_save(false);
+            self.write_all.push(path);
           }
         }
+      }
+      Command::Edit { path: Some(path), encoding, force } => {
+        let path =
+          if path.is_absolute() { path } else { store.workspace.root.as_path().join(path) };
+        self.open(&path, None, &mut store.workspace);
 
-        self.tabs[self.active].popup = None;
+        if let Some(encoding) = encoding
+          && let Some(editor) = self.active_editor()
+        {
+          editor.editor.reopen_with_encoding(&encoding, force);
+        }
       }
-      Event::Exit => return true,
+      Command::Split { axis } => self.split(axis, store),
+
+      command => warn!("cannot run {command:?} without an editor"),
     }
 
     false
   }
 
+  /// Shows how many files `:wa` wrote, once every save has finished.
+  fn report_write_all(&mut self, workspace: &Workspace) {
+    let paths = std::mem::take(&mut self.write_all);
+    let failed = workspace
+      .editors()
+      .filter(|e| e.file().is_some_and(|f| paths.iter().any(|p| p == f)))
+      .filter(|e| e.status().is_some_and(|s| !s.success))
+      .count();
+
+    let Some(editor) = self.active_editor() else { return };
+    match (failed, paths.len()) {
+      (0, 1) => editor.editor.show_message("1 file written"),
+      (0, n) => editor.editor.show_message(format!("{n} files written")),
+      (failed, n) => {
+        editor.editor.show_error(format!("{failed} of {n} files could not be written"))
+      }
+    }
+  }
+
+  fn close_active_view(&mut self) {
+    let tab = &mut self.tabs[self.active];
+    let active_view = tab.content.active();
+    tab.content.close(active_view, &mut self.views.views);
+    // FIXME: Deduplicate layouts closing themselves (ie, terminal exit).
+    self.views.views.remove(&active_view);
+  }
+
+  fn split(&mut self, axis: be_config::Axis, store: &mut RenderStore) {
+    let new_view = self.split_active_view(store);
+    self.tabs[self.active].content.split(
+      match axis {
+        be_config::Axis::Horizontal => Axis::Horizontal,
+        be_config::Axis::Vertical => Axis::Vertical,
+      },
+      &mut self.views.views,
+      store,
+      new_view,
+    );
+  }
+
   fn split_active_view(&mut self, store: &mut RenderStore) -> ViewId {
     match self.active_view().content {
       ViewContent::Editor(ref e) => {
@@ -185,7 +185,28 @@ impl Render<'_> {
     let mut current_background = None;
     let mut background_idx = 0;
 
-    for line in text.layout.lines() {
+    for (i, line) in text.layout.lines().enumerate() {
+      // A background that continues onto a wrapped line is drawn to the end of
+      // the previous line, and starts again at the start of this one.
+      if i > 0
+        && let Some((background_x, brush)) = &mut current_background
+      {
+        if let Some(brush) = brush
+          && let Some(prev) = text.layout.get(i - 1)
+        {
+          let metrics = prev.metrics();
+          let rect = Rect::new(
+            *background_x,
+            metrics.min_coord as f64,
+            (metrics.offset + metrics.advance) as f64,
+            metrics.max_coord as f64,
+          )
+          .expand();
+          self.scene.fill(Fill::NonZero, transform, &*brush, None, &rect);
+        }
+        *background_x = 0.0;
+      }
+
       for item in line.items() {
         let parley::PositionedLayoutItem::GlyphRun(glyph_run) = item else { continue };
 
@@ -588,10 +609,12 @@ impl TextLayout {
     rect.scale_from_origin(1.0 / self.scale)
   }
 
-  pub fn index(&self, x: f64, mode: CursorMode) -> usize {
-    if let Some((cluster, side)) =
-      parley::Cluster::from_point(&self.layout, (x * self.scale) as f32, 0.0)
-    {
+  pub fn index(&self, pos: Point, mode: CursorMode) -> usize {
+    if let Some((cluster, side)) = parley::Cluster::from_point(
+      &self.layout,
+      (pos.x * self.scale) as f32,
+      (pos.y * self.scale) as f32,
+    ) {
       if cluster.is_rtl() {
         match side {
           ClusterSide::Left => cluster.text_range().end,
@@ -1,7 +1,7 @@
 use be_input::{Key, KeyStroke};
 use kurbo::{Point, Vec2};
 use winit::{
-  event::WindowEvent,
+  event::{StartCause, WindowEvent},
   event_loop::{self, ActiveEventLoop},
   keyboard::NamedKey,
   window::Window,
@@ -103,6 +103,14 @@ impl winit::application::ApplicationHandler<Event> for App {
     });
   }
 
+  fn new_events(&mut self, _: &ActiveEventLoop, cause: StartCause) {
+    if let StartCause::ResumeTimeReached { .. } = cause
+      && let Some(init) = &self.init
+    {
+      init.window.request_redraw();
+    }
+  }
+
   fn user_event(&mut self, event_loop: &ActiveEventLoop, event: Event) {
     if let Some(init) = &mut self.init {
       if init.app.state.on_event(event, &mut init.app.store) {
@@ -290,6 +298,8 @@ impl winit::application::ApplicationHandler<Event> for App {
           if init.app.state.animated() {
             init.window.request_redraw();
             event_loop.set_control_flow(event_loop::ControlFlow::Poll);
+          } else if let Some(at) = init.app.state.wake_at() {
+            event_loop.set_control_flow(event_loop::ControlFlow::WaitUntil(at));
           } else {
             event_loop.set_control_flow(event_loop::ControlFlow::Wait);
           }
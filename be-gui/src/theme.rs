@@ -108,6 +108,7 @@ impl Theme {
         ("error", Highlight::empty().with_underline(Underline::Color(oklch(0.8, 0.12, 30.0)))),
         ("warning", Highlight::empty().with_underline(Underline::Color(oklch(0.8, 0.12, 120.0)))),
         ("search_result", Highlight::empty().with_background(oklch(0.5, 0.03, 288.0))),
+        ("selection", Highlight::empty().with_background(oklch(0.4, 0.05, 250.0))),
       ]),
     }
   }
@@ -213,6 +214,9 @@ impl SyntaxTheme {
         HighlightKey::SearchResult => {
           highlight.merge_from(&self.entries["search_result"]);
         }
+        HighlightKey::Selection => {
+          highlight.merge_from(&self.entries["selection"]);
+        }
 
         HighlightKey::TreeSitter(key) => {
           let mut cur = *key;
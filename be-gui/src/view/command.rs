@@ -1,18 +1,37 @@
-use be_input::{Action, Direction, Edit, Move};
+use std::path::PathBuf;
+
+use be_editor::{CommandHistory, CommandState};
+use be_input::{Action, Edit};
+use be_shared::SharedHandle;
+use be_workspace::Workspace;
 use kurbo::{Point, Rect, RoundedRect, Stroke};
-use unicode_segmentation::UnicodeSegmentation;
 
 use crate::{Notify, Render};
 
 pub struct CommandView {
-  notify: Notify,
+  notify:  Notify,
+  history: SharedHandle<CommandHistory>,
+  root:    PathBuf,
 
-  command: String,
-  cursor:  usize, // in bytes
+  command: CommandState,
 }
 
 impl CommandView {
-  pub fn new(notify: Notify) -> Self { CommandView { command: String::new(), cursor: 0, notify } }
+  pub fn new(notify: Notify, workspace: &Workspace) -> Self {
+    CommandView {
+      notify,
+      history: workspace.history.clone(),
+      root: workspace.root.as_path().to_path_buf(),
+      command: CommandState::default(),
+    }
+  }
+
+  pub fn with_text(mut self, text: String) -> Self {
+    self.command.set_text(text);
+    self
+  }
+
+  pub fn text(&self) -> &str { &self.command.text }
 
   pub fn draw(&mut self, render: &mut Render) {
     let bounds = Rect::from_origin_size(Point::ZERO, render.size());
@@ -35,55 +54,21 @@ impl CommandView {
     render.fill(&bounds, render.theme().background);
     render.stroke(&bounds, render.theme().background_raised_outline, Stroke::new(stroke));
 
-    let layout = render.layout_text(crate::Font::Editor, &self.command, render.theme().text);
+    let layout = render.layout_text(crate::Font::Editor, &self.command.text, render.theme().text);
     let text_pos = Point::new(20.0, render.size().height - 40.0);
     render.draw_text(&layout, text_pos);
 
-    let cursor = layout.cursor(self.cursor as usize, crate::CursorMode::Line);
+    let cursor = layout.cursor(self.command.cursor, crate::CursorMode::Line);
     render.fill(&(cursor + text_pos.to_vec2()), render.theme().text);
   }
 
   pub fn perform_action(&mut self, action: Action) {
     match action {
-      Action::Move { m: Move::Single(Direction::Left), .. } => self.move_cursor(-1),
-      Action::Move { m: Move::Single(Direction::Right), .. } => self.move_cursor(1),
-
       Action::Edit { e: Edit::Insert('\n'), .. } => {
-        self.notify.editor_event(be_editor::EditorEvent::RunCommand(self.command.clone()));
-      }
-      Action::Edit { e: Edit::Insert(c), .. } => {
-        self.command.insert(self.cursor, c);
-        self.move_cursor(1);
-      }
-      Action::Edit { e: Edit::Delete(Move::Single(Direction::Right)), .. } => {
-        self.delete_graphemes(1);
+        self.history.push(&self.command.text);
+        self.notify.editor_event(be_editor::EditorEvent::RunCommand(self.command.text.clone()));
       }
-      Action::Edit { e: Edit::Backspace, .. } => {
-        if self.cursor > 0 {
-          self.move_cursor(-1);
-          self.delete_graphemes(1);
-        }
-      }
-
-      _ => {}
+      action => self.command.perform_action(action, &self.history, &self.root),
     }
   }
-
-  fn move_cursor(&mut self, dist: i32) {
-    if dist >= 0 {
-      for c in self.command[self.cursor..].graphemes(true).take(dist as usize) {
-        self.cursor += c.len();
-      }
-    } else {
-      for c in self.command[..self.cursor].graphemes(true).rev().take(-dist as usize) {
-        self.cursor -= c.len();
-      }
-    }
-  }
-
-  fn delete_graphemes(&mut self, len: usize) {
-    let count =
-      self.command[self.cursor..].graphemes(true).take(len).map(|g| g.len()).sum::<usize>();
-    self.command.replace_range(self.cursor..self.cursor + count, "");
-  }
 }
@@ -1,17 +1,17 @@
 use std::{collections::HashMap, io, path::PathBuf};
 
 use be_animation::Animation;
-use be_doc::Cursor;
+use be_doc::{Anchor, Cursor, FileEncoding};
 use be_editor::{CommandMode, EditorEvent, EditorState, IndentLevel};
 use be_fs::WatcherHandle;
 use be_input::{Action, Mode};
-use be_shared::SharedHandle;
+use be_shared::{SharedHandle, WeakHandle};
 use be_workspace::Workspace;
 use kurbo::{Arc, Circle, Line, Point, Rect, RoundedRect, Size, Stroke, Triangle, Vec2};
 
 use crate::{
   CursorMode, Font, Layout, MouseButton, MouseEvent, Render, RenderStore, TextLayout,
-  theme::Underline,
+  theme::Underline, view::HexView,
 };
 
 pub struct EditorView {
@@ -28,18 +28,39 @@ pub struct EditorView {
   line_numbers:      Vec<TextLayout>,
   line_number_width: f64,
 
-  /// The minimum visible line. This will always be a real line in the file. It
-  /// may only be partially visible.
+  /// The line on the first visible row. This will always be a real line in the
+  /// file. It may only be partially visible.
   min_line: be_doc::Line,
-  /// The maximum visible line. This might not be a real line in the file, if
-  /// the file is too short, or the user has scrolled down. It may only be
-  /// partially visible.
+  /// The line on the last visible row. This might not be a real line in the
+  /// file, if the file is too short, or the user has scrolled down. It may
+  /// only be partially visible.
   max_line: be_doc::Line,
+  /// The line shown on each visible row, starting with `min_line`, and which
+  /// of the line's screen rows it is. A closed fold takes up a single row, so
+  /// these skip the lines it hides, and a soft wrapped line takes up several.
+  rows:     Vec<(be_doc::Line, usize)>,
 
-  definition_history: Vec<(Cursor, PathBuf)>,
+  /// How many columns fit next to the gutter.
+  view_columns: usize,
+  /// The width that the cached layouts were wrapped at.
+  wrap_width:   Option<usize>,
+
+  definition_history: Vec<Definition>,
   watcher:            WatcherHandle,
 
   progress_animation: Animation,
+
+  /// Shown instead of the text for binary files.
+  hex: HexView,
+}
+
+/// A position to go back to after going to a definition.
+struct Definition {
+  path:   PathBuf,
+  cursor: Cursor,
+  /// The position anchored in the editor it was recorded in, which follows
+  /// edits for as long as that editor has the file open.
+  anchor: Option<(WeakHandle<EditorState>, Anchor)>,
 }
 
 enum Focus {
@@ -62,13 +83,19 @@ impl EditorView {
 
       min_line:          be_doc::Line(0),
       max_line:          be_doc::Line(0),
+      rows:              vec![],
       line_numbers:      vec![],
       line_number_width: 0.0,
 
+      view_columns: 0,
+      wrap_width:   None,
+
       definition_history: vec![],
       watcher:            store.workspace.fs.add_handle(),
 
       progress_animation: Animation::linear(2.0),
+
+      hex: HexView::default(),
     };
 
     view.progress_animation.set_repeat(true);
@@ -124,6 +151,9 @@ impl EditorView {
 
     for line in self.editor.damages() {
       self.cached_layouts.remove(&line.as_usize());
+      // The summary of a closed fold counts the lines it hides.
+      let shown = self.editor.shown_line(*line);
+      self.cached_layouts.remove(&shown.as_usize());
     }
 
     let line_height = layout.store.text.font_metrics().line_height;
@@ -132,7 +162,11 @@ impl EditorView {
       self,
       kurbo::Axis::Horizontal,
       crate::Distance::Pixels(-line_height),
-      |state, layout| state.layout_editor(layout),
+      |state, layout| {
+        if !state.editor.is_binary() {
+          state.layout_editor(layout)
+        }
+      },
       |_, _| {},
     );
   }
@@ -146,7 +180,14 @@ impl EditorView {
       self,
       kurbo::Axis::Horizontal,
       crate::Distance::Pixels(-line_height),
-      |state, render| state.draw_editor(render),
+      |state, render| {
+        if state.editor.is_binary() {
+          let focused = state.focused();
+          state.hex.draw(&state.editor, focused, render);
+        } else {
+          state.draw_editor(render);
+        }
+      },
       |state, render| state.draw_status(render),
     );
 
@@ -170,7 +211,15 @@ impl EditorView {
   pub fn perform_action(&mut self, action: Action) {
     match action {
       Action::Move { count: _, m: be_input::Move::BackDefinition } => {
-        if let Some((cursor, path)) = self.definition_history.pop() {
+        if let Some(Definition { path, mut cursor, anchor }) = self.definition_history.pop() {
+          if let Some((editor, anchor)) = anchor
+            && let Some(mut editor) = editor.upgrade()
+            && editor.file() == Some(path.as_path())
+          {
+            cursor = editor.anchored_cursor(anchor).unwrap_or(cursor);
+            editor.remove_anchor(anchor);
+          }
+
           if let Some(file) = self.editor.file()
             && *file == path
           {
@@ -188,7 +237,9 @@ impl EditorView {
   }
 
   pub fn record_definition(&mut self, path: PathBuf, cursor: Cursor) {
-    self.definition_history.push((cursor, path));
+    let anchor = (self.editor.file() == Some(path.as_path()))
+      .then(|| (SharedHandle::downgrade(&self.editor), self.editor.anchor_cursor(cursor)));
+    self.definition_history.push(Definition { path, cursor, anchor });
   }
 
   pub fn on_mouse(
@@ -198,6 +249,22 @@ impl EditorView {
     store: &RenderStore,
   ) -> crate::CursorKind {
     let line_height = store.text.font_metrics().line_height;
+    // Splits of the same file share an editor, so it's given this view's width
+    // before using its rows.
+    self.editor.set_view_columns(self.view_columns);
+
+    if self.editor.is_binary() {
+      let pos = match ev {
+        MouseEvent::Move { pos }
+        | MouseEvent::Button { pos, .. }
+        | MouseEvent::Scroll { pos, .. } => *pos,
+        _ => return crate::CursorKind::Default,
+      };
+      if pos.y < size.height - line_height {
+        let size = Size::new(size.width, size.height - line_height);
+        return self.hex.on_mouse(&mut self.editor, ev, size, store);
+      }
+    }
 
     match ev {
       MouseEvent::Move { pos } => {
@@ -207,7 +274,7 @@ impl EditorView {
           if pos.x >= self.gutter_width() {
             return crate::CursorKind::Beam;
           } else {
-            let Some(line) = self.line_for_mouse(store, pos.y) else {
+            let Some((line, _)) = self.row_for_mouse(store, pos.y) else {
               return crate::CursorKind::Default;
             };
 
@@ -225,9 +292,10 @@ impl EditorView {
         if pos.y >= size.height - line_height {
           // status bar
         } else {
-          let line = self
-            .line_for_mouse(store, pos.y)
-            .unwrap_or_else(|| be_doc::Line(self.doc().len_lines().saturating_sub(1)));
+          let (line, row_in_line) = self.row_for_mouse(store, pos.y).unwrap_or_else(|| {
+            let line = be_doc::Line(self.doc().len_lines().saturating_sub(1));
+            (line, self.editor.screen_rows(line) - 1)
+          });
           let Some(layout) = self.cached_layouts.get(&line.0) else {
             return crate::CursorKind::Default;
           };
@@ -237,7 +305,11 @@ impl EditorView {
           };
 
           if pos.x >= self.gutter_width() {
-            let column_byte = layout.index(pos.x - self.gutter_width(), cursor_mode);
+            // Each screen row of a wrapped line is a line of its layout.
+            let pos =
+              Point::new(pos.x - self.gutter_width(), (row_in_line as f64 + 0.5) * line_height);
+            // The summary of a closed fold is past the end of the line.
+            let column_byte = layout.index(pos, cursor_mode).min(self.doc().line(line).byte_len());
             let column = self.doc().line(line).byte_slice(..column_byte).graphemes().count();
 
             self.editor.move_to(Cursor {
@@ -271,10 +343,11 @@ impl EditorView {
             let max_fully_visible_row =
               ((self.scroll.y + size.height) / line_height).floor() as usize - 1 - scroll_offset;
 
-            if self.cursor().line.as_usize() < min_fully_visible_row {
-              self.editor.move_to_line(be_doc::Line(min_fully_visible_row));
-            } else if self.cursor().line.as_usize() > max_fully_visible_row {
-              self.editor.move_to_line(be_doc::Line(max_fully_visible_row));
+            let cursor_row = self.editor.screen_row(self.cursor());
+            if cursor_row < min_fully_visible_row {
+              self.editor.move_to_row(min_fully_visible_row);
+            } else if cursor_row > max_fully_visible_row {
+              self.editor.move_to_row(max_fully_visible_row);
             }
           }
         }
@@ -286,90 +359,102 @@ impl EditorView {
     crate::CursorKind::Default
   }
 
-  fn line_for_mouse(&self, store: &RenderStore, y: f64) -> Option<be_doc::Line> {
+  /// Returns the line under the mouse, and which of its screen rows the mouse
+  /// is on.
+  fn row_for_mouse(&self, store: &RenderStore, y: f64) -> Option<(be_doc::Line, usize)> {
     let line_height = store.text.font_metrics().line_height;
 
     let line_region_y = self.scroll.y + y;
-    let line = (line_region_y / line_height).floor() as usize;
-    if line < self.doc().len_lines() { Some(be_doc::Line(line)) } else { None }
+    let (line, row_in_line) =
+      self.editor.row_position((line_region_y / line_height).floor() as usize);
+    if line.as_usize() < self.doc().len_lines() { Some((line, row_in_line)) } else { None }
   }
 
   fn layout_editor(&mut self, layout: &mut Layout) {
     let line_height = layout.store.text.font_metrics().line_height;
+    let character_width = layout.store.text.font_metrics().character_width;
     let scroll_offset = self.editor.config.borrow().settings.editor.scroll_offset as usize;
 
+    // Layout the length line number by default. If `character_width` is wrong, then
+    // we'll still take the `max()` below.
+    self.line_number_width =
+      character_width * ((self.doc().len_lines() as f64).log10().floor() + 1.0);
+
+    self.view_columns =
+      ((layout.size().width - self.gutter_width()) / character_width).floor().max(1.0) as usize;
+    self.editor.set_view_columns(self.view_columns);
+    let wrap_width = self.editor.wrap_width();
+    if wrap_width != self.wrap_width {
+      self.cached_layouts.clear();
+      self.wrap_width = wrap_width;
+    }
+    if wrap_width.is_some() {
+      self.scroll.x = 0.0;
+    }
+
+    // Scrolling works in rows rather than lines, as closed folds take up a
+    // single row, and soft wrapped lines take up several.
+    let total_rows = self.editor.total_rows().max(1);
+
     if self.focused() {
+      let cursor_row = self.editor.screen_row(self.cursor());
       let min_fully_visible_row = (self.scroll.y / line_height).ceil() as usize + scroll_offset;
       let max_fully_visible_row =
         ((self.scroll.y + layout.size().height) / line_height).floor() as usize - 1 - scroll_offset;
 
-      if self.cursor().line.as_usize() < min_fully_visible_row {
-        let target_line = self
-          .cursor()
-          .line
-          .as_usize()
-          .saturating_sub(scroll_offset)
-          .clamp(0, self.doc().len_lines());
-
-        self.scroll.y = target_line as f64 * line_height;
-      } else if self.cursor().line.as_usize() > max_fully_visible_row {
-        let target_line = self
-          .cursor()
-          .line
-          .as_usize()
-          .saturating_add(scroll_offset + 1)
-          .clamp(0, self.doc().len_lines());
-
-        self.scroll.y = (target_line as f64 * line_height) - layout.size().height;
-      }
-    }
+      if cursor_row < min_fully_visible_row {
+        let target_row = cursor_row.saturating_sub(scroll_offset).clamp(0, total_rows);
 
-    self.min_line = be_doc::Line(
-      ((self.scroll.y / line_height).floor() as usize)
-        .clamp(0, self.doc().len_lines().saturating_sub(1)),
-    );
-    self.max_line = be_doc::Line(
-      (((self.scroll.y + layout.size().height) / line_height).ceil() as usize)
-        .clamp(0, self.doc().len_lines().saturating_sub(1)),
-    );
+        self.scroll.y = target_row as f64 * line_height;
+      } else if cursor_row > max_fully_visible_row {
+        let target_row = cursor_row.saturating_add(scroll_offset + 1).clamp(0, total_rows);
 
-    let start = self.doc().byte_of_line(self.min_line);
-    let end = if self.max_line.as_usize() >= self.doc().len_lines() {
-      self.doc().len()
-    } else {
-      self.doc().byte_of_line(self.max_line + 1)
-    };
+        self.scroll.y = (target_row as f64 * line_height) - layout.size().height;
+      }
+    }
 
-    let mut index = start;
-    let mut i = self.min_line.as_usize();
+    let min_row = ((self.scroll.y / line_height).floor() as usize).clamp(0, total_rows - 1);
+    let max_row = (((self.scroll.y + layout.size().height) / line_height).ceil() as usize)
+      .clamp(0, total_rows - 1);
+    let (mut line, mut row_in_line) = self.editor.row_position(min_row);
+    self.min_line = line;
+    self.max_line = self.editor.line_of_row(max_row);
 
+    self.rows.clear();
     self.line_numbers.clear();
-    // Layout the length line number by default. If `character_width` is wrong, then
-    // we'll still take the `max()` below.
-    self.line_number_width = layout.store.text.font_metrics().character_width
-      * ((self.doc().len_lines() as f64).log10().floor() + 1.0);
 
-    while index < end {
+    for _ in min_row..=max_row {
+      let i = line.as_usize();
+      if i >= self.doc().len_lines() {
+        break;
+      }
+      let index = self.doc().byte_of_line(line);
       if self.layout_line(layout, i, index).is_none() {
         break;
       };
 
-      let color = if self.focused() && self.cursor().line.as_usize() == i {
+      let color = if self.focused() && self.cursor().line == line && row_in_line == 0 {
         layout.theme().text
       } else {
         layout.theme().text_dim
       };
 
-      let line_number_text = (i + 1).to_string();
+      // The rows that a line wraps onto show an arrow instead of a line number.
+      let line_number_text = if row_in_line == 0 { (i + 1).to_string() } else { "↪".to_string() };
       let number_layout = layout.layout_text(Font::Editor, &line_number_text, color);
       self.line_number_width = self.line_number_width.max(number_layout.size().width);
       self.line_numbers.push(number_layout);
+      self.rows.push((line, row_in_line));
 
-      i += 1;
-      index += self.doc().range(index..).raw_lines().next().unwrap().byte_len();
+      row_in_line += 1;
+      if row_in_line >= self.editor.screen_rows(line) {
+        line = self.editor.closed_fold_at(line).unwrap_or(line) + 1;
+        row_in_line = 0;
+      }
     }
 
     if self.focused()
+      && wrap_width.is_none()
       && let Some(mode) = self.cursor_mode()
       && let Some(line_layout) = self.cached_layouts.get(&self.cursor().line.as_usize())
     {
@@ -384,6 +469,8 @@ impl EditorView {
   }
 
   fn draw_editor(&mut self, render: &mut Render) {
+    self.editor.set_view_columns(self.view_columns);
+
     render.fill(
       &Rect::new(0.0, 0.0, render.size().width, render.size().height),
       render.theme().background,
@@ -394,12 +481,7 @@ impl EditorView {
     let start_y = -(self.scroll.y % line_height);
 
     let mut y = start_y;
-    for i in self.min_line.as_usize()..=self.max_line.as_usize() {
-      if self.cached_layouts.get(&i).is_none() {
-        break;
-      }
-
-      let layout = &self.line_numbers[i - self.min_line.as_usize()];
+    for layout in &self.line_numbers {
       render.draw_text(
         layout,
         Point::new(LINE_NUMBER_MARGIN_LEFT + self.line_number_width - layout.size().width, y),
@@ -418,21 +500,23 @@ impl EditorView {
           self.editor.config.borrow().settings.editor.indent_width as usize,
           Vec2::new(-self.scroll.x, start_y),
         );
-        for i in self.min_line.as_usize()..=self.max_line.as_usize() {
-          if self.cached_layouts.get(&i).is_none() {
-            break;
+        for (row, &(line, row_in_line)) in self.rows.iter().enumerate() {
+          let i = line.as_usize();
+          let Some(layout) = self.cached_layouts.get(&i) else { break };
+          indent_guides
+            .visit(self.editor.guess_indent(line, be_input::VerticalDirection::Up), render);
+
+          // A wrapped line is drawn once, from its first row, which may be above
+          // the view.
+          if row == 0 || row_in_line == 0 {
+            let origin = Point::new(-self.scroll.x, y - row_in_line as f64 * line_height);
+            render.draw_text(&layout, origin);
+
+            if self.editor.closed_fold_at(line).is_none() {
+              self.draw_trailing_spaces(i, layout, origin, render);
+            }
           }
 
-          let layout = self.cached_layouts.get(&i).unwrap();
-          indent_guides.visit(
-            self.editor.guess_indent(be_doc::Line(i), be_input::VerticalDirection::Up),
-            render,
-          );
-
-          render.draw_text(&layout, Point::new(-self.scroll.x, y));
-
-          self.draw_trailing_spaces(i, layout.size().width - self.scroll.x, y, render);
-
           y += line_height;
         }
 
@@ -440,6 +524,19 @@ impl EditorView {
       },
     );
 
+    // The other cursors are outlined, like the cursor of an unfocused editor.
+    if let Some(mode) = self.cursor_mode() {
+      for cursor in self.editor.cursors() {
+        let Some(layout) = self.cached_layouts.get(&cursor.line.as_usize()) else { continue };
+        let Some(row) = self.rows.iter().position(|(l, _)| *l == cursor.line) else { continue };
+
+        let top = start_y + (row as f64 - self.rows[row].1 as f64) * line_height;
+        let rect = layout.cursor(self.doc().cursor_column_offset(cursor), mode)
+          + Vec2::new(self.gutter_width() - self.scroll.x, top);
+        render.stroke(&rect.inset(-0.5 * render.scale()), render.theme().text, Stroke::new(1.0));
+      }
+    }
+
     if let Some(mode) = self.cursor_mode() {
       let line = self.cursor().line.as_usize();
       let Some(layout) = &self.cached_layouts.get(&line) else {
@@ -447,12 +544,11 @@ impl EditorView {
         return;
       };
 
-      if line >= self.min_line.as_usize() && line <= self.max_line.as_usize() {
+      if let Some(row) = self.rows.iter().position(|(l, _)| l.as_usize() == line) {
+        // The cursor is positioned from the first row of its line.
+        let top = start_y + (row as f64 - self.rows[row].1 as f64) * line_height;
         let cursor = layout.cursor(self.doc().cursor_column_offset(self.cursor()), mode)
-          + Vec2::new(
-            self.gutter_width() - self.scroll.x,
-            start_y + (line - self.min_line.as_usize()) as f64 * line_height,
-          );
+          + Vec2::new(self.gutter_width() - self.scroll.x, top);
         if self.focused() {
           render.fill(&cursor.ceil(), render.theme().text);
           self.draw_completions(cursor, render);
@@ -511,6 +607,22 @@ impl EditorView {
       render.draw_text(&layout, (render.size().width - 50.0, render.size().height - line_height));
     }
 
+    // Indicators are drawn right to left, before the file type.
+    let mut right = render.size().width - 60.0;
+    let indicators = [
+      Some(self.editor.line_ending().to_string()),
+      self.editor.encoding().filter(|e| *e != FileEncoding::UTF_8).map(|e| e.to_string()),
+      self.editor.is_large().then(|| {
+        if self.editor.is_loading() { "large file (loading)" } else { "large file" }.to_string()
+      }),
+    ];
+    for indicator in indicators.into_iter().flatten() {
+      let layout = render.layout_text(Font::Ui, &indicator, render.theme().text);
+      right -= layout.size().width;
+      render.draw_text(&layout, (right, render.size().height - line_height));
+      right -= 10.0;
+    }
+
     let rect =
       RoundedRect::new(render.size().width - 18.0, 2.0, render.size().width - 2.0, 18.0, 3.0);
     let (mode_color, mode_char) = if !self.focused() {
@@ -578,17 +690,24 @@ impl EditorView {
     }
   }
 
-  fn draw_trailing_spaces(&self, i: usize, end_of_line: f64, y: f64, render: &mut Render) {
-    let mut shape = Circle::new((0.0, y + render.store.text.font_metrics().line_height / 2.0), 1.5);
-    for (i, c) in self.doc().line(be_doc::Line(i)).chars().rev().enumerate() {
-      if c == ' ' {
-        shape.center.x = end_of_line
-          - (i as f64 * render.store.text.font_metrics().character_width)
-          - render.store.text.font_metrics().character_width / 2.0;
-        render.fill(&shape, render.theme().background_raised);
-      } else {
+  fn draw_trailing_spaces(
+    &self,
+    i: usize,
+    layout: &TextLayout,
+    origin: Point,
+    render: &mut Render,
+  ) {
+    let line = self.doc().line(be_doc::Line(i));
+    let mut index = line.byte_len();
+    for c in line.chars().rev() {
+      if c != ' ' {
         break;
       }
+
+      // The dots are placed with the layout, so they follow the line as it wraps.
+      index -= 1;
+      let space = layout.cursor(index, CursorMode::Block) + origin.to_vec2();
+      render.fill(&Circle::new(space.center(), 1.5), render.theme().background_raised);
     }
   }
 
@@ -611,16 +730,13 @@ impl EditorView {
           }
 
           if change.after().is_empty() {
-            let y =
-              start_y + (change.after().start - self.min_line.as_usize()) as f64 * line_height;
+            let y = start_y + self.rows_from_min_line(change.after().start) * line_height;
 
             let shape = Triangle::new((0.0, y - 4.0), (0.0, y + 4.0), (4.0, y));
             render.fill(&shape, render.theme().diff_remove);
           } else {
-            let min_y = start_y
-              + (change.after().start as f64 - self.min_line.as_usize() as f64) * line_height;
-            let max_y =
-              start_y + (change.after().end as f64 - self.min_line.as_usize() as f64) * line_height;
+            let min_y = start_y + self.rows_from_min_line(change.after().start) * line_height;
+            let max_y = start_y + self.rows_from_min_line(change.after().end) * line_height;
 
             let shape = Rect::new(0.0, min_y, 4.0, max_y);
             render.fill(
@@ -637,6 +753,21 @@ impl EditorView {
     }
   }
 
+  /// The number of rows between the first visible row and the first row of
+  /// `line`. Lines hidden in a closed fold are on the row of the fold.
+  fn rows_from_min_line(&self, line: usize) -> f64 {
+    let line = self.editor.shown_line(be_doc::Line(line));
+    match self.rows.iter().position(|&(l, _)| l >= line) {
+      Some(row) if row > 0 || self.rows[row].0 == line => row as f64 - self.rows[row].1 as f64,
+      // Above the view.
+      Some(_) => {
+        let (first, row_in_line) = self.rows[0];
+        self.editor.row_of_line(line) as f64 - (self.editor.row_of_line(first) + row_in_line) as f64
+      }
+      None => self.rows.len() as f64,
+    }
+  }
+
   fn change_gutter_for_line(&self, line: be_doc::Line) -> Option<()> {
     self.editor.changes.as_ref()?.hunk_for_line(line).map(|_| ())
   }
@@ -728,7 +859,16 @@ impl EditorView {
     let line = self.editor.doc().range(index..).raw_lines().next()?;
     let max_index = index + line.byte_len();
 
-    let line_string = line.to_string();
+    let mut line_string = line.to_string();
+    // A closed fold shows its first line, followed by how many lines it hides.
+    let summary = self.editor.closed_fold_at(be_doc::Line(i)).map(|end| {
+      let text_len = line_string.trim_end_matches(['\r', '\n']).len();
+      // Leading spaces keep the line ending's old offsets on char boundaries.
+      let summary = format!("  ⋯ {} lines", end.as_usize() - i);
+      line_string.insert_str(text_len, &summary);
+      text_len..text_len + summary.len()
+    });
+
     let theme = &layout.store.theme;
     let mut line_number_layout = layout.store.text.layout_builder(
       Font::Editor,
@@ -736,6 +876,28 @@ impl EditorView {
       layout.theme().text,
       layout.scale(),
     );
+    // The summary of a closed fold is never wrapped.
+    let wrap_width = self.wrap_width.filter(|_| summary.is_none());
+    if wrap_width.is_some() {
+      // The editor decides where the line wraps, so that moving by screen rows
+      // matches what's shown. The grapheme before each break is allowed to
+      // wrap, and the rest of the line isn't.
+      line_number_layout
+        .apply_default(parley::StyleProperty::TextWrapMode(parley::TextWrapMode::NoWrap));
+      line_number_layout
+        .apply_default(parley::StyleProperty::OverflowWrap(parley::OverflowWrap::Anywhere));
+
+      let mut wraps = self.editor.line_wraps(be_doc::Line(i)).into_iter().peekable();
+      let mut start = 0;
+      for (column, g) in line.graphemes().enumerate() {
+        let end = start + g.len();
+        if wraps.next_if_eq(&be_doc::Column(column + 1)).is_some() {
+          line_number_layout
+            .apply(start..end, parley::StyleProperty::TextWrapMode(parley::TextWrapMode::Wrap));
+        }
+        start = end;
+      }
+    }
 
     let highlights = self.editor.highlights(index..max_index);
     let mut prev = index;
@@ -782,8 +944,16 @@ impl EditorView {
       prev = pos;
     }
 
+    if let Some(summary) = summary {
+      line_number_layout.color_range(summary, theme.text_dim);
+    }
+
     let (text_layout, backgrounds) = line_number_layout.build(&line_string);
-    let text_layout = layout.build_layout(text_layout, backgrounds);
+    let text_layout = if wrap_width.is_some() {
+      layout.build_wrapped_layout(text_layout, backgrounds)
+    } else {
+      layout.build_layout(text_layout, backgrounds)
+    };
 
     Some(entry.insert(text_layout))
   }
@@ -0,0 +1,157 @@
+use be_editor::{EditorState, HEX_ROW_LEN};
+use be_input::Mode;
+use kurbo::{Point, Rect, Size, Stroke};
+
+use crate::{Font, MouseButton, MouseEvent, Render, RenderStore};
+
+/// The width of the offset column, in characters, including the gap after it.
+const OFFSET_WIDTH: usize = 10;
+/// Each byte takes 2 digits and a space, and there is an extra space between
+/// the two halves of a row.
+const HEX_WIDTH: usize = HEX_ROW_LEN * 3 + 1;
+
+/// Shows a binary file as rows of offsets, hex bytes, and their ASCII
+/// characters.
+#[derive(Default)]
+pub struct HexView {
+  /// The first visible row.
+  scroll: usize,
+}
+
+impl HexView {
+  /// The character column of the high nibble of the `i`th byte in a row.
+  fn hex_column(i: usize) -> usize {
+    OFFSET_WIDTH + i * 3 + if i >= HEX_ROW_LEN / 2 { 1 } else { 0 }
+  }
+  fn ascii_column(i: usize) -> usize { OFFSET_WIDTH + HEX_WIDTH + 1 + i }
+
+  pub fn draw(&mut self, editor: &EditorState, focused: bool, render: &mut Render) {
+    render.fill(
+      &Rect::new(0.0, 0.0, render.size().width, render.size().height),
+      render.theme().background,
+    );
+
+    let metrics = render.store.text.font_metrics();
+    let (line_height, char_width) = (metrics.line_height, metrics.character_width);
+    let visible_rows = ((render.size().height / line_height).floor() as usize).max(1);
+
+    // Keep the cursor on screen.
+    let (cursor, low_nibble) = editor.hex_cursor();
+    let cursor_row = cursor / HEX_ROW_LEN;
+    if cursor_row < self.scroll {
+      self.scroll = cursor_row;
+    } else if cursor_row >= self.scroll + visible_rows {
+      self.scroll = cursor_row + 1 - visible_rows;
+    }
+
+    let len = editor.hex_len();
+    let rows = len.div_ceil(HEX_ROW_LEN);
+    for row in self.scroll..rows.min(self.scroll + visible_rows + 1) {
+      let y = (row - self.scroll) as f64 * line_height;
+      let start = row * HEX_ROW_LEN;
+      let end = (start + HEX_ROW_LEN).min(len);
+
+      let offset =
+        render.layout_text(Font::Editor, &format!("{start:08x}"), render.theme().text_dim);
+      render.draw_text(&offset, Point::new(0.0, y));
+
+      let mut hex = String::with_capacity(HEX_WIDTH);
+      let mut ascii = String::with_capacity(HEX_ROW_LEN);
+      for i in start..end {
+        if i - start == HEX_ROW_LEN / 2 {
+          hex.push(' ');
+        }
+        // Text in the document that isn't hex fails the save until it's fixed.
+        match editor.hex_byte(i) {
+          Some(byte) => {
+            hex.push_str(&format!("{byte:02x} "));
+            ascii.push(if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' });
+          }
+          None => {
+            hex.push_str("?? ");
+            ascii.push('?');
+          }
+        }
+      }
+
+      let hex = render.layout_text(Font::Editor, &hex, render.theme().text);
+      render.draw_text(&hex, Point::new(OFFSET_WIDTH as f64 * char_width, y));
+      let ascii = render.layout_text(Font::Editor, &ascii, render.theme().text_dim);
+      render.draw_text(&ascii, Point::new(Self::ascii_column(0) as f64 * char_width, y));
+    }
+
+    if cursor_row < self.scroll || cursor_row > self.scroll + visible_rows {
+      return;
+    }
+
+    let y = (cursor_row - self.scroll) as f64 * line_height;
+    let i = cursor % HEX_ROW_LEN;
+    let x = (Self::hex_column(i) + if low_nibble { 1 } else { 0 }) as f64 * char_width;
+    let cursor_rect = match editor.mode() {
+      Mode::Insert if focused => Rect::new(x, y, x + 2.0, y + line_height),
+      Mode::Replace if focused => {
+        Rect::new(x, y + line_height - 2.0, x + char_width, y + line_height)
+      }
+      _ => Rect::new(x, y, x + char_width, y + line_height),
+    };
+    if focused {
+      render.fill(&cursor_rect, render.theme().text);
+    } else {
+      render.stroke(&cursor_rect, render.theme().text, Stroke::new(1.0));
+    }
+
+    // Outline the character of the current byte.
+    let x = Self::ascii_column(i) as f64 * char_width;
+    render.stroke(
+      &Rect::new(x, y, x + char_width, y + line_height),
+      render.theme().text_dim,
+      Stroke::new(1.0),
+    );
+  }
+
+  pub fn on_mouse(
+    &mut self,
+    editor: &mut EditorState,
+    ev: &MouseEvent,
+    size: Size,
+    store: &RenderStore,
+  ) -> crate::CursorKind {
+    let metrics = store.text.font_metrics();
+    let (line_height, char_width) = (metrics.line_height, metrics.character_width);
+
+    match ev {
+      MouseEvent::Button { pos, pressed: true, button: MouseButton::Left } => {
+        let row = self.scroll + (pos.y / line_height).floor() as usize;
+        let column = (pos.x / char_width).floor() as usize;
+
+        let i = if column >= Self::ascii_column(0) {
+          column - Self::ascii_column(0)
+        } else {
+          (0..HEX_ROW_LEN).rev().find(|&i| column >= Self::hex_column(i)).unwrap_or(0)
+        };
+
+        editor.hex_move_to(
+          (row * HEX_ROW_LEN + i.min(HEX_ROW_LEN - 1)).min(editor.hex_len().saturating_sub(1)),
+        );
+      }
+
+      MouseEvent::Scroll { delta, .. } => {
+        let rows = (-delta.y / line_height).round() as isize;
+        let max = editor.hex_len().div_ceil(HEX_ROW_LEN).saturating_sub(1);
+        self.scroll = self.scroll.saturating_add_signed(rows).min(max);
+
+        // Keep the cursor within the visible rows.
+        let visible_rows = ((size.height / line_height).floor() as usize).max(1);
+        let (cursor, _) = editor.hex_cursor();
+        let row = (cursor / HEX_ROW_LEN).clamp(self.scroll, self.scroll + visible_rows - 1);
+        editor.hex_move_to(
+          (row * HEX_ROW_LEN + cursor % HEX_ROW_LEN).min(editor.hex_len().saturating_sub(1)),
+        );
+      }
+
+      _ => return crate::CursorKind::Default,
+    }
+
+    crate::CursorKind::Beam
+  }
+}
@@ -6,14 +6,18 @@ use crate::{Layout, Render, RenderStore};
 mod command;
 mod editor;
 mod file_tree;
+mod hex;
 mod search;
 mod terminal;
+mod which_key;
 
 pub use command::CommandView;
 pub use editor::EditorView;
 pub use file_tree::FileTree;
+pub use hex::HexView;
 pub use search::Search;
 pub use terminal::TerminalView;
+pub use which_key::WhichKey;
 
 pub struct View {
   pub content: ViewContent,
@@ -0,0 +1,97 @@
+//! The popup listing the keys that can come next, once a key sequence like
+//! `<C-w>` or `g` has been pending for a moment.
+
+use std::time::{Duration, Instant};
+
+use be_input::KeyStroke;
+use kurbo::{Point, Rect};
+
+use crate::{
+  Font, Render,
+  widget::{Border, Borders, Corners},
+};
+
+/// How long a sequence is pending before the popup shows up.
+const DELAY: Duration = Duration::from_millis(500);
+
+const PADDING: f64 = 10.0;
+const COLUMN_GAP: f64 = 30.0;
+const MAX_ROWS: usize = 12;
+/// The space between the popup and the sides and top of the tab.
+const MARGIN: f64 = 20.0;
+/// The space below the popup, which keeps the status bar visible.
+const BOTTOM_MARGIN: f64 = 50.0;
+
+pub struct WhichKey {
+  since:     Instant,
+  pub hints: Vec<(KeyStroke, String)>,
+}
+
+impl WhichKey {
+  pub fn new(hints: Vec<(KeyStroke, String)>) -> Self { WhichKey { since: Instant::now(), hints } }
+
+  pub fn visible(&self, now: Instant) -> bool {
+    !self.hints.is_empty() && now.duration_since(self.since) >= DELAY
+  }
+
+  /// When the window needs to be redrawn to show the popup. Nothing else
+  /// redraws it once the delay is up.
+  pub fn wake_at(&self, now: Instant) -> Option<Instant> {
+    let at = self.since + DELAY;
+    (!self.hints.is_empty() && at > now).then_some(at)
+  }
+
+  /// Draws the popup along the bottom of the tab, with the hints in columns.
+  pub fn draw(&self, render: &mut Render) {
+    if !self.visible(render.now()) {
+      return;
+    }
+
+    let hints = self
+      .hints
+      .iter()
+      .map(|(key, description)| {
+        let key = render.layout_text(Font::Editor, &key_name(*key), render.theme().text);
+        let description = render.layout_text(Font::Ui, description, render.theme().text_dim);
+        (key, description)
+      })
+      .collect::<Vec<_>>();
+
+    let key_width = hints.iter().map(|(k, _)| k.size().width).fold(0.0, f64::max);
+    let description_width = hints.iter().map(|(_, d)| d.size().width).fold(0.0, f64::max);
+    let row_height =
+      hints.iter().map(|(k, d)| k.size().height.max(d.size().height)).fold(0.0, f64::max);
+    let column_width = key_width + PADDING + description_width + COLUMN_GAP;
+
+    // The popup is as small as the hints allow, and shrinks to fit the tab.
+    let max_width = (render.size().width - MARGIN * 2.0).max(0.0);
+    let max_height = (render.size().height - MARGIN - BOTTOM_MARGIN).max(0.0);
+    let max_columns = (((max_width - PADDING * 2.0 + COLUMN_GAP) / column_width) as usize).max(1);
+    let max_rows = (((max_height - PADDING * 2.0) / row_height) as usize).clamp(1, MAX_ROWS);
+    let rows = hints.len().div_ceil(max_columns).min(max_rows);
+    let columns = hints.len().div_ceil(rows).min(max_columns);
+
+    let width = (columns as f64 * column_width - COLUMN_GAP + PADDING * 2.0).min(max_width);
+    let height = (rows as f64 * row_height + PADDING * 2.0).min(max_height);
+    let bottom = MARGIN + max_height;
+    let bounds = Rect::new(MARGIN, bottom - height, MARGIN + width, bottom);
+
+    render.clipped(bounds, |render| {
+      let border = Border { borders: Borders::all(1.0), radius: Corners::all(10.0) };
+      border.draw_inside(render, render.theme().background_raised);
+      border.draw_border(render);
+
+      // Hints that don't fit are left out.
+      for (i, (key, description)) in hints.iter().enumerate().take(rows * columns) {
+        let x = PADDING + (i / rows) as f64 * column_width;
+        let y = PADDING + (i % rows) as f64 * row_height;
+        render.draw_text(key, Point::new(x + key_width - key.size().width, y));
+        render.draw_text(description, Point::new(x + key_width + PADDING, y));
+      }
+    });
+  }
+}
+
+fn key_name(key: KeyStroke) -> String {
+  if key == KeyStroke::char(' ') { "<Space>".into() } else { key.to_string() }
+}
@@ -12,6 +12,7 @@ mod button;
 mod padding;
 mod stack;
 
+pub use border::Border;
 pub use button::Button;
 pub use stack::{Align, Justify, Stack};
 
@@ -1,24 +1,72 @@
 use std::num::NonZero;
 
-use crate::{KeyStroke, Mode, VisualMode, key::Key};
-use be_config::Axis;
+use crate::{Key, KeyStroke, Mode, VisualMode};
+use be_config::{Axis, KeySettings};
 
+#[derive(Clone)]
 pub enum Action {
-  SetMode { mode: Mode, delta: i32 },
+  SetMode {
+    mode:  Mode,
+    delta: i32,
+  },
   OpenSearch,
-  Append { after: bool },
-  Move { count: Option<NonZero<u32>>, m: Move },
-  Edit { count: Option<NonZero<u32>>, e: Edit },
+  Append {
+    after: bool,
+  },
+  Move {
+    count: Option<NonZero<u32>>,
+    m:     Move,
+  },
+  Edit {
+    count: Option<NonZero<u32>>,
+    e:     Edit,
+  },
   Tab,
-  Control { char: char },
-  Navigate { nav: Navigation },
+  Control {
+    char: char,
+  },
+  Navigate {
+    nav: Navigation,
+  },
   Autocomplete,
-  MoveCompletion { next: bool },
+  MoveCompletion {
+    next: bool,
+  },
   Copy,
   Paste,
+  SelectRegister {
+    name: char,
+  },
+  Repeat {
+    count: Option<NonZero<u32>>,
+  },
+  RecordMacro {
+    name: char,
+  },
+  /// Replays a macro. Without a name, replays the last played macro.
+  PlayMacro {
+    name:  Option<char>,
+    count: Option<NonZero<u32>>,
+  },
+  /// Sets a mark at the cursor, as with `ma`.
+  SetMark {
+    name: char,
+  },
+  /// Opens or closes folds, as with `zc` or `zR`.
+  Fold {
+    op: FoldOp,
+  },
+  /// Adds or removes cursors, as with `<C-n>` or `<C-j>`.
+  Cursor {
+    op: CursorOp,
+  },
+  /// Runs an ex command from a key binding in the config.
+  Command {
+    command: String,
+  },
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Navigation {
   OpenSearch,
   Direction(Direction),
@@ -26,8 +74,12 @@ pub enum Navigation {
   Tab(u8),
 }
 
+#[derive(Copy, Clone)]
 pub enum Move {
   Single(Direction),
+  /// Moves up or down a screen row of a soft wrapped line, as with `gj` and
+  /// `gk`.
+  ScreenLine(VerticalDirection),
 
   NextWord,
   EndWord,
@@ -48,6 +100,75 @@ pub enum Move {
 
   GotoDefinition,
   BackDefinition,
+  /// Moves through the jumplist, as with `<C-o>` and `<C-i>`.
+  Jump(ChangeDirection),
+
+  /// Moves to the start of the next fold or the end of the previous one, as
+  /// with `zj` and `zk`.
+  Fold(ChangeDirection),
+
+  /// Swaps the cursor with the other end of the visual selection.
+  SwapAnchor,
+
+  /// Jumps to a mark. `'a` jumps to the line of the mark, and is linewise
+  /// after an operator, while `` `a `` jumps to the exact position.
+  Mark {
+    name:     char,
+    linewise: bool,
+  },
+
+  /// Selects a text object, like `iw` or `a(`. This is only meaningful after an
+  /// operator or in visual mode.
+  TextObject {
+    inner:  bool,
+    object: TextObject,
+  },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TextObject {
+  Word,
+  BigWord,
+  Sentence,
+  Paragraph,
+  /// Text between a pair of quotes on the current line.
+  Quote(char),
+  /// Text between a pair of brackets, stored as the opening bracket.
+  Bracket(char),
+
+  // These use the tree-sitter `textobjects.scm` query.
+  Function,
+  Class,
+  Parameter,
+  Comment,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FoldOp {
+  /// Closes the innermost open fold around the cursor, as with `zc`.
+  Close,
+  /// Opens the outermost closed fold around the cursor, as with `zo`.
+  Open,
+  /// Opens the fold around the cursor if it's closed, and closes it otherwise,
+  /// as with `za`.
+  Toggle,
+  /// Opens every fold, as with `zR`.
+  OpenAll,
+  /// Closes every fold, as with `zM`.
+  CloseAll,
+}
+
+#[derive(Copy, Clone)]
+pub enum CursorOp {
+  /// Adds a cursor at the next match of the word under the cursor, as with
+  /// `<C-n>`.
+  NextMatch,
+  /// Adds a cursor on the line above or below, as with `<C-k>` and `<C-j>`.
+  Add(VerticalDirection),
+  /// Splits the visual selection into a cursor on each line, as with `<M-s>`.
+  SplitSelection,
+  /// Removes every cursor but the main one, as with `<Esc>`.
+  Clear,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -56,6 +177,7 @@ pub enum ChangeDirection {
   Prev,
 }
 
+#[derive(Copy, Clone)]
 pub enum Edit {
   Insert(char),
   Replace(char),
@@ -63,11 +185,41 @@ pub enum Edit {
   Cut(Move),
   DeleteLine,
   CutLine,
+  Yank(Move),
+  YankLine,
+  /// Reindents the lines covered by a move, as with `=j`.
+  Reindent(Move),
+  /// Reindents the current line, as with `==`.
+  ReindentLine,
+  /// Comments or uncomments the lines covered by a move, as with `gcj`.
+  Comment(Move),
+  /// Comments or uncomments the current line, as with `gcc`.
+  CommentLine,
   DeleteRestOfLine,
-  Paste { after: bool },
+  Paste {
+    after: bool,
+  },
   Backspace,
   Undo,
   Redo,
+  /// Moves to the previous state of the undo tree in time, as with `g-`.
+  Earlier,
+  /// Moves to the next state of the undo tree in time, as with `g+`.
+  Later,
+  SwitchCase,
+  Visual(Operator),
+}
+
+/// An operator applied to the current visual selection.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Operator {
+  Delete,
+  Change,
+  Yank,
+  Indent,
+  Dedent,
+  Reindent,
+  Comment,
   SwitchCase,
 }
 
@@ -91,7 +243,35 @@ pub enum VerticalDirection {
 }
 
 impl Action {
-  pub fn from_input(mode: Mode, input: &[KeyStroke]) -> Result<Action, ActionError> {
+  /// Parses the keys typed so far, returning the action and how many of the
+  /// keys it used. The bindings from the config in `keys` are checked first,
+  /// and then the built-in bindings.
+  ///
+  /// Keys are held back while they could start a binding from the config. If
+  /// that binding isn't finished, the built-in bindings might only use some of
+  /// the keys, and the rest should be handled again, one at a time.
+  pub fn from_input(
+    keys: &KeySettings,
+    mode: Mode,
+    input: &[KeyStroke],
+  ) -> Result<(Action, usize), ActionError> {
+    if let Some(res) = crate::keymap::lookup(keys, mode, input) {
+      return res.map(|action| (action, input.len()));
+    }
+
+    // No built-in binding starts with another one, so the shortest keys that
+    // make an action are what would have run if there was nothing to wait for.
+    for len in 1..input.len() {
+      match Action::from_builtin_input(mode, &input[..len]) {
+        Ok(action) => return Ok((action, len)),
+        Err(ActionError::Incomplete) => {}
+        Err(ActionError::Unrecognized) => return Err(ActionError::Unrecognized),
+      }
+    }
+    Action::from_builtin_input(mode, input).map(|action| (action, input.len()))
+  }
+
+  fn from_builtin_input(mode: Mode, input: &[KeyStroke]) -> Result<Action, ActionError> {
     let mut count = 0;
 
     macro_rules! e {
@@ -121,20 +301,13 @@ impl Action {
           _ => Err(ActionError::Unrecognized),
         },
 
-        (Mode::Normal, Key::Char(' ')) if !key.control => {
-          match iter.next().ok_or(ActionError::Incomplete)?.key {
-            Key::Char('s') => Ok(Action::Navigate { nav: Navigation::OpenSearch }),
-            _ => Err(ActionError::Unrecognized),
-          }
-        }
-
         (Mode::Insert, Key::Char('C')) if key.control => Ok(Action::Copy),
         (Mode::Insert, Key::Char('V')) if key.control => Ok(Action::Paste),
 
         (Mode::Insert, Key::Char(' ')) if key.control => Ok(Action::Autocomplete),
         (Mode::Insert, Key::Char('p')) if key.control => Ok(Action::MoveCompletion { next: false }),
         (Mode::Insert, Key::Char(c)) if key.control => Ok(Action::Control { char: c }),
-        (Mode::Insert, Key::Tab) => Ok(Action::Tab),
+        (Mode::Insert | Mode::Command, Key::Tab) => Ok(Action::Tab),
 
         (Mode::Insert | Mode::Command, Key::Char(c)) => e!(Insert(c)),
         (Mode::Insert | Mode::Command, Key::Backspace) => e!(Backspace),
@@ -148,16 +321,45 @@ impl Action {
 
         (Mode::Visual(_), Key::Escape) => Ok(Action::SetMode { mode: Mode::Normal, delta: 0 }),
 
-        (Mode::Normal, Key::Char('0')) if count != 0 => {
+        (Mode::Normal | Mode::Visual(_), Key::Char('0')) if count != 0 => {
           count *= 10;
           continue;
         }
-        (Mode::Normal, Key::Char(c @ '1'..='9')) => {
+        (Mode::Normal | Mode::Visual(_), Key::Char(c @ '1'..='9')) => {
           count *= 10;
           count += u32::from(c) - u32::from('0');
           continue;
         }
 
+        // === registers ===
+        (Mode::Normal | Mode::Visual(_), Key::Char('"')) => {
+          match iter.next().ok_or(ActionError::Incomplete)?.key {
+            Key::Char(c @ ('"' | '_' | '+' | 'a'..='z' | 'A'..='Z' | '0'..='9')) => {
+              Ok(Action::SelectRegister { name: c })
+            }
+            _ => Err(ActionError::Unrecognized),
+          }
+        }
+
+        (Mode::Normal, Key::Char('q')) => match iter.next().ok_or(ActionError::Incomplete)?.key {
+          Key::Char(c @ ('"' | 'a'..='z' | 'A'..='Z' | '0'..='9')) => {
+            Ok(Action::RecordMacro { name: c })
+          }
+          _ => Err(ActionError::Unrecognized),
+        },
+        (Mode::Normal, Key::Char('@')) => match iter.next().ok_or(ActionError::Incomplete)?.key {
+          Key::Char('@') => Ok(Action::PlayMacro { name: None, count: NonZero::new(count) }),
+          Key::Char(c @ ('"' | 'a'..='z' | 'A'..='Z' | '0'..='9')) => {
+            Ok(Action::PlayMacro { name: Some(c), count: NonZero::new(count) })
+          }
+          _ => Err(ActionError::Unrecognized),
+        },
+
+        (Mode::Normal, Key::Char('m')) => match iter.next().ok_or(ActionError::Incomplete)?.key {
+          Key::Char(c @ 'a'..='z') => Ok(Action::SetMark { name: c }),
+          _ => Err(ActionError::Unrecognized),
+        },
+
         // === edits ===
         (Mode::Normal, Key::Char('r')) if !key.control => {
           match iter.next().ok_or(ActionError::Incomplete)?.key {
@@ -166,20 +368,84 @@ impl Action {
           }
         }
         (Mode::Normal, Key::Char('x')) => e!(Delete(Move::Single(Direction::Right))),
-        (Mode::Normal, Key::Char('d')) => match iter.next().ok_or(ActionError::Incomplete)? {
-          KeyStroke { key: Key::Char('d'), .. } => e!(DeleteLine),
-          k => parse_move(k, iter).map(|m| Action::Edit { e: Edit::Delete(m), count: None }),
+        (Mode::Normal, Key::Char('d')) => match parse_operator('d', count, iter)? {
+          (count, None) => Ok(Action::Edit { count, e: Edit::DeleteLine }),
+          (count, Some(m)) => Ok(Action::Edit { count, e: Edit::Delete(m) }),
+        },
+        (Mode::Normal, Key::Char('c')) => match parse_operator('c', count, iter)? {
+          (count, None) => Ok(Action::Edit { count, e: Edit::CutLine }),
+          (count, Some(m)) => Ok(Action::Edit { count, e: Edit::Cut(m) }),
+        },
+        (Mode::Normal, Key::Char('y')) => match parse_operator('y', count, iter)? {
+          (count, None) => Ok(Action::Edit { count, e: Edit::YankLine }),
+          (count, Some(m)) => Ok(Action::Edit { count, e: Edit::Yank(m) }),
         },
-        (Mode::Normal, Key::Char('c')) => match iter.next().ok_or(ActionError::Incomplete)? {
-          KeyStroke { key: Key::Char('c'), .. } => e!(CutLine),
-          k => parse_move(k, iter).map(|m| Action::Edit { e: Edit::Cut(m), count: None }),
+        (Mode::Normal, Key::Char('=')) => match parse_operator('=', count, iter)? {
+          (count, None) => Ok(Action::Edit { count, e: Edit::ReindentLine }),
+          (count, Some(m)) => Ok(Action::Edit { count, e: Edit::Reindent(m) }),
         },
+        (Mode::Normal, Key::Char('g'))
+          if matches!(iter.clone().next().map(|k| k.key), Some(Key::Char('c'))) =>
+        {
+          iter.next();
+          match parse_operator('c', count, iter)? {
+            (count, None) => Ok(Action::Edit { count, e: Edit::CommentLine }),
+            (count, Some(m)) => Ok(Action::Edit { count, e: Edit::Comment(m) }),
+          }
+        }
+        (Mode::Normal, Key::Char('Y')) => e!(YankLine),
         (Mode::Normal, Key::Char('D')) => e!(DeleteRestOfLine),
         (Mode::Normal, Key::Char('p')) => e!(Paste { after: true }),
         (Mode::Normal, Key::Char('P')) => e!(Paste { after: false }),
         (Mode::Normal, Key::Char('u')) => e!(Undo),
         (Mode::Normal, Key::Char('r')) if key.control => e!(Redo),
+        (Mode::Normal, Key::Char('g'))
+          if matches!(iter.clone().next().map(|k| k.key), Some(Key::Char('-' | '+'))) =>
+        {
+          match iter.next().unwrap().key {
+            Key::Char('-') => e!(Earlier),
+            _ => e!(Later),
+          }
+        }
         (Mode::Normal, Key::Char('~')) => e!(SwitchCase),
+        (Mode::Normal, Key::Char('.')) => Ok(Action::Repeat { count: NonZero::new(count) }),
+
+        // === folds ===
+        (Mode::Normal, Key::Char('z'))
+          if matches!(
+            iter.clone().next().map(|k| k.key),
+            Some(Key::Char('c' | 'o' | 'a' | 'R' | 'M'))
+          ) =>
+        {
+          let op = match iter.next().unwrap().key {
+            Key::Char('c') => FoldOp::Close,
+            Key::Char('o') => FoldOp::Open,
+            Key::Char('a') => FoldOp::Toggle,
+            Key::Char('R') => FoldOp::OpenAll,
+            _ => FoldOp::CloseAll,
+          };
+          Ok(Action::Fold { op })
+        }
+
+        // === cursors ===
+        (Mode::Normal, Key::Char('n')) if key.control => {
+          Ok(Action::Cursor { op: CursorOp::NextMatch })
+        }
+        (Mode::Normal, Key::Char('j')) if key.control => {
+          Ok(Action::Cursor { op: CursorOp::Add(VerticalDirection::Down) })
+        }
+        (Mode::Normal, Key::Char('k')) if key.control => {
+          Ok(Action::Cursor { op: CursorOp::Add(VerticalDirection::Up) })
+        }
+        (Mode::Normal, Key::Escape) => Ok(Action::Cursor { op: CursorOp::Clear }),
+        (Mode::Visual(_), Key::Char('s')) if key.alt => {
+          Ok(Action::Cursor { op: CursorOp::SplitSelection })
+        }
+
+        // === jumps ===
+        (Mode::Normal, Key::Char('o')) if key.control => m!(Jump(ChangeDirection::Prev)),
+        (Mode::Normal, Key::Char('i')) if key.control => m!(Jump(ChangeDirection::Next)),
+        (Mode::Normal, Key::Tab) => m!(Jump(ChangeDirection::Next)),
 
         // === modes ===
         (Mode::Normal, Key::Char('i')) => Ok(Action::SetMode { mode: Mode::Insert, delta: 0 }),
@@ -199,6 +465,41 @@ impl Action {
         (Mode::Normal, Key::Char(':')) => Ok(Action::SetMode { mode: Mode::Command, delta: 0 }),
         (Mode::Normal, Key::Char('/')) => Ok(Action::OpenSearch),
 
+        // === visual ===
+        (Mode::Visual(_), Key::Char('C')) if key.control => Ok(Action::Copy),
+        (Mode::Visual(_), Key::Char(':')) => Ok(Action::SetMode { mode: Mode::Command, delta: 0 }),
+        (Mode::Visual(_), Key::Char('d' | 'x')) => e!(Visual(Operator::Delete)),
+        (Mode::Visual(_), Key::Char('c' | 's')) => e!(Visual(Operator::Change)),
+        (Mode::Visual(_), Key::Char('y')) => e!(Visual(Operator::Yank)),
+        (Mode::Visual(_), Key::Char('>')) => e!(Visual(Operator::Indent)),
+        (Mode::Visual(_), Key::Char('<')) => e!(Visual(Operator::Dedent)),
+        (Mode::Visual(_), Key::Char('=')) => e!(Visual(Operator::Reindent)),
+        (Mode::Visual(_), Key::Char('g'))
+          if matches!(iter.clone().next().map(|k| k.key), Some(Key::Char('c'))) =>
+        {
+          e!(Visual(Operator::Comment))
+        }
+        (Mode::Visual(_), Key::Char('~')) => e!(Visual(Operator::SwitchCase)),
+        (Mode::Visual(_), Key::Char('o')) => m!(SwapAnchor),
+        (Mode::Visual(VisualMode::Block), Key::Char('v')) if key.control => {
+          Ok(Action::SetMode { mode: Mode::Normal, delta: 0 })
+        }
+        (Mode::Visual(_), Key::Char('v')) if key.control => {
+          Ok(Action::SetMode { mode: Mode::Visual(VisualMode::Block), delta: 0 })
+        }
+        (Mode::Visual(VisualMode::Character), Key::Char('v')) => {
+          Ok(Action::SetMode { mode: Mode::Normal, delta: 0 })
+        }
+        (Mode::Visual(_), Key::Char('v')) => {
+          Ok(Action::SetMode { mode: Mode::Visual(VisualMode::Character), delta: 0 })
+        }
+        (Mode::Visual(VisualMode::Line), Key::Char('V')) => {
+          Ok(Action::SetMode { mode: Mode::Normal, delta: 0 })
+        }
+        (Mode::Visual(_), Key::Char('V')) => {
+          Ok(Action::SetMode { mode: Mode::Visual(VisualMode::Line), delta: 0 })
+        }
+
         (Mode::Normal | Mode::Visual(_), _) => {
           parse_move(key, iter).map(|m| Action::Move { count: NonZero::new(count), m })
         }
@@ -211,6 +512,39 @@ impl Action {
   }
 }
 
+/// Parses the motion after an operator, like the `3w` in `d3w`. The motion is
+/// `None` when the operator is doubled, like `dd`. Counts before and after the
+/// operator are multiplied together, so `2d3w` deletes six words.
+fn parse_operator(
+  op: char,
+  count: u32,
+  mut iter: impl Iterator<Item = KeyStroke>,
+) -> Result<(Option<NonZero<u32>>, Option<Move>), ActionError> {
+  let mut motion_count = 0_u32;
+
+  loop {
+    let key = iter.next().ok_or(ActionError::Incomplete)?;
+    match key.key {
+      Key::Char('0') if motion_count != 0 => motion_count = motion_count.saturating_mul(10),
+      Key::Char(c @ '1'..='9') => {
+        motion_count =
+          motion_count.saturating_mul(10).saturating_add(u32::from(c) - u32::from('0'));
+      }
+      _ => {
+        let count = match (count, motion_count) {
+          (0, 0) => None,
+          (count, motion_count) => NonZero::new(count.max(1).saturating_mul(motion_count.max(1))),
+        };
+
+        if key.key == Key::Char(op) {
+          return Ok((count, None));
+        }
+        return Ok((count, Some(parse_move(key, iter)?)));
+      }
+    }
+  }
+}
+
 fn parse_move(
   key: KeyStroke,
   mut iter: impl Iterator<Item = KeyStroke>,
@@ -234,6 +568,8 @@ fn parse_move(
     Key::Char('g') => match iter.next().ok_or(ActionError::Incomplete)?.key {
       Key::Char('g') => FileStart,
       Key::Char('d') => GotoDefinition,
+      Key::Char('j') | Key::ArrowDown => ScreenLine(VerticalDirection::Down),
+      Key::Char('k') | Key::ArrowUp => ScreenLine(VerticalDirection::Up),
       _ => return Err(ActionError::Unrecognized),
     },
     Key::Char('G') => FileEnd,
@@ -255,8 +591,42 @@ fn parse_move(
       Key::Char('g') => Diagnostic(ChangeDirection::Next),
       _ => return Err(ActionError::Unrecognized),
     },
+    Key::Char('z') => match iter.next().ok_or(ActionError::Incomplete)?.key {
+      Key::Char('j') => Fold(ChangeDirection::Next),
+      Key::Char('k') => Fold(ChangeDirection::Prev),
+      _ => return Err(ActionError::Unrecognized),
+    },
     Key::Char('t') if key.control => BackDefinition,
+    Key::Char(c @ ('\'' | '`')) => match iter.next().ok_or(ActionError::Incomplete)?.key {
+      // `''` and ``` `` ``` are the same mark, which is set before each jump.
+      Key::Char(name @ ('a'..='z' | '.' | '^')) => Mark { name, linewise: c == '\'' },
+      Key::Char('\'' | '`') => Mark { name: '\'', linewise: c == '\'' },
+      _ => return Err(ActionError::Unrecognized),
+    },
+    Key::Char(c @ ('i' | 'a')) => TextObject {
+      inner:  c == 'i',
+      object: parse_text_object(iter.next().ok_or(ActionError::Incomplete)?)?,
+    },
 
     _ => return Err(ActionError::Unrecognized),
   })
 }
+
+fn parse_text_object(key: KeyStroke) -> Result<TextObject, ActionError> {
+  Ok(match key.key {
+    Key::Char('w') => TextObject::Word,
+    Key::Char('W') => TextObject::BigWord,
+    Key::Char('s') => TextObject::Sentence,
+    Key::Char('p') => TextObject::Paragraph,
+    Key::Char(c @ ('"' | '\'' | '`')) => TextObject::Quote(c),
+    Key::Char('(' | ')' | 'b') => TextObject::Bracket('('),
+    Key::Char('{' | '}' | 'B') => TextObject::Bracket('{'),
+    Key::Char('[' | ']') => TextObject::Bracket('['),
+    Key::Char('<' | '>') => TextObject::Bracket('<'),
+    Key::Char('f') => TextObject::Function,
+    Key::Char('c') => TextObject::Class,
+    Key::Char('a') => TextObject::Parameter,
+    Key::Char('/') => TextObject::Comment,
+    _ => return Err(ActionError::Unrecognized),
+  })
+}
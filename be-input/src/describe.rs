@@ -0,0 +1,168 @@
+//! Short descriptions of actions, like "delete word", for listing the keys
+//! that are bound to them.
+
+use be_config::Axis;
+
+use crate::{
+  Action, ChangeDirection, CursorOp, Direction, Edit, FoldOp, KeyStroke, Mode, Move, Navigation,
+  Operator, TextObject, VerticalDirection, VisualMode,
+};
+
+impl Action {
+  pub fn description(&self) -> String {
+    match self {
+      Action::SetMode { mode, delta } => match mode {
+        Mode::Normal => "normal mode".into(),
+        Mode::Insert if *delta > 0 => "append".into(),
+        Mode::Insert => "insert".into(),
+        Mode::Visual(VisualMode::Character) => "visual mode".into(),
+        Mode::Visual(VisualMode::Line) => "visual line mode".into(),
+        Mode::Visual(VisualMode::Block) => "visual block mode".into(),
+        Mode::Replace => "replace mode".into(),
+        Mode::Command => "command line".into(),
+      },
+      Action::OpenSearch => "search".into(),
+      Action::Append { after: true } => "open line below".into(),
+      Action::Append { after: false } => "open line above".into(),
+      Action::Move { m, .. } => m.description(),
+      Action::Edit { e, .. } => e.description(),
+      Action::Tab => "tab".into(),
+      Action::Control { char } => format!("control {char}"),
+      Action::Navigate { nav } => match nav {
+        Navigation::OpenSearch => "find file".into(),
+        Navigation::Direction(dir) => format!("focus {}", direction(*dir)),
+        Navigation::Split(Axis::Vertical) => "split vertically".into(),
+        Navigation::Split(Axis::Horizontal) => "split horizontally".into(),
+        Navigation::Tab(i) => format!("tab {i}"),
+      },
+      Action::Autocomplete => "autocomplete".into(),
+      Action::MoveCompletion { next: true } => "next completion".into(),
+      Action::MoveCompletion { next: false } => "previous completion".into(),
+      Action::Copy => "copy".into(),
+      Action::Paste => "paste".into(),
+      Action::SelectRegister { name } => format!("register {name}"),
+      Action::Repeat { .. } => "repeat last edit".into(),
+      Action::RecordMacro { name } => format!("record macro {name}"),
+      Action::PlayMacro { name: Some(name), .. } => format!("play macro {name}"),
+      Action::PlayMacro { name: None, .. } => "play last macro".into(),
+      Action::SetMark { name } => format!("set mark {name}"),
+      Action::Fold { op } => match op {
+        FoldOp::Close => "close fold",
+        FoldOp::Open => "open fold",
+        FoldOp::Toggle => "toggle fold",
+        FoldOp::OpenAll => "open all folds",
+        FoldOp::CloseAll => "close all folds",
+      }
+      .into(),
+      Action::Cursor { op } => match op {
+        CursorOp::NextMatch => "add cursor at next match",
+        CursorOp::Add(VerticalDirection::Up) => "add cursor above",
+        CursorOp::Add(VerticalDirection::Down) => "add cursor below",
+        CursorOp::SplitSelection => "cursor on each line",
+        CursorOp::Clear => "clear cursors",
+      }
+      .into(),
+      Action::Command { command } => format!(":{command}"),
+    }
+  }
+}
+
+impl Move {
+  pub fn description(&self) -> String {
+    let next = |dir: &ChangeDirection| match dir {
+      ChangeDirection::Next => "next",
+      ChangeDirection::Prev => "previous",
+    };
+
+    match self {
+      Move::Single(dir) => direction(*dir).into(),
+      Move::ScreenLine(VerticalDirection::Up) => "screen line up".into(),
+      Move::ScreenLine(VerticalDirection::Down) => "screen line down".into(),
+      Move::NextWord => "next word".into(),
+      Move::EndWord => "end of word".into(),
+      Move::PrevWord => "previous word".into(),
+      Move::Char(c, ChangeDirection::Next) => format!("to {}", KeyStroke::char(*c)),
+      Move::Char(c, ChangeDirection::Prev) => format!("back to {}", KeyStroke::char(*c)),
+      Move::LineStart => "start of line".into(),
+      Move::LineStartOfText => "first non-blank".into(),
+      Move::LineEnd => "end of line".into(),
+      Move::MatchingBracket => "matching bracket".into(),
+      Move::FileStart => "start of file".into(),
+      Move::FileEnd => "end of file".into(),
+      Move::Result(dir) => format!("{} match", next(dir)),
+      Move::Change(dir) => format!("{} change", next(dir)),
+      Move::Diagnostic(dir) => format!("{} diagnostic", next(dir)),
+      Move::GotoDefinition => "go to definition".into(),
+      Move::BackDefinition => "back from definition".into(),
+      Move::Jump(ChangeDirection::Next) => "newer jump".into(),
+      Move::Jump(ChangeDirection::Prev) => "older jump".into(),
+      Move::Fold(dir) => format!("{} fold", next(dir)),
+      Move::SwapAnchor => "other end of selection".into(),
+      Move::Mark { name, linewise: true } => format!("line of mark {name}"),
+      Move::Mark { name, linewise: false } => format!("mark {name}"),
+      Move::TextObject { inner, object } => {
+        let object = match object {
+          TextObject::Word => "word".into(),
+          TextObject::BigWord => "WORD".into(),
+          TextObject::Sentence => "sentence".into(),
+          TextObject::Paragraph => "paragraph".into(),
+          TextObject::Quote(c) => format!("{c} quotes"),
+          TextObject::Bracket(c) => format!("{c} block"),
+          TextObject::Function => "function".into(),
+          TextObject::Class => "class".into(),
+          TextObject::Parameter => "parameter".into(),
+          TextObject::Comment => "comment".into(),
+        };
+        format!("{} {object}", if *inner { "inner" } else { "around" })
+      }
+    }
+  }
+}
+
+impl Edit {
+  pub fn description(&self) -> String {
+    match self {
+      Edit::Insert(c) => format!("insert {}", KeyStroke::char(*c)),
+      Edit::Replace(c) => format!("replace with {}", KeyStroke::char(*c)),
+      Edit::Delete(m) => format!("delete {}", m.description()),
+      Edit::Cut(m) => format!("change {}", m.description()),
+      Edit::DeleteLine => "delete line".into(),
+      Edit::CutLine => "change line".into(),
+      Edit::Yank(m) => format!("yank {}", m.description()),
+      Edit::YankLine => "yank line".into(),
+      Edit::Reindent(m) => format!("reindent {}", m.description()),
+      Edit::ReindentLine => "reindent line".into(),
+      Edit::Comment(m) => format!("comment {}", m.description()),
+      Edit::CommentLine => "comment line".into(),
+      Edit::DeleteRestOfLine => "delete to end of line".into(),
+      Edit::Paste { after: true } => "paste after".into(),
+      Edit::Paste { after: false } => "paste before".into(),
+      Edit::Backspace => "backspace".into(),
+      Edit::Undo => "undo".into(),
+      Edit::Redo => "redo".into(),
+      Edit::Earlier => "earlier state".into(),
+      Edit::Later => "later state".into(),
+      Edit::SwitchCase => "switch case".into(),
+      Edit::Visual(op) => match op {
+        Operator::Delete => "delete selection",
+        Operator::Change => "change selection",
+        Operator::Yank => "yank selection",
+        Operator::Indent => "indent selection",
+        Operator::Dedent => "dedent selection",
+        Operator::Reindent => "reindent selection",
+        Operator::Comment => "comment selection",
+        Operator::SwitchCase => "switch case of selection",
+      }
+      .into(),
+    }
+  }
+}
+
+fn direction(dir: Direction) -> &'static str {
+  match dir {
+    Direction::Up => "up",
+    Direction::Down => "down",
+    Direction::Left => "left",
+    Direction::Right => "right",
+  }
+}
@@ -0,0 +1,379 @@
+use std::{collections::HashMap, num::NonZero};
+
+use be_config::{Axis, Binding, KeyAction, KeySettings, Keymap};
+
+use crate::{
+  Action, ActionError, ChangeDirection, CursorOp, Direction, Edit, FoldOp, Key, KeyStroke, Mode,
+  Move, Navigation, Operator, VerticalDirection, VisualMode,
+};
+
+/// The bindings from the config for `mode`, if it has any.
+pub(crate) fn keymap(keys: &KeySettings, mode: Mode) -> Option<&Keymap> {
+  match mode {
+    Mode::Normal => Some(&keys.normal),
+    Mode::Insert => Some(&keys.insert),
+    Mode::Visual(_) => Some(&keys.visual),
+    Mode::Replace | Mode::Command => None,
+  }
+}
+
+/// Looks up `input` in the bindings from the config. Returns `None` if no
+/// binding starts with `input`, in which case the built-in bindings apply.
+pub(crate) fn lookup(
+  keys: &KeySettings,
+  mode: Mode,
+  input: &[KeyStroke],
+) -> Option<Result<Action, ActionError>> {
+  let keymap = keymap(keys, mode)?;
+
+  // A count can come before a binding, as with the built-in bindings.
+  let (count, input) = match mode {
+    Mode::Normal | Mode::Visual(_) => split_count(input),
+    _ => (None, input),
+  };
+
+  let mut incomplete = false;
+  for (sequence, binding) in keymap.sequences() {
+    if sequence == input {
+      return Some(match binding {
+        Binding::Action(action) => Ok(key_action(*action, mode, count)),
+        Binding::Command(command) => Ok(Action::Command { command: command.clone() }),
+        Binding::Unbound => Err(ActionError::Unrecognized),
+      });
+    }
+
+    if sequence.starts_with(input) && *binding != Binding::Unbound {
+      incomplete = true;
+    }
+  }
+
+  incomplete.then_some(Err(ActionError::Incomplete))
+}
+
+/// The keys that can come after a pending sequence. Finding them means trying
+/// every key after the sequence, so they are only found once for each one.
+#[derive(Default)]
+pub struct Continuations {
+  cache: HashMap<(Mode, Vec<KeyStroke>), Hints>,
+}
+
+/// Keys, with a description of what each one does.
+type Hints = Vec<(KeyStroke, String)>;
+
+impl Continuations {
+  /// Every key that can come after `input`, with a description of what it
+  /// does. Keys that start a longer sequence are described as `+more`. This is
+  /// empty when nothing but a count has been typed.
+  pub fn get(
+    &mut self,
+    keys: &KeySettings,
+    mode: Mode,
+    input: &[KeyStroke],
+  ) -> &[(KeyStroke, String)] {
+    // A count doesn't change what the keys after it do.
+    let sequence = match mode {
+      Mode::Normal | Mode::Visual(_) => split_count(input).1,
+      _ => input,
+    };
+
+    self
+      .cache
+      .entry((mode, sequence.to_vec()))
+      .or_insert_with(|| continuations(keys, mode, sequence))
+  }
+}
+
+fn continuations(keys: &KeySettings, mode: Mode, sequence: &[KeyStroke]) -> Hints {
+  if sequence.is_empty() {
+    return vec![];
+  }
+
+  // The built-in bindings are a big match, so they are found by trying every
+  // key after `sequence`. Bindings from the config can use any key, so the next
+  // key of each one is tried as well.
+  let mut candidates = vec![];
+  if let Some(keymap) = keymap(keys, mode) {
+    for (binding_keys, binding) in keymap.sequences() {
+      if *binding != Binding::Unbound
+        && binding_keys.len() > sequence.len()
+        && binding_keys.starts_with(sequence)
+      {
+        candidates.push(binding_keys[sequence.len()]);
+      }
+    }
+  }
+  // Outside of normal and visual mode, the built-in bindings only look at the
+  // first key, so they are only tried after built-in sequences like `<C-w>`.
+  if matches!(mode, Mode::Normal | Mode::Visual(_)) || lookup(keys, mode, sequence).is_none() {
+    candidates.extend(probe_keys());
+  }
+
+  let mut input = sequence.to_vec();
+  let mut found: Vec<(KeyStroke, String)> = vec![];
+  for key in candidates {
+    if found.iter().any(|(k, _)| *k == key) {
+      continue;
+    }
+
+    input.push(key);
+    let res = Action::from_input(keys, mode, &input);
+    input.pop();
+
+    let description = match res {
+      Ok((action, _)) => action.description(),
+      // Digits that don't finish a sequence are part of a count.
+      Err(ActionError::Incomplete) if is_digit(key) => continue,
+      Err(ActionError::Incomplete) => "+more".to_string(),
+      Err(ActionError::Unrecognized) => continue,
+    };
+
+    // Keys like `<C-h>` or `<Left>` are left out when they do the same thing
+    // as a plain character.
+    let plain = matches!(key.key, Key::Char(_)) && !key.control && !key.alt;
+    if !plain && found.iter().any(|(_, d)| *d == description) {
+      continue;
+    }
+
+    found.push((key, description));
+  }
+
+  found
+}
+
+/// The keys tried by `continuations`, in the order they are listed.
+fn probe_keys() -> impl Iterator<Item = KeyStroke> {
+  let modified =
+    |control, alt| ('a'..='z').map(move |c| KeyStroke { key: Key::Char(c), control, alt });
+  let special = [
+    Key::Char('\n'),
+    Key::Escape,
+    Key::Tab,
+    Key::Backspace,
+    Key::Delete,
+    Key::ArrowUp,
+    Key::ArrowDown,
+    Key::ArrowLeft,
+    Key::ArrowRight,
+  ];
+
+  ('a'..='z')
+    .chain('A'..='Z')
+    .chain('0'..='9')
+    .chain((' '..='~').filter(|c| !c.is_ascii_alphanumeric()))
+    .map(KeyStroke::char)
+    .chain(special.map(|key| KeyStroke { key, control: false, alt: false }))
+    .chain(modified(true, false))
+    .chain(modified(false, true))
+}
+
+fn is_digit(key: KeyStroke) -> bool {
+  matches!(key.key, Key::Char('0'..='9')) && !key.control && !key.alt
+}
+
+fn split_count(input: &[KeyStroke]) -> (Option<NonZero<u32>>, &[KeyStroke]) {
+  let mut count = 0_u32;
+  let mut len = 0;
+  for key in input {
+    match key.key {
+      Key::Char(c @ '0'..='9') if !key.control && !key.alt && (c != '0' || len > 0) => {
+        count = count.saturating_mul(10).saturating_add(u32::from(c) - u32::from('0'));
+        len += 1;
+      }
+      _ => break,
+    }
+  }
+
+  (NonZero::new(count), &input[len..])
+}
+
+fn key_action(action: KeyAction, mode: Mode, count: Option<NonZero<u32>>) -> Action {
+  let m = |m| Action::Move { count, m };
+  let e = |e| Action::Edit { count, e };
+  let set_mode = |mode, delta| Action::SetMode { mode, delta };
+
+  match action {
+    KeyAction::MoveLeft => m(Move::Single(Direction::Left)),
+    KeyAction::MoveRight => m(Move::Single(Direction::Right)),
+    KeyAction::MoveUp => m(Move::Single(Direction::Up)),
+    KeyAction::MoveDown => m(Move::Single(Direction::Down)),
+    KeyAction::NextWord => m(Move::NextWord),
+    KeyAction::EndWord => m(Move::EndWord),
+    KeyAction::PrevWord => m(Move::PrevWord),
+    KeyAction::LineStart => m(Move::LineStart),
+    KeyAction::LineStartOfText => m(Move::LineStartOfText),
+    KeyAction::LineEnd => m(Move::LineEnd),
+    KeyAction::MatchingBracket => m(Move::MatchingBracket),
+    KeyAction::FileStart => m(Move::FileStart),
+    KeyAction::FileEnd => m(Move::FileEnd),
+    KeyAction::NextResult => m(Move::Result(ChangeDirection::Next)),
+    KeyAction::PrevResult => m(Move::Result(ChangeDirection::Prev)),
+    KeyAction::NextChange => m(Move::Change(ChangeDirection::Next)),
+    KeyAction::PrevChange => m(Move::Change(ChangeDirection::Prev)),
+    KeyAction::NextDiagnostic => m(Move::Diagnostic(ChangeDirection::Next)),
+    KeyAction::PrevDiagnostic => m(Move::Diagnostic(ChangeDirection::Prev)),
+    KeyAction::GotoDefinition => m(Move::GotoDefinition),
+    KeyAction::BackDefinition => m(Move::BackDefinition),
+    KeyAction::JumpBack => m(Move::Jump(ChangeDirection::Prev)),
+    KeyAction::JumpForward => m(Move::Jump(ChangeDirection::Next)),
+
+    KeyAction::DeleteChar => e(Edit::Delete(Move::Single(Direction::Right))),
+    KeyAction::DeleteLine => e(Edit::DeleteLine),
+    KeyAction::CutLine => e(Edit::CutLine),
+    KeyAction::YankLine => e(Edit::YankLine),
+    KeyAction::DeleteRestOfLine => e(Edit::DeleteRestOfLine),
+    KeyAction::PasteAfter => e(Edit::Paste { after: true }),
+    KeyAction::PasteBefore => e(Edit::Paste { after: false }),
+    KeyAction::Backspace => e(Edit::Backspace),
+    KeyAction::Undo => e(Edit::Undo),
+    KeyAction::Redo => e(Edit::Redo),
+    KeyAction::Earlier => e(Edit::Earlier),
+    KeyAction::Later => e(Edit::Later),
+    KeyAction::SwitchCase => e(Edit::SwitchCase),
+    KeyAction::ReindentLine => e(Edit::ReindentLine),
+    KeyAction::CommentLine => e(Edit::CommentLine),
+    KeyAction::Repeat => Action::Repeat { count },
+
+    // Leaving insert mode moves the cursor back onto the last character typed.
+    KeyAction::Normal => set_mode(Mode::Normal, if mode == Mode::Insert { -1 } else { 0 }),
+    KeyAction::Insert => set_mode(Mode::Insert, 0),
+    KeyAction::Append => set_mode(Mode::Insert, 1),
+    KeyAction::OpenBelow => Action::Append { after: true },
+    KeyAction::OpenAbove => Action::Append { after: false },
+    KeyAction::Visual => set_mode(Mode::Visual(VisualMode::Character), 0),
+    KeyAction::VisualLine => set_mode(Mode::Visual(VisualMode::Line), 0),
+    KeyAction::VisualBlock => set_mode(Mode::Visual(VisualMode::Block), 0),
+    KeyAction::Command => set_mode(Mode::Command, 0),
+    KeyAction::Search => Action::OpenSearch,
+
+    KeyAction::DeleteSelection => e(Edit::Visual(Operator::Delete)),
+    KeyAction::ChangeSelection => e(Edit::Visual(Operator::Change)),
+    KeyAction::YankSelection => e(Edit::Visual(Operator::Yank)),
+    KeyAction::IndentSelection => e(Edit::Visual(Operator::Indent)),
+    KeyAction::DedentSelection => e(Edit::Visual(Operator::Dedent)),
+    KeyAction::ReindentSelection => e(Edit::Visual(Operator::Reindent)),
+    KeyAction::CommentSelection => e(Edit::Visual(Operator::Comment)),
+
+    KeyAction::CloseFold => Action::Fold { op: FoldOp::Close },
+    KeyAction::OpenFold => Action::Fold { op: FoldOp::Open },
+    KeyAction::ToggleFold => Action::Fold { op: FoldOp::Toggle },
+    KeyAction::OpenAllFolds => Action::Fold { op: FoldOp::OpenAll },
+    KeyAction::CloseAllFolds => Action::Fold { op: FoldOp::CloseAll },
+
+    KeyAction::AddCursorAtNextMatch => Action::Cursor { op: CursorOp::NextMatch },
+    KeyAction::AddCursorAbove => Action::Cursor { op: CursorOp::Add(VerticalDirection::Up) },
+    KeyAction::AddCursorBelow => Action::Cursor { op: CursorOp::Add(VerticalDirection::Down) },
+    KeyAction::SplitSelection => Action::Cursor { op: CursorOp::SplitSelection },
+    KeyAction::ClearCursors => Action::Cursor { op: CursorOp::Clear },
+
+    KeyAction::FindFile => Action::Navigate { nav: Navigation::OpenSearch },
+    KeyAction::FocusLeft => Action::Navigate { nav: Navigation::Direction(Direction::Left) },
+    KeyAction::FocusRight => Action::Navigate { nav: Navigation::Direction(Direction::Right) },
+    KeyAction::FocusUp => Action::Navigate { nav: Navigation::Direction(Direction::Up) },
+    KeyAction::FocusDown => Action::Navigate { nav: Navigation::Direction(Direction::Down) },
+    KeyAction::SplitVertical => Action::Navigate { nav: Navigation::Split(Axis::Vertical) },
+    KeyAction::SplitHorizontal => Action::Navigate { nav: Navigation::Split(Axis::Horizontal) },
+
+    KeyAction::Autocomplete => Action::Autocomplete,
+    KeyAction::Copy => Action::Copy,
+    KeyAction::Paste => Action::Paste,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use be_config::Config;
+  use expect_test::{Expect, expect};
+
+  use super::*;
+
+  fn check(mode: Mode, input: &str, expect: Expect) {
+    let keys = &Config::default_ref().settings.keys;
+    let mut continuations = Continuations::default();
+    let hints = continuations.get(keys, mode, &KeyStroke::parse_all(input));
+
+    let mut out = String::new();
+    for (key, description) in hints {
+      out += &format!("{:<6}{description}\n", key.to_string());
+    }
+    expect.assert_eq(&out);
+  }
+
+  #[test]
+  fn after_g() {
+    check(
+      Mode::Normal,
+      "g",
+      expect![@r#"
+        c     +more
+        d     go to definition
+        g     start of file
+        j     screen line down
+        k     screen line up
+        +     later state
+        -     earlier state
+      "#],
+    );
+  }
+
+  #[test]
+  fn after_window() {
+    check(
+      Mode::Normal,
+      "<C-w>",
+      expect![@r#"
+        f     split horizontally
+        h     focus left
+        j     focus down
+        k     focus up
+        l     focus right
+        v     split vertically
+        0     tab 0
+        1     tab 1
+        2     tab 2
+        3     tab 3
+        4     tab 4
+        5     tab 5
+        6     tab 6
+        7     tab 7
+        8     tab 8
+        9     tab 9
+      "#],
+    );
+  }
+
+  #[test]
+  fn operator_pending() {
+    check(
+      Mode::Normal,
+      "2d",
+      expect![@r#"
+        a     +more
+        b     delete previous word
+        d     delete line
+        e     delete end of word
+        f     +more
+        g     +more
+        h     delete left
+        i     +more
+        j     delete down
+        k     delete up
+        l     delete right
+        n     delete next match
+        w     delete next word
+        z     +more
+        F     +more
+        G     delete end of file
+        N     delete previous match
+        0     delete start of line
+        $     delete end of line
+        %     delete matching bracket
+        '     +more
+        [     +more
+        ]     +more
+        ^     delete first non-blank
+        `     +more
+        <C-t> delete back from definition
+      "#],
+    );
+  }
+}
@@ -1,6 +1,7 @@
 mod action;
 mod clipboard;
-mod key;
+mod describe;
+mod keymap;
 
 #[derive(Default, Copy, Clone, Debug, Hash, PartialEq, Eq)]
 pub enum Mode {
@@ -20,5 +21,6 @@ pub enum VisualMode {
 }
 
 pub use action::*;
+pub use be_config::{Key, KeyStroke};
 pub use clipboard::*;
-pub use key::*;
+pub use keymap::Continuations;
@@ -37,9 +37,11 @@ pub struct LspState {
 
 #[derive(Default)]
 pub struct FileState {
-  pub version:     u32,
-  pub doc:         DocumentSnapshot,
-  pub diagnostics: Vec<Diagnostic>,
+  pub version:             u32,
+  pub doc:                 DocumentSnapshot,
+  pub diagnostics:         Vec<Diagnostic>,
+  /// The version of the document that `diagnostics` were published for.
+  pub diagnostics_version: u32,
 }
 
 impl From<DocumentSnapshot> for FileState {
@@ -516,6 +516,7 @@ fn on_publish_diagnostics(state: &mut LspState, params: lsp::PublishDiagnosticsP
     return;
   }
 
+  file.diagnostics_version = file.version;
   file.diagnostics.clear();
   file.diagnostics.extend(params.diagnostics.into_iter().map(|d| Diagnostic {
     range:    decode_range(encoding, &file.doc, d.range),
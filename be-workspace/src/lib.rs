@@ -8,7 +8,7 @@ use std::{
 };
 
 use be_config::Config;
-use be_editor::{EditorEvent, EditorState};
+use be_editor::{CommandHistory, EditorEvent, EditorState, JumpList, Registers};
 use be_fs::{WorkspaceRoot, WorkspaceWatcher};
 use be_git::Repo;
 use be_input::Clipboard;
@@ -24,6 +24,9 @@ pub struct Workspace {
   pub repo:      SharedHandle<Option<Repo>>,
   pub lsp:       Rc<RefCell<LanguageServerStore>>,
   pub clipboard: SharedHandle<Clipboard>,
+  pub registers: SharedHandle<Registers>,
+  pub jumps:     SharedHandle<JumpList>,
+  pub history:   SharedHandle<CommandHistory>,
 
   notifier: Arc<Mutex<Box<dyn Fn(WorkspaceEvent) + Send>>>,
 
@@ -67,6 +70,9 @@ impl Workspace {
       repo: SharedHandle::new(Some(repo)),
       lsp: Rc::new(RefCell::new(lsp)),
       clipboard: SharedHandle::new(Clipboard::dummy()),
+      registers: SharedHandle::default(),
+      jumps: SharedHandle::default(),
+      history: SharedHandle::new(CommandHistory::load()),
 
       notifier,
 
@@ -87,6 +93,14 @@ impl Workspace {
       move |ev| (notifier.lock())(WorkspaceEvent::Editor(ev))
     }));
     editor.clipboard = self.clipboard.clone();
+    editor.registers = self.registers.clone();
+    editor.jumps = self.jumps.clone();
+    editor.history = self.history.clone();
+    editor.root = self.root.as_path().to_path_buf();
+    editor.waker = Some(Arc::new({
+      let notifier = self.notifier.clone();
+      move || (notifier.lock())(WorkspaceEvent::Refresh)
+    }));
 
     let handle = SharedHandle::new(editor);
 
@@ -115,6 +129,12 @@ impl Workspace {
     *self.notifier.lock() = Box::new(wake);
   }
 
+  /// Forgets an editor, so that it no longer counts as an open file. Views that
+  /// still show it keep it alive until they're closed.
+  pub fn close_editor(&mut self, editor: &EditorState) {
+    self.editors.retain(|_, e| !std::ptr::eq(&**e, editor));
+  }
+
   pub fn editors(&self) -> impl Iterator<Item = &SharedHandle<EditorState>> {
     self.editors.values()
   }